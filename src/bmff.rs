@@ -0,0 +1,248 @@
+// ISO-BMFF（MP4 / MOV / HEIC）のボックス構造と、そこから求めるファイルの終わり
+use crate::declared_size::{self, DeclaredSize};
+
+pub const FTYP: &[u8] = b"ftyp";
+// Canon CR3 のメジャーブランド
+pub const CR3_BRAND: &[u8] = b"crx ";
+// 長さ欄 + 種類
+const BOX_HEADER_LEN: usize = 8;
+// 長さ欄が 1 のボックスは、種類の後ろに64ビットの長さ（largesize）が続く
+const LARGE_BOX_HEADER_LEN: usize = 16;
+// 長さ欄 + "ftyp" + メジャーブランド + マイナーバージョン（互換ブランドは4バイトずつ続く）
+const MIN_FTYP_LEN: u32 = 16;
+const MAX_FTYP_LEN: u32 = 256;
+
+#[derive(Debug, PartialEq)]
+pub enum BmffEnd {
+    Found(usize),
+    Incomplete,
+    Invalid,
+}
+
+fn u32_be(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn u64_be(data: &[u8], pos: usize) -> Option<u64> {
+    let bytes = data.get(pos..pos + 8)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+// data はボックスの長さ欄から始まっていること
+// "ftyp" は偶然現れやすいので、手前の長さ欄が ftyp ボックスとして噛み合うものだけを候補にする
+pub fn plausible_ftyp(data: &[u8]) -> bool {
+    let Some(size) = u32_be(data, 0) else {
+        return false;
+    };
+    data.get(4..8) == Some(FTYP)
+        && (MIN_FTYP_LEN..=MAX_FTYP_LEN).contains(&size)
+        && size.is_multiple_of(4)
+}
+
+// 英数字と空白（と © で始まる QuickTime のメタデータ）だけでできた4文字
+fn is_box_type(kind: &[u8]) -> bool {
+    kind.iter()
+        .all(|&b| b.is_ascii_alphanumeric() || b == b' ' || b == 0xA9)
+}
+
+// ftyp から最上位のボックスを順にたどり、ボックスとして読めなくなった位置で終える
+// ftyp だけで終わるものは偽物とみなす
+pub fn find_bmff_end(data: &[u8], max_file_size: u64, eof: bool) -> BmffEnd {
+    if !plausible_ftyp(data) {
+        return BmffEnd::Invalid;
+    }
+
+    let mut pos = 0;
+    let mut boxes = 0;
+    loop {
+        let (Some(size), Some(kind)) = (u32_be(data, pos), data.get(pos + 4..pos + 8)) else {
+            // 次のボックスのヘッダがまだバッファにない
+            return if eof {
+                end_at(pos, boxes)
+            } else {
+                BmffEnd::Incomplete
+            };
+        };
+        if !is_box_type(kind) {
+            return end_at(pos, boxes);
+        }
+
+        let (len, header_len) = match size {
+            // 最後のボックスがファイルの終わりまで続く
+            0 if eof => return BmffEnd::Found(data.len()),
+            0 => return BmffEnd::Incomplete,
+            // 4GiB を超える動画の mdat など
+            1 => match u64_be(data, pos + BOX_HEADER_LEN) {
+                Some(large_size) => (large_size, LARGE_BOX_HEADER_LEN),
+                None if eof => return end_at(pos, boxes),
+                None => return BmffEnd::Incomplete,
+            },
+            n => (n as u64, BOX_HEADER_LEN),
+        };
+        let Some(end) = (pos as u64).checked_add(len) else {
+            return end_at(pos, boxes);
+        };
+        match declared_size::check(end, pos + header_len, max_file_size) {
+            DeclaredSize::Plausible(end) if end <= data.len() => {
+                pos = end;
+                boxes += 1;
+            }
+            DeclaredSize::Plausible(_) if !eof => return BmffEnd::Incomplete,
+            // ボックスの途中でデバイスが終わっている
+            DeclaredSize::Plausible(_) => return BmffEnd::Invalid,
+            DeclaredSize::Implausible => return end_at(pos, boxes),
+        }
+    }
+}
+
+fn end_at(pos: usize, boxes: usize) -> BmffEnd {
+    if boxes >= 2 {
+        BmffEnd::Found(pos)
+    } else {
+        BmffEnd::Invalid
+    }
+}
+
+// ftyp のメジャーブランドが CR3 のもの（MP4 とは別の種類として扱う）
+pub fn is_cr3(data: &[u8]) -> bool {
+    data.get(8..12) == Some(CR3_BRAND)
+}
+
+// メジャーブランドから拡張子を決める
+pub fn extension(data: &[u8]) -> &'static str {
+    match data.get(8..12) {
+        Some(b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1") => "heic",
+        Some(b"qt  ") => "mov",
+        _ => "mp4",
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // ftyp + 中身が payload バイトの mdat
+    pub fn minimal_mp4(brand: &[u8; 4], payload: usize) -> Vec<u8> {
+        let mut data = 20u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(brand);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(b"isom");
+        data.extend_from_slice(&((8 + payload) as u32).to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.resize(data.len() + payload, 0x42);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_mp4;
+    use super::*;
+
+    // ---------------------------
+    // Tests for find_bmff_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_end_after_last_box_when_garbage_follows() {
+        // 1. setup
+        let mp4 = minimal_mp4(b"isom", 32);
+        let mut data = mp4.clone();
+        data.extend_from_slice(&[0xFF; 16]);
+
+        // 2. execute
+        let result = find_bmff_end(&data, 1 << 20, false);
+
+        // 3. verify
+        assert_eq!(result, BmffEnd::Found(mp4.len()));
+    }
+
+    #[test]
+    fn test_should_return_incomplete_when_mdat_is_not_buffered_yet() {
+        // 1. setup
+        let mp4 = minimal_mp4(b"isom", 1024);
+        let data = &mp4[..100];
+
+        // 2. execute
+        let result = find_bmff_end(data, 1 << 20, false);
+
+        // 3. verify
+        assert_eq!(result, BmffEnd::Incomplete);
+    }
+
+    #[test]
+    fn test_should_reject_ftyp_when_box_size_is_not_plausible() {
+        // 1. setup
+        let mut data = minimal_mp4(b"isom", 8);
+        data[..4].copy_from_slice(&0x7FFF_FFFFu32.to_be_bytes());
+
+        // 2. execute
+        let result = plausible_ftyp(&data);
+
+        // 3. verify
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_should_detect_cr3_when_major_brand_is_crx() {
+        // 1. setup
+        let cr3 = minimal_mp4(b"crx ", 8);
+        let mp4 = minimal_mp4(b"isom", 8);
+
+        // 2. execute
+        let results = (is_cr3(&cr3), is_cr3(&mp4));
+
+        // 3. verify
+        assert_eq!(results, (true, false));
+    }
+
+    #[test]
+    fn test_should_follow_largesize_when_box_size_field_is_one() {
+        // 1. setup
+        let mut data = minimal_mp4(b"isom", 0)[..20].to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&(16u64 + 40).to_be_bytes());
+        data.extend_from_slice(&[0x42; 40]);
+        let len = data.len();
+        data.extend_from_slice(&[0xFF; 16]);
+
+        // 2. execute
+        let result = find_bmff_end(&data, 1 << 20, false);
+
+        // 3. verify
+        assert_eq!(result, BmffEnd::Found(len));
+    }
+
+    #[test]
+    fn test_should_wait_for_largesize_when_it_is_not_buffered_yet() {
+        // 1. setup
+        let mut data = minimal_mp4(b"isom", 0)[..20].to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&[0x00; 4]);
+
+        // 2. execute
+        let result = find_bmff_end(&data, 1 << 20, false);
+
+        // 3. verify
+        assert_eq!(result, BmffEnd::Incomplete);
+    }
+
+    // ---------------------------
+    // Tests for extension
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_heic_when_major_brand_is_heic() {
+        // 1. setup
+        let data = minimal_mp4(b"heic", 8);
+
+        // 2. execute
+        let result = extension(&data);
+
+        // 3. verify
+        assert_eq!(result, "heic");
+    }
+}
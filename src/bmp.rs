@@ -0,0 +1,150 @@
+// BMPのヘッダの検証と、そこに書かれたファイルサイズ
+const FILE_HEADER_LEN: usize = 14;
+// BITMAPCOREHEADER / BITMAPINFOHEADER / V2 / V3 / V4 / V5
+const DIB_HEADER_SIZES: [usize; 6] = [12, 40, 52, 56, 108, 124];
+
+#[derive(Debug, PartialEq)]
+pub enum BmpHeader {
+    // 申告されたファイルサイズと、ピクセルデータの位置（ファイルはこれより短くはない）
+    Valid { size: u64, pixel_offset: usize },
+    Incomplete,
+    Invalid,
+}
+
+fn u32_at(data: &[u8], pos: usize) -> usize {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize
+}
+
+// data は "BM" から始まっていること
+// "BM" は偶然現れやすいので、サイズ以外の欄が噛み合わなければ偽物とみなす
+// （サイズ欄だけが壊れている場合の扱いは呼び出し側で決める）
+pub fn parse_header(data: &[u8]) -> BmpHeader {
+    if data.len() < FILE_HEADER_LEN + 4 {
+        return BmpHeader::Incomplete;
+    }
+
+    let size = u32_at(data, 2);
+    let reserved = u32_at(data, 6);
+    let pixel_offset = u32_at(data, 10);
+    let dib_header_size = u32_at(data, 14);
+
+    if reserved != 0
+        || !DIB_HEADER_SIZES.contains(&dib_header_size)
+        || pixel_offset < FILE_HEADER_LEN + dib_header_size
+    {
+        return BmpHeader::Invalid;
+    }
+
+    BmpHeader::Valid {
+        size: size as u64,
+        pixel_offset,
+    }
+}
+
+// BITMAPINFOHEADER 以降の (幅, 高さ)。下から上に並ぶ BMP は高さが負
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let header_size = u32::from_le_bytes(data.get(14..18)?.try_into().ok()?);
+    if header_size < 40 {
+        return None;
+    }
+    let width = i32::from_le_bytes(data.get(18..22)?.try_into().ok()?);
+    let height = i32::from_le_bytes(data.get(22..26)?.try_into().ok()?);
+    (width != 0 && height != 0).then_some((width.unsigned_abs(), height.unsigned_abs()))
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // BITMAPINFOHEADER を持つBMPを組み立てる（ピクセルデータは pixels バイト）
+    pub fn minimal_bmp(pixels: usize) -> Vec<u8> {
+        let pixel_offset = 14 + 40;
+        let size = pixel_offset + pixels;
+        let mut data = vec![b'B', b'M'];
+        data.extend_from_slice(&(size as u32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(pixel_offset as u32).to_le_bytes());
+        data.extend_from_slice(&40u32.to_le_bytes());
+        data.extend_from_slice(&1i32.to_le_bytes()); // 幅
+        data.extend_from_slice(&1i32.to_le_bytes()); // 高さ
+        data.extend_from_slice(&1u16.to_le_bytes()); // プレーン数
+        data.extend_from_slice(&24u16.to_le_bytes()); // ビット数
+        data.resize(pixel_offset, 0x00);
+        data.resize(size, 0x7F);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_bmp;
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse_header
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_declared_size_when_header_is_consistent() {
+        // 1. setup
+        let bmp = minimal_bmp(4);
+        let mut data = bmp.clone();
+        data.extend_from_slice(&[0xEE; 32]); // 後ろに続くゴミ
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            BmpHeader::Valid {
+                size: bmp.len() as u64,
+                pixel_offset: 54,
+            }
+        );
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_bm_is_followed_by_random_bytes() {
+        // 1. setup
+        let mut data = vec![b'B', b'M'];
+        data.extend_from_slice(&[0x13, 0x57, 0x9B, 0xDF, 0x02, 0x46, 0x8A, 0xCE]);
+        data.resize(64, 0x5A);
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(result, BmpHeader::Invalid);
+    }
+
+    #[test]
+    fn test_should_return_incomplete_when_header_is_not_buffered_yet() {
+        // 1. setup
+        let bmp = minimal_bmp(1024);
+        let data = &bmp[..10];
+
+        // 2. execute
+        let result = parse_header(data);
+
+        // 3. verify
+        assert_eq!(result, BmpHeader::Incomplete);
+    }
+
+    // ---------------------------
+    // Tests for dimensions
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_absolute_height_when_bmp_is_top_down() {
+        // 1. setup
+        let mut bmp = minimal_bmp(16);
+        bmp[18..22].copy_from_slice(&640i32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&(-480i32).to_le_bytes());
+
+        // 2. execute
+        let result = dimensions(&bmp);
+
+        // 3. verify
+        assert_eq!(result, Some((640, 480)));
+    }
+}
@@ -0,0 +1,865 @@
+// スタートシグネチャの位置からファイルの終端を決める（スキャンと単発の切り出しで共通）
+use crate::bmff::{self, BmffEnd};
+use crate::bmp::{self, BmpHeader};
+use crate::cli::Options;
+use crate::color;
+use crate::declared_size::{self, DeclaredSize};
+use crate::dicom::{self, DicomEnd};
+use crate::jpeg::{self, JpegEnd, JpegEndStrategy};
+use crate::mpf;
+use crate::psd::{self, PsdHeader};
+use crate::raf::{self, RafHeader};
+use crate::run_log::log_eprintln;
+use crate::source::{self, ReadError, Source};
+use crate::tga::{self, TgaStart};
+use crate::tiff::{self, ParseError, TiffEnd};
+use crate::trim;
+use crate::webp::{self, WebpHeader};
+use crate::x3f::{self, X3fEnd};
+use crate::xmp::{self, XmpEnd};
+use crate::zip::{self, ZipEnd};
+use crate::{
+    BUFFER_CAP_FACTOR, FileType, find_all_starts, find_signature, longest_signature, next_end_check,
+};
+
+// 終端をどう決めたかによる確からしさ（manifest の confidence）
+pub const CONFIDENCE_STRUCTURE: f64 = 1.0;
+pub const CONFIDENCE_END_MARKER: f64 = 0.75;
+pub const CONFIDENCE_NEXT_SIGNATURE: f64 = 0.5;
+pub const CONFIDENCE_BUFFER_END: f64 = 0.25;
+
+// --min-confidence ...:quarantine で、しきい値を下回ったファイルを分けて置くフォルダ
+pub const LOW_CONFIDENCE_DIR: &str = "low-confidence";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinConfidence {
+    pub threshold: f64,
+    // 下回ったファイルを捨てずに LOW_CONFIDENCE_DIR に保存する
+    pub quarantine: bool,
+}
+
+impl MinConfidence {
+    // "0.5" または "0.5:quarantine"
+    pub fn parse(value: &str) -> Option<Self> {
+        let (threshold, quarantine) = match value.split_once(':') {
+            Some((threshold, "quarantine")) => (threshold, true),
+            Some(_) => return None,
+            None => (value, false),
+        };
+        let threshold: f64 = threshold.trim().parse().ok()?;
+        (0.0..=1.0).contains(&threshold).then_some(MinConfidence {
+            threshold,
+            quarantine,
+        })
+    }
+}
+
+// 終端の決め方。種類ごとの既定は default_strategy で、--prefer-strategy-per-type で変えられる
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndStrategy {
+    // JPEG のセグメントや TIFF の IFD の連鎖をたどる
+    SegmentWalk,
+    // ヘッダに書かれたファイルサイズ
+    LengthField,
+    // ISO-BMFF の最上位のボックスの長さをたどる
+    IffBox,
+    // 次のスタートシグネチャまで（上限で打ち切る）
+    NextSignature,
+    // 終端のレコードやフッター、終了シグネチャを探す
+    FooterSearch,
+}
+
+impl EndStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "segment-walk" => Some(EndStrategy::SegmentWalk),
+            "length-field" => Some(EndStrategy::LengthField),
+            "iff-box" => Some(EndStrategy::IffBox),
+            "next-signature" => Some(EndStrategy::NextSignature),
+            "footer-search" => Some(EndStrategy::FooterSearch),
+            _ => None,
+        }
+    }
+}
+
+// 種類ごとに、その形式の構造に合った終端の決め方
+pub fn default_strategy(file_type: FileType) -> EndStrategy {
+    match file_type {
+        FileType::Jpeg
+        | FileType::Rw2
+        | FileType::Dng
+        | FileType::Orf
+        | FileType::Nrw
+        | FileType::Tiff
+        | FileType::Dicom => EndStrategy::SegmentWalk,
+        FileType::Bmp | FileType::Webp | FileType::Raf | FileType::Psd => EndStrategy::LengthField,
+        FileType::Mp4 | FileType::Cr3 => EndStrategy::IffBox,
+        FileType::Crw => EndStrategy::NextSignature,
+        FileType::Zip | FileType::Tga | FileType::X3f | FileType::Xmp => EndStrategy::FooterSearch,
+        FileType::Custom(signature) if signature.end.is_empty() => EndStrategy::NextSignature,
+        FileType::Custom(_) => EndStrategy::FooterSearch,
+    }
+}
+
+// file_type に使える決め方か。既定のほかは、スタートシグネチャを持つ種類の next-signature だけ
+pub fn supports_strategy(file_type: FileType, strategy: EndStrategy) -> bool {
+    strategy == default_strategy(file_type)
+        || (strategy == EndStrategy::NextSignature && file_type.signature_len() > 0)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CarveEnd {
+    // スタートシグネチャから数えた長さと確からしさ
+    Found(usize, f64),
+    // バッファが足りず、まだ判断できない
+    NeedMoreData,
+    // 偽のスタートシグネチャ
+    Invalid,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CarvedFile {
+    pub file_type: FileType,
+    pub offset: u64,
+    pub data: Vec<u8>,
+    pub confidence: f64,
+}
+
+// data は file_type のスタートシグネチャから始まっていること
+// --trim-trailing なら、次のスタートシグネチャやバッファの終わりで切ったものの後ろのゼロを落とす
+// --prefer-strategy-per-type で既定と違う決め方（next-signature）を選んだ種類は、構造を読まない
+pub fn find_end(data: &[u8], file_type: FileType, options: &Options, eof: bool) -> CarveEnd {
+    let strategy = options.end_strategy_for(file_type);
+    let end = if strategy == default_strategy(file_type) {
+        structural_end(data, file_type, options, eof)
+    } else {
+        capped_next_signature_end(data, file_type, options, eof)
+    };
+    match end {
+        CarveEnd::Found(len, confidence)
+            if options.trim_trailing && confidence <= CONFIDENCE_NEXT_SIGNATURE =>
+        {
+            CarveEnd::Found(trim::trimmed_len(&data[..len], file_type), confidence)
+        }
+        end => end,
+    }
+}
+
+fn structural_end(data: &[u8], file_type: FileType, options: &Options, eof: bool) -> CarveEnd {
+    match file_type {
+        // JPEGの場合、エンドマーカーを探す
+        FileType::Jpeg => {
+            let strategy = options.jpeg_end_strategy;
+            let next_start = if strategy == JpegEndStrategy::Last {
+                let skip = file_type.signature_len();
+                match find_all_starts(data.get(skip..).unwrap_or_default()).first() {
+                    Some((next_idx, _)) => Some(skip + *next_idx),
+                    None if eof => Some(data.len()),
+                    None => None,
+                }
+            } else {
+                None
+            };
+
+            let end = match jpeg::find_jpeg_end(data, strategy, next_start) {
+                JpegEnd::Found(len) if strategy == JpegEndStrategy::Strict => {
+                    let len = if options.jpeg_eoi_run {
+                        jpeg::extend_eoi_run(data, len)
+                    } else {
+                        len
+                    };
+                    CarveEnd::Found(len, CONFIDENCE_STRUCTURE)
+                }
+                JpegEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_END_MARKER),
+                JpegEnd::Incomplete => CarveEnd::NeedMoreData,
+                JpegEnd::Invalid => CarveEnd::Invalid,
+            };
+            match end {
+                CarveEnd::Found(len, confidence) if !options.split_mpo => {
+                    mpo_end(data, len, confidence, options, eof)
+                }
+                end => end,
+            }
+        }
+        // RW2 / ORF / NRW / TIFF の場合、IFDの連鎖をたどって求めた本来のサイズを使い、
+        // 解析できなければ次のスタートシグネチャまで（読み込みブロックの境目では切らず、上限で打ち切る）
+        FileType::Rw2 | FileType::Orf | FileType::Nrw | FileType::Tiff => {
+            match tiff::find_tiff_end(data) {
+                TiffEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_STRUCTURE),
+                TiffEnd::Incomplete if !eof => CarveEnd::NeedMoreData,
+                TiffEnd::Incomplete | TiffEnd::Invalid => {
+                    capped_next_signature_end(data, file_type, options, eof)
+                }
+            }
+        }
+        // DNGの場合、DNGVersion タグを確かめてから RW2 と同じくIFDのストリップ・タイルから求める
+        FileType::Dng => match tiff::has_dng_version(data) {
+            Ok(true) => match tiff::find_tiff_end(data) {
+                TiffEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_STRUCTURE),
+                TiffEnd::Incomplete if !eof => CarveEnd::NeedMoreData,
+                TiffEnd::Incomplete | TiffEnd::Invalid => {
+                    capped_next_signature_end(data, file_type, options, eof)
+                }
+            },
+            Err(ParseError::NeedMoreData) if !eof => CarveEnd::NeedMoreData,
+            Ok(false) | Err(_) => CarveEnd::Invalid,
+        },
+        // ISO-BMFF（CR3 も含む）の場合、最上位のボックスの長さをたどる
+        FileType::Mp4 | FileType::Cr3 => {
+            match bmff::find_bmff_end(data, options.max_file_size_for(file_type), eof) {
+                BmffEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_STRUCTURE),
+                BmffEnd::Incomplete => CarveEnd::NeedMoreData,
+                BmffEnd::Invalid => CarveEnd::Invalid,
+            }
+        }
+        // CRWの場合、最後を示すマーカーがないので次のスタートシグネチャまで（上限で打ち切る）
+        FileType::Crw => capped_next_signature_end(data, file_type, options, eof),
+        // ZIPの場合、セントラルディレクトリと噛み合う終端レコードまで（途中のローカルヘッダは無視する）
+        FileType::Zip => match zip::find_zip_end(data, options.max_file_size_for(file_type)) {
+            ZipEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_STRUCTURE),
+            ZipEnd::Incomplete if !eof => CarveEnd::NeedMoreData,
+            ZipEnd::Incomplete | ZipEnd::Invalid => CarveEnd::Invalid,
+        },
+        // BMPの場合、ヘッダに書かれたファイルサイズちょうど
+        FileType::Bmp => match bmp::parse_header(data) {
+            BmpHeader::Valid { size, pixel_offset } => {
+                declared_end(data, file_type, size, pixel_offset, options, eof)
+            }
+            BmpHeader::Incomplete if !eof => CarveEnd::NeedMoreData,
+            BmpHeader::Incomplete | BmpHeader::Invalid => CarveEnd::Invalid,
+        },
+        // RAFの場合、ヘッダに書かれたプレビューと RAW データのうち最も後ろの終わりまで
+        FileType::Raf => match raf::parse_header(data) {
+            RafHeader::Valid { size } => {
+                declared_end(data, file_type, size, raf::HEADER_LEN, options, eof)
+            }
+            RafHeader::Incomplete if !eof => CarveEnd::NeedMoreData,
+            RafHeader::Incomplete | RafHeader::Invalid => CarveEnd::Invalid,
+        },
+        // PSDの場合、ヘッダの後ろのセクションの長さと、画像データの寸法から求めた終わりまで
+        // 画像データが ZIP 圧縮なら長さが分からないので次のスタートシグネチャまで
+        FileType::Psd => match psd::parse_header(data) {
+            PsdHeader::Valid { size } => {
+                declared_end(data, file_type, size, psd::HEADER_LEN, options, eof)
+            }
+            PsdHeader::Unsized => capped_next_signature_end(data, file_type, options, eof),
+            PsdHeader::Incomplete if !eof => CarveEnd::NeedMoreData,
+            PsdHeader::Incomplete | PsdHeader::Invalid => CarveEnd::Invalid,
+        },
+        // X3Fの場合、末尾のポインタと指し合うディレクトリの後ろまで（上限までに見つからなければ偽物）
+        FileType::X3f => match x3f::find_x3f_end(data) {
+            X3fEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_STRUCTURE),
+            X3fEnd::Incomplete
+                if !eof && (data.len() as u64) < options.max_file_size_for(file_type) =>
+            {
+                CarveEnd::NeedMoreData
+            }
+            X3fEnd::Incomplete | X3fEnd::Invalid => CarveEnd::Invalid,
+        },
+        // XMPの場合、パケットの終わりの処理命令か xmpmeta の閉じタグまで（上限までに見つからなければ偽物）
+        FileType::Xmp => match xmp::find_xmp_end(data) {
+            XmpEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_END_MARKER),
+            XmpEnd::Incomplete
+                if !eof && (data.len() as u64) < options.max_file_size_for(file_type) =>
+            {
+                CarveEnd::NeedMoreData
+            }
+            XmpEnd::Incomplete | XmpEnd::Invalid => CarveEnd::Invalid,
+        },
+        // DICOMの場合、データ要素を長さでたどり、タグが昇順でなくなるところまで
+        // データセットが deflate 圧縮なら要素をたどれないので次のスタートシグネチャまで
+        FileType::Dicom => {
+            let max_len =
+                usize::try_from(options.max_file_size_for(file_type)).unwrap_or(usize::MAX);
+            match dicom::find_dicom_end(data, max_len, eof) {
+                DicomEnd::Found(len) => CarveEnd::Found(len, CONFIDENCE_STRUCTURE),
+                DicomEnd::Unsized => capped_next_signature_end(data, file_type, options, eof),
+                DicomEnd::Incomplete if !eof => CarveEnd::NeedMoreData,
+                DicomEnd::Incomplete => next_signature_end(data, file_type),
+                DicomEnd::Invalid => CarveEnd::Invalid,
+            }
+        }
+        // --add-signature の種類は、終了シグネチャの終わりまで（なければ次のスタートシグネチャまで）
+        FileType::Custom(signature) if signature.end.is_empty() => {
+            capped_next_signature_end(data, file_type, options, eof)
+        }
+        FileType::Custom(signature) => custom_end(data, file_type, signature.end, options, eof),
+        // TGAは先頭からではなく、フッターから find_start_before で探す
+        FileType::Tga => CarveEnd::Invalid,
+        // WebPの場合、RIFFヘッダに書かれたサイズちょうど
+        FileType::Webp => match webp::parse_header(data) {
+            WebpHeader::Valid { size } => declared_end(data, file_type, size, 20, options, eof),
+            WebpHeader::Incomplete if !eof => CarveEnd::NeedMoreData,
+            WebpHeader::Incomplete | WebpHeader::Invalid => CarveEnd::Invalid,
+        },
+    }
+}
+
+// data は file_type のフッターで終わっていること
+// フッターから後ろ向きに戻って見つけたファイル先頭の位置と確からしさ（先頭はフッターより最大 data.len() 前）
+pub fn find_start_before(data: &[u8], file_type: FileType) -> Option<(usize, f64)> {
+    match file_type {
+        FileType::Tga => match tga::find_start(data)? {
+            TgaStart::Exact(start) => Some((start, CONFIDENCE_STRUCTURE)),
+            TgaStart::ExtensionArea(start) => Some((start, CONFIDENCE_END_MARKER)),
+            TgaStart::HeaderOnly(start) => Some((start, CONFIDENCE_NEXT_SIGNATURE)),
+        },
+        _ => None,
+    }
+}
+
+// MPF の索引が1枚目より後ろの画像を指していれば、最後の画像の終わりまでを1つの MPO にする
+fn mpo_end(
+    data: &[u8],
+    first_len: usize,
+    confidence: f64,
+    options: &Options,
+    eof: bool,
+) -> CarveEnd {
+    let first_only = CarveEnd::Found(first_len, confidence);
+    let Some(images) = mpf::images(&data[..first_len]) else {
+        return first_only;
+    };
+    let Some(total) = mpf::total_len(&images).filter(|&total| {
+        total > first_len && total as u64 <= options.max_file_size_for(FileType::Jpeg)
+    }) else {
+        return first_only;
+    };
+    if total > data.len() {
+        // 2枚目以降の途中でデバイスが終わっていれば、1枚目だけを残す
+        return if eof {
+            first_only
+        } else {
+            CarveEnd::NeedMoreData
+        };
+    }
+    // 索引が指す位置にJPEGがなければ、別のファイルで上書きされているとみなす
+    let all_jpeg = images.iter().all(|image| {
+        data.get(image.offset..)
+            .is_some_and(|rest| rest.starts_with(FileType::Jpeg.start_signatures()[0].magic))
+    });
+    if all_jpeg {
+        CarveEnd::Found(total, confidence)
+    } else {
+        first_only
+    }
+}
+
+// 次のスタートシグネチャ（なければバッファの終わり）までを1ファイルとみなす
+fn next_signature_end(data: &[u8], file_type: FileType) -> CarveEnd {
+    let skip = file_type.signature_len();
+    match find_all_starts(data.get(skip..).unwrap_or_default()).first() {
+        Some((next_idx, _)) => CarveEnd::Found(skip + *next_idx, CONFIDENCE_NEXT_SIGNATURE),
+        None => CarveEnd::Found(data.len(), CONFIDENCE_BUFFER_END),
+    }
+}
+
+// next_signature_end と同じく次のスタートシグネチャまでだが、途中のバッファの終わりでは切らずに読み足し、
+// 種類ごとの上限に達したらそこで打ち切る
+fn capped_next_signature_end(
+    data: &[u8],
+    file_type: FileType,
+    options: &Options,
+    eof: bool,
+) -> CarveEnd {
+    let limit = usize::try_from(options.max_file_size_for(file_type)).unwrap_or(usize::MAX);
+    let skip = file_type.signature_len();
+    let searched = data.get(skip..limit.min(data.len())).unwrap_or_default();
+    match find_all_starts(searched).first() {
+        Some((next_idx, _)) => CarveEnd::Found(skip + *next_idx, CONFIDENCE_NEXT_SIGNATURE),
+        None if data.len() >= limit => CarveEnd::Found(limit, CONFIDENCE_BUFFER_END),
+        None if eof => CarveEnd::Found(data.len(), CONFIDENCE_BUFFER_END),
+        None => CarveEnd::NeedMoreData,
+    }
+}
+
+// 上限までに終了シグネチャが見つからなければ、そのファイルではなかったとみなす
+fn custom_end(
+    data: &[u8],
+    file_type: FileType,
+    end: &[u8],
+    options: &Options,
+    eof: bool,
+) -> CarveEnd {
+    let limit = usize::try_from(options.max_file_size_for(file_type)).unwrap_or(usize::MAX);
+    let skip = file_type.signature_len();
+    let searched = data.get(skip..limit.min(data.len())).unwrap_or_default();
+    match find_signature(searched, end) {
+        Some(idx) => CarveEnd::Found(skip + idx + end.len(), CONFIDENCE_END_MARKER),
+        None if data.len() >= limit || eof => CarveEnd::Invalid,
+        None => CarveEnd::NeedMoreData,
+    }
+}
+
+// 長さを自己申告する形式の終端。申告がありえない値なら次のスタートシグネチャまでにする
+fn declared_end(
+    data: &[u8],
+    file_type: FileType,
+    declared: u64,
+    min_len: usize,
+    options: &Options,
+    eof: bool,
+) -> CarveEnd {
+    match declared_size::check(declared, min_len, options.max_file_size_for(file_type)) {
+        DeclaredSize::Plausible(len) if len <= data.len() => {
+            CarveEnd::Found(len, CONFIDENCE_STRUCTURE)
+        }
+        DeclaredSize::Plausible(_) if !eof => CarveEnd::NeedMoreData,
+        // 申告より前にデバイスが終わっている
+        DeclaredSize::Plausible(_) => CarveEnd::Invalid,
+        DeclaredSize::Implausible => next_signature_end(data, file_type),
+    }
+}
+
+// 既知のオフセットにあるファイルを1つだけ切り出す（全体スキャンはしない）
+// 全体スキャンと同じく、終端検出は読んだ長さが倍になるたびにやり直し、上限の BUFFER_CAP_FACTOR 倍で諦める
+pub fn carve_at<R: Source>(reader: &mut R, offset: u64, options: &Options) -> Option<CarvedFile> {
+    reader.seek_to(offset).ok()?;
+
+    let longest_signature = longest_signature(&options.types);
+    let mut data = Vec::new();
+    let mut temp = vec![0u8; options.block_size];
+    // 次に終端検出をやり直すまでに読む長さ
+    let mut next_check = 0;
+
+    loop {
+        let eof = match source::read_block(reader, &mut temp, offset + data.len() as u64) {
+            Ok(0) => true,
+            Ok(n) => {
+                data.extend_from_slice(&temp[..n]);
+                false
+            }
+            Err(e) => {
+                let error = ReadError {
+                    offset: offset + data.len() as u64,
+                    file_index: None,
+                    error: e,
+                };
+                log_eprintln!("{}", color::error(format_args!("{}", error)));
+                true
+            }
+        };
+        if (data.len() < longest_signature || data.len() < next_check) && !eof {
+            continue;
+        }
+
+        let file_type = *options.types.iter().find(|t| {
+            t.start_signatures()
+                .iter()
+                .any(|signature| signature.matches(&data))
+                && t.claims(&data)
+        })?;
+
+        match find_end(&data, file_type, options, eof) {
+            CarveEnd::Found(len, confidence) => {
+                data.truncate(len);
+                return Some(CarvedFile {
+                    file_type,
+                    offset,
+                    data,
+                    confidence,
+                });
+            }
+            CarveEnd::NeedMoreData
+                if !eof
+                    && (data.len() as u64)
+                        < BUFFER_CAP_FACTOR
+                            .saturating_mul(options.max_file_size_for(file_type)) =>
+            {
+                next_check = next_end_check(data.len(), file_type, options);
+            }
+            CarveEnd::NeedMoreData | CarveEnd::Invalid => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+    use std::io::Cursor;
+
+    // ---------------------------
+    // Tests for find_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_fall_back_to_next_signature_when_bmp_declares_absurd_size() {
+        // 1. setup
+        let mut bmp = bmp::test_fixtures::minimal_bmp(64);
+        bmp[2..6].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+        let mut data = bmp.clone();
+        data.extend_from_slice(&minimal_jpeg(&[0x01]));
+
+        // 2. execute
+        let result = find_end(&data, FileType::Bmp, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            CarveEnd::Found(bmp.len(), CONFIDENCE_NEXT_SIGNATURE)
+        );
+    }
+
+    #[test]
+    fn test_should_fall_back_to_next_signature_when_webp_declares_absurd_size() {
+        // 1. setup
+        let mut webp = webp::test_fixtures::minimal_webp(64);
+        webp[4..8].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+        let mut data = webp.clone();
+        data.extend_from_slice(&minimal_jpeg(&[0x01]));
+
+        // 2. execute
+        let result = find_end(&data, FileType::Webp, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            CarveEnd::Found(webp.len(), CONFIDENCE_NEXT_SIGNATURE)
+        );
+    }
+
+    #[test]
+    fn test_should_use_declared_size_when_webp_size_is_within_max_file_size() {
+        // 1. setup
+        let webp = webp::test_fixtures::minimal_webp(64);
+        let mut data = webp.clone();
+        data.extend_from_slice(&[0x00; 32]);
+
+        // 2. execute
+        let result = find_end(&data, FileType::Webp, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(result, CarveEnd::Found(webp.len(), CONFIDENCE_STRUCTURE));
+    }
+
+    #[test]
+    fn test_should_honor_each_types_cap_when_max_size_differs_by_type() {
+        // 1. setup
+        let bmp = bmp::test_fixtures::minimal_bmp(512);
+        let webp = webp::test_fixtures::minimal_webp(512);
+        let jpeg = minimal_jpeg(&[0x01]);
+        let options = Options {
+            max_file_size_by_type: vec![(FileType::Bmp, 256), (FileType::Webp, 4096)],
+            ..Options::default()
+        };
+
+        // 2. execute
+        let bmp_result = find_end(
+            &[bmp.as_slice(), &jpeg].concat(),
+            FileType::Bmp,
+            &options,
+            false,
+        );
+        let webp_result = find_end(
+            &[webp.as_slice(), &jpeg].concat(),
+            FileType::Webp,
+            &options,
+            false,
+        );
+
+        // 3. verify
+        // BMP の申告は上限を超えるので信じず、次のシグネチャまでにする
+        assert_eq!(
+            bmp_result,
+            CarveEnd::Found(bmp.len(), CONFIDENCE_NEXT_SIGNATURE)
+        );
+        assert_eq!(
+            webp_result,
+            CarveEnd::Found(webp.len(), CONFIDENCE_STRUCTURE)
+        );
+    }
+
+    #[test]
+    fn test_should_carve_whole_mpo_when_mpf_index_lists_second_image() {
+        // 1. setup
+        let (mpo, _) = mpf::test_fixtures::two_image_mpo();
+        let mut data = mpo.clone();
+        data.extend_from_slice(&[0x00; 32]);
+
+        // 2. execute
+        let result = find_end(&data, FileType::Jpeg, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(result, CarveEnd::Found(mpo.len(), CONFIDENCE_STRUCTURE));
+    }
+
+    #[test]
+    fn test_should_stop_at_first_image_when_split_mpo_is_enabled() {
+        // 1. setup
+        let (mpo, first_len) = mpf::test_fixtures::two_image_mpo();
+        let options = Options {
+            split_mpo: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let result = find_end(&mpo, FileType::Jpeg, &options, false);
+
+        // 3. verify
+        assert_eq!(result, CarveEnd::Found(first_len, CONFIDENCE_STRUCTURE));
+    }
+
+    #[test]
+    fn test_should_end_crw_at_next_signature_when_another_file_follows() {
+        // 1. setup
+        let mut crw = FileType::Crw.start_signatures()[0].magic.to_vec();
+        crw.resize(300, 0x33);
+        let mut data = crw.clone();
+        data.extend_from_slice(&minimal_jpeg(&[0x01]));
+
+        // 2. execute
+        let result = find_end(&data, FileType::Crw, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            CarveEnd::Found(crw.len(), CONFIDENCE_NEXT_SIGNATURE)
+        );
+    }
+
+    #[test]
+    fn test_should_cut_crw_at_cap_when_no_signature_follows() {
+        // 1. setup
+        let mut data = FileType::Crw.start_signatures()[0].magic.to_vec();
+        data.resize(1000, 0x33);
+        let options = Options {
+            max_file_size_by_type: vec![(FileType::Crw, 512)],
+            ..Options::default()
+        };
+
+        // 2. execute
+        let capped = find_end(&data, FileType::Crw, &options, false);
+        let waiting = find_end(&data, FileType::Crw, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(capped, CarveEnd::Found(512, CONFIDENCE_BUFFER_END));
+        assert_eq!(waiting, CarveEnd::NeedMoreData);
+    }
+
+    #[test]
+    fn test_should_wait_for_next_signature_when_rw2_ifd_cannot_be_parsed() {
+        // 1. setup
+        // IFD を指すオフセットが壊れていて、構造から終わりを求められない RW2
+        let mut rw2 = tiff::test_fixtures::little_endian_tiff(&[], 40);
+        rw2[4..8].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+        let data = [rw2.as_slice(), &[0x5A; 200]].concat();
+        let options = Options {
+            max_file_size_by_type: vec![(FileType::Rw2, 128)],
+            ..Options::default()
+        };
+
+        // 2. execute
+        let waiting = find_end(&data, FileType::Rw2, &Options::default(), false);
+        let capped = find_end(&data, FileType::Rw2, &options, false);
+        let at_eof = find_end(&data, FileType::Rw2, &Options::default(), true);
+
+        // 3. verify
+        assert_eq!(waiting, CarveEnd::NeedMoreData);
+        assert_eq!(capped, CarveEnd::Found(128, CONFIDENCE_BUFFER_END));
+        assert_eq!(at_eof, CarveEnd::Found(data.len(), CONFIDENCE_BUFFER_END));
+    }
+
+    #[test]
+    fn test_should_find_distant_end_when_signature_limit_exceeds_global_cap() {
+        // 1. setup
+        let mp4 = bmff::test_fixtures::minimal_mp4(b"isom", 8192);
+        let bmp = bmp::test_fixtures::minimal_bmp(8192);
+        // MP4 は形式ごとの上限を持ち、BMP は持たないので 4096 で打ち切られる
+        let options = Options {
+            max_file_size: 4096,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let mp4_result = find_end(&mp4, FileType::Mp4, &options, true);
+        let bmp_result = find_end(&bmp, FileType::Bmp, &options, true);
+
+        // 3. verify
+        assert_eq!(mp4_result, CarveEnd::Found(mp4.len(), CONFIDENCE_STRUCTURE));
+        assert_eq!(
+            bmp_result,
+            CarveEnd::Found(bmp.len(), CONFIDENCE_BUFFER_END)
+        );
+        assert_eq!(options.max_file_size_for(FileType::Mp4), 4 << 30);
+    }
+
+    #[test]
+    fn test_should_stay_inside_buffer_when_data_is_only_the_start_signature() {
+        // 1. setup
+        let options = Options::default();
+        let cases: Vec<(FileType, Vec<u8>)> = FileType::ALL
+            .iter()
+            .flat_map(|t| {
+                t.start_signatures()
+                    .iter()
+                    .map(move |s| (*t, [vec![0x00; s.offset], s.magic.to_vec()].concat()))
+            })
+            .collect();
+
+        for (file_type, signature) in cases {
+            for eof in [false, true] {
+                // 2. execute
+                let result = find_end(&signature, file_type, &options, eof);
+
+                // 3. verify
+                if let CarveEnd::Found(len, _) = result {
+                    assert!(len <= signature.len(), "{:?} eof={}", file_type, eof);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_cut_at_next_signature_when_rw2_strategy_is_overridden() {
+        // 1. setup
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        let data = [rw2.as_slice(), &[0x00; 30], &minimal_jpeg(&[0x01])].concat();
+        let options = Options {
+            end_strategy_by_type: vec![(FileType::Rw2, EndStrategy::NextSignature)],
+            ..Options::default()
+        };
+
+        // 2. execute
+        let overridden = find_end(&data, FileType::Rw2, &options, false);
+        let default = find_end(&data, FileType::Rw2, &Options::default(), false);
+
+        // 3. verify
+        assert_eq!(
+            overridden,
+            CarveEnd::Found(rw2.len() + 30, CONFIDENCE_NEXT_SIGNATURE)
+        );
+        assert_eq!(default, CarveEnd::Found(rw2.len(), CONFIDENCE_STRUCTURE));
+    }
+
+    // ---------------------------
+    // Tests for default_strategy
+    // ---------------------------
+
+    #[test]
+    fn test_should_designate_strategy_matching_format_when_type_is_known() {
+        // 1. setup
+        let types = FileType::ALL;
+
+        // 2. execute
+        let strategies: Vec<(FileType, EndStrategy)> =
+            types.iter().map(|t| (*t, default_strategy(*t))).collect();
+
+        // 3. verify
+        assert_eq!(
+            strategies,
+            vec![
+                (FileType::Jpeg, EndStrategy::SegmentWalk),
+                (FileType::Rw2, EndStrategy::SegmentWalk),
+                (FileType::Zip, EndStrategy::FooterSearch),
+                (FileType::Bmp, EndStrategy::LengthField),
+                (FileType::Webp, EndStrategy::LengthField),
+                (FileType::Dng, EndStrategy::SegmentWalk),
+                (FileType::Mp4, EndStrategy::IffBox),
+                (FileType::Crw, EndStrategy::NextSignature),
+                (FileType::Tga, EndStrategy::FooterSearch),
+                (FileType::Cr3, EndStrategy::IffBox),
+                (FileType::Orf, EndStrategy::SegmentWalk),
+                (FileType::Raf, EndStrategy::LengthField),
+                (FileType::Psd, EndStrategy::LengthField),
+                (FileType::Nrw, EndStrategy::SegmentWalk),
+                (FileType::X3f, EndStrategy::FooterSearch),
+                (FileType::Tiff, EndStrategy::SegmentWalk),
+                (FileType::Xmp, EndStrategy::FooterSearch),
+                (FileType::Dicom, EndStrategy::SegmentWalk),
+            ]
+        );
+    }
+
+    // ---------------------------
+    // Tests for carve_at
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_planted_file_when_offset_points_at_signature() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut device = vec![0x00; 4096];
+        device.extend_from_slice(&jpeg);
+        device.extend_from_slice(&[0x00; 100]);
+        let mut reader = Cursor::new(device);
+
+        // 2. execute
+        let result = carve_at(&mut reader, 4096, &Options::default());
+
+        // 3. verify
+        let carved = result.unwrap();
+        assert_eq!(carved.file_type, FileType::Jpeg);
+        assert_eq!(carved.offset, 4096);
+        assert_eq!(carved.data, jpeg);
+    }
+
+    #[test]
+    fn test_should_stop_at_buffer_cap_when_start_has_no_end() {
+        // 1. setup
+        // SOI と SOS の後ろがゼロのまま終わらない JPEG と、読み足しを何度も要する完結した JPEG
+        let mut unterminated = vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x03, 0x01];
+        unterminated.resize(64 * 1024, 0x00);
+        let complete = minimal_jpeg(&[0x11; 20000]);
+        let options = Options {
+            block_size: 512,
+            max_file_size_by_type: vec![(FileType::Jpeg, 4096)],
+            ..Options::default()
+        };
+        let mut reader = Cursor::new(unterminated);
+        let mut complete_reader = Cursor::new(complete.clone());
+
+        // 2. execute
+        let result = carve_at(&mut reader, 0, &options);
+        let found = carve_at(
+            &mut complete_reader,
+            0,
+            &Options {
+                block_size: 512,
+                ..Options::default()
+            },
+        );
+
+        // 3. verify
+        assert_eq!(result, None);
+        assert!(reader.position() <= 2 * 4096 + 512, "{}", reader.position());
+        assert_eq!(found.unwrap().data, complete);
+    }
+
+    #[test]
+    fn test_should_return_none_when_offset_has_no_signature() {
+        // 1. setup
+        let mut device = vec![0x00; 4096];
+        device.extend_from_slice(&minimal_jpeg(&[0x01]));
+        let mut reader = Cursor::new(device);
+
+        // 2. execute
+        let result = carve_at(&mut reader, 100, &Options::default());
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_should_return_none_when_type_of_signature_is_not_active() {
+        // 1. setup
+        let mut reader = Cursor::new(minimal_jpeg(&[0x01]));
+        let options = Options {
+            types: vec![FileType::Rw2],
+            ..Options::default()
+        };
+
+        // 2. execute
+        let result = carve_at(&mut reader, 0, &options);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
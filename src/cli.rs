@@ -0,0 +1,1258 @@
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::thread;
+
+use crate::carve::{self, EndStrategy, MinConfidence};
+use crate::color::ColorChoice;
+use crate::jpeg::JpegEndStrategy;
+use crate::listing::{Filter, SortKey};
+use crate::manifest::{OffsetBase, ReportFormat};
+use crate::output::{ArchiveFormat, OutputPattern};
+use crate::strategy::{self, ScanStrategy};
+use crate::{DEVICE_PATH, FileType, OUTPUT_DIR, READ_BLOCK_SIZE, custom};
+
+const DEFAULT_MAX_CANDIDATES_PER_BLOCK: usize = 4096;
+const DEFAULT_MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+const DEFAULT_UNKNOWN_MIN_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub device_path: PathBuf,
+    pub output_dir: PathBuf,
+    // 検出領域以外をゼロ埋めした再構成イメージを書き出す（診断用）
+    pub zero_fill_gaps: bool,
+    pub jpeg_end_strategy: JpegEndStrategy,
+    // strict の EOI の後ろに FF の詰め物を挟んで FF D9 が続くカメラ向けに、その並びの最後の EOI で終える
+    pub jpeg_eoi_run: bool,
+    pub report: Option<ReportFormat>,
+    pub output_pattern: Option<OutputPattern>,
+    // 書き出す合計バイト数の上限
+    pub max_total_size: Option<u64>,
+    // 1ファイルの申告サイズとして信じる上限（これを超える申告はヘッダの破損とみなす）
+    // 形式ごとの上限（Signature の end_search_limit）を持つ種類には使わない
+    pub max_file_size: u64,
+    // --max-size jpeg=50M,mp4=4G のような種類ごとの上限（ない種類は max_file_size）
+    pub max_file_size_by_type: Vec<(FileType, u64)>,
+    // --prefer-strategy-per-type jpeg=next-signature のような種類ごとの終端の決め方（ない種類は既定）
+    pub end_strategy_by_type: Vec<(FileType, EndStrategy)>,
+    // 抽出対象のファイル種類
+    pub types: Vec<FileType>,
+    // 全体をスキャンせず、このオフセットのファイルだけを切り出す
+    pub carve_at: Option<u64>,
+    // 1ブロック内の候補がこれを超えたらノイズとみなして読み飛ばす（None なら無効）
+    pub max_candidates_per_block: Option<usize>,
+    // 個別のファイルではなく tar / zip アーカイブにまとめて書き出す
+    pub archive: Option<PathBuf>,
+    // 終端検出や書き込みをせず、スタートシグネチャを数えるだけ（スキャン速度の計測用）
+    pub dry_run_scan_only: bool,
+    // デバイスがマウントされていても続行する
+    pub force: bool,
+    // デバイス全体が暗号化されているように見えても続行する
+    pub skip_encryption_check: bool,
+    // 大きなデバイスでも、スキャンを始める前の確認をしない
+    pub yes: bool,
+    // 切り出した中身を先頭バイトでも判別し、食い違えば警告して拡張子を直す
+    pub sniff: bool,
+    // このファイルに列挙されたオフセットだけを切り出す
+    pub worklist: Option<PathBuf>,
+    pub color: ColorChoice,
+    // 1ファイルごとの Saved 行を出さず、進捗と最後の集計だけを表示する
+    pub summary_only: bool,
+    // RW2 に埋め込まれたJPEGプレビューを .preview.jpg として別に保存する
+    pub extract_preview: bool,
+    // max_file_size まで（またはデバイスの終わりまで）読んでも終端が見つからない候補を偽物とみなして先へ進む
+    pub skip_unterminated: bool,
+    // デバイスの概要（サイズ、パーティション、マウント状況）を表示して終了する
+    pub device_info: bool,
+    // パーティションテーブルのこの番号（1始まり）の範囲だけをスキャンする
+    pub partition: Option<usize>,
+    // manifest.json とファイル名のオフセットをデバイスとパーティションのどちらの先頭から数えるか
+    pub offset_base: OffsetBase,
+    // スキャンしているパーティションの先頭（コマンドラインではなく、見つけたパーティションから入れる）
+    pub partition_start: u64,
+    // 前回の manifest.json に載っていて出力先に同じサイズで残っているファイルは切り出し直さない
+    pub resume: bool,
+    // 中身が同じファイルは1つ目へのハードリンクとして置く（作れなければコピー）
+    pub flatten_duplicates_to_hardlinks: bool,
+    // 復旧したファイルの一覧を画像付きの index.html にする
+    pub html_report: bool,
+    // 保存先ディレクトリを作らない（ないディレクトリへの保存はエラーにする）
+    pub no_create_output: bool,
+    // 確からしさがこれを下回るファイルは保存しない（quarantine なら別フォルダに保存する）
+    pub min_confidence: Option<MinConfidence>,
+    // selftest: 組み込みのフィクスチャを切り出して PASS / FAIL を表示して終了する
+    pub selftest: bool,
+    // list-recovered: 出力先の manifest.json を --filter / --sort で絞り込み・並べ替えて表示して終了する
+    pub list_recovered: bool,
+    pub list_filters: Vec<Filter>,
+    pub list_sort: Option<SortKey>,
+    // ファイル先頭の候補をデバイス先頭からこのバイト数の倍数の位置だけに絞って探す（1 なら1バイトずつ）
+    // 探す位置そのものを減らして速くする代わりに、境界からずれて始まるファイルは見落とす
+    pub scan_step: usize,
+    // MPF の索引を持つJPEG（MPO）をまとめず、1枚ずつ別のJPEGとして切り出す
+    pub split_mpo: bool,
+    // 全体をスキャンしたとき、復旧したファイルに含まれるセクタを1ビットずつ表したビットマップも書き出す
+    pub sector_bitmap: bool,
+    // 画像は image_000001.jpg ではなく 6000x4000_000001.jpg のように画素数で名前を付ける（読めなければ通常の名前）
+    pub rename_by_dimensions: bool,
+    // パーティションテーブルのすべてのパーティションを順にスキャンし、partition_0/ などに分けて保存する
+    pub partition_scan: bool,
+    // デバイスから1回に読むバイト数
+    pub block_size: usize,
+    // ブロックの終わりから次のブロックへ持ち越して探し直すバイト数（None なら最も長いシグネチャ - 1）
+    // シグネチャより短いと境界をまたぐものを見逃し、長いと同じ範囲を何度も探すことになる
+    pub overlap: Option<usize>,
+    // 見つかった順の番号ではなくデバイス上のオフセットで名前を付ける（image_000000004096.jpg）
+    // 読み方や --block-size を変えて実行し直しても、同じファイルには同じ名前が付く
+    pub stable_names: bool,
+    // ファイルの終わりから次のクラスタ境界までのバイト列も image_000001.slack として書き出す
+    pub include_trailing_slack: bool,
+    // どの形式としても切り出さなかった領域を unknown/region_<オフセット>.bin に書き出す
+    pub dump_unknown: bool,
+    // --dump-unknown で書き出す領域の最小のバイト数
+    pub unknown_min_size: u64,
+    // 最初に保存するファイルの番号（複数のカードを1つのフォルダにまとめるときに名前がぶつからないように）
+    pub counter_start: usize,
+    // 少しずつ読むか、全体をメモリに読み込んでからスキャンするか（--partition と組み合わせると常に少しずつ読む）
+    pub strategy: ScanStrategy,
+    // 最初に見つかる候補をこの個数だけ表にして終える（何も保存しない）
+    pub preview: Option<usize>,
+    // 保存するファイルを gzip にして image_000001.bmp.gz のように置く（JPEG などすでに圧縮された形式はそのまま）
+    pub compress: bool,
+    // すでに圧縮された形式も gzip にする
+    pub compress_all: bool,
+    // JPEG / BMP / WebP のうち画像として開けそうにないものを failed/ に保存し、理由をマニフェストに残す
+    pub quarantine_on_decode_failure: bool,
+    pub validate_raw: bool,
+    pub rescan_failed: bool,
+    // 切り出した結果を FAT32 のディレクトリと突き合わせ、reconciliation.txt に書き出す
+    pub verify_against_fs: bool,
+    // FAT32 / exFAT で使用中のクラスタから始まるファイルは保存しない（削除されたものだけを残す）
+    pub only_deleted: bool,
+    // FAT32 のチェーンが連続していないファイルは、チェーンの順にクラスタを読んでつなぎ直して保存する
+    pub reassemble_fragments: bool,
+    // 次のスタートシグネチャやバッファの終わりで切ったファイルの後ろのゼロを落とす
+    pub trim_trailing: bool,
+    // 読み込みの速度を記録するたびに、出力先の progress.json に進捗を書く
+    pub progress_file: bool,
+    // --dry-run-scan-only でシグネチャを数えるスレッド数（既定は使えるコア数。切り出しは1スレッドのまま）
+    pub threads: usize,
+    // 読み込み範囲の先頭からこのバイト数を読み飛ばす（ベンダーの予約領域など）
+    pub skip_start: u64,
+    // Linux で、順に読むことと読み終えた範囲は要らないことを posix_fadvise でカーネルに伝える
+    pub fadvise: bool,
+    // 読み込みブロック・ファイルの上限・全体の読み込みをこのバイト数に収める（小さな機器向け）
+    pub max_memory: Option<u64>,
+    // 読み取りエラーのとき、デバイスを開き直して読み直す回数（0 なら開き直さない）
+    pub reconnect_retries: u32,
+    // 最後の集計に、サイズの大きいファイルをこの個数まで表示する
+    pub top: Option<usize>,
+    // デバイスが報告するサイズで止めず、読み取りが実際に失敗するまで読み続ける
+    pub scan_beyond_size: bool,
+    // スキャン中の進捗を、このアドレスで待ち受ける HTTP から JSON で返す
+    pub serve_status: Option<SocketAddr>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            device_path: PathBuf::from(DEVICE_PATH),
+            output_dir: PathBuf::from(OUTPUT_DIR),
+            zero_fill_gaps: false,
+            jpeg_end_strategy: JpegEndStrategy::Strict,
+            jpeg_eoi_run: false,
+            report: None,
+            output_pattern: None,
+            max_total_size: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_file_size_by_type: Vec::new(),
+            end_strategy_by_type: Vec::new(),
+            types: FileType::ALL.to_vec(),
+            carve_at: None,
+            max_candidates_per_block: Some(DEFAULT_MAX_CANDIDATES_PER_BLOCK),
+            archive: None,
+            dry_run_scan_only: false,
+            force: false,
+            skip_encryption_check: false,
+            yes: false,
+            sniff: false,
+            worklist: None,
+            color: ColorChoice::Auto,
+            summary_only: false,
+            extract_preview: false,
+            skip_unterminated: false,
+            device_info: false,
+            partition: None,
+            offset_base: OffsetBase::Device,
+            partition_start: 0,
+            resume: false,
+            flatten_duplicates_to_hardlinks: false,
+            html_report: false,
+            no_create_output: false,
+            min_confidence: None,
+            selftest: false,
+            list_recovered: false,
+            list_filters: Vec::new(),
+            list_sort: None,
+            scan_step: 1,
+            split_mpo: false,
+            sector_bitmap: false,
+            rename_by_dimensions: false,
+            partition_scan: false,
+            block_size: READ_BLOCK_SIZE,
+            overlap: None,
+            stable_names: false,
+            include_trailing_slack: false,
+            dump_unknown: false,
+            unknown_min_size: DEFAULT_UNKNOWN_MIN_SIZE,
+            counter_start: 0,
+            strategy: ScanStrategy::Auto,
+            preview: None,
+            compress: false,
+            compress_all: false,
+            quarantine_on_decode_failure: false,
+            validate_raw: false,
+            rescan_failed: false,
+            verify_against_fs: false,
+            only_deleted: false,
+            reassemble_fragments: false,
+            trim_trailing: false,
+            progress_file: false,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            skip_start: 0,
+            fadvise: false,
+            max_memory: None,
+            reconnect_retries: 5,
+            scan_beyond_size: false,
+            serve_status: None,
+            top: None,
+        }
+    }
+}
+
+impl Options {
+    // file_type の申告サイズとして信じる上限
+    // --max-size の指定、形式ごとの end_search_limit、--max-file-size の順に優先する
+    pub fn max_file_size_for(&self, file_type: FileType) -> u64 {
+        self.max_file_size_by_type
+            .iter()
+            .find(|(t, _)| *t == file_type)
+            .map_or_else(
+                || file_type.end_search_limit().unwrap_or(self.max_file_size),
+                |(_, size)| *size,
+            )
+    }
+
+    // file_type の終端の決め方。--prefer-strategy-per-type の指定がなければ種類ごとの既定
+    pub fn end_strategy_for(&self, file_type: FileType) -> EndStrategy {
+        self.end_strategy_by_type
+            .iter()
+            .find(|(t, _)| *t == file_type)
+            .map_or_else(|| carve::default_strategy(file_type), |(_, s)| *s)
+    }
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let mut types_given = false;
+    let mut ignore_types_given = false;
+    let mut custom_types = Vec::new();
+    // --add-signature の種類も指定できるよう、種類を揃えてから読む（後の指定が優先）
+    let mut max_sizes = Vec::new();
+    let mut end_strategies = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "selftest" => options.selftest = true,
+            "list-recovered" => options.list_recovered = true,
+            "--filter" => {
+                let value = next_value(&mut args, &arg)?;
+                options.list_filters.push(Filter::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には type=種類 / min-size=サイズ / min-confidence=値 を指定してください: {}",
+                        arg, value
+                    )
+                })?);
+            }
+            "--sort" => {
+                let value = next_value(&mut args, &arg)?;
+                options.list_sort = Some(SortKey::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には offset / type / size / confidence / date を指定してください: {}",
+                        arg, value
+                    )
+                })?);
+            }
+            "--zero-fill-gaps" => options.zero_fill_gaps = true,
+            "--dry-run-scan-only" => options.dry_run_scan_only = true,
+            "--force" => options.force = true,
+            "--skip-encryption-check" => options.skip_encryption_check = true,
+            "--yes" => options.yes = true,
+            "--sniff" => options.sniff = true,
+            "--summary-only" => options.summary_only = true,
+            "--extract-preview" => options.extract_preview = true,
+            "--skip-unterminated" => options.skip_unterminated = true,
+            "--device-info" => options.device_info = true,
+            "--resume" => options.resume = true,
+            "--split-mpo" => options.split_mpo = true,
+            "--sector-bitmap" => options.sector_bitmap = true,
+            "--rename-by-dimensions" => options.rename_by_dimensions = true,
+            "--partition-scan" => options.partition_scan = true,
+            "--stable-names" => options.stable_names = true,
+            "--include-trailing-slack" => options.include_trailing_slack = true,
+            "--dump-unknown" => options.dump_unknown = true,
+            "--unknown-min-size" => {
+                let value = next_value(&mut args, &arg)?;
+                options.unknown_min_size = parse_size(&value)
+                    .ok_or_else(|| format!("{} のサイズを解釈できません: {}", arg, value))?;
+            }
+            "--block-size" => {
+                let value = next_value(&mut args, &arg)?;
+                options.block_size = parse_size(&value)
+                    .and_then(|size| usize::try_from(size).ok())
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| {
+                        format!("{} には1以上のサイズを指定してください: {}", arg, value)
+                    })?;
+            }
+            "--overlap" => {
+                let value = next_value(&mut args, &arg)?;
+                options.overlap = Some(
+                    parse_size(&value)
+                        .and_then(|size| usize::try_from(size).ok())
+                        .ok_or_else(|| format!("{} のサイズを解釈できません: {}", arg, value))?,
+                );
+            }
+            "--quarantine-on-decode-failure" => options.quarantine_on_decode_failure = true,
+            "--validate-raw" => options.validate_raw = true,
+            "--rescan-failed" => options.rescan_failed = true,
+            "--verify-against-fs" => options.verify_against_fs = true,
+            "--only-deleted" => options.only_deleted = true,
+            "--reassemble-fragments" => options.reassemble_fragments = true,
+            "--trim-trailing" => options.trim_trailing = true,
+            "--progress-file" => options.progress_file = true,
+            "--jpeg-eoi-run" => options.jpeg_eoi_run = true,
+            "--compress" => options.compress = true,
+            "--compress-all" => {
+                options.compress = true;
+                options.compress_all = true;
+            }
+            "--html-report" => options.html_report = true,
+            "--no-create-output" => options.no_create_output = true,
+            "--flatten-duplicates-to-hardlinks" => options.flatten_duplicates_to_hardlinks = true,
+            "--jpeg-end-strategy" => {
+                let value = next_value(&mut args, &arg)?;
+                options.jpeg_end_strategy = JpegEndStrategy::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には strict / last / first を指定してください: {}",
+                        arg, value
+                    )
+                })?;
+            }
+            "--report" => {
+                let value = next_value(&mut args, &arg)?;
+                options.report =
+                    Some(ReportFormat::parse(&value).ok_or_else(|| {
+                        format!("{} には csv を指定してください: {}", arg, value)
+                    })?);
+            }
+            "--output-pattern" => {
+                let value = next_value(&mut args, &arg)?;
+                options.output_pattern =
+                    Some(OutputPattern::parse(&value).ok_or_else(|| {
+                        format!("{} には date を指定してください: {}", arg, value)
+                    })?);
+            }
+            "--max-total-size" => {
+                let value = next_value(&mut args, &arg)?;
+                options.max_total_size = Some(parse_size(&value).ok_or_else(|| {
+                    format!(
+                        "{} にはサイズ（例: 50G, 512M）を指定してください: {}",
+                        arg, value
+                    )
+                })?);
+            }
+            "--max-file-size" => {
+                let value = next_value(&mut args, &arg)?;
+                options.max_file_size = parse_size(&value).ok_or_else(|| {
+                    format!(
+                        "{} にはサイズ（例: 64M, 1G）を指定してください: {}",
+                        arg, value
+                    )
+                })?;
+            }
+            "--max-size" => max_sizes.push(next_value(&mut args, &arg)?),
+            "--prefer-strategy-per-type" => end_strategies.push(next_value(&mut args, &arg)?),
+            "--add-signature" => {
+                custom_types.push(custom::parse(&next_value(&mut args, &arg)?)?);
+            }
+            "--types" => {
+                options.types = parse_types(&next_value(&mut args, &arg)?)?;
+                types_given = true;
+            }
+            "--ignore-types" => {
+                let ignored = parse_types(&next_value(&mut args, &arg)?)?;
+                options.types.retain(|t| !ignored.contains(t));
+                ignore_types_given = true;
+            }
+            "--carve-at" => {
+                let value = next_value(&mut args, &arg)?;
+                options.carve_at = Some(parse_offset(&value).ok_or_else(|| {
+                    format!(
+                        "{} にはオフセット（10進数か 0x から始まる16進数）を指定してください: {}",
+                        arg, value
+                    )
+                })?);
+            }
+            "--max-candidates-per-block" => {
+                let value = next_value(&mut args, &arg)?;
+                let limit: usize = value
+                    .parse()
+                    .map_err(|_| format!("{} には個数を指定してください: {}", arg, value))?;
+                // 0 はガードを無効にする
+                options.max_candidates_per_block = (limit > 0).then_some(limit);
+            }
+            "--counter-start" => {
+                let value = next_value(&mut args, &arg)?;
+                options.counter_start = value
+                    .parse()
+                    .map_err(|_| format!("{} には0以上の整数を指定してください: {}", arg, value))?;
+            }
+            "--preview" => {
+                let value = next_value(&mut args, &arg)?;
+                options.preview = Some(value.parse().ok().filter(|&limit| limit > 0).ok_or_else(
+                    || format!("{} には1以上の個数を指定してください: {}", arg, value),
+                )?);
+            }
+            "--fadvise" => options.fadvise = true,
+            "--skip-start" => {
+                let value = next_value(&mut args, &arg)?;
+                options.skip_start = parse_size(&value)
+                    .ok_or_else(|| format!("{} のサイズを解釈できません: {}", arg, value))?;
+            }
+            "--threads" => {
+                let value = next_value(&mut args, &arg)?;
+                options.threads = value
+                    .parse()
+                    .ok()
+                    .filter(|&threads| threads > 0)
+                    .ok_or_else(|| {
+                        format!("{} には1以上のスレッド数を指定してください: {}", arg, value)
+                    })?;
+            }
+            "--top" => {
+                let value = next_value(&mut args, &arg)?;
+                options.top = Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!("{} には1以上の個数を指定してください: {}", arg, value)
+                })?);
+            }
+            "--scan-beyond-size" => options.scan_beyond_size = true,
+            "--serve-status" => {
+                let value = next_value(&mut args, &arg)?;
+                options.serve_status = Some(parse_listen_addr(&value).ok_or_else(|| {
+                    format!(
+                        "{} には :8080 や 127.0.0.1:8080 のようなアドレスを指定してください: {}",
+                        arg, value
+                    )
+                })?);
+            }
+            "--reconnect-retries" => {
+                let value = next_value(&mut args, &arg)?;
+                options.reconnect_retries = value
+                    .parse()
+                    .map_err(|_| format!("{} には0以上の回数を指定してください: {}", arg, value))?;
+            }
+            "--step" => {
+                let value = next_value(&mut args, &arg)?;
+                options.scan_step =
+                    value.parse().ok().filter(|&step| step > 0).ok_or_else(|| {
+                        format!("{} には1以上のバイト数を指定してください: {}", arg, value)
+                    })?;
+            }
+            "--partition" => {
+                let value = next_value(&mut args, &arg)?;
+                let index: usize =
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|&index| index > 0)
+                        .ok_or_else(|| {
+                            format!("{} には1以上の番号を指定してください: {}", arg, value)
+                        })?;
+                options.partition = Some(index);
+            }
+            "--min-confidence" => {
+                let value = next_value(&mut args, &arg)?;
+                options.min_confidence = Some(MinConfidence::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には 0 から 1 の値（例: 0.5, 0.5:quarantine）を指定してください: {}",
+                        arg, value
+                    )
+                })?);
+            }
+            "--worklist" => {
+                options.worklist = Some(PathBuf::from(next_value(&mut args, &arg)?));
+            }
+            "--max-memory" => {
+                let value = next_value(&mut args, &arg)?;
+                options.max_memory = Some(
+                    parse_size(&value)
+                        .ok_or_else(|| format!("{} のサイズを解釈できません: {}", arg, value))?,
+                );
+            }
+            "--offset-base" => {
+                let value = next_value(&mut args, &arg)?;
+                options.offset_base = OffsetBase::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には partition / device を指定してください: {}",
+                        arg, value
+                    )
+                })?;
+            }
+            "--strategy" => {
+                let value = next_value(&mut args, &arg)?;
+                options.strategy = ScanStrategy::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には auto / stream / load を指定してください: {}",
+                        arg, value
+                    )
+                })?;
+            }
+            "--color" => {
+                let value = next_value(&mut args, &arg)?;
+                options.color = ColorChoice::parse(&value).ok_or_else(|| {
+                    format!(
+                        "{} には always / never / auto を指定してください: {}",
+                        arg, value
+                    )
+                })?;
+            }
+            "--archive" => {
+                let path = PathBuf::from(next_value(&mut args, &arg)?);
+                if ArchiveFormat::from_path(&path).is_none() {
+                    return Err(format!(
+                        "{} には .tar か .zip のパスを指定してください: {}",
+                        arg,
+                        path.display()
+                    ));
+                }
+                options.archive = Some(path);
+            }
+            _ => return Err(format!("不明なオプションです: {}", arg)),
+        }
+    }
+
+    // --types / --ignore-types は組み込みの種類だけを選ぶので、足した種類はその後ろに加える
+    for file_type in custom_types {
+        if options.types.iter().any(|t| t.name() == file_type.name()) {
+            return Err(format!(
+                "--add-signature の種類名が重複しています: {}",
+                file_type.name()
+            ));
+        }
+        options.types.push(file_type);
+    }
+    for value in &max_sizes {
+        for (file_type, size) in parse_max_sizes(value, &options.types)? {
+            options
+                .max_file_size_by_type
+                .retain(|(t, _)| *t != file_type);
+            options.max_file_size_by_type.push((file_type, size));
+        }
+    }
+    for value in &end_strategies {
+        for (file_type, strategy) in parse_end_strategies(value, &options.types)? {
+            options
+                .end_strategy_by_type
+                .retain(|(t, _)| *t != file_type);
+            options.end_strategy_by_type.push((file_type, strategy));
+        }
+    }
+    if types_given && ignore_types_given {
+        return Err("--types と --ignore-types は同時に指定できません".to_string());
+    }
+    if !options.list_recovered && (!options.list_filters.is_empty() || options.list_sort.is_some())
+    {
+        return Err("--filter / --sort は list-recovered と一緒に指定してください".to_string());
+    }
+    if options.carve_at.is_some() && options.worklist.is_some() {
+        return Err("--carve-at と --worklist は同時に指定できません".to_string());
+    }
+    // オフセット指定はデバイス先頭からなので、パーティションの選択とは組み合わせない
+    if options.partition.is_some() && (options.carve_at.is_some() || options.worklist.is_some()) {
+        return Err("--partition は --carve-at / --worklist と同時に指定できません".to_string());
+    }
+    // パーティションごとにフォルダを分けるので、1つのアーカイブや1つのパーティションの指定とは組み合わせない
+    if options.partition_scan
+        && (options.partition.is_some()
+            || options.archive.is_some()
+            || options.carve_at.is_some()
+            || options.worklist.is_some())
+    {
+        return Err(
+            "--partition-scan は --partition / --archive / --carve-at / --worklist と同時に指定できません"
+                .to_string(),
+        );
+    }
+    // オフセット指定はデバイス先頭からの位置なので、読み飛ばしとは組み合わせない
+    if options.skip_start > 0 && (options.carve_at.is_some() || options.worklist.is_some()) {
+        return Err("--skip-start は --carve-at / --worklist と同時に指定できません".to_string());
+    }
+    // 全体スキャンの先頭だけを見るものなので、パーティションごとの実行やオフセット指定とは組み合わせない
+    if options.preview.is_some()
+        && (options.partition_scan || options.carve_at.is_some() || options.worklist.is_some())
+    {
+        return Err(
+            "--preview は --partition-scan / --carve-at / --worklist と同時に指定できません"
+                .to_string(),
+        );
+    }
+    if options.offset_base == OffsetBase::Partition
+        && options.partition.is_none()
+        && !options.partition_scan
+    {
+        return Err(
+            "--offset-base partition は --partition か --partition-scan と一緒に指定してください"
+                .to_string(),
+        );
+    }
+    // 全体を読み込むときは、報告されたサイズまでしか読まない
+    if options.scan_beyond_size && options.strategy == ScanStrategy::Load {
+        return Err("--scan-beyond-size は --strategy load と同時に指定できません".to_string());
+    }
+    // パーティションだけを読み込むことはできない
+    if options.strategy == ScanStrategy::Load
+        && (options.partition.is_some() || options.partition_scan)
+    {
+        return Err(
+            "--strategy load は --partition / --partition-scan と同時に指定できません".to_string(),
+        );
+    }
+    // アーカイブは作り直しになるので、前回のファイルを残したことにはできない
+    if options.resume && options.archive.is_some() {
+        return Err("--resume は --archive と同時に指定できません".to_string());
+    }
+    if options.fadvise && !cfg!(target_os = "linux") {
+        return Err("--fadvise は Linux でのみ使えます".to_string());
+    }
+    if options.types.is_empty() {
+        return Err("抽出対象のファイル種類が残っていません".to_string());
+    }
+    if let Some(limit) = options.max_memory {
+        strategy::fit_to_memory(&mut options, limit)?;
+    }
+
+    Ok(options)
+}
+
+// "50G" や "512K" のような接尾辞付きのサイズ（1024倍単位）をバイト数にする
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.char_indices().last()? {
+        (idx, 'K' | 'k') => (&value[..idx], 1u64 << 10),
+        (idx, 'M' | 'm') => (&value[..idx], 1u64 << 20),
+        (idx, 'G' | 'g') => (&value[..idx], 1u64 << 30),
+        (idx, 'T' | 't') => (&value[..idx], 1u64 << 40),
+        _ => (value, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+// 10進数か、0x から始まる16進数のオフセット
+pub fn parse_offset(value: &str) -> Option<u64> {
+    let value = value.trim();
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// ":8080" のようにホストを省いたものは、このマシンの中（127.0.0.1）からだけ受け付ける
+// ほかのマシンから見られるようにするには "0.0.0.0:8080" のように明示する
+fn parse_listen_addr(value: &str) -> Option<SocketAddr> {
+    let value = value.trim();
+    match value.strip_prefix(':') {
+        Some(port) => Some(SocketAddr::from(([127, 0, 0, 1], port.parse().ok()?))),
+        None => value.parse().ok(),
+    }
+}
+
+// "jpeg,rw2" のようなカンマ区切りの種類名
+fn parse_types(value: &str) -> Result<Vec<FileType>, String> {
+    value
+        .split(',')
+        .map(|name| {
+            FileType::parse(name.trim()).ok_or_else(|| format!("不明なファイル種類です: {}", name))
+        })
+        .collect()
+}
+
+// 組み込みの種類か、--add-signature で足した種類の名前
+fn parse_type_name(name: &str, types: &[FileType]) -> Result<FileType, String> {
+    FileType::parse(name)
+        .or_else(|| types.iter().copied().find(|t| t.name() == name))
+        .ok_or_else(|| format!("不明なファイル種類です: {}", name))
+}
+
+// "jpeg=50M,mp4=4G" のような 種類=サイズ のカンマ区切り
+fn parse_max_sizes(value: &str, types: &[FileType]) -> Result<Vec<(FileType, u64)>, String> {
+    value
+        .split(',')
+        .map(|pair| {
+            let (name, size) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("種類=サイズ の形式で指定してください: {}", pair))?;
+            let file_type = parse_type_name(name.trim(), types)?;
+            let size = parse_size(size)
+                .ok_or_else(|| format!("サイズ（例: 50M, 4G）を指定してください: {}", size))?;
+            Ok((file_type, size))
+        })
+        .collect()
+}
+
+// 既定と next-signature のほかは、その種類の構造に合わないので受け付けない
+fn parse_end_strategies(
+    value: &str,
+    types: &[FileType],
+) -> Result<Vec<(FileType, EndStrategy)>, String> {
+    value
+        .split(',')
+        .map(|pair| {
+            let (name, strategy) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("種類=決め方 の形式で指定してください: {}", pair))?;
+            let file_type = parse_type_name(name.trim(), types)?;
+            let parsed = EndStrategy::parse(strategy.trim()).ok_or_else(|| {
+                format!(
+                    "segment-walk / length-field / iff-box / next-signature / footer-search のいずれかを指定してください: {}",
+                    strategy
+                )
+            })?;
+            if !carve::supports_strategy(file_type, parsed) {
+                return Err(format!(
+                    "{} には {} を使えません",
+                    file_type.name(),
+                    strategy.trim()
+                ));
+            }
+            Ok((file_type, parsed))
+        })
+        .collect()
+}
+
+fn next_value<I: Iterator<Item = String>>(args: &mut I, name: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} には値が必要です", name))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    // ---------------------------
+    // Tests for parse_args
+    // ---------------------------
+
+    #[test]
+    fn test_should_use_defaults_when_no_args_are_given() {
+        // 1. setup
+        let input = args(&[]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.device_path, PathBuf::from(DEVICE_PATH));
+        assert_eq!(options.output_dir, PathBuf::from(OUTPUT_DIR));
+        assert!(!options.zero_fill_gaps);
+        assert_eq!(options.jpeg_end_strategy, JpegEndStrategy::Strict);
+        assert!(!options.yes);
+    }
+
+    #[test]
+    fn test_should_enable_zero_fill_gaps_when_flag_is_given() {
+        // 1. setup
+        let input = args(&["--zero-fill-gaps"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert!(options.zero_fill_gaps);
+    }
+
+    #[test]
+    fn test_should_parse_jpeg_end_strategy_when_value_is_given() {
+        // 1. setup
+        let input = args(&["--jpeg-end-strategy", "last"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.jpeg_end_strategy, JpegEndStrategy::Last);
+    }
+
+    #[test]
+    fn test_should_append_inline_signatures_after_selected_types_when_add_signature_is_given() {
+        // 1. setup
+        let input = args(&[
+            "--add-signature",
+            "mrw:004D524D::mrw",
+            "--types",
+            "jpeg",
+            "--add-signature",
+            "png:89504E47:49454E44:png",
+        ]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        let names: Vec<&str> = options.types.iter().map(FileType::name).collect();
+        assert_eq!(names, vec!["jpeg", "mrw", "png"]);
+    }
+
+    #[test]
+    fn test_should_return_error_when_inline_signature_name_is_repeated() {
+        // 1. setup
+        let input = args(&[
+            "--add-signature",
+            "png:89504E47:49454E44:png",
+            "--add-signature",
+            "png:89504E47::png",
+        ]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_return_error_when_filter_is_given_without_list_recovered() {
+        // 1. setup
+        let input = args(&["--filter", "type=jpeg"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_parse_output_pattern_when_date_is_given() {
+        // 1. setup
+        let input = args(&["--output-pattern", "date"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.output_pattern, Some(OutputPattern::Date));
+    }
+
+    #[test]
+    fn test_should_return_error_when_jpeg_end_strategy_is_unknown() {
+        // 1. setup
+        let input = args(&["--jpeg-end-strategy", "middle"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_parse_max_total_size_when_suffix_is_given() {
+        // 1. setup
+        let input = args(&["--max-total-size", "50G"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.max_total_size, Some(50 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_should_keep_only_listed_types_when_types_is_given() {
+        // 1. setup
+        let input = args(&["--types", "jpeg"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.types, vec![FileType::Jpeg]);
+    }
+
+    #[test]
+    fn test_should_remove_listed_types_when_ignore_types_is_given() {
+        // 1. setup
+        let input = args(&["--ignore-types", "rw2"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(
+            options.types,
+            vec![
+                FileType::Jpeg,
+                FileType::Zip,
+                FileType::Bmp,
+                FileType::Webp,
+                FileType::Dng,
+                FileType::Mp4,
+                FileType::Crw,
+                FileType::Tga,
+                FileType::Cr3,
+                FileType::Orf,
+                FileType::Raf,
+                FileType::Psd,
+                FileType::Nrw,
+                FileType::X3f,
+                FileType::Tiff,
+                FileType::Xmp,
+                FileType::Dicom
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_return_error_when_types_and_ignore_types_are_both_given() {
+        // 1. setup
+        let input = args(&["--types", "jpeg", "--ignore-types", "rw2"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_parse_hex_offset_when_carve_at_is_given() {
+        // 1. setup
+        let input = args(&["--carve-at", "0x1000"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.carve_at, Some(4096));
+    }
+
+    #[test]
+    fn test_should_disable_candidate_guard_when_limit_is_zero() {
+        // 1. setup
+        let input = args(&["--max-candidates-per-block", "0"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.max_candidates_per_block, None);
+    }
+
+    #[test]
+    fn test_should_disable_color_when_color_never_is_given() {
+        // 1. setup
+        let input = args(&["--color", "never"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.color, ColorChoice::Never);
+        assert!(!crate::color::should_color(options.color, true));
+    }
+
+    #[test]
+    fn test_should_return_error_when_archive_extension_is_unknown() {
+        // 1. setup
+        let input = args(&["--archive", "recovered.7z"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_return_error_when_resume_is_combined_with_archive() {
+        // 1. setup
+        let input = args(&["--resume", "--archive", "recovered.tar"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_apply_per_type_caps_when_max_size_is_given() {
+        // 1. setup
+        let input = args(&["--max-size", "jpeg=50M,mp4=4G"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.max_file_size_for(FileType::Jpeg), 50 << 20);
+        assert_eq!(options.max_file_size_for(FileType::Mp4), 4 << 30);
+        assert_eq!(
+            options.max_file_size_for(FileType::Rw2),
+            DEFAULT_MAX_FILE_SIZE
+        );
+    }
+
+    #[test]
+    fn test_should_apply_max_size_to_inline_signature_when_given_before_add_signature() {
+        // 1. setup
+        let input = args(&[
+            "--max-size",
+            "mrw=1M,jpeg=2M",
+            "--add-signature",
+            "mrw:004D524D::mrw",
+            "--max-size",
+            "jpeg=3M",
+        ]);
+        let unknown = args(&["--max-size", "mrw=1M"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+        let result = parse_args(unknown);
+
+        // 3. verify
+        let mrw = *options.types.last().unwrap();
+        assert_eq!(mrw.name(), "mrw");
+        assert_eq!(options.max_file_size_for(mrw), 1 << 20);
+        assert_eq!(options.max_file_size_for(FileType::Jpeg), 3 << 20);
+        assert!(result.unwrap_err().contains("mrw"));
+    }
+
+    #[test]
+    fn test_should_override_only_listed_types_when_prefer_strategy_per_type_is_given() {
+        // 1. setup
+        let input = args(&[
+            "--prefer-strategy-per-type",
+            "rw2=next-signature,jpeg=segment-walk",
+        ]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(
+            options.end_strategy_for(FileType::Rw2),
+            EndStrategy::NextSignature
+        );
+        assert_eq!(
+            options.end_strategy_for(FileType::Jpeg),
+            EndStrategy::SegmentWalk
+        );
+        assert_eq!(options.end_strategy_for(FileType::Mp4), EndStrategy::IffBox);
+    }
+
+    #[test]
+    fn test_should_return_error_when_strategy_does_not_fit_type() {
+        // 1. setup
+        let inputs = [
+            args(&["--prefer-strategy-per-type", "jpeg=iff-box"]),
+            args(&["--prefer-strategy-per-type", "tga=next-signature"]),
+            args(&["--prefer-strategy-per-type", "bmp=guess"]),
+        ];
+
+        // 2. execute
+        let results: Vec<_> = inputs.into_iter().map(parse_args).collect();
+
+        // 3. verify
+        assert!(results.iter().all(Result::is_err));
+    }
+
+    #[test]
+    fn test_should_listen_on_loopback_when_serve_status_omits_host() {
+        // 1. setup
+        let input = args(&["--serve-status", ":8080"]);
+        let invalid = args(&["--serve-status", "8080"]);
+
+        // 2. execute
+        let result = parse_args(input);
+        let rejected = parse_args(invalid);
+
+        // 3. verify
+        assert_eq!(
+            result.unwrap().serve_status,
+            Some("127.0.0.1:8080".parse().unwrap())
+        );
+        assert!(rejected.is_err());
+    }
+
+    #[test]
+    fn test_should_return_error_when_scan_beyond_size_is_combined_with_load() {
+        // 1. setup
+        let input = args(&["--scan-beyond-size", "--strategy", "load"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert_eq!(
+            result.err(),
+            Some("--scan-beyond-size は --strategy load と同時に指定できません".to_string())
+        );
+    }
+
+    #[test]
+    fn test_should_return_error_when_partition_scan_is_combined_with_partition() {
+        // 1. setup
+        let input = args(&["--partition-scan", "--partition", "1"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_return_error_when_offset_base_partition_has_no_partition() {
+        // 1. setup
+        let without = args(&["--offset-base", "partition"]);
+        let with = args(&["--offset-base", "partition", "--partition", "1"]);
+
+        // 2. execute
+        let results = [parse_args(without), parse_args(with)];
+
+        // 3. verify
+        assert!(results[0].is_err());
+        assert_eq!(
+            results[1].as_ref().unwrap().offset_base,
+            OffsetBase::Partition
+        );
+    }
+
+    #[test]
+    fn test_should_parse_block_size_with_suffix_when_block_size_is_given() {
+        // 1. setup
+        let input = args(&["--block-size", "64K", "--stable-names"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(options.block_size, 64 * 1024);
+        assert!(options.stable_names);
+    }
+
+    #[test]
+    fn test_should_return_error_when_preview_is_combined_with_carve_at() {
+        // 1. setup
+        let input = args(&["--preview", "20", "--carve-at", "0x1000"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_return_error_when_step_is_zero() {
+        // 1. setup
+        let input = args(&["--step", "0"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_parse_min_confidence_with_quarantine_when_suffix_is_given() {
+        // 1. setup
+        let input = args(&["--min-confidence", "0.5:quarantine"]);
+
+        // 2. execute
+        let options = parse_args(input).unwrap();
+
+        // 3. verify
+        assert_eq!(
+            options.min_confidence,
+            Some(MinConfidence {
+                threshold: 0.5,
+                quarantine: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_return_error_when_min_confidence_is_out_of_range() {
+        // 1. setup
+        let input = args(&["--min-confidence", "1.5"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+
+    // ---------------------------
+    // Tests for parse_size
+    // ---------------------------
+
+    #[test]
+    fn test_should_parse_plain_bytes_when_no_suffix_is_given() {
+        // 1. setup
+        let value = "4096";
+
+        // 2. execute
+        let result = parse_size(value);
+
+        // 3. verify
+        assert_eq!(result, Some(4096));
+    }
+
+    #[test]
+    fn test_should_return_none_when_size_is_not_a_number() {
+        // 1. setup
+        let value = "lotsG";
+
+        // 2. execute
+        let result = parse_size(value);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_should_return_error_when_option_is_unknown() {
+        // 1. setup
+        let input = args(&["--unknown"]);
+
+        // 2. execute
+        let result = parse_args(input);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+}
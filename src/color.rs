@@ -0,0 +1,124 @@
+// 端末向けの色付き出力（保存は緑、読み飛ばしは黄、エラーは赤）
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// init を呼ぶまでは色を付けない（テストなど）
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    // 出力先が端末のときだけ色を付ける
+    Auto,
+}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            "auto" => Some(ColorChoice::Auto),
+            _ => None,
+        }
+    }
+}
+
+pub fn should_color(choice: ColorChoice, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_terminal,
+    }
+}
+
+// リダイレクトされた側だけ色を付けないよう、標準出力と標準エラーを別々に判定する
+pub fn init(choice: ColorChoice) {
+    STDOUT_COLOR.store(
+        should_color(choice, io::stdout().is_terminal()),
+        Ordering::Relaxed,
+    );
+    STDERR_COLOR.store(
+        should_color(choice, io::stderr().is_terminal()),
+        Ordering::Relaxed,
+    );
+}
+
+fn paint<T: Display>(text: T, code: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", code, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// 標準出力向け
+pub fn saved<T: Display>(text: T) -> String {
+    paint(text, GREEN, STDOUT_COLOR.load(Ordering::Relaxed))
+}
+
+// 標準エラー向け
+pub fn skipped<T: Display>(text: T) -> String {
+    paint(text, YELLOW, STDERR_COLOR.load(Ordering::Relaxed))
+}
+
+// 標準エラー向け
+pub fn error<T: Display>(text: T) -> String {
+    paint(text, RED, STDERR_COLOR.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for should_color
+    // ---------------------------
+
+    #[test]
+    fn test_should_disable_color_when_never_is_chosen_even_on_terminal() {
+        // 1. setup
+        let choice = ColorChoice::parse("never").unwrap();
+
+        // 2. execute
+        let enabled = should_color(choice, true);
+
+        // 3. verify
+        assert!(!enabled);
+        assert_eq!(paint("Saved: a.jpg", GREEN, enabled), "Saved: a.jpg");
+    }
+
+    #[test]
+    fn test_should_follow_terminal_when_auto_is_chosen() {
+        // 1. setup
+        let choice = ColorChoice::Auto;
+
+        // 2. execute
+        let on_terminal = should_color(choice, true);
+        let redirected = should_color(choice, false);
+
+        // 3. verify
+        assert!(on_terminal);
+        assert!(!redirected);
+    }
+
+    #[test]
+    fn test_should_wrap_text_in_escape_codes_when_color_is_enabled() {
+        // 1. setup
+        let text = "Saved: a.jpg";
+
+        // 2. execute
+        let result = paint(text, GREEN, true);
+
+        // 3. verify
+        assert_eq!(result, "\x1b[32mSaved: a.jpg\x1b[0m");
+    }
+}
@@ -0,0 +1,129 @@
+// 大きなデバイスをスキャンする前に、パスとサイズとモデルを表示して続けるか確かめる
+// 別のディスク（/dev/sdX の取り違え）を読み始めないための確認で、--yes か端末でなければ尋ねない
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+// これより小さいもの（小さなイメージファイルなど）は尋ねない
+pub const MIN_SIZE: u64 = 1 << 30;
+
+#[derive(Debug)]
+pub struct Target<'a> {
+    pub path: &'a Path,
+    pub size: u64,
+    pub model: Option<String>,
+}
+
+// y / yes と答えたときだけ続ける（空の入力や入力の終わりは中止）
+fn ask<R: BufRead, W: Write>(input: &mut R, out: &mut W, target: &Target) -> io::Result<bool> {
+    writeln!(out, "対象デバイス: {}", target.path.display())?;
+    writeln!(
+        out,
+        "サイズ: {} バイト（{:.1} GiB）",
+        target.size,
+        target.size as f64 / (1u64 << 30) as f64
+    )?;
+    writeln!(out, "モデル: {}", target.model.as_deref().unwrap_or("不明"))?;
+    write!(
+        out,
+        "このデバイスをスキャンしますか？（--yes で確認を省けます） [y/N]: "
+    )?;
+    out.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+// --yes か、標準出力が端末でないか、MIN_SIZE より小さければ尋ねずに続ける
+pub fn confirm<R: BufRead, W: Write>(
+    input: &mut R,
+    out: &mut W,
+    target: &Target,
+    yes: bool,
+    interactive: bool,
+) -> io::Result<bool> {
+    if yes || !interactive || target.size < MIN_SIZE {
+        return Ok(true);
+    }
+    ask(input, out, target)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::{BufReader, Read};
+
+    // 読まれたら失敗する入力（尋ねずに進むことを確かめる）
+    struct UnreadableInput;
+
+    impl Read for UnreadableInput {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            panic!("確認の入力を読もうとしました");
+        }
+    }
+
+    fn large_target() -> Target<'static> {
+        Target {
+            path: Path::new("/dev/sdz"),
+            size: 32 << 30,
+            model: Some("SD Card Reader".to_string()),
+        }
+    }
+
+    // ---------------------------
+    // Tests for confirm
+    // ---------------------------
+
+    #[test]
+    fn test_should_proceed_without_prompt_when_yes_is_given() {
+        // 1. setup
+        let mut input = BufReader::new(UnreadableInput);
+        let mut out = Vec::new();
+
+        // 2. execute
+        let result = confirm(&mut input, &mut out, &large_target(), true, true);
+
+        // 3. verify
+        assert!(result.unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_should_proceed_without_blocking_when_stdout_is_not_terminal() {
+        // 1. setup
+        let mut input = BufReader::new(UnreadableInput);
+        let mut out = Vec::new();
+
+        // 2. execute
+        let result = confirm(&mut input, &mut out, &large_target(), false, false);
+
+        // 3. verify
+        assert!(result.unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_should_show_device_and_follow_answer_when_terminal_asks() {
+        // 1. setup
+        let answers: [&[u8]; 3] = [b"y\n", b"n\n", b""];
+        let mut out = Vec::new();
+
+        // 2. execute
+        let results: Vec<bool> = answers
+            .iter()
+            .map(|answer| {
+                confirm(&mut &answer[..], &mut out, &large_target(), false, true).unwrap()
+            })
+            .collect();
+
+        // 3. verify
+        assert_eq!(results, vec![true, false, false]);
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("対象デバイス: /dev/sdz"));
+        assert!(text.contains("サイズ: 34359738368 バイト（32.0 GiB）"));
+        assert!(text.contains("モデル: SD Card Reader"));
+    }
+}
@@ -0,0 +1,268 @@
+// 画面への表示（1ファイルごとの Saved 行、--summary-only のときの進捗、最後の集計）
+use std::cmp::Reverse;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::Summary;
+use crate::cli::Options;
+use crate::manifest::ManifestEntry;
+use crate::{color, eta};
+
+// --summary-only のとき、この個数ごとに進捗を書き換える
+const PROGRESS_INTERVAL: usize = 100;
+
+// summary は保存したファイルを数え終えた状態で渡すこと
+pub fn report_saved<W: Write>(
+    out: &mut W,
+    options: &Options,
+    summary: &Summary,
+    display_name: &str,
+) -> io::Result<()> {
+    if !options.summary_only {
+        return writeln!(
+            out,
+            "{}",
+            color::saved(format_args!("Saved: {}", display_name))
+        );
+    }
+
+    if !summary.entries.len().is_multiple_of(PROGRESS_INTERVAL) {
+        return Ok(());
+    }
+    write!(out, "\r{}", progress_line(summary))?;
+    out.flush()
+}
+
+// --summary-only のとき、読み込みの速度を記録するたびに進捗を書き換える
+pub fn report_progress<W: Write>(
+    out: &mut W,
+    options: &Options,
+    summary: &Summary,
+) -> io::Result<()> {
+    if !options.summary_only {
+        return Ok(());
+    }
+    write!(out, "\r{}", progress_line(summary))?;
+    out.flush()
+}
+
+// 範囲の大きさが分からなければ割合を出さず、残り時間は「不明」
+fn progress_line(summary: &Summary) -> String {
+    let scanned_mib = summary.scanned_bytes / (1024 * 1024);
+    match summary.total_bytes.filter(|&total| total > 0) {
+        Some(total) => {
+            let remaining = total.saturating_sub(summary.scanned_bytes);
+            format!(
+                "保存済み: {} 個（{} MiB 走査、{:.1}%、残り {}）",
+                summary.entries.len(),
+                scanned_mib,
+                summary.scanned_bytes.min(total) as f64 * 100.0 / total as f64,
+                eta::format_eta(summary.throughput.eta(remaining))
+            )
+        }
+        None => format!(
+            "保存済み: {} 個（{} MiB 走査、残り {}）",
+            summary.entries.len(),
+            scanned_mib,
+            eta::format_eta(None)
+        ),
+    }
+}
+
+// サイズの大きい順に n 個（同じサイズなら見つけた順）
+fn largest(entries: &[ManifestEntry], n: usize) -> Vec<&ManifestEntry> {
+    let mut sorted: Vec<&ManifestEntry> = entries.iter().collect();
+    sorted.sort_by_key(|entry| Reverse(entry.size));
+    sorted.truncate(n);
+    sorted
+}
+
+pub fn write_summary<W: Write>(
+    out: &mut W,
+    options: &Options,
+    summary: &Summary,
+    duration: Duration,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "\n復旧完了: {} 個のファイルを保存しました",
+        summary.entries.len()
+    )?;
+    if summary.resumed_files > 0 {
+        writeln!(
+            out,
+            "うち {} 個は前回の実行で保存済みのため切り出しを省略しました",
+            summary.resumed_files
+        )?;
+    }
+    if summary.skipped_allocated > 0 {
+        writeln!(
+            out,
+            "ファイルシステムで使用中のため保存しなかったファイル: {} 個",
+            summary.skipped_allocated
+        )?;
+    }
+    if summary.noise_blocks > 0 {
+        writeln!(
+            out,
+            "ノイズとみなして読み飛ばしたブロック: {} 個",
+            summary.noise_blocks
+        )?;
+    }
+    if summary.skipped_hole_bytes > 0 {
+        writeln!(
+            out,
+            "スパース領域として {} バイトを読み飛ばしました",
+            summary.skipped_hole_bytes
+        )?;
+    }
+    if let Some(n) = options.top
+        && !summary.entries.is_empty()
+    {
+        writeln!(out, "サイズの大きいファイル（上位 {} 個）:", n)?;
+        for entry in largest(&summary.entries, n) {
+            writeln!(
+                out,
+                "  {:<6}  {:>12} バイト  {}",
+                entry.file_type.name(),
+                entry.size,
+                entry.filename
+            )?;
+        }
+    }
+    writeln!(out, "実行時間: {:.2?}", duration)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::FileType;
+
+    fn entry(index: usize, file_type: FileType, size: u64) -> ManifestEntry {
+        ManifestEntry {
+            index,
+            file_type,
+            offset: 0,
+            size,
+            sha256: String::new(),
+            confidence: 1.0,
+            filename: format!("image_{:06}.{}", index, file_type.extension()),
+            fragmented: false,
+            gps: None,
+            decode_failure: None,
+            stored_size: None,
+        }
+    }
+
+    // ---------------------------
+    // Tests for report_saved
+    // ---------------------------
+
+    #[test]
+    fn test_should_print_only_summary_when_summary_only_is_enabled() {
+        // 1. setup
+        let options = Options {
+            summary_only: true,
+            ..Options::default()
+        };
+        let mut summary = Summary::default();
+        let mut out = Vec::new();
+
+        // 2. execute
+        for i in 0..3 {
+            summary.entries.push(entry(i, FileType::Jpeg, 0));
+            summary.counter = i + 1;
+            report_saved(&mut out, &options, &summary, "recovered/image.jpg").unwrap();
+        }
+        write_summary(&mut out, &options, &summary, Duration::from_secs(1)).unwrap();
+
+        // 3. verify
+        let printed = String::from_utf8(out).unwrap();
+        assert!(!printed.contains("Saved:"));
+        assert!(printed.contains("復旧完了: 3 個のファイルを保存しました"));
+    }
+
+    #[test]
+    fn test_should_show_percent_and_unknown_eta_when_throughput_is_not_measured_yet() {
+        // 1. setup
+        let options = Options {
+            summary_only: true,
+            ..Options::default()
+        };
+        let summary = Summary {
+            scanned_bytes: 256 * 1024 * 1024,
+            total_bytes: Some(1024 * 1024 * 1024),
+            ..Summary::default()
+        };
+        let mut out = Vec::new();
+
+        // 2. execute
+        report_progress(&mut out, &options, &summary).unwrap();
+
+        // 3. verify
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\r保存済み: 0 個（256 MiB 走査、25.0%、残り 不明）"
+        );
+    }
+
+    // ---------------------------
+    // Tests for write_summary
+    // ---------------------------
+
+    #[test]
+    fn test_should_list_largest_files_first_when_top_is_given() {
+        // 1. setup
+        let options = Options {
+            top: Some(3),
+            ..Options::default()
+        };
+        let summary = Summary {
+            entries: vec![
+                entry(0, FileType::Jpeg, 300),
+                entry(1, FileType::Rw2, 20_000_000),
+                entry(2, FileType::Bmp, 40),
+                entry(3, FileType::Mp4, 90_000_000),
+                entry(4, FileType::Jpeg, 300),
+            ],
+            ..Summary::default()
+        };
+        let mut out = Vec::new();
+
+        // 2. execute
+        write_summary(&mut out, &options, &summary, Duration::from_secs(1)).unwrap();
+
+        // 3. verify
+        let printed = String::from_utf8(out).unwrap();
+        let listed: Vec<&str> = printed
+            .lines()
+            .skip_while(|line| !line.starts_with("サイズの大きいファイル"))
+            .skip(1)
+            .take_while(|line| line.starts_with("  "))
+            .filter_map(|line| line.split_whitespace().last())
+            .collect();
+        assert_eq!(
+            listed,
+            vec!["image_000003.mp4", "image_000001.rw2", "image_000000.jpg"]
+        );
+        assert!(printed.contains("  rw2         20000000 バイト  image_000001.rw2"));
+    }
+
+    #[test]
+    fn test_should_print_saved_line_when_summary_only_is_disabled() {
+        // 1. setup
+        let options = Options::default();
+        let summary = Summary::default();
+        let mut out = Vec::new();
+
+        // 2. execute
+        report_saved(&mut out, &options, &summary, "recovered/image.jpg").unwrap();
+
+        // 3. verify
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Saved: recovered/image.jpg\n"
+        );
+    }
+}
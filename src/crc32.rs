@@ -0,0 +1,85 @@
+// CRC-32 (IEEE 802.3)。ZIPやPNGのチェックサムに使う
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = build_table();
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+#[derive(Clone)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32 { state: 0xFFFF_FFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state = TABLE[((self.state ^ byte as u32) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    pub fn finalize(&self) -> u32 {
+        !self.state
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for crc32
+    // ---------------------------
+
+    #[test]
+    fn test_should_match_check_value_when_input_is_standard_digits() {
+        // 1. setup
+        let data = b"123456789";
+
+        // 2. execute
+        let result = crc32(data);
+
+        // 3. verify
+        assert_eq!(result, 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_should_return_zero_when_input_is_empty() {
+        // 1. setup
+        let data = b"";
+
+        // 2. execute
+        let result = crc32(data);
+
+        // 3. verify
+        assert_eq!(result, 0);
+    }
+}
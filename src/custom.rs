@@ -0,0 +1,122 @@
+// --add-signature 'name:starthex:endhex:ext' で、設定ファイルなしに組み込みにない形式を足す
+// FileType は Copy のまま使いたいので、解析したシグネチャは実行中ずっと残す（'static にする）
+use crate::{FileType, Signature};
+
+#[derive(Debug, PartialEq)]
+pub struct CustomSignature {
+    pub name: &'static str,
+    pub extension: &'static str,
+    pub start: [Signature; 1],
+    // 空なら次のスタートシグネチャまで
+    pub end: &'static [u8],
+}
+
+pub fn parse(spec: &str) -> Result<FileType, String> {
+    let fields: Vec<&str> = spec.split(':').collect();
+    let [name, start, end, extension] = fields[..] else {
+        return Err(format!(
+            "name:開始の16進:終了の16進:拡張子 の形式で指定してください: {}",
+            spec
+        ));
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "種類名には英数字と _ だけを使ってください: {}",
+            name
+        ));
+    }
+    if FileType::parse(name).is_some() {
+        return Err(format!("組み込みの種類と同じ名前は使えません: {}", name));
+    }
+    if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!(
+            "拡張子には英数字だけを使ってください: {}",
+            extension
+        ));
+    }
+    let start = parse_hex(start)
+        .filter(|magic| !magic.is_empty())
+        .ok_or_else(|| {
+            format!(
+                "開始シグネチャを16進数（例: FFD8）で指定してください: {}",
+                start
+            )
+        })?;
+    let end = parse_hex(end).ok_or_else(|| {
+        format!(
+            "終了シグネチャを16進数（例: FFD9）で指定してください: {}",
+            end
+        )
+    })?;
+
+    let signature = CustomSignature {
+        name: Box::leak(name.to_string().into_boxed_str()),
+        extension: Box::leak(extension.to_ascii_lowercase().into_boxed_str()),
+        start: [Signature {
+            magic: Box::leak(start.into_boxed_slice()),
+            offset: 0,
+            end_search_limit: None,
+        }],
+        end: Box::leak(end.into_boxed_slice()),
+    };
+    Ok(FileType::Custom(Box::leak(Box::new(signature))))
+}
+
+// 2桁ずつの16進数。空文字列は空のバイト列
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse
+    // ---------------------------
+
+    #[test]
+    fn test_should_parse_start_and_end_magic_when_spec_is_valid() {
+        // 1. setup
+        let spec = "png:89504E47:49454E44AE426082:PNG";
+
+        // 2. execute
+        let file_type = parse(spec).unwrap();
+
+        // 3. verify
+        let FileType::Custom(signature) = file_type else {
+            panic!("カスタムの種類になっていません: {:?}", file_type);
+        };
+        assert_eq!(signature.name, "png");
+        assert_eq!(signature.extension, "png");
+        assert_eq!(signature.start[0].magic, &[0x89, 0x50, 0x4E, 0x47]);
+        assert_eq!(
+            signature.end,
+            &[0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn test_should_return_error_when_hex_is_odd_or_not_hex() {
+        // 1. setup
+        let specs = [
+            "x:FFD:FFD9:x",
+            "x:FFZZ:FFD9:x",
+            "x::FFD9:x",
+            "jpeg:FFD8:FFD9:jpg",
+        ];
+
+        // 2. execute
+        let results: Vec<_> = specs.iter().map(|spec| parse(spec)).collect();
+
+        // 3. verify
+        assert!(results.iter().all(Result::is_err));
+    }
+}
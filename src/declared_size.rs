@@ -0,0 +1,66 @@
+// 長さを自己申告する形式（BMP / WebP など）の、申告サイズの妥当性チェック
+// 壊れたヘッダがでたらめなサイズを申告してゴミを何ギガバイトも切り出すのを防ぐ
+
+#[derive(Debug, PartialEq)]
+pub enum DeclaredSize {
+    Plausible(usize),
+    // ヘッダ自体より短い、または上限を超える
+    Implausible,
+}
+
+// min_len はヘッダなど、ファイルとして最低限必要な長さ
+pub fn check(declared: u64, min_len: usize, max_file_size: u64) -> DeclaredSize {
+    if declared < min_len as u64 || declared > max_file_size {
+        return DeclaredSize::Implausible;
+    }
+    match usize::try_from(declared) {
+        Ok(len) => DeclaredSize::Plausible(len),
+        Err(_) => DeclaredSize::Implausible,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for check
+    // ---------------------------
+
+    #[test]
+    fn test_should_accept_size_when_within_bounds() {
+        // 1. setup
+        let declared = 4096;
+
+        // 2. execute
+        let result = check(declared, 54, 1 << 20);
+
+        // 3. verify
+        assert_eq!(result, DeclaredSize::Plausible(4096));
+    }
+
+    #[test]
+    fn test_should_reject_size_when_beyond_max_file_size() {
+        // 1. setup
+        let declared = 0xFFFF_FFF0;
+
+        // 2. execute
+        let result = check(declared, 54, 1 << 20);
+
+        // 3. verify
+        assert_eq!(result, DeclaredSize::Implausible);
+    }
+
+    #[test]
+    fn test_should_reject_size_when_smaller_than_header() {
+        // 1. setup
+        let declared = 10;
+
+        // 2. execute
+        let result = check(declared, 54, 1 << 20);
+
+        // 3. verify
+        assert_eq!(result, DeclaredSize::Implausible);
+    }
+}
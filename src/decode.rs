@@ -0,0 +1,66 @@
+// --quarantine-on-decode-failure: 画像として開けそうにないものを failed/ に分ける
+// 画素までは展開せず、表示に必要な欄が読めるかどうかだけを確かめる
+use crate::bmp::{self, BmpHeader};
+use crate::{FileType, jpeg, webp};
+
+pub const FAILED_DIR: &str = "failed";
+
+// 開けないと分かった理由を返す。画像でない種類や、確かめる手段のない種類は Ok
+pub fn check(file_type: FileType, data: &[u8]) -> Result<(), &'static str> {
+    match file_type {
+        FileType::Jpeg => {
+            jpeg::dimensions(data).ok_or("SOF から画素数を読めません")?;
+            // 次のシグネチャやバッファの上限で打ち切ったものは EOI がない
+            if !data.ends_with(&[0xFF, 0xD9]) {
+                return Err("EOI で終わっていません");
+            }
+            Ok(())
+        }
+        FileType::Bmp => {
+            bmp::dimensions(data).ok_or("情報ヘッダから画素数を読めません")?;
+            match bmp::parse_header(data) {
+                BmpHeader::Valid { pixel_offset, .. } if pixel_offset < data.len() => Ok(()),
+                _ => Err("ピクセルデータがありません"),
+            }
+        }
+        FileType::Webp => webp::dimensions(data)
+            .map(|_| ())
+            .ok_or("VP8 / VP8L / VP8X チャンクを読めません"),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+
+    // ---------------------------
+    // Tests for check
+    // ---------------------------
+
+    #[test]
+    fn test_should_fail_jpeg_when_there_is_no_sof() {
+        // 1. setup
+        let data = minimal_jpeg(&[0x01, 0x02, 0x03]);
+
+        // 2. execute
+        let result = check(FileType::Jpeg, &data);
+
+        // 3. verify
+        assert_eq!(result, Err("SOF から画素数を読めません"));
+    }
+
+    #[test]
+    fn test_should_pass_bmp_when_header_and_pixels_are_present() {
+        // 1. setup
+        let data = bmp::test_fixtures::minimal_bmp(16);
+
+        // 2. execute
+        let result = check(FileType::Bmp, &data);
+
+        // 3. verify
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -0,0 +1,227 @@
+// --device-info: カービングの前に対象デバイスの概要を表示する
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::partition::{self, PartitionTable};
+
+#[derive(Debug)]
+pub struct DeviceInfo {
+    pub size: u64,
+    pub sector_size: u64,
+    pub table: PartitionTable,
+    // table のパーティションと同じ順
+    pub filesystems: Vec<Option<&'static str>>,
+    // パーティションを切らずにデバイス全体をフォーマットしている場合
+    pub whole_filesystem: Option<&'static str>,
+}
+
+// デバイスの先頭がパーティションテーブルか、ファイルシステムのブートセクタか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    // MBR / GPT があり、パーティションの位置はテーブルから決まる
+    WholeDisk,
+    // テーブルがなく先頭がファイルシステム（パーティション単体のイメージや、スーパーフロッピー形式のカード）
+    SinglePartition(&'static str),
+    // どちらとも判別できない（暗号化や先頭の破損など）
+    Unknown,
+}
+
+impl DeviceInfo {
+    pub fn layout(&self) -> Layout {
+        match (&self.table, self.whole_filesystem) {
+            (PartitionTable::None, Some(filesystem)) => Layout::SinglePartition(filesystem),
+            (PartitionTable::None, None) => Layout::Unknown,
+            _ => Layout::WholeDisk,
+        }
+    }
+}
+
+// 末尾に近ければ len より短く返す
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub fn inspect<R: Read + Seek>(reader: &mut R, sector_size: u64) -> io::Result<DeviceInfo> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    let head = read_at(reader, 0, partition::TABLE_READ_LEN)?;
+    let table = partition::parse(&head, sector_size);
+
+    let mut filesystems = Vec::new();
+    for part in table.partitions() {
+        let boot_sector = read_at(reader, part.start, partition::BOOT_SECTOR_READ_LEN)?;
+        filesystems.push(partition::detect_filesystem(&boot_sector));
+    }
+    let whole_filesystem = match table {
+        PartitionTable::None => partition::detect_filesystem(&head),
+        _ => None,
+    };
+
+    Ok(DeviceInfo {
+        size,
+        sector_size,
+        table,
+        filesystems,
+        whole_filesystem,
+    })
+}
+
+// ブロックデバイスなら論理セクタサイズ、それ以外（イメージファイルなど）は 512
+pub fn sector_size(file: &File) -> u64 {
+    #[cfg(target_os = "linux")]
+    {
+        sys::logical_sector_size(file).unwrap_or(partition::DEFAULT_SECTOR_SIZE)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = file;
+        partition::DEFAULT_SECTOR_SIZE
+    }
+}
+
+// ブロックデバイスのモデル名（/sys/class/block の device/model）。パーティションなら親のディスクのもの
+pub fn model(path: &Path) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        sys::model(path)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::{c_int, c_ulong};
+    use std::fs::{self, File};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::FileTypeExt;
+    use std::path::Path;
+
+    const BLKSSZGET: c_ulong = 0x1268;
+
+    unsafe extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    pub fn model(path: &Path) -> Option<String> {
+        let name = fs::canonicalize(path).ok()?.file_name()?.to_owned();
+        let block = Path::new("/sys/class/block").join(name);
+        [block.join("device/model"), block.join("../device/model")]
+            .iter()
+            .find_map(|model| fs::read_to_string(model).ok())
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty())
+    }
+
+    pub fn logical_sector_size(file: &File) -> Option<u64> {
+        if !file.metadata().ok()?.file_type().is_block_device() {
+            return None;
+        }
+        let mut size: c_int = 0;
+        // SAFETY: fd は file が所有する有効なディスクリプタで、BLKSSZGET は int を書き込む
+        let result = unsafe { ioctl(file.as_raw_fd(), BLKSSZGET, &mut size as *mut c_int) };
+        (result == 0 && size > 0).then_some(size as u64)
+    }
+}
+
+pub fn write_report<W: Write>(
+    out: &mut W,
+    device: &Path,
+    info: &DeviceInfo,
+    mounted: &[(String, String)],
+) -> io::Result<()> {
+    writeln!(out, "デバイス: {}", device.display())?;
+    writeln!(out, "サイズ: {} バイト", info.size)?;
+    writeln!(out, "セクタサイズ: {} バイト", info.sector_size)?;
+    writeln!(out, "パーティションテーブル: {}", info.table.name())?;
+    match info.layout() {
+        Layout::WholeDisk => writeln!(out, "構成: ディスク全体のイメージ")?,
+        Layout::SinglePartition(_) => writeln!(
+            out,
+            "構成: パーティション単体のイメージ（先頭がファイルシステム）"
+        )?,
+        Layout::Unknown => writeln!(out, "構成: 不明")?,
+    }
+
+    if let Some(filesystem) = info.whole_filesystem {
+        writeln!(out, "ファイルシステム: {}", filesystem)?;
+    }
+    for (part, filesystem) in info.table.partitions().iter().zip(&info.filesystems) {
+        writeln!(
+            out,
+            "  #{} 開始: {} 長さ: {} 種類: {} ファイルシステム: {}",
+            part.index,
+            part.start,
+            part.len,
+            part.label,
+            filesystem.unwrap_or("不明")
+        )?;
+    }
+
+    if mounted.is_empty() {
+        writeln!(out, "マウント: なし")?;
+    }
+    for (source, target) in mounted {
+        writeln!(out, "マウント: {} -> {}", source, target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::partition::test_fixtures::{fat32_boot_sector, mbr};
+    use std::io::Cursor;
+
+    // ---------------------------
+    // Tests for inspect
+    // ---------------------------
+
+    #[test]
+    fn test_should_report_partitions_and_filesystems_when_device_has_mbr() {
+        // 1. setup
+        let mut device = mbr(&[(0x0C, 8, 64)]);
+        device.resize(72 * 512, 0x00);
+        device[8 * 512 + 82..8 * 512 + 90].copy_from_slice(b"FAT32   ");
+        let mut reader = Cursor::new(device);
+
+        // 2. execute
+        let info = inspect(&mut reader, 512).unwrap();
+        let mut out = Vec::new();
+        write_report(&mut out, Path::new("/dev/sdz"), &info, &[]).unwrap();
+
+        // 3. verify
+        assert_eq!(info.size, 72 * 512);
+        assert_eq!(info.filesystems, vec![Some("FAT32")]);
+        let report = String::from_utf8(out).unwrap();
+        assert_eq!(info.layout(), Layout::WholeDisk);
+        assert!(report.contains("パーティションテーブル: MBR"));
+        assert!(report.contains("構成: ディスク全体のイメージ"));
+        assert!(report.contains("#1 開始: 4096 長さ: 32768 種類: 0x0c ファイルシステム: FAT32"));
+    }
+
+    #[test]
+    fn test_should_report_single_partition_when_image_starts_with_boot_sector() {
+        // 1. setup
+        let mut device = fat32_boot_sector(8, 16);
+        device.resize(64 * 512, 0x00);
+        let mut reader = Cursor::new(device);
+
+        // 2. execute
+        let info = inspect(&mut reader, 512).unwrap();
+        let mut out = Vec::new();
+        write_report(&mut out, Path::new("part.img"), &info, &[]).unwrap();
+
+        // 3. verify
+        assert_eq!(info.layout(), Layout::SinglePartition("FAT32"));
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.contains("構成: パーティション単体のイメージ"));
+    }
+}
@@ -0,0 +1,383 @@
+// DICOM の終端。128 バイトのプリアンブルと "DICM" の後ろに、(グループ, 要素) のタグと長さ付きの
+// データ要素が並ぶ。ファイルの長さを示す欄はないので、要素を長さでたどり、タグが昇順でなくなるか
+// 要素として読めなくなったところを終わりとする
+// 先頭のファイルメタ情報（グループ 0002）は常に明示的 VR のリトルエンディアンで、
+// その後ろのデータセットはメタ情報の転送構文に従う
+pub const MAGIC: &[u8] = b"DICM";
+pub const PREAMBLE_LEN: usize = 128;
+
+const META_START: usize = PREAMBLE_LEN + 4;
+const META_GROUP: u16 = 0x0002;
+const TAG_TRANSFER_SYNTAX: (u16, u16) = (0x0002, 0x0010);
+// 長さが決まっていない要素（シーケンスやカプセル化された画素データ）
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+// 項目と区切りは転送構文にかかわらず タグ + 4 バイトの長さ
+const DELIMITER_GROUP: u16 = 0xFFFE;
+const ITEM: (u16, u16) = (0xFFFE, 0xE000);
+const ITEM_DELIMITATION: (u16, u16) = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITATION: (u16, u16) = (0xFFFE, 0xE0DD);
+const MAX_DEPTH: usize = 16;
+
+const IMPLICIT_LITTLE_ENDIAN: &[u8] = b"1.2.840.10008.1.2";
+const EXPLICIT_BIG_ENDIAN: &[u8] = b"1.2.840.10008.1.2.2";
+const DEFLATED: &[u8] = b"1.2.840.10008.1.2.1.99";
+
+const VRS: [&[u8; 2]; 34] = [
+    b"AE", b"AS", b"AT", b"CS", b"DA", b"DS", b"DT", b"FD", b"FL", b"IS", b"LO", b"LT", b"OB",
+    b"OD", b"OF", b"OL", b"OV", b"OW", b"PN", b"SH", b"SL", b"SQ", b"SS", b"ST", b"SV", b"TM",
+    b"UC", b"UI", b"UL", b"UN", b"UR", b"US", b"UT", b"UV",
+];
+// 予約の 2 バイトの後ろに 4 バイトの長さ欄を持つ VR（ほかは 2 バイトの長さ欄）
+const LONG_VRS: [&[u8; 2]; 13] = [
+    b"OB", b"OD", b"OF", b"OL", b"OV", b"OW", b"SQ", b"SV", b"UC", b"UN", b"UR", b"UT", b"UV",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum DicomEnd {
+    // 最後に読めたデータ要素の後ろまでの長さ
+    Found(usize),
+    // データセットが deflate 圧縮で、要素をたどれない
+    Unsized,
+    Incomplete,
+    Invalid,
+}
+
+// 要素を読み進められなかった理由
+#[derive(Debug, PartialEq)]
+enum Stop {
+    Incomplete,
+    Invalid,
+}
+
+#[derive(Clone, Copy)]
+struct Encoding {
+    explicit: bool,
+    big_endian: bool,
+}
+
+const META_ENCODING: Encoding = Encoding {
+    explicit: true,
+    big_endian: false,
+};
+
+struct Header {
+    tag: (u16, u16),
+    header_len: usize,
+    len: u32,
+}
+
+fn u16_at(data: &[u8], pos: usize, big_endian: bool) -> Result<u16, Stop> {
+    let bytes: [u8; 2] = data
+        .get(pos..pos + 2)
+        .ok_or(Stop::Incomplete)?
+        .try_into()
+        .unwrap();
+    Ok(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn u32_at(data: &[u8], pos: usize, big_endian: bool) -> Result<u32, Stop> {
+    let bytes: [u8; 4] = data
+        .get(pos..pos + 4)
+        .ok_or(Stop::Incomplete)?
+        .try_into()
+        .unwrap();
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn read_header(data: &[u8], pos: usize, encoding: Encoding) -> Result<Header, Stop> {
+    let big_endian = encoding.big_endian;
+    let tag = (
+        u16_at(data, pos, big_endian)?,
+        u16_at(data, pos + 2, big_endian)?,
+    );
+    if tag.0 == DELIMITER_GROUP || !encoding.explicit {
+        return Ok(Header {
+            tag,
+            header_len: 8,
+            len: u32_at(data, pos + 4, big_endian)?,
+        });
+    }
+    let vr = data.get(pos + 4..pos + 6).ok_or(Stop::Incomplete)?;
+    if !VRS.iter().any(|known| known[..] == *vr) {
+        return Err(Stop::Invalid);
+    }
+    if LONG_VRS.iter().any(|long| long[..] == *vr) {
+        Ok(Header {
+            tag,
+            header_len: 12,
+            len: u32_at(data, pos + 8, big_endian)?,
+        })
+    } else {
+        Ok(Header {
+            tag,
+            header_len: 8,
+            len: u16_at(data, pos + 6, big_endian)? as u32,
+        })
+    }
+}
+
+// pos の要素の後ろの位置。長さの決まっていない要素は、中の項目を区切りまでたどる
+fn skip_element(
+    data: &[u8],
+    pos: usize,
+    encoding: Encoding,
+    max_len: usize,
+    depth: usize,
+) -> Result<usize, Stop> {
+    let header = read_header(data, pos, encoding)?;
+    let value = pos + header.header_len;
+    if header.len == UNDEFINED_LENGTH {
+        if depth >= MAX_DEPTH {
+            return Err(Stop::Invalid);
+        }
+        return skip_items(data, value, encoding, max_len, depth + 1);
+    }
+    let end = value
+        .checked_add(header.len as usize)
+        .filter(|&end| end <= max_len)
+        .ok_or(Stop::Invalid)?;
+    if end > data.len() {
+        return Err(Stop::Incomplete);
+    }
+    Ok(end)
+}
+
+// シーケンス区切りの後ろの位置
+fn skip_items(
+    data: &[u8],
+    mut pos: usize,
+    encoding: Encoding,
+    max_len: usize,
+    depth: usize,
+) -> Result<usize, Stop> {
+    loop {
+        let big_endian = encoding.big_endian;
+        let tag = (
+            u16_at(data, pos, big_endian)?,
+            u16_at(data, pos + 2, big_endian)?,
+        );
+        let len = u32_at(data, pos + 4, big_endian)?;
+        pos = match tag {
+            SEQUENCE_DELIMITATION => return Ok(pos + 8),
+            ITEM if len == UNDEFINED_LENGTH => {
+                let mut inner = pos + 8;
+                loop {
+                    let tag = (
+                        u16_at(data, inner, big_endian)?,
+                        u16_at(data, inner + 2, big_endian)?,
+                    );
+                    if tag == ITEM_DELIMITATION {
+                        break inner + 8;
+                    }
+                    inner = skip_element(data, inner, encoding, max_len, depth)?;
+                }
+            }
+            ITEM => {
+                let end = (pos + 8)
+                    .checked_add(len as usize)
+                    .filter(|&end| end <= max_len)
+                    .ok_or(Stop::Invalid)?;
+                if end > data.len() {
+                    return Err(Stop::Incomplete);
+                }
+                end
+            }
+            _ => return Err(Stop::Invalid),
+        };
+    }
+}
+
+// 転送構文の UID は偶数長にするため末尾に NUL か空白を詰めることがある
+fn trim_uid(value: &[u8]) -> &[u8] {
+    let len = value
+        .iter()
+        .rposition(|&b| b != 0 && b != b' ')
+        .map_or(0, |i| i + 1);
+    &value[..len]
+}
+
+// data はプリアンブルの先頭（"DICM" の 128 バイト前）から始まっていること
+// max_len を超える長さの要素は読めないものとみなす
+pub fn find_dicom_end(data: &[u8], max_len: usize, eof: bool) -> DicomEnd {
+    let mut pos = META_START;
+    let mut transfer_syntax = None;
+    let mut last_tag = (0, 0);
+    loop {
+        let group = match u16_at(data, pos, false) {
+            Ok(group) => group,
+            Err(_) if eof => break,
+            Err(_) => return DicomEnd::Incomplete,
+        };
+        if group != META_GROUP {
+            break;
+        }
+        let header = match read_header(data, pos, META_ENCODING) {
+            Ok(header) => header,
+            Err(Stop::Incomplete) if !eof => return DicomEnd::Incomplete,
+            Err(_) => return DicomEnd::Invalid,
+        };
+        let end = match skip_element(data, pos, META_ENCODING, max_len, 0) {
+            Ok(end) => end,
+            Err(Stop::Incomplete) if !eof => return DicomEnd::Incomplete,
+            Err(_) => return DicomEnd::Invalid,
+        };
+        if header.tag <= last_tag {
+            return DicomEnd::Invalid;
+        }
+        if header.tag == TAG_TRANSFER_SYNTAX {
+            transfer_syntax = Some(trim_uid(&data[pos + header.header_len..end]));
+        }
+        last_tag = header.tag;
+        pos = end;
+    }
+    // ファイルメタ情報がなければ、"DICM" はたまたま一致しただけ
+    let Some(transfer_syntax) = transfer_syntax else {
+        return DicomEnd::Invalid;
+    };
+    let encoding = match transfer_syntax {
+        DEFLATED => return DicomEnd::Unsized,
+        IMPLICIT_LITTLE_ENDIAN => Encoding {
+            explicit: false,
+            big_endian: false,
+        },
+        EXPLICIT_BIG_ENDIAN => Encoding {
+            explicit: true,
+            big_endian: true,
+        },
+        // 明示的 VR のリトルエンディアンと、それで画素データを包む圧縮形式（JPEG など）
+        _ => META_ENCODING,
+    };
+
+    loop {
+        let header = match read_header(data, pos, encoding) {
+            Ok(header) => header,
+            Err(Stop::Incomplete) if !eof => return DicomEnd::Incomplete,
+            Err(_) => return DicomEnd::Found(pos),
+        };
+        // データセットの最上位にある項目や区切り、昇順でないタグは、別のデータ
+        if header.tag.0 == DELIMITER_GROUP || header.tag <= last_tag {
+            return DicomEnd::Found(pos);
+        }
+        match skip_element(data, pos, encoding, max_len, 0) {
+            Ok(end) => pos = end,
+            // eof なら、要素の途中でデバイスが終わっている
+            Err(Stop::Incomplete) => return DicomEnd::Incomplete,
+            Err(Stop::Invalid) => return DicomEnd::Found(pos),
+        }
+        last_tag = header.tag;
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::PREAMBLE_LEN;
+
+    fn element(data: &mut Vec<u8>, tag: (u16, u16), vr: &[u8; 2], value: &[u8]) {
+        data.extend_from_slice(&tag.0.to_le_bytes());
+        data.extend_from_slice(&tag.1.to_le_bytes());
+        data.extend_from_slice(vr);
+        if matches!(vr, b"OB" | b"OW") {
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        } else {
+            data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        }
+        data.extend_from_slice(value);
+    }
+
+    // 明示的 VR のリトルエンディアンで、モダリティ・行数・pixel_len バイトの画素データを持つ DICOM
+    // encapsulated なら画素データを長さの決まっていない OB にし、空のオフセット表と 1 個の断片で包む
+    pub fn minimal_dicom(pixel_len: usize, encapsulated: bool) -> Vec<u8> {
+        let mut meta = Vec::new();
+        element(&mut meta, (0x0002, 0x0010), b"UI", b"1.2.840.10008.1.2.1\0");
+        let mut data = vec![0x00; PREAMBLE_LEN];
+        data.extend_from_slice(b"DICM");
+        element(
+            &mut data,
+            (0x0002, 0x0000),
+            b"UL",
+            &(meta.len() as u32).to_le_bytes(),
+        );
+        data.extend_from_slice(&meta);
+        element(&mut data, (0x0008, 0x0060), b"CS", b"US");
+        element(&mut data, (0x0028, 0x0010), b"US", &480u16.to_le_bytes());
+        let pixels = vec![0x80; pixel_len];
+        if encapsulated {
+            data.extend_from_slice(&[0xE0, 0x7F, 0x10, 0x00]);
+            data.extend_from_slice(b"OB\0\0");
+            data.extend_from_slice(&u32::MAX.to_le_bytes());
+            for (tag, value) in [(0xE000u16, &[][..]), (0xE000, &pixels[..]), (0xE0DD, &[])] {
+                data.extend_from_slice(&0xFFFEu16.to_le_bytes());
+                data.extend_from_slice(&tag.to_le_bytes());
+                data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                data.extend_from_slice(value);
+            }
+        } else {
+            element(&mut data, (0x7FE0, 0x0010), b"OW", &pixels);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_dicom;
+    use super::*;
+
+    // ---------------------------
+    // Tests for find_dicom_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_end_after_pixel_data_when_junk_follows() {
+        // 1. setup
+        let dicom = minimal_dicom(64, false);
+        let data = [dicom.as_slice(), &[0xEE; 40]].concat();
+
+        // 2. execute
+        let result = find_dicom_end(&data, usize::MAX, false);
+
+        // 3. verify
+        assert_eq!(result, DicomEnd::Found(dicom.len()));
+    }
+
+    #[test]
+    fn test_should_follow_items_to_sequence_delimiter_when_pixel_data_is_encapsulated() {
+        // 1. setup
+        let dicom = minimal_dicom(64, true);
+        // 次のファイルのプリアンブル（タグ (0000,0000) は昇順にならない）
+        let data = [dicom.as_slice(), &[0x00; 40]].concat();
+
+        // 2. execute
+        let result = find_dicom_end(&data, usize::MAX, false);
+
+        // 3. verify
+        assert_eq!(result, DicomEnd::Found(dicom.len()));
+    }
+
+    #[test]
+    fn test_should_wait_or_reject_when_pixel_data_is_cut_or_meta_is_missing() {
+        // 1. setup
+        let dicom = minimal_dicom(64, false);
+        let mut no_meta = dicom.clone();
+        no_meta[132..134].copy_from_slice(&0x0008u16.to_le_bytes());
+
+        // 2. execute
+        let results = [
+            find_dicom_end(&dicom[..dicom.len() - 10], usize::MAX, false),
+            find_dicom_end(&no_meta, usize::MAX, true),
+        ];
+
+        // 3. verify
+        assert_eq!(results, [DicomEnd::Incomplete, DicomEnd::Invalid]);
+    }
+}
@@ -0,0 +1,90 @@
+// 保存先ファイルシステムの空き容量
+use std::path::Path;
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod sys {
+    use std::ffi::{CString, c_char, c_int};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    // glibc / 64bit Linux の struct statvfs
+    #[repr(C)]
+    #[derive(Default)]
+    struct StatVfs {
+        f_bsize: u64,
+        f_frsize: u64,
+        f_blocks: u64,
+        f_bfree: u64,
+        f_bavail: u64,
+        f_files: u64,
+        f_ffree: u64,
+        f_favail: u64,
+        f_fsid: u64,
+        f_flag: u64,
+        f_namemax: u64,
+        f_spare: [c_int; 6],
+    }
+
+    unsafe extern "C" {
+        fn statvfs(path: *const c_char, buf: *mut StatVfs) -> c_int;
+    }
+
+    pub fn available_bytes(path: &Path) -> Option<u64> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat = StatVfs::default();
+        // SAFETY: c_path はNUL終端された有効な文字列で、stat は書き込み可能な領域
+        let result = unsafe { statvfs(c_path.as_ptr(), &mut stat) };
+        if result != 0 {
+            return None;
+        }
+        Some(stat.f_bavail.saturating_mul(stat.f_frsize))
+    }
+}
+
+// 取得できない環境では None（チェックを行わない）
+pub fn available_bytes(path: &Path) -> Option<u64> {
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    {
+        sys::available_bytes(path)
+    }
+    #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for available_bytes
+    // ---------------------------
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_should_report_free_space_when_path_exists() {
+        // 1. setup
+        let path = std::env::temp_dir();
+
+        // 2. execute
+        let result = available_bytes(&path);
+
+        // 3. verify
+        assert!(result.is_some());
+    }
+
+    #[test]
+    fn test_should_return_none_when_path_does_not_exist() {
+        // 1. setup
+        let path = Path::new("/nonexistent/sdcard_test/path");
+
+        // 2. execute
+        let result = available_bytes(path);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
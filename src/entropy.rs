@@ -0,0 +1,193 @@
+// デバイス全体が暗号化されていないかの見積もり（BitLocker / LUKS などはどこを読んでも乱数に見える）
+// JPEG の中身もエントロピーは高いが、バイトの出現頻度に偏りが残るのでカイ二乗値で見分ける。
+// ファイルシステムの管理領域や空き領域のゼロも混ざるので、デバイス全体から広く標本を取る
+// xz や deflate の圧縮データも乱数に見えるので、先頭にパーティションテーブルやファイルシステムがなく、
+// 先頭の標本のほかはすべて乱数に見えるときだけ暗号化とみなす
+use std::io;
+
+use crate::partition::{self, PartitionTable};
+use crate::source::{self, Source};
+
+const SAMPLE_LEN: usize = 4096;
+const SAMPLE_COUNT: u64 = 64;
+// これより標本が取れない小さなデバイスは判定しない
+const MIN_SAMPLES: u64 = 16;
+// 4096 バイトの一様乱数のエントロピーは推定の偏りで 7.95 前後になる
+const MIN_ENTROPY: f64 = 7.9;
+// 自由度 255 のカイ二乗値の平均は 255、標準偏差は約 22.6 なので、その 6 倍ほど上まで
+const MAX_CHI_SQUARED: f64 = 400.0;
+
+fn histogram(block: &[u8]) -> [u32; 256] {
+    let mut counts = [0u32; 256];
+    for &byte in block {
+        counts[byte as usize] += 1;
+    }
+    counts
+}
+
+// 1バイトあたりのシャノンエントロピー（ビット）
+fn shannon_entropy(block: &[u8]) -> f64 {
+    let len = block.len() as f64;
+    histogram(block)
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// バイトが一様に現れるとしたときのカイ二乗値
+fn chi_squared(block: &[u8]) -> f64 {
+    let expected = block.len() as f64 / 256.0;
+    histogram(block)
+        .iter()
+        .map(|&count| {
+            let diff = count as f64 - expected;
+            diff * diff / expected
+        })
+        .sum()
+}
+
+fn looks_random(block: &[u8]) -> bool {
+    shannon_entropy(block) >= MIN_ENTROPY && chi_squared(block) <= MAX_CHI_SQUARED
+}
+
+// 暗号化されていれば平文の管理領域はない（BitLocker のブートセクタはファイルシステムとして判別しない）
+fn has_plain_layout(head: &[u8]) -> bool {
+    partition::parse(head, partition::DEFAULT_SECTOR_SIZE) != PartitionTable::None
+        || partition::detect_filesystem(head).is_some()
+}
+
+// 先頭から末尾まで等間隔に標本を読み、先頭の標本のほかがすべて乱数に見えれば true
+// 先頭の標本は平文のヘッダ（LUKS のヘッダや BitLocker のブートセクタ）を含むので数えない
+// 読み終えたら start_offset に戻す
+pub fn looks_encrypted<R: Source>(reader: &mut R) -> io::Result<bool> {
+    let start = reader.start_offset();
+    let Some(len) = reader.total_len() else {
+        return Ok(false);
+    };
+    let count = SAMPLE_COUNT.min(len / SAMPLE_LEN as u64);
+    if count < MIN_SAMPLES {
+        return Ok(false);
+    }
+
+    let head = source::read_at(reader, start, partition::BOOT_SECTOR_READ_LEN as u64)?;
+    let mut encrypted = !has_plain_layout(&head);
+    let stride = (len - SAMPLE_LEN as u64) / (count - 1);
+    for i in 1..count {
+        if !encrypted {
+            break;
+        }
+        let block = source::read_at(reader, start + i * stride, SAMPLE_LEN as u64)?;
+        encrypted = looks_random(&block);
+    }
+    reader.seek_to(start)?;
+    Ok(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+
+    // xorshift64 で作る再現できる乱数列
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    }
+
+    // ---------------------------
+    // Tests for looks_encrypted
+    // ---------------------------
+
+    #[test]
+    fn test_should_report_encrypted_when_whole_device_is_random() {
+        // 1. setup
+        let mut device = random_bytes(1 << 20);
+        // 平文のヘッダがあっても乱数の部分が大半なら暗号化とみなす
+        device[..512].copy_from_slice(&[0x00; 512]);
+        let mut reader = Cursor::new(device);
+
+        // 2. execute
+        let result = looks_encrypted(&mut reader).unwrap();
+
+        // 3. verify
+        assert!(result);
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn test_should_not_report_encrypted_when_device_has_zeroed_free_space() {
+        // 1. setup
+        let mut device = random_bytes(1 << 20);
+        device[1 << 19..].fill(0x00);
+
+        // 2. execute
+        let result = looks_encrypted(&mut Cursor::new(device)).unwrap();
+
+        // 3. verify
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_should_not_report_encrypted_when_compressed_data_sits_behind_a_partition_table() {
+        // 1. setup
+        // xz のように乱数と見分けられない圧縮データで埋まったカード
+        let compressed = random_bytes(1 << 20);
+        let mut partitioned = compressed.clone();
+        partitioned[..512].copy_from_slice(&partition::test_fixtures::mbr(&[(0x0c, 8, 2040)]));
+        let mut formatted = compressed;
+        formatted[..512].copy_from_slice(&partition::test_fixtures::fat32_boot_sector(8, 16));
+
+        // 2. execute
+        let results = [
+            looks_encrypted(&mut Cursor::new(partitioned)).unwrap(),
+            looks_encrypted(&mut Cursor::new(formatted)).unwrap(),
+        ];
+
+        // 3. verify
+        assert_eq!(results, [false, false]);
+    }
+
+    #[test]
+    fn test_should_not_report_encrypted_when_one_region_is_not_random() {
+        // 1. setup
+        // deflate のブロックのように、一部だけバイトの出現頻度に偏りが残る
+        let mut device = random_bytes(1 << 20);
+        let skewed = device.len() / 2;
+        device[skewed..skewed + (64 << 10)].fill(0x20);
+
+        // 2. execute
+        let result = looks_encrypted(&mut Cursor::new(device)).unwrap();
+
+        // 3. verify
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_should_not_look_random_when_bytes_are_skewed() {
+        // 1. setup
+        // すべての値は出てくるがエントロピーの高い圧縮データのように偏りがある
+        let block: Vec<u8> = random_bytes(SAMPLE_LEN)
+            .into_iter()
+            .enumerate()
+            .map(|(i, byte)| if i % 8 == 0 { 0xFF } else { byte })
+            .collect();
+
+        // 2. execute
+        let result = looks_random(&block);
+
+        // 3. verify
+        assert!(!result);
+    }
+}
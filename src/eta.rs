@@ -0,0 +1,106 @@
+// 進捗表示の残り時間。直近の読み込み速度から求め、遅い領域で一時的に落ちても大きく跳ねないようにする
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// 前の記録からこれだけ経ってから次を記録する
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// 速度は直近のこの個数の区間（おおよそ秒数）から求める
+const WINDOW_SAMPLES: usize = 30;
+
+#[derive(Debug, Default)]
+pub struct Throughput {
+    // (開始からの時間, それまでに読んだバイト数)
+    samples: VecDeque<(Duration, u64)>,
+}
+
+impl Throughput {
+    // 記録したら true（進捗の表示もこの間隔に合わせる）
+    pub fn record(&mut self, elapsed: Duration, scanned_bytes: u64) -> bool {
+        if let Some(&(last, _)) = self.samples.back()
+            && elapsed < last + SAMPLE_INTERVAL
+        {
+            return false;
+        }
+        self.samples.push_back((elapsed, scanned_bytes));
+        if self.samples.len() > WINDOW_SAMPLES + 1 {
+            self.samples.pop_front();
+        }
+        true
+    }
+
+    // 窓の両端の差から求めた1秒あたりのバイト数
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        let (&(first_at, first_bytes), &(last_at, last_bytes)) =
+            (self.samples.front()?, self.samples.back()?);
+        let seconds = (last_at - first_at).as_secs_f64();
+        (seconds > 0.0 && last_bytes > first_bytes)
+            .then(|| (last_bytes - first_bytes) as f64 / seconds)
+    }
+
+    pub fn eta(&self, remaining_bytes: u64) -> Option<Duration> {
+        let bytes_per_second = self.bytes_per_second()?;
+        Some(Duration::from_secs_f64(
+            remaining_bytes as f64 / bytes_per_second,
+        ))
+    }
+}
+
+// 時:分:秒、分からなければ「不明」
+pub fn format_eta(eta: Option<Duration>) -> String {
+    match eta {
+        Some(eta) => {
+            let seconds = eta.as_secs();
+            format!(
+                "{}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        }
+        None => "不明".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for Throughput
+    // ---------------------------
+
+    #[test]
+    fn test_should_estimate_from_recent_window_when_early_throughput_was_slow() {
+        // 1. setup
+        let mut throughput = Throughput::default();
+        // 最初の 10 秒は 1 MB/秒、その後の 30 秒は 10 MB/秒
+        let mut scanned = 0;
+        for second in 0..=40 {
+            throughput.record(Duration::from_secs(second), scanned);
+            scanned += if second < 10 { 1_000_000 } else { 10_000_000 };
+        }
+
+        // 2. execute
+        let eta = throughput.eta(600_000_000);
+
+        // 3. verify
+        assert_eq!(eta, Some(Duration::from_secs(60)));
+        assert_eq!(format_eta(eta), "0:01:00");
+    }
+
+    #[test]
+    fn test_should_ignore_samples_closer_than_interval_when_recording() {
+        // 1. setup
+        let mut throughput = Throughput::default();
+
+        // 2. execute
+        let first = throughput.record(Duration::from_millis(0), 0);
+        let too_soon = throughput.record(Duration::from_millis(500), 100);
+
+        // 3. verify
+        assert!(first);
+        assert!(!too_soon);
+        assert_eq!(format_eta(throughput.eta(1000)), "不明");
+    }
+}
@@ -0,0 +1,308 @@
+// EXIF（JPEG の APP1 か、TIFF ベースの RAW そのもの）から読む撮影情報
+use crate::FileType;
+use crate::tiff::{Ifd, TAG_EXIF_IFD, TAG_GPS_IFD, TiffReader};
+
+const MARKER_PREFIX: u8 = 0xFF;
+const MARKER_APP1: u8 = 0xE1;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_EOI: u8 = 0xD9;
+const EXIF_IDENTIFIER: &[u8] = b"Exif\0\0";
+
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+const TIFF_RATIONAL: u16 = 5;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TIFF_ASCII: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsCoordinates {
+    // 南緯・西経は負
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// 年・月・日の順に比べれば日付の前後になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CaptureDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+// EXIF の TIFF ヘッダから始まる部分。JPEG は APP1 の中身、TIFF ベースの形式はファイルそのもの
+fn tiff_data(file_type: FileType, data: &[u8]) -> Option<&[u8]> {
+    match file_type {
+        FileType::Jpeg => find_app1_exif(data),
+        FileType::Rw2 | FileType::Dng | FileType::Orf | FileType::Nrw | FileType::Tiff => {
+            Some(data)
+        }
+        _ => None,
+    }
+}
+
+// JPEG の SOS より前にある APP1 の "Exif\0\0" の後ろ
+fn find_app1_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != MARKER_PREFIX {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == MARKER_SOS || marker == MARKER_EOI {
+            return None;
+        }
+        let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+        let body = data.get(pos + 4..(pos + 2 + length).min(data.len()))?;
+        if marker == MARKER_APP1
+            && let Some(tiff) = body.strip_prefix(EXIF_IDENTIFIER)
+        {
+            return Some(tiff);
+        }
+        pos += 2 + length;
+    }
+}
+
+// IFD0 から GPS IFD をたどり、緯度・経度の両方がそろっていれば10進の度にして返す
+pub fn gps(file_type: FileType, data: &[u8]) -> Option<GpsCoordinates> {
+    let tiff = TiffReader::new(tiff_data(file_type, data)?).ok()?;
+    let ifd0 = tiff.read_ifd(tiff.first_ifd().ok()?).ok()?;
+    let gps_ifd = tiff
+        .read_ifd(ifd0.find(TAG_GPS_IFD)?.value_offset as usize)
+        .ok()?;
+
+    let latitude = degrees(&tiff, &gps_ifd, TAG_GPS_LATITUDE)?;
+    let longitude = degrees(&tiff, &gps_ifd, TAG_GPS_LONGITUDE)?;
+    let sign = |tag: u16, negative: u8| match gps_ifd.find(tag) {
+        Some(entry) if value_byte(&tiff, entry.value_field) == Some(negative) => -1.0,
+        _ => 1.0,
+    };
+    Some(GpsCoordinates {
+        latitude: latitude * sign(TAG_GPS_LATITUDE_REF, b'S'),
+        longitude: longitude * sign(TAG_GPS_LONGITUDE_REF, b'W'),
+    })
+}
+
+// 撮影日時（Exif IFD の DateTimeOriginal）を優先し、なければ IFD0 の DateTime（最終更新日時）を使う
+pub fn capture_date(file_type: FileType, data: &[u8]) -> Option<CaptureDate> {
+    let tiff = TiffReader::new(tiff_data(file_type, data)?).ok()?;
+    let ifd0 = tiff.read_ifd(tiff.first_ifd().ok()?).ok()?;
+    let original = ifd0
+        .find(TAG_EXIF_IFD)
+        .and_then(|entry| tiff.read_ifd(entry.value_offset as usize).ok())
+        .and_then(|exif_ifd| date_value(&tiff, &exif_ifd, TAG_DATE_TIME_ORIGINAL));
+    original.or_else(|| date_value(&tiff, &ifd0, TAG_DATE_TIME))
+}
+
+// "YYYY:MM:DD HH:MM:SS" の日付の部分。未設定のカメラが書く "0000:00:00" などは None
+fn date_value(tiff: &TiffReader, ifd: &Ifd, tag: u16) -> Option<CaptureDate> {
+    let entry = ifd.find(tag)?;
+    if entry.field_type != TIFF_ASCII {
+        return None;
+    }
+    let (pos, len) = tiff.value_location(entry)?;
+    let text = tiff.bytes(pos, len.min(10)).ok()?;
+    let number = |range: std::ops::Range<usize>| -> Option<u16> {
+        let digits = text.get(range)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    };
+    if text.get(4) != Some(&b':') || text.get(7) != Some(&b':') {
+        return None;
+    }
+    let date = CaptureDate {
+        year: number(0..4)?,
+        month: number(5..7)? as u8,
+        day: number(8..10)? as u8,
+    };
+    (date.year > 0 && (1..=12).contains(&date.month) && (1..=31).contains(&date.day))
+        .then_some(date)
+}
+
+// 度・分・秒の RATIONAL 3つ
+fn degrees(tiff: &TiffReader, ifd: &Ifd, tag: u16) -> Option<f64> {
+    let entry = ifd.find(tag)?;
+    if entry.field_type != TIFF_RATIONAL || entry.count != 3 {
+        return None;
+    }
+    let (pos, _) = tiff.value_location(entry)?;
+    let mut parts = [0.0; 3];
+    for (i, part) in parts.iter_mut().enumerate() {
+        let numerator = tiff.u32_at(pos + i * 8).ok()?;
+        let denominator = tiff.u32_at(pos + i * 8 + 4).ok()?;
+        if denominator == 0 {
+            return None;
+        }
+        *part = numerator as f64 / denominator as f64;
+    }
+    let value = parts[0] + parts[1] / 60.0 + parts[2] / 3600.0;
+    (value <= 180.0).then_some(value)
+}
+
+// ASCII 2バイト（"N\0" など）の1文字目
+fn value_byte(tiff: &TiffReader, value_field: usize) -> Option<u8> {
+    tiff.bytes(value_field, 1).ok().map(|bytes| bytes[0])
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // 緯度・経度を (度, 分, 秒×100) で持つ GPS IFD だけの EXIF を APP1 に入れたJPEG
+    pub fn jpeg_with_gps(
+        latitude: (char, [u32; 3]),
+        longitude: (char, [u32; 3]),
+        scan_data: &[u8],
+    ) -> Vec<u8> {
+        // TIFF ヘッダ(8) + IFD0(2+12+4) + GPS IFD(2+12*4+4) の後ろに RATIONAL を置く
+        let gps_ifd = 8 + 18;
+        let rationals = gps_ifd + 54;
+        let mut tiff = vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00];
+        let entry = |tiff: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: u32| {
+            tiff.extend_from_slice(&tag.to_le_bytes());
+            tiff.extend_from_slice(&field_type.to_le_bytes());
+            tiff.extend_from_slice(&count.to_le_bytes());
+            tiff.extend_from_slice(&value.to_le_bytes());
+        };
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        entry(&mut tiff, 0x8825, 4, 1, gps_ifd as u32);
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(&4u16.to_le_bytes());
+        entry(&mut tiff, 1, 2, 2, latitude.0 as u32);
+        entry(&mut tiff, 2, 5, 3, rationals as u32);
+        entry(&mut tiff, 3, 2, 2, longitude.0 as u32);
+        entry(&mut tiff, 4, 5, 3, rationals as u32 + 24);
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        for (value, denominator) in latitude
+            .1
+            .iter()
+            .chain(longitude.1.iter())
+            .zip([1u32, 1, 100].iter().cycle())
+        {
+            tiff.extend_from_slice(&value.to_le_bytes());
+            tiff.extend_from_slice(&denominator.to_le_bytes());
+        }
+
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(&tiff);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x03, 0x01]);
+        data.extend_from_slice(scan_data);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    // Exif IFD に DateTimeOriginal（"YYYY:MM:DD HH:MM:SS"）だけを持つ EXIF を APP1 に入れたJPEG
+    pub fn jpeg_with_capture_date(date_time: &str, scan_data: &[u8]) -> Vec<u8> {
+        // TIFF ヘッダ(8) + IFD0(2+12+4) + Exif IFD(2+12+4) の後ろに文字列を置く
+        let exif_ifd = 8 + 18;
+        let text = exif_ifd + 18;
+        let mut tiff = vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00];
+        let entry = |tiff: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: u32| {
+            tiff.extend_from_slice(&1u16.to_le_bytes());
+            tiff.extend_from_slice(&tag.to_le_bytes());
+            tiff.extend_from_slice(&field_type.to_le_bytes());
+            tiff.extend_from_slice(&count.to_le_bytes());
+            tiff.extend_from_slice(&value.to_le_bytes());
+            tiff.extend_from_slice(&0u32.to_le_bytes());
+        };
+        entry(&mut tiff, 0x8769, 4, 1, exif_ifd as u32);
+        entry(
+            &mut tiff,
+            0x9003,
+            2,
+            date_time.len() as u32 + 1,
+            text as u32,
+        );
+        tiff.extend_from_slice(date_time.as_bytes());
+        tiff.push(0x00);
+
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(&tiff);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x03, 0x01]);
+        data.extend_from_slice(scan_data);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::{jpeg_with_capture_date, jpeg_with_gps};
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+
+    // ---------------------------
+    // Tests for gps
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_signed_decimal_degrees_when_jpeg_has_gps_ifd() {
+        // 1. setup
+        // 南緯 33°52'4.00" 東経 151°12'36.00"（シドニー）
+        let data = jpeg_with_gps(('S', [33, 52, 400]), ('E', [151, 12, 3600]), &[0x01]);
+
+        // 2. execute
+        let result = gps(FileType::Jpeg, &data).unwrap();
+
+        // 3. verify
+        assert!((result.latitude - -(33.0 + 52.0 / 60.0 + 4.0 / 3600.0)).abs() < 1e-9);
+        assert!((result.longitude - (151.0 + 12.0 / 60.0 + 36.0 / 3600.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_should_return_none_when_jpeg_has_no_exif() {
+        // 1. setup
+        let data = minimal_jpeg(&[0x01, 0x02]);
+
+        // 2. execute
+        let result = gps(FileType::Jpeg, &data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    // ---------------------------
+    // Tests for capture_date
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_date_when_exif_ifd_has_date_time_original() {
+        // 1. setup
+        let data = jpeg_with_capture_date("2023:07:14 09:30:00", &[0x01]);
+
+        // 2. execute
+        let result = capture_date(FileType::Jpeg, &data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            Some(CaptureDate {
+                year: 2023,
+                month: 7,
+                day: 14
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_return_none_when_date_is_unset() {
+        // 1. setup
+        let data = jpeg_with_capture_date("0000:00:00 00:00:00", &[0x01]);
+
+        // 2. execute
+        let result = capture_date(FileType::Jpeg, &data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
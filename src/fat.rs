@@ -0,0 +1,575 @@
+// FAT32 のディレクトリをたどり、ファイルシステムにまだ載っているファイルの位置と大きさを集める
+// 削除済みのエントリ（先頭が 0xE5）は載っていないものとして数えない
+// FAT32 の FAT と exFAT の割り当てビットマップから、クラスタが使用中かどうかも読む
+// FAT32 のチェーンが途切れずに残っていれば、連続していないクラスタに分かれたファイルもつなぎ直せる
+use std::collections::HashSet;
+use std::io;
+
+use crate::partition;
+use crate::source::{Source, read_at};
+
+const DIR_ENTRY_LEN: usize = 32;
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+// 名前の先頭が本当に 0xE5 のときはこう書かれる
+const ENTRY_KANJI_E5: u8 = 0x05;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+// 壊れた FAT やループしたディレクトリを延々とたどらないための上限
+const MAX_DIRECTORY_CLUSTERS: usize = 65536;
+const MAX_DIRECTORIES: usize = 4096;
+// exFAT のルートディレクトリにある割り当てビットマップのエントリと、2つ目のビットマップを示すフラグ
+const EXFAT_ENTRY_BITMAP: u8 = 0x81;
+const EXFAT_SECOND_BITMAP: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FsFile {
+    // ルートからの "DCIM/100CANON/IMG_0001.JPG" のようなパス（8.3 形式の名前）
+    pub path: String,
+    // 最初のクラスタのデバイス上のオフセット
+    pub offset: u64,
+    pub size: u64,
+}
+
+// FAT のチェーンが連続していないファイル
+#[derive(Debug, Clone, PartialEq)]
+pub struct FragmentedFile {
+    // 最初のクラスタのデバイス上のオフセット
+    pub offset: u64,
+    pub size: u64,
+    // ファイルの先頭から順に、連続するクラスタの (デバイス上のオフセット, バイト数)
+    pub extents: Vec<(u64, u64)>,
+}
+
+// クラスタ2から数えて、使用中なら true（FAT の値が 0 でない、またはビットマップのビットが立っている）
+#[derive(Debug)]
+pub struct Allocation {
+    // クラスタ2のデバイス上のオフセット
+    heap_start: u64,
+    cluster_size: u64,
+    allocated: Vec<bool>,
+}
+
+impl Allocation {
+    // offset を含むクラスタが使用中か。クラスタヒープの外なら None
+    pub fn is_allocated(&self, offset: u64) -> Option<bool> {
+        let index = offset.checked_sub(self.heap_start)? / self.cluster_size;
+        self.allocated.get(usize::try_from(index).ok()?).copied()
+    }
+}
+
+struct Volume {
+    // ファイルシステムのデバイス上の先頭
+    start: u64,
+    exfat: bool,
+    fat_offset: u64,
+    // 1つ目の FAT のバイト数
+    fat_len: u64,
+    heap_offset: u64,
+    cluster_size: u64,
+    // 分からなければ FAT の長さから決める
+    cluster_count: Option<u64>,
+    root_cluster: u32,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u64> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as u64)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u64> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as u64)
+}
+
+impl Volume {
+    fn parse(boot_sector: &[u8], start: u64) -> Option<Self> {
+        let filesystem = partition::detect_filesystem(boot_sector)?;
+        let layout = partition::cluster_layout(boot_sector)?;
+        let (fat_offset, fat_len, cluster_count, root_cluster) = match filesystem {
+            "FAT32" => {
+                let bytes_per_sector = u16_at(boot_sector, 11)?;
+                let total_sectors = match u16_at(boot_sector, 19)? {
+                    0 => u32_at(boot_sector, 32)?,
+                    n => n,
+                };
+                let cluster_count = (total_sectors * bytes_per_sector)
+                    .checked_sub(layout.heap_offset)
+                    .map(|len| len / layout.cluster_size)
+                    .filter(|&count| count > 0);
+                (
+                    u16_at(boot_sector, 14)? * bytes_per_sector,
+                    u32_at(boot_sector, 36)? * bytes_per_sector,
+                    cluster_count,
+                    u32_at(boot_sector, 44)?,
+                )
+            }
+            "exFAT" => {
+                let bytes_per_sector = 1u64 << (*boot_sector.get(108)?).min(12);
+                (
+                    u32_at(boot_sector, 80)? * bytes_per_sector,
+                    u32_at(boot_sector, 84)? * bytes_per_sector,
+                    Some(u32_at(boot_sector, 92)?),
+                    u32_at(boot_sector, 96)?,
+                )
+            }
+            _ => return None,
+        };
+        if root_cluster < 2 || layout.cluster_size == 0 {
+            return None;
+        }
+        Some(Volume {
+            start,
+            exfat: filesystem == "exFAT",
+            fat_offset,
+            fat_len,
+            heap_offset: layout.heap_offset,
+            cluster_size: layout.cluster_size,
+            cluster_count,
+            root_cluster: root_cluster as u32,
+        })
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.start + self.heap_offset + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    // FAT32 の1つ目の FAT を、クラスタ番号で引ける値の並びとして読む（下位 28 ビットだけ）
+    fn read_fat<R: Source>(&self, reader: &mut R) -> io::Result<Vec<u32>> {
+        // FAT の先頭2つはクラスタ0と1の分（予約）
+        let fat_len = self
+            .cluster_count
+            .map_or(u64::MAX, |count| (count + 2) * 4)
+            .min(self.fat_len);
+        let fat = read_at(reader, self.start + self.fat_offset, fat_len)?;
+        Ok(fat
+            .chunks_exact(4)
+            .map(|entry| {
+                u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) & FAT_ENTRY_MASK
+            })
+            .collect())
+    }
+
+    // first から size バイト分のクラスタを FAT でたどり、連続する範囲にまとめる
+    // 必要な数のクラスタをたどる前にチェーンが終わったり、ループしたりすれば None
+    fn extents(&self, fat: &[u32], first: u32, size: u64) -> Option<Vec<(u64, u64)>> {
+        let mut extents: Vec<(u64, u64)> = Vec::new();
+        let mut remaining = size;
+        let mut cluster = first;
+        let mut visited = HashSet::new();
+        while remaining > 0 {
+            if !(2..END_OF_CHAIN).contains(&cluster) || !visited.insert(cluster) {
+                return None;
+            }
+            let offset = self.cluster_offset(cluster);
+            let len = remaining.min(self.cluster_size);
+            match extents.last_mut() {
+                Some((start, extent_len)) if *start + *extent_len == offset => *extent_len += len,
+                _ => extents.push((offset, len)),
+            }
+            remaining -= len;
+            cluster = *fat.get(cluster as usize)?;
+        }
+        Some(extents)
+    }
+
+    // first から FAT をたどったクラスタの中身をつなげる
+    fn read_chain<R: Source>(&self, reader: &mut R, first: u32) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut visited = HashSet::new();
+        let mut cluster = first;
+        while (2..END_OF_CHAIN).contains(&cluster)
+            && visited.len() < MAX_DIRECTORY_CLUSTERS
+            && visited.insert(cluster)
+        {
+            let block = read_at(reader, self.cluster_offset(cluster), self.cluster_size)?;
+            let short = (block.len() as u64) < self.cluster_size;
+            data.extend_from_slice(&block);
+            if short {
+                break;
+            }
+            let entry = read_at(reader, self.start + self.fat_offset + cluster as u64 * 4, 4)?;
+            let Ok(entry) = <[u8; 4]>::try_from(entry.as_slice()) else {
+                break;
+            };
+            cluster = u32::from_le_bytes(entry) & FAT_ENTRY_MASK;
+        }
+        Ok(data)
+    }
+}
+
+// "IMG_0001JPG" の空白を除いて "IMG_0001.JPG" にする
+fn short_name(entry: &[u8]) -> String {
+    let mut base = entry[..8].to_vec();
+    if base[0] == ENTRY_KANJI_E5 {
+        base[0] = ENTRY_DELETED;
+    }
+    let base = String::from_utf8_lossy(&base).trim_end().to_string();
+    let extension = String::from_utf8_lossy(&entry[8..11])
+        .trim_end()
+        .to_string();
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+// start がデバイス上の FAT32 の先頭なら、載っているファイルをオフセット順に返す。FAT32 でなければ None
+pub fn list_files<R: Source>(reader: &mut R, start: u64) -> io::Result<Option<Vec<FsFile>>> {
+    let boot_sector = read_at(reader, start, partition::BOOT_SECTOR_READ_LEN as u64)?;
+    let Some(fs) = Volume::parse(&boot_sector, start).filter(|fs| !fs.exfat) else {
+        return Ok(None);
+    };
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(fs.root_cluster, String::new())];
+    while let Some((cluster, directory)) = pending.pop() {
+        if visited.len() >= MAX_DIRECTORIES || !visited.insert(cluster) {
+            continue;
+        }
+        let data = fs.read_chain(reader, cluster)?;
+        for entry in data.chunks_exact(DIR_ENTRY_LEN) {
+            match entry[0] {
+                ENTRY_END => break,
+                ENTRY_DELETED => continue,
+                _ => {}
+            }
+            let attributes = entry[11];
+            if attributes & ATTR_LONG_NAME == ATTR_LONG_NAME || attributes & ATTR_VOLUME_ID != 0 {
+                continue;
+            }
+            let name = short_name(entry);
+            if name == "." || name == ".." {
+                continue;
+            }
+            let high = u16::from_le_bytes([entry[20], entry[21]]) as u32;
+            let low = u16::from_le_bytes([entry[26], entry[27]]) as u32;
+            let first_cluster = high << 16 | low;
+            let size = u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]) as u64;
+            let path = if directory.is_empty() {
+                name
+            } else {
+                format!("{}/{}", directory, name)
+            };
+            if attributes & ATTR_DIRECTORY != 0 {
+                pending.push((first_cluster, path));
+            } else if first_cluster >= 2 && size > 0 {
+                files.push(FsFile {
+                    path,
+                    offset: fs.cluster_offset(first_cluster),
+                    size,
+                });
+            }
+        }
+    }
+    files.sort_by_key(|file| file.offset);
+    Ok(Some(files))
+}
+
+// start がデバイス上の FAT32 / exFAT の先頭なら、クラスタごとの使用状況を返す。どちらでもなければ None
+pub fn allocation<R: Source>(reader: &mut R, start: u64) -> io::Result<Option<Allocation>> {
+    let boot_sector = read_at(reader, start, partition::BOOT_SECTOR_READ_LEN as u64)?;
+    let Some(fs) = Volume::parse(&boot_sector, start) else {
+        return Ok(None);
+    };
+
+    let allocated: Vec<bool> = if fs.exfat {
+        let root = fs.read_chain(reader, fs.root_cluster)?;
+        let bitmap = root
+            .chunks_exact(DIR_ENTRY_LEN)
+            .take_while(|entry| entry[0] != ENTRY_END)
+            .find(|entry| entry[0] == EXFAT_ENTRY_BITMAP && entry[1] & EXFAT_SECOND_BITMAP == 0);
+        let Some(entry) = bitmap else {
+            return Ok(None);
+        };
+        let first_cluster = u32::from_le_bytes([entry[20], entry[21], entry[22], entry[23]]);
+        let bitmap = fs.read_chain(reader, first_cluster)?;
+        let count = fs.cluster_count.unwrap_or(0).min(bitmap.len() as u64 * 8) as usize;
+        (0..count)
+            .map(|i| bitmap[i / 8] & (1 << (i % 8)) != 0)
+            .collect()
+    } else {
+        fs.read_fat(reader)?
+            .iter()
+            .skip(2)
+            .map(|&entry| entry != 0)
+            .collect()
+    };
+    Ok(Some(Allocation {
+        heap_start: fs.cluster_offset(2),
+        cluster_size: fs.cluster_size,
+        allocated,
+    }))
+}
+
+// start がデバイス上の FAT32 の先頭なら、載っているファイルのうちチェーンが連続していないものを返す
+// 削除済みのファイルはチェーンが消えているので含まない。FAT32 でなければ None
+pub fn fragmented_files<R: Source>(
+    reader: &mut R,
+    start: u64,
+) -> io::Result<Option<Vec<FragmentedFile>>> {
+    let Some(files) = list_files(reader, start)? else {
+        return Ok(None);
+    };
+    let boot_sector = read_at(reader, start, partition::BOOT_SECTOR_READ_LEN as u64)?;
+    let Some(fs) = Volume::parse(&boot_sector, start) else {
+        return Ok(None);
+    };
+    let fat = fs.read_fat(reader)?;
+    let heap_start = fs.cluster_offset(2);
+    let fragmented = files
+        .iter()
+        .filter_map(|file| {
+            let first = (file.offset - heap_start) / fs.cluster_size + 2;
+            let extents = fs.extents(&fat, u32::try_from(first).ok()?, file.size)?;
+            (extents.len() > 1).then_some(FragmentedFile {
+                offset: file.offset,
+                size: file.size,
+                extents,
+            })
+        })
+        .collect();
+    Ok(Some(fragmented))
+}
+
+// 範囲を順に読んでつなげる
+pub fn read_fragments<R: Source>(reader: &mut R, file: &FragmentedFile) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(file.size as usize);
+    for &(offset, len) in &file.extents {
+        let part = read_at(reader, offset, len)?;
+        if (part.len() as u64) < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        data.extend_from_slice(&part);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use crate::partition::test_fixtures::fat32_boot_sector;
+
+    pub const SECTOR: usize = 512;
+    // 予約 32 セクタ + FAT 1 セクタ × 2
+    pub const HEAP_OFFSET: usize = (32 + 2) * SECTOR;
+
+    // 1クラスタ 512 バイトの FAT32。ルートディレクトリ（クラスタ2）に files を順に置き、
+    // 中身はクラスタ3から隙間なく並べる。deleted のものはエントリの先頭を 0xE5 にし、FAT のチェーンも 0 に戻す
+    pub fn fat32_image(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut image = fat32_boot_sector(1, 1);
+        image[44..48].copy_from_slice(&2u32.to_le_bytes());
+        image.resize(HEAP_OFFSET + SECTOR, 0x00);
+
+        let mut fat = vec![0x0FFF_FFF8u32, 0x0FFF_FFFF, 0x0FFF_FFFF];
+        let mut directory = Vec::new();
+        for &(name, data, deleted) in files {
+            let first = fat.len() as u32;
+            let clusters = data.len().div_ceil(SECTOR).max(1) as u32;
+            for cluster in first..first + clusters {
+                fat.push(if deleted {
+                    0
+                } else if cluster + 1 == first + clusters {
+                    0x0FFF_FFFF
+                } else {
+                    cluster + 1
+                });
+            }
+            let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+            let mut entry = format!("{:<8}{:<3}", base, extension).into_bytes();
+            if deleted {
+                entry[0] = 0xE5;
+            }
+            entry.push(0x20); // アーカイブ属性
+            entry.resize(20, 0x00);
+            entry.extend_from_slice(&((first >> 16) as u16).to_le_bytes());
+            entry.resize(26, 0x00);
+            entry.extend_from_slice(&(first as u16).to_le_bytes());
+            entry.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&entry);
+
+            let offset = HEAP_OFFSET + (first as usize - 2) * SECTOR;
+            image.resize(offset + clusters as usize * SECTOR, 0x00);
+            image[offset..offset + data.len()].copy_from_slice(data);
+        }
+        image[HEAP_OFFSET..HEAP_OFFSET + directory.len()].copy_from_slice(&directory);
+        for (i, entry) in fat.iter().enumerate() {
+            let pos = 32 * SECTOR + i * 4;
+            image[pos..pos + 4].copy_from_slice(&entry.to_le_bytes());
+        }
+        image
+    }
+
+    // 1クラスタ 512 バイトの exFAT。クラスタ2が割り当てビットマップ、クラスタ3がルートディレクトリで、
+    // allocated に挙げたクラスタ（4 以降）も使用中にする
+    pub fn exfat_image(cluster_count: u32, allocated: &[u32]) -> Vec<u8> {
+        let mut image = vec![0u8; 512];
+        image[3..11].copy_from_slice(b"EXFAT   ");
+        image[80..84].copy_from_slice(&24u32.to_le_bytes());
+        image[84..88].copy_from_slice(&8u32.to_le_bytes());
+        image[88..92].copy_from_slice(&32u32.to_le_bytes());
+        image[92..96].copy_from_slice(&cluster_count.to_le_bytes());
+        image[96..100].copy_from_slice(&3u32.to_le_bytes());
+        image[108] = 9;
+        image[109] = 0;
+        image[510] = 0x55;
+        image[511] = 0xAA;
+        image.resize((32 + cluster_count as usize) * SECTOR, 0x00);
+
+        for (i, entry) in [0xFFFF_FFF8u32, 0xFFFF_FFFF, 0xFFFF_FFFF, 0xFFFF_FFFF]
+            .iter()
+            .enumerate()
+        {
+            let pos = 24 * SECTOR + i * 4;
+            image[pos..pos + 4].copy_from_slice(&entry.to_le_bytes());
+        }
+        let bitmap = 32 * SECTOR;
+        for cluster in [2, 3].iter().chain(allocated) {
+            let bit = (cluster - 2) as usize;
+            image[bitmap + bit / 8] |= 1 << (bit % 8);
+        }
+        let root = 33 * SECTOR;
+        image[root] = 0x81;
+        image[root + 20..root + 24].copy_from_slice(&2u32.to_le_bytes());
+        image[root + 24..root + 32]
+            .copy_from_slice(&(cluster_count as u64).div_ceil(8).to_le_bytes());
+        image
+    }
+
+    // IMG_0001.JPG の中身の先頭 first_clusters クラスタをクラスタ3から置き、
+    // 間に OTHER.BIN（gap）を挟んで、残りをその後ろのクラスタに置いた FAT32
+    pub fn fragmented_fat32_image(data: &[u8], first_clusters: usize, gap: &[u8]) -> Vec<u8> {
+        let split = first_clusters * SECTOR;
+        let mut image = fat32_image(&[
+            ("IMG_0001.JPG", &data[..split], false),
+            ("OTHER.BIN", gap, false),
+            ("REST.BIN", &data[split..], false),
+        ]);
+        // 前半の最後のクラスタから残りのクラスタへチェーンをつなぎ、REST.BIN のエントリは消す
+        let last = 3 + first_clusters - 1;
+        let rest = 3 + first_clusters + gap.len().div_ceil(SECTOR).max(1);
+        let pos = 32 * SECTOR + last * 4;
+        image[pos..pos + 4].copy_from_slice(&(rest as u32).to_le_bytes());
+        image[HEAP_OFFSET + 28..HEAP_OFFSET + 32]
+            .copy_from_slice(&(data.len() as u32).to_le_bytes());
+        image[HEAP_OFFSET + 64] = 0xE5;
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::{HEAP_OFFSET, SECTOR, fat32_image};
+    use super::*;
+    use std::io::Cursor;
+
+    // ---------------------------
+    // Tests for list_files
+    // ---------------------------
+
+    #[test]
+    fn test_should_list_live_files_and_skip_deleted_when_root_has_both() {
+        // 1. setup
+        let image = fat32_image(&[
+            ("IMG_0001.JPG", &[0x11; 700], false),
+            ("IMG_0002.JPG", &[0x22; 10], true),
+            ("NOTES.TXT", b"hello", false),
+        ]);
+
+        // 2. execute
+        let files = list_files(&mut Cursor::new(image), 0).unwrap().unwrap();
+
+        // 3. verify
+        assert_eq!(
+            files,
+            vec![
+                FsFile {
+                    path: "IMG_0001.JPG".to_string(),
+                    offset: (HEAP_OFFSET + SECTOR) as u64,
+                    size: 700,
+                },
+                FsFile {
+                    path: "NOTES.TXT".to_string(),
+                    offset: (HEAP_OFFSET + 4 * SECTOR) as u64,
+                    size: 5,
+                },
+            ]
+        );
+    }
+
+    // ---------------------------
+    // Tests for fragmented_files
+    // ---------------------------
+
+    #[test]
+    fn test_should_follow_fat_chain_when_file_is_split_around_another_file() {
+        // 1. setup
+        let data: Vec<u8> = (0..1300).map(|i| (i % 251) as u8).collect();
+        let image = test_fixtures::fragmented_fat32_image(&data, 1, &[0x77; 600]);
+        let cluster = |n: usize| (HEAP_OFFSET + (n - 2) * SECTOR) as u64;
+        let mut reader = Cursor::new(image);
+
+        // 2. execute
+        let files = fragmented_files(&mut reader, 0).unwrap().unwrap();
+        let reassembled = read_fragments(&mut reader, &files[0]).unwrap();
+
+        // 3. verify
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extents, vec![(cluster(3), 512), (cluster(6), 788)]);
+        assert_eq!(reassembled, data);
+    }
+
+    // ---------------------------
+    // Tests for allocation
+    // ---------------------------
+
+    #[test]
+    fn test_should_mark_deleted_file_clusters_free_when_fat32_chain_is_cleared() {
+        // 1. setup
+        let image = fat32_image(&[
+            ("IMG_0001.JPG", &[0x11; 700], false),
+            ("IMG_0002.JPG", &[0x22; 10], true),
+        ]);
+        let cluster = |n: usize| (HEAP_OFFSET + (n - 2) * SECTOR) as u64;
+
+        // 2. execute
+        let allocation = allocation(&mut Cursor::new(image), 0).unwrap().unwrap();
+
+        // 3. verify
+        assert_eq!(allocation.is_allocated(cluster(3) + 10), Some(true));
+        assert_eq!(allocation.is_allocated(cluster(4)), Some(true));
+        assert_eq!(allocation.is_allocated(cluster(5)), Some(false));
+        assert_eq!(allocation.is_allocated(0), None);
+    }
+
+    #[test]
+    fn test_should_read_bitmap_bits_when_filesystem_is_exfat() {
+        // 1. setup
+        let image = test_fixtures::exfat_image(16, &[4]);
+        let cluster = |n: u64| 32 * SECTOR as u64 + (n - 2) * SECTOR as u64;
+
+        // 2. execute
+        let allocation = allocation(&mut Cursor::new(image), 0).unwrap().unwrap();
+
+        // 3. verify
+        assert_eq!(allocation.is_allocated(cluster(4)), Some(true));
+        assert_eq!(allocation.is_allocated(cluster(5)), Some(false));
+        assert_eq!(allocation.is_allocated(cluster(18)), None);
+    }
+
+    #[test]
+    fn test_should_return_none_when_start_is_not_fat32() {
+        // 1. setup
+        let image = vec![0x00; 4096];
+
+        // 2. execute
+        let result = list_files(&mut Cursor::new(image), 0).unwrap();
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
@@ -0,0 +1,96 @@
+// フッター（end_signatures）で終わりを示す形式のために、スキャン中に読んだブロックからフッターの位置を集める
+// 先頭はスキャンを終えてから、フッターの手前を読み直して後ろ向きに探す（carve::find_start_before）
+use crate::FileType;
+
+pub struct FooterScanner {
+    types: Vec<FileType>,
+    // ブロック境界をまたぐフッターのために持ち越す、直前のブロックの末尾
+    tail: Vec<u8>,
+    // (フッターの終わりのデバイス上のオフセット, 種類)
+    pub found: Vec<(u64, FileType)>,
+}
+
+impl FooterScanner {
+    pub fn new(types: &[FileType]) -> Self {
+        FooterScanner {
+            types: types
+                .iter()
+                .copied()
+                .filter(|t| !t.end_signatures().is_empty())
+                .collect(),
+            tail: Vec::new(),
+            found: Vec::new(),
+        }
+    }
+
+    // block_offset は block[0] のデバイス上のオフセット
+    pub fn feed(&mut self, block: &[u8], block_offset: u64) {
+        if self.types.is_empty() {
+            return;
+        }
+        let carried = self.tail.len();
+        let data = [self.tail.as_slice(), block].concat();
+        let data_offset = block_offset - carried as u64;
+        for &file_type in &self.types {
+            for signature in file_type.end_signatures() {
+                for (idx, window) in data.windows(signature.magic.len()).enumerate() {
+                    // 持ち越した部分だけに収まるものは前のブロックで見つけている
+                    if idx + signature.magic.len() <= carried || window != signature.magic {
+                        continue;
+                    }
+                    let Some(footer_start) = idx.checked_sub(signature.offset) else {
+                        continue;
+                    };
+                    let footer_end = data_offset + (footer_start + signature.len()) as u64;
+                    self.found.push((footer_end, file_type));
+                }
+            }
+        }
+
+        let keep = self
+            .types
+            .iter()
+            .flat_map(|t| t.end_signatures())
+            .map(|signature| signature.len())
+            .max()
+            .unwrap_or(0);
+        self.tail = data[data.len().saturating_sub(keep)..].to_vec();
+    }
+
+    // スパースファイルの穴を読み飛ばしたら、持ち越した末尾とは繋がらない
+    pub fn skip(&mut self) {
+        self.tail.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tga;
+
+    // ---------------------------
+    // Tests for FooterScanner
+    // ---------------------------
+
+    #[test]
+    fn test_should_find_footer_once_when_it_straddles_blocks() {
+        // 1. setup
+        let tga = tga::test_fixtures::minimal_tga(2, 2);
+        let mut device = vec![0x00; 1000];
+        device[300..300 + tga.len()].copy_from_slice(&tga);
+        let mut scanner = FooterScanner::new(&[FileType::Tga]);
+
+        // 2. execute
+        // マジックの途中で区切る
+        for (i, block) in device.chunks(300 + tga.len() - 5).enumerate() {
+            scanner.feed(block, (i * (300 + tga.len() - 5)) as u64);
+        }
+
+        // 3. verify
+        assert_eq!(
+            scanner.found,
+            vec![((300 + tga.len()) as u64, FileType::Tga)]
+        );
+    }
+}
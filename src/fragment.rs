@@ -0,0 +1,96 @@
+// 連続していないクラスタをつないで切り出した（断片化した）可能性の判定
+// カービングでは正しく復元できないので、別フォルダに分けて期待値を下げておく
+use crate::FileType;
+
+pub const FRAGMENTED_DIR: &str = "fragmented";
+
+// 1クラスタ分以上ゼロが続いたあとでデータが再開したら、別ファイルのクラスタか未使用領域をまたいだとみなす
+const ZERO_RUN_THRESHOLD: usize = 4096;
+
+const JPEG_SOS: &[u8] = &[0xFF, 0xDA];
+const JPEG_EOI: &[u8] = &[0xFF, 0xD9];
+
+pub fn looks_fragmented(file_type: FileType, data: &[u8]) -> bool {
+    match file_type {
+        // エントロピー符号化されたデータにはゼロがこれほど続くことはまずない
+        FileType::Jpeg => {
+            let Some(sos) = data
+                .windows(JPEG_SOS.len())
+                .position(|window| window == JPEG_SOS)
+            else {
+                return false;
+            };
+            let scan = data.get(sos + JPEG_SOS.len()..).unwrap_or_default();
+            has_gap(scan.strip_suffix(JPEG_EOI).unwrap_or(scan))
+        }
+        _ => false,
+    }
+}
+
+// 長いゼロの連続のあとに、まだゼロ以外のデータが続くか
+fn has_gap(data: &[u8]) -> bool {
+    let mut zero_run = 0;
+    for &byte in data {
+        if byte != 0 {
+            if zero_run >= ZERO_RUN_THRESHOLD {
+                return true;
+            }
+            zero_run = 0;
+        } else {
+            zero_run += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+
+    // ---------------------------
+    // Tests for looks_fragmented
+    // ---------------------------
+
+    #[test]
+    fn test_should_flag_jpeg_when_scan_data_has_zero_cluster_then_resumes() {
+        // 1. setup
+        let mut scan = vec![0x5A; 2048];
+        scan.extend_from_slice(&[0x00; ZERO_RUN_THRESHOLD]);
+        scan.extend_from_slice(&[0xA5; 2048]);
+        let jpeg = minimal_jpeg(&scan);
+
+        // 2. execute
+        let result = looks_fragmented(FileType::Jpeg, &jpeg);
+
+        // 3. verify
+        assert!(result);
+    }
+
+    #[test]
+    fn test_should_not_flag_jpeg_when_zeros_only_precede_end_marker() {
+        // 1. setup
+        let mut scan = vec![0x5A; 2048];
+        scan.extend_from_slice(&[0x00; ZERO_RUN_THRESHOLD * 2]);
+        let jpeg = minimal_jpeg(&scan);
+
+        // 2. execute
+        let result = looks_fragmented(FileType::Jpeg, &jpeg);
+
+        // 3. verify
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_should_not_flag_jpeg_when_scan_data_is_contiguous() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x5A; 8192]);
+
+        // 2. execute
+        let result = looks_fragmented(FileType::Jpeg, &jpeg);
+
+        // 3. verify
+        assert!(!result);
+    }
+}
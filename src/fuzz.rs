@@ -0,0 +1,252 @@
+// カービング処理にでたらめなバイト列を流し込み、パニックしないことを確かめるファズテスト
+//
+// このクレートはバイナリだけで cargo-fuzz のターゲットから呼べるライブラリがないため、
+// 通常のテストとして、決まった種から作る擬似乱数で入力を組み立てる。
+// 既定の回数は cargo test で数秒に収まる程度なので、長く回したいときは
+//
+//     FUZZ_ITERATIONS=100000 cargo test fuzz -- --nocapture
+//
+// のように回数を増やす。FUZZ_SEED で種を変えられ、失敗したときは種と回数を表示する。
+use std::io::Cursor;
+
+use crate::cli::Options;
+use crate::jpeg::JpegEndStrategy;
+use crate::test_util::{minimal_jpeg, temp_output_dir};
+use crate::{
+    bmff, bmp, carve, dicom, exif, find_starts, mpf, psd, raf, recover, tga, tiff, webp, x3f, xmp,
+    zip,
+};
+
+const DEFAULT_ITERATIONS: usize = 500;
+const DEFAULT_SEED: u64 = 0x5DCA_4D00_1234_5678;
+const MAX_INPUT_LEN: usize = 4096;
+const STRATEGIES: [JpegEndStrategy; 3] = [
+    JpegEndStrategy::Strict,
+    JpegEndStrategy::Last,
+    JpegEndStrategy::First,
+];
+
+// xorshift64（テストの再現性のためだけのもの）
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+// 本物のファイルと、ヘッダの途中で切れたり長さ欄が壊れたりしたもの
+fn seed_corpus() -> Vec<Vec<u8>> {
+    let rw2 = tiff::test_fixtures::little_endian_tiff(
+        &[
+            (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+            (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+        ],
+        50,
+    );
+    let dng = tiff::test_fixtures::little_endian_tiff(
+        &[
+            (tiff::TAG_STRIP_OFFSETS, 4, 2, 200),
+            (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, u32::MAX),
+            (tiff::TAG_SUB_IFDS, 4, 1, 8),
+            (tiff::TAG_DNG_VERSION, 1, 4, 0x0000_0401),
+        ],
+        64,
+    );
+    let mut bmp_absurd = bmp::test_fixtures::minimal_bmp(8);
+    bmp_absurd[2..6].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut raf_absurd = raf::test_fixtures::minimal_raf([(0x80, 0x40), (0, 0), (0, 0)]);
+    raf_absurd[0x68..0x70].copy_from_slice(&[0xFF; 8]);
+    let mut psd_absurd = psd::test_fixtures::minimal_psd(4, 2, false);
+    psd_absurd[26..30].copy_from_slice(&[0xFF; 4]);
+    let mut nrw = tiff::test_fixtures::little_endian_tiff(&[(tiff::TAG_MAKE, 2, 6, 26)], 40);
+    nrw[26..32].copy_from_slice(b"NIKON\0");
+    let x3f = x3f::test_fixtures::minimal_x3f(32);
+    let x3f_truncated = x3f[..x3f.len() - 4].to_vec();
+    let mut webp_short = webp::test_fixtures::minimal_webp(8);
+    webp_short[4..8].copy_from_slice(&0u32.to_le_bytes());
+
+    vec![
+        minimal_jpeg(&[0x01, 0x02, 0x03]),
+        // 長さ 0 / 1 / 0xFFFF のセグメント
+        vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x00],
+        vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x01, 0xFF, 0xD9],
+        vec![0xFF, 0xD8, 0xFF, 0xDB, 0xFF, 0xFF],
+        vec![0xFF, 0xD8, 0xFF],
+        mpf::test_fixtures::two_image_mpo().0,
+        exif::test_fixtures::jpeg_with_gps(('N', [35, 39, 3150]), ('W', [139, 42, 300]), &[0x01]),
+        [crate::CRW_START, &[0x00; 32]].concat(),
+        [crate::ORF_START, &[0x08, 0x00, 0x00, 0x00, 0x00, 0x00]].concat(),
+        tga::test_fixtures::minimal_tga(2, 2),
+        raf::test_fixtures::minimal_raf([(0x80, 0x40), (0xC0, 0x10), (0xD0, 0x30)]),
+        raf_absurd,
+        psd::test_fixtures::minimal_psd(8, 2, false),
+        psd::test_fixtures::minimal_psd(8, 2, true),
+        psd_absurd,
+        nrw,
+        x3f,
+        x3f_truncated,
+        tiff::test_fixtures::two_page_tiff(),
+        xmp::test_fixtures::xmp_packet(4),
+        xmp::PACKET_END.to_vec(),
+        dicom::test_fixtures::minimal_dicom(16, false),
+        dicom::test_fixtures::minimal_dicom(16, true),
+        rw2,
+        dng,
+        vec![0x4D, 0x4D, 0x00, 0x2A, 0xFF, 0xFF, 0xFF, 0xF0],
+        vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0xFF, 0xFF],
+        zip::test_fixtures::minimal_zip(&[("a.txt", b"hello")], b"comment"),
+        vec![0x50, 0x4B, 0x03, 0x04, 0x50, 0x4B, 0x05, 0x06],
+        bmp::test_fixtures::minimal_bmp(16),
+        bmp_absurd,
+        webp::test_fixtures::minimal_webp(16),
+        webp_short,
+        b"RIFF".to_vec(),
+        b"BM".to_vec(),
+        bmff::test_fixtures::minimal_mp4(b"heic", 32),
+        bmff::test_fixtures::minimal_mp4(b"crx ", 32),
+        // 長さ 0（ファイルの終わりまで）と 1（64ビット長）のボックス
+        [
+            &bmff::test_fixtures::minimal_mp4(b"isom", 0)[..20],
+            &[0, 0, 0, 0],
+            b"mdat",
+        ]
+        .concat(),
+        [
+            &bmff::test_fixtures::minimal_mp4(b"qt  ", 0)[..20],
+            &[0, 0, 0, 1],
+            b"mdat",
+        ]
+        .concat(),
+    ]
+}
+
+fn mutate(rng: &mut Rng, corpus: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = corpus[rng.below(corpus.len())].clone();
+    for _ in 0..1 + rng.below(8) {
+        match rng.below(5) {
+            // バイトを書き換える
+            0 if !data.is_empty() => {
+                let pos = rng.below(data.len());
+                data[pos] = rng.byte();
+            }
+            // 途中で切る
+            1 => data.truncate(rng.below(data.len() + 1)),
+            // でたらめなバイトを挟む
+            2 => {
+                let pos = rng.below(data.len() + 1);
+                let noise: Vec<u8> = (0..rng.below(64)).map(|_| rng.byte()).collect();
+                data.splice(pos..pos, noise);
+            }
+            // 別の種を繋げる
+            3 => {
+                let other = &corpus[rng.below(corpus.len())];
+                data.extend_from_slice(other);
+            }
+            // 長さ欄になりそうな位置に大きな値を入れる
+            _ if data.len() >= 4 => {
+                let pos = rng.below(data.len() - 3);
+                data[pos..pos + 4].copy_from_slice(&(rng.next() as u32).to_le_bytes());
+            }
+            _ => {}
+        }
+    }
+    data.truncate(MAX_INPUT_LEN);
+    data
+}
+
+// 本番と同じく、スタートシグネチャの位置から終端検出にかける
+fn find_end_at_every_start(data: &[u8], options: &Options) {
+    let mut from = 0;
+    while from < data.len() {
+        let Some(&(idx, file_type)) = find_starts(&data[from..], &options.types).first() else {
+            break;
+        };
+        let start = from + idx;
+        for eof in [false, true] {
+            if let carve::CarveEnd::Found(len, _) =
+                carve::find_end(&data[start..], file_type, options, eof)
+            {
+                assert!(
+                    start + len <= data.len(),
+                    "終端がバッファの外を指しています"
+                );
+            }
+        }
+        from = start + 1;
+    }
+}
+
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for the carve pipeline
+    // ---------------------------
+
+    #[test]
+    fn test_should_never_panic_when_carving_mutated_signatures() {
+        // 1. setup
+        let iterations = env_or("FUZZ_ITERATIONS", DEFAULT_ITERATIONS as u64) as usize;
+        let seed = env_or("FUZZ_SEED", DEFAULT_SEED);
+        let mut rng = Rng(seed.max(1));
+        let corpus = seed_corpus();
+        let output_dir = temp_output_dir("fuzz");
+        let base = Options {
+            output_dir: output_dir.clone(),
+            max_file_size: MAX_INPUT_LEN as u64,
+            skip_unterminated: true,
+            extract_preview: true,
+            sniff: true,
+            trim_trailing: true,
+            jpeg_eoi_run: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        for iteration in 0..iterations {
+            let data = mutate(&mut rng, &corpus);
+            let options = Options {
+                jpeg_end_strategy: STRATEGIES[iteration % STRATEGIES.len()],
+                ..base.clone()
+            };
+            let result = std::panic::catch_unwind(|| {
+                find_end_at_every_start(&data, &options);
+                recover(&mut Cursor::new(data.clone()), &options);
+                carve::carve_at(&mut Cursor::new(data.clone()), 0, &options);
+            });
+
+            // 3. verify
+            assert!(
+                result.is_ok(),
+                "FUZZ_SEED={} の {} 回目でパニックしました: {:02x?}",
+                seed,
+                iteration,
+                data
+            );
+        }
+    }
+}
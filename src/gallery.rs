@@ -0,0 +1,106 @@
+// --html-report: 復旧したファイルをブラウザで見渡すための index.html
+// 縮小画像は作らず、ブラウザが表示できる形式はそのまま小さく並べる
+use crate::manifest::ManifestEntry;
+
+pub const GALLERY_FILE_NAME: &str = "index.html";
+
+// ブラウザが <img> で表示できる拡張子（RAW や ZIP はリンクだけにする）
+const VIEWABLE_EXTENSIONS: [&str; 5] = ["jpg", "png", "gif", "bmp", "webp"];
+
+const HEADER: &str = r#"<!DOCTYPE html>
+<html lang="ja">
+<head>
+<meta charset="utf-8">
+<title>復旧したファイル</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+.grid { display: flex; flex-wrap: wrap; gap: 12px; }
+.item { width: 180px; font-size: 12px; word-break: break-all; }
+.item img { width: 180px; height: 135px; object-fit: contain; background: #eee; }
+.item .placeholder { width: 180px; height: 135px; display: flex; align-items: center; justify-content: center; background: #ddd; }
+</style>
+</head>
+<body>
+"#;
+
+pub fn to_html(entries: &[ManifestEntry]) -> String {
+    let mut html = String::from(HEADER);
+    html.push_str(&format!(
+        "<h1>復旧したファイル: {} 個</h1>\n<div class=\"grid\">\n",
+        entries.len()
+    ));
+    for entry in entries {
+        let href = html_escape(&entry.filename);
+        let viewable = entry
+            .filename
+            .rsplit_once('.')
+            .is_some_and(|(_, extension)| VIEWABLE_EXTENSIONS.contains(&extension));
+        let preview = if viewable {
+            format!("<img src=\"{}\" loading=\"lazy\" alt=\"\">", href)
+        } else {
+            format!(
+                "<span class=\"placeholder\">{}</span>",
+                entry.file_type.name()
+            )
+        };
+        html.push_str(&format!(
+            "<div class=\"item\"><a href=\"{}\">{}</a><br>{}<br>{} バイト / オフセット {}</div>\n",
+            href, preview, href, entry.size, entry.offset
+        ));
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::FileType;
+
+    fn entry(index: usize, file_type: FileType, filename: &str) -> ManifestEntry {
+        ManifestEntry {
+            index,
+            file_type,
+            offset: index as u64 * 4096,
+            size: 1024,
+            sha256: String::new(),
+            confidence: 1.0,
+            filename: filename.to_string(),
+            fragmented: false,
+            gps: None,
+            decode_failure: None,
+            stored_size: None,
+        }
+    }
+
+    // ---------------------------
+    // Tests for to_html
+    // ---------------------------
+
+    #[test]
+    fn test_should_show_images_inline_and_link_raw_files_when_types_are_mixed() {
+        // 1. setup
+        let entries = vec![
+            entry(0, FileType::Jpeg, "image_000000.jpg"),
+            entry(1, FileType::Rw2, "image_000001.rw2"),
+        ];
+
+        // 2. execute
+        let html = to_html(&entries);
+
+        // 3. verify
+        assert!(html.contains("<img src=\"image_000000.jpg\""));
+        assert!(html.contains("<a href=\"image_000001.rw2\">"));
+        assert!(!html.contains("<img src=\"image_000001.rw2\""));
+        assert!(html.contains("1024 バイト / オフセット 4096"));
+    }
+}
@@ -0,0 +1,337 @@
+// --compress: 切り出したファイルを gzip（RFC 1952）にして保存する
+// 外部のクレートは使わないので、LZ77 と固定ハフマン符号だけの簡単な deflate（RFC 1951）で圧縮する
+use crate::FileType;
+use crate::cli::Options;
+use crate::crc32;
+
+pub const EXTENSION_SUFFIX: &str = ".gz";
+
+// すでに圧縮されていて、gzip にしてもほとんど小さくならない拡張子
+const COMPRESSED_EXTENSIONS: [&str; 14] = [
+    "jpg", "mpo", "png", "gif", "webp", "zip", "docx", "xlsx", "pptx", "pdf", "mp4", "mov", "heic",
+    "cr3",
+];
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// 一致を探して前の位置をたどる回数（速さと圧縮率の釣り合い）
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// 保存する拡張子（file_extension で決めたもの）から、圧縮して保存するかを決める
+pub fn should_compress(options: &Options, file_type: FileType, extension: &str) -> bool {
+    options.compress
+        && (options.compress_all
+            || !(COMPRESSED_EXTENSIONS.contains(&extension) || file_type == FileType::Jpeg))
+}
+
+// compress の結果がなりうる最大の長さ（ヘッダーとトレーラー、BTYPE と終わりの記号を含む）
+// 固定ハフマンではリテラル1つが最大9ビットで、3バイト以上の一致はそれより短くなる
+pub fn max_compressed_len(len: u64) -> u64 {
+    18 + (len.saturating_mul(9) + 17) / 8
+}
+
+// compress がデータの長さによらずに使う、一致を探すための表の大きさ
+pub const WORKING_MEMORY: u64 =
+    (((1 << HASH_BITS) + WINDOW_SIZE) * std::mem::size_of::<usize>()) as u64;
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // ID1 ID2, deflate, フラグなし, 更新時刻なし, 追加フラグなし, OS 不明
+    let mut out = vec![0x1F, 0x8B, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0xFF];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32::crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+// ビットは下位から詰める（ハフマン符号だけは上位ビットから）
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, len: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += len;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+// 固定ハフマン符号のリテラル・長さの記号
+fn write_symbol(writer: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, len: usize, distance: usize) {
+    let code = LENGTH_BASE.partition_point(|&base| base as usize <= len) - 1;
+    write_symbol(writer, 257 + code as u16);
+    writer.write(
+        (len - LENGTH_BASE[code] as usize) as u32,
+        LENGTH_EXTRA[code] as u32,
+    );
+    let code = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    writer.write_code(code as u32, 5);
+    writer.write(
+        (distance - DISTANCE_BASE[code] as usize) as u32,
+        DISTANCE_EXTRA[code] as u32,
+    );
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value = u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], 0]);
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+// 全体を1つの固定ハフマンブロックにする
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: Vec::new(),
+        bits: 0,
+        count: 0,
+    };
+    // BFINAL = 1, BTYPE = 01（固定ハフマン）
+    writer.write(0b011, 3);
+
+    // head は同じハッシュの最後の位置 + 1（0 はまだない）、prev はその1つ前
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut prev = vec![0usize; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, distance) = longest_match(data, pos, &head, &prev);
+        if len >= MIN_MATCH {
+            write_match(&mut writer, len, distance);
+            for p in pos..pos + len {
+                insert(data, &mut head, &mut prev, p);
+            }
+            pos += len;
+        } else {
+            write_symbol(&mut writer, data[pos] as u16);
+            insert(data, &mut head, &mut prev, pos);
+            pos += 1;
+        }
+    }
+    write_symbol(&mut writer, 256);
+    writer.finish()
+}
+
+fn insert(data: &[u8], head: &mut [usize], prev: &mut [usize], pos: usize) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        prev[pos % WINDOW_SIZE] = head[h];
+        head[h] = pos + 1;
+    }
+}
+
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max_len = (data.len() - pos).min(MAX_MATCH);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(data, pos)];
+    for _ in 0..MAX_CHAIN {
+        let Some(start) = candidate.checked_sub(1) else {
+            break;
+        };
+        if pos - start > WINDOW_SIZE - 1 {
+            break;
+        }
+        let len = data[start..]
+            .iter()
+            .zip(&data[pos..pos + max_len])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best.0 {
+            best = (len, pos - start);
+            if len == max_len {
+                break;
+            }
+        }
+        let next = prev[start % WINDOW_SIZE];
+        // 窓を一周して上書きされた位置には戻らない
+        if next == 0 || next > start {
+            break;
+        }
+        candidate = next;
+    }
+    best
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::{DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            self.pos += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, len: u8) -> usize {
+            (0..len).fold(0, |value, i| value | (self.bit() as usize) << i)
+        }
+
+        fn code(&mut self, len: u32) -> u32 {
+            (0..len).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        fn symbol(&mut self) -> usize {
+            let code = self.code(7);
+            if code <= 0b0010111 {
+                return 256 + code as usize;
+            }
+            let code = code << 1 | self.bit();
+            match code {
+                0x30..=0xBF => (code - 0x30) as usize,
+                0xC0..=0xC7 => (280 + code - 0xC0) as usize,
+                _ => (144 + (code << 1 | self.bit()) - 0x190) as usize,
+            }
+        }
+    }
+
+    // compress が書いた固定ハフマンブロック1つだけの gzip を戻す
+    pub fn decompress(gzip: &[u8]) -> Vec<u8> {
+        assert_eq!(&gzip[..3], &[0x1F, 0x8B, 0x08]);
+        let mut reader = BitReader {
+            data: &gzip[10..gzip.len() - 8],
+            pos: 0,
+        };
+        assert_eq!(reader.bits(3), 0b011);
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            match reader.symbol() {
+                literal @ 0..=255 => out.push(literal as u8),
+                256 => break,
+                symbol => {
+                    let code = symbol - 257;
+                    let len = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code]);
+                    let code = reader.code(5) as usize;
+                    let distance = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code]);
+                    let start = out.len() - distance;
+                    for i in 0..len {
+                        out.push(out[start + i]);
+                    }
+                }
+            }
+        }
+        let trailer = &gzip[gzip.len() - 8..];
+        assert_eq!(trailer[..4], crate::crc32::crc32(&out).to_le_bytes());
+        assert_eq!(trailer[4..], (out.len() as u32).to_le_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::decompress;
+    use super::*;
+
+    // ---------------------------
+    // Tests for compress
+    // ---------------------------
+
+    #[test]
+    fn test_should_round_trip_and_shrink_when_data_repeats() {
+        // 1. setup
+        let mut data: Vec<u8> = (0..=255).collect();
+        for i in 0..2000 {
+            data.extend_from_slice(&[(i % 7) as u8, 0x42, 0x42, (i % 3) as u8]);
+        }
+        data.extend_from_slice(&[0x00; 70_000]);
+
+        // 2. execute
+        let gzip = compress(&data);
+
+        // 3. verify
+        assert!(gzip.len() < data.len() / 10);
+        assert_eq!(decompress(&gzip), data);
+    }
+
+    #[test]
+    fn test_should_round_trip_when_data_is_empty() {
+        // 1. setup
+        let data = [];
+
+        // 2. execute
+        let gzip = compress(&data);
+
+        // 3. verify
+        assert_eq!(decompress(&gzip), data);
+        assert_eq!(gzip.len() as u64, max_compressed_len(0));
+    }
+
+    // ---------------------------
+    // Tests for max_compressed_len
+    // ---------------------------
+
+    #[test]
+    fn test_should_not_exceed_max_compressed_len_when_data_does_not_repeat() {
+        // 1. setup
+        // 一致がなく、どのバイトも9ビットのリテラルになる（0x90 以上）
+        let mut state = 0x12345678u32;
+        let data: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                0x90 | (state >> 24) as u8
+            })
+            .collect();
+
+        // 2. execute
+        let gzip = compress(&data);
+
+        // 3. verify
+        assert!(gzip.len() > data.len());
+        assert!(gzip.len() as u64 <= max_compressed_len(data.len() as u64));
+        assert_eq!(decompress(&gzip), data);
+    }
+}
@@ -0,0 +1,308 @@
+// JPEGの終端（EOI）検出
+const MARKER_PREFIX: u8 = 0xFF;
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_EOI: u8 = 0xD9;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_TEM: u8 = 0x01;
+const MARKER_STUFFING: u8 = 0x00;
+const MARKER_RST: std::ops::RangeInclusive<u8> = 0xD0..=0xD7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JpegEndStrategy {
+    // セグメント構造をたどり、スキャンデータの後ろにある本物のEOIで終える
+    Strict,
+    // 次のスタートシグネチャより手前にある最後の FF D9 で終える
+    Last,
+    // 最初に現れた FF D9 で終える
+    First,
+}
+
+impl JpegEndStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strict" => Some(JpegEndStrategy::Strict),
+            "last" => Some(JpegEndStrategy::Last),
+            "first" => Some(JpegEndStrategy::First),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JpegEnd {
+    // SOIからEOI直後までの長さ
+    Found(usize),
+    // バッファが足りず、まだ判断できない
+    Incomplete,
+    // JPEGとして構造が壊れている（偽のスタートシグネチャ）
+    Invalid,
+}
+
+// data は SOI (FF D8) から始まっていること
+// next_start は次のスタートシグネチャの位置（Last戦略でのみ使用、未確定なら None）
+pub fn find_jpeg_end(data: &[u8], strategy: JpegEndStrategy, next_start: Option<usize>) -> JpegEnd {
+    match strategy {
+        JpegEndStrategy::Strict => find_strict_end(data),
+        JpegEndStrategy::Last => match next_start {
+            Some(limit) => find_last_end(&data[..limit.min(data.len())]),
+            None => JpegEnd::Incomplete,
+        },
+        JpegEndStrategy::First => match find_eoi(data, 2) {
+            Some(idx) => JpegEnd::Found(idx + 2),
+            None => JpegEnd::Incomplete,
+        },
+    }
+}
+
+fn find_eoi(data: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(2)
+        .position(|w| w == [MARKER_PREFIX, MARKER_EOI])
+        .map(|idx| from + idx)
+}
+
+fn find_last_end(data: &[u8]) -> JpegEnd {
+    match data.get(2..).and_then(|body| {
+        body.windows(2)
+            .rposition(|w| w == [MARKER_PREFIX, MARKER_EOI])
+    }) {
+        Some(idx) => JpegEnd::Found(2 + idx + 2),
+        None => JpegEnd::Invalid,
+    }
+}
+
+fn find_strict_end(data: &[u8]) -> JpegEnd {
+    let mut pos = 2;
+
+    loop {
+        match data.get(pos) {
+            None => return JpegEnd::Incomplete,
+            Some(&MARKER_PREFIX) => {}
+            Some(_) => return JpegEnd::Invalid,
+        }
+
+        // マーカー前の FF は詰め物として読み飛ばす
+        let mut marker_pos = pos + 1;
+        while data.get(marker_pos) == Some(&MARKER_PREFIX) {
+            marker_pos += 1;
+        }
+        let marker = match data.get(marker_pos) {
+            Some(&m) => m,
+            None => return JpegEnd::Incomplete,
+        };
+        pos = marker_pos + 1;
+
+        match marker {
+            MARKER_EOI => return JpegEnd::Found(pos),
+            MARKER_TEM => continue,
+            m if MARKER_RST.contains(&m) => continue,
+            MARKER_STUFFING | MARKER_SOI => return JpegEnd::Invalid,
+            _ => {
+                let length = match data.get(pos..pos + 2) {
+                    Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+                    None => return JpegEnd::Incomplete,
+                };
+                if length < 2 {
+                    return JpegEnd::Invalid;
+                }
+                pos += length;
+
+                if marker == MARKER_SOS {
+                    match skip_entropy_coded_data(data, pos) {
+                        Some(next_marker) => pos = next_marker,
+                        None => return JpegEnd::Incomplete,
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Strict で見つけた EOI の直後（end）に、FF の詰め物を挟んで FF D9 が続いていれば、その並びの最後の EOI まで伸ばす
+// 後ろが詰め物の FF だけなら含めず、EOI で終える
+pub fn extend_eoi_run(data: &[u8], mut end: usize) -> usize {
+    let mut pos = end;
+    while data.get(pos) == Some(&MARKER_PREFIX) {
+        while data.get(pos) == Some(&MARKER_PREFIX) {
+            pos += 1;
+        }
+        if data.get(pos) != Some(&MARKER_EOI) {
+            break;
+        }
+        pos += 1;
+        end = pos;
+    }
+    end
+}
+
+// SOS より前の SOFn（SOF0〜SOF15 のうち DHT / JPG / DAC を除く）から (幅, 高さ) を読む
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != MARKER_PREFIX {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        let segment = data.get(pos + 2..)?;
+        let length = u16::from_be_bytes([*segment.first()?, *segment.get(1)?]) as usize;
+        match marker {
+            MARKER_SOS | MARKER_EOI => return None,
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                // 長さ, 精度, 高さ, 幅
+                let height = u16::from_be_bytes([*segment.get(3)?, *segment.get(4)?]);
+                let width = u16::from_be_bytes([*segment.get(5)?, *segment.get(6)?]);
+                return (width > 0 && height > 0).then_some((width as u32, height as u32));
+            }
+            _ if length < 2 => return None,
+            _ => pos += 2 + length,
+        }
+    }
+}
+
+// スキャンデータ中の FF 00（バイトスタッフィング）と RSTn は飛ばし、次のマーカー位置を返す
+fn skip_entropy_coded_data(data: &[u8], mut pos: usize) -> Option<usize> {
+    while let (Some(&byte), Some(&next)) = (data.get(pos), data.get(pos + 1)) {
+        if byte == MARKER_PREFIX {
+            if next == MARKER_STUFFING || MARKER_RST.contains(&next) {
+                pos += 2;
+                continue;
+            }
+            return Some(pos);
+        }
+        pos += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // APP1の中身とスキャンデータ後のゴミの両方に FF D9 を含むJPEG
+    // 戻り値: (データ, 本物のEOI直後の位置, 次のスタートシグネチャの位置)
+    fn jpeg_with_multiple_eoi_markers() -> (Vec<u8>, usize, usize) {
+        let mut data = vec![0xFF, 0xD8];
+        // APP1: 長さ6 (長さフィールド2 + 中身4)、中身に FF D9
+        data.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x06, 0x12, 0xFF, 0xD9, 0x34]);
+        // SOS: 長さ3 + スキャンデータ（FF 00 のスタッフィングと RST0 を含む）
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x03, 0x01]);
+        data.extend_from_slice(&[0x55, 0xFF, 0x00, 0x66, 0xFF, 0xD0, 0x77]);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        let true_end = data.len();
+        // EOIの後ろのゴミにも FF D9
+        data.extend_from_slice(&[0x00, 0xAB, 0xFF, 0xD9, 0x00]);
+        let next_start = data.len();
+        data.extend_from_slice(&[0x49, 0x49, 0x2A, 0x00]);
+        (data, true_end, next_start)
+    }
+
+    // ---------------------------
+    // Tests for find_jpeg_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_end_at_real_eoi_when_strategy_is_strict() {
+        // 1. setup
+        let (data, true_end, _) = jpeg_with_multiple_eoi_markers();
+
+        // 2. execute
+        let result = find_jpeg_end(&data, JpegEndStrategy::Strict, None);
+
+        // 3. verify
+        assert_eq!(result, JpegEnd::Found(true_end));
+    }
+
+    #[test]
+    fn test_should_end_at_last_eoi_before_next_start_when_strategy_is_last() {
+        // 1. setup
+        let (data, _, next_start) = jpeg_with_multiple_eoi_markers();
+
+        // 2. execute
+        let result = find_jpeg_end(&data, JpegEndStrategy::Last, Some(next_start));
+
+        // 3. verify
+        assert_eq!(result, JpegEnd::Found(next_start - 1));
+    }
+
+    #[test]
+    fn test_should_end_at_first_eoi_when_strategy_is_first() {
+        // 1. setup
+        let (data, _, _) = jpeg_with_multiple_eoi_markers();
+
+        // 2. execute
+        let result = find_jpeg_end(&data, JpegEndStrategy::First, None);
+
+        // 3. verify
+        assert_eq!(result, JpegEnd::Found(9)); // APP1の中の FF D9 の直後
+    }
+
+    #[test]
+    fn test_should_return_incomplete_when_strict_walk_runs_out_of_data() {
+        // 1. setup
+        let (data, true_end, _) = jpeg_with_multiple_eoi_markers();
+
+        // 2. execute
+        let result = find_jpeg_end(&data[..true_end - 1], JpegEndStrategy::Strict, None);
+
+        // 3. verify
+        assert_eq!(result, JpegEnd::Incomplete);
+    }
+
+    #[test]
+    fn test_should_read_width_and_height_when_sof0_follows_app_segments() {
+        // 1. setup
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        // SOF0: 長さ 11, 精度 8, 高さ 4000, 幅 6000, 成分 1
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x0F, 0xA0, 0x17, 0x70, 0x01]);
+        data.extend_from_slice(&[0x01, 0x11, 0x00]);
+
+        // 2. execute
+        let result = dimensions(&data);
+
+        // 3. verify
+        assert_eq!(result, Some((6000, 4000)));
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_segment_structure_is_broken() {
+        // 1. setup
+        let data = [0xFF, 0xD8, 0x12, 0x34, 0xFF, 0xD9];
+
+        // 2. execute
+        let result = find_jpeg_end(&data, JpegEndStrategy::Strict, None);
+
+        // 3. verify
+        assert_eq!(result, JpegEnd::Invalid);
+    }
+
+    // ---------------------------
+    // Tests for extend_eoi_run
+    // ---------------------------
+
+    #[test]
+    fn test_should_stop_at_eoi_when_only_ff_padding_follows() {
+        // 1. setup
+        let data = [0xFF, 0xD8, 0xFF, 0xD9, 0xFF, 0xFF, 0xFF, 0xD8];
+
+        // 2. execute
+        let result = extend_eoi_run(&data, 4);
+
+        // 3. verify
+        assert_eq!(result, 4);
+    }
+
+    #[test]
+    fn test_should_end_at_last_eoi_when_eois_repeat_across_padding() {
+        // 1. setup
+        let data = [
+            0xFF, 0xD8, 0xFF, 0xD9, 0xFF, 0xD9, 0xFF, 0xFF, 0xD9, 0xFF, 0x00,
+        ];
+
+        // 2. execute
+        let result = extend_eoi_run(&data, 4);
+
+        // 3. verify
+        assert_eq!(result, 9);
+    }
+}
@@ -0,0 +1,225 @@
+// list-recovered: 出力先の manifest.json を読み、スキャンし直さずに絞り込み・並べ替えて一覧にする
+use std::cmp::Reverse;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::FileType;
+use crate::cli::{Options, parse_size};
+use crate::exif::{self, CaptureDate};
+use crate::gzip;
+use crate::manifest::{self, ManifestEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Type(FileType),
+    MinSize(u64),
+    MinConfidence(f64),
+}
+
+impl Filter {
+    // "type=jpeg" / "min-size=1M" / "min-confidence=0.5"
+    pub fn parse(value: &str) -> Option<Self> {
+        let (key, value) = value.split_once('=')?;
+        match key {
+            "type" => FileType::parse(value).map(Filter::Type),
+            "min-size" => parse_size(value).map(Filter::MinSize),
+            "min-confidence" => value.parse().ok().map(Filter::MinConfidence),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, entry: &ManifestEntry) -> bool {
+        match *self {
+            Filter::Type(file_type) => entry.file_type == file_type,
+            Filter::MinSize(size) => entry.size >= size,
+            Filter::MinConfidence(confidence) => entry.confidence >= confidence,
+        }
+    }
+}
+
+// オフセット・種類・撮影日は小さい順、サイズと確からしさは大きい順
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Offset,
+    Type,
+    Size,
+    Confidence,
+    Date,
+}
+
+impl SortKey {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "offset" => Some(SortKey::Offset),
+            "type" => Some(SortKey::Type),
+            "size" => Some(SortKey::Size),
+            "confidence" => Some(SortKey::Confidence),
+            "date" => Some(SortKey::Date),
+            _ => None,
+        }
+    }
+}
+
+// 撮影日は manifest.json にないので、保存したファイルの EXIF から読む（gzip にしたものは読まない）
+fn capture_date(output_dir: &Path, entry: &ManifestEntry) -> Option<CaptureDate> {
+    if entry.filename.ends_with(gzip::EXTENSION_SUFFIX) {
+        return None;
+    }
+    let data = fs::read(output_dir.join(&entry.filename)).ok()?;
+    exif::capture_date(entry.file_type, &data)
+}
+
+// すべての条件に合うものを、sort が None なら manifest.json の順のまま返す
+pub fn select(
+    entries: Vec<ManifestEntry>,
+    filters: &[Filter],
+    sort: Option<SortKey>,
+    output_dir: &Path,
+) -> Vec<ManifestEntry> {
+    let mut selected: Vec<ManifestEntry> = entries
+        .into_iter()
+        .filter(|entry| filters.iter().all(|filter| filter.matches(entry)))
+        .collect();
+    match sort {
+        Some(SortKey::Offset) => selected.sort_by_key(|entry| entry.offset),
+        Some(SortKey::Type) => selected.sort_by_key(|entry| entry.file_type.name()),
+        Some(SortKey::Size) => selected.sort_by_key(|entry| Reverse(entry.size)),
+        Some(SortKey::Confidence) => selected.sort_by(|a, b| b.confidence.total_cmp(&a.confidence)),
+        // 撮影日の分からないものは最後に回す
+        Some(SortKey::Date) => {
+            selected.sort_by_cached_key(|entry| {
+                let date = capture_date(output_dir, entry);
+                (date.is_none(), date)
+            });
+        }
+        None => {}
+    }
+    selected
+}
+
+pub fn write_listing<W: Write>(out: &mut W, entries: &[ManifestEntry]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<6}  {:>14}  {:>12}  {:>10}  ファイル名",
+        "種類", "オフセット", "サイズ", "確からしさ"
+    )?;
+    for entry in entries {
+        writeln!(
+            out,
+            "{:<6}  {:>#14x}  {:>12}  {:>10.2}  {}",
+            entry.file_type.name(),
+            entry.offset,
+            entry.size,
+            entry.confidence,
+            entry.filename
+        )?;
+    }
+    writeln!(out, "{} 件", entries.len())
+}
+
+// manifest.json を読めなければ、その理由を Err で返す
+pub fn run<W: Write>(out: &mut W, options: &Options) -> Result<(), String> {
+    let path = options.output_dir.join(manifest::MANIFEST_FILE_NAME);
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("{} を読めません: {}", path.display(), e))?;
+    let entries = manifest::from_json(&text)
+        .ok_or_else(|| format!("{} の形式が正しくありません", path.display()))?;
+    let selected = select(
+        entries,
+        &options.list_filters,
+        options.list_sort,
+        &options.output_dir,
+    );
+    write_listing(out, &selected).map_err(|e| format!("出力エラー: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::temp_output_dir;
+
+    fn entry(index: usize, file_type: FileType, size: u64, filename: &str) -> ManifestEntry {
+        ManifestEntry {
+            index,
+            file_type,
+            offset: 512 * index as u64,
+            size,
+            sha256: "ab".repeat(32),
+            confidence: 1.0,
+            filename: filename.to_string(),
+            fragmented: false,
+            gps: None,
+            decode_failure: None,
+            stored_size: None,
+        }
+    }
+
+    // ---------------------------
+    // Tests for run
+    // ---------------------------
+
+    #[test]
+    fn test_should_list_only_matching_type_by_size_when_filter_and_sort_are_given() {
+        // 1. setup
+        let output_dir = temp_output_dir("list_recovered");
+        let entries = vec![
+            entry(0, FileType::Jpeg, 100, "image_000000.jpg"),
+            entry(1, FileType::Rw2, 5000, "image_000001.rw2"),
+            entry(2, FileType::Jpeg, 300, "image_000002.jpg"),
+        ];
+        fs::write(
+            output_dir.join(manifest::MANIFEST_FILE_NAME),
+            manifest::to_json(&entries),
+        )
+        .unwrap();
+        let options = Options {
+            output_dir,
+            list_filters: vec![Filter::parse("type=jpeg").unwrap()],
+            list_sort: SortKey::parse("size"),
+            ..Options::default()
+        };
+        let mut out = Vec::new();
+
+        // 2. execute
+        run(&mut out, &options).unwrap();
+
+        // 3. verify
+        let text = String::from_utf8(out).unwrap();
+        let names: Vec<&str> = text
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.ends_with(" 件"))
+            .filter_map(|line| line.split_whitespace().last())
+            .collect();
+        assert_eq!(names, vec!["image_000002.jpg", "image_000000.jpg"]);
+        assert!(text.ends_with("2 件\n"));
+    }
+
+    #[test]
+    fn test_should_put_undated_files_last_when_sorted_by_date() {
+        // 1. setup
+        let output_dir = temp_output_dir("list_recovered_date");
+        let entries = vec![
+            entry(0, FileType::Jpeg, 1, "image_000000.jpg"),
+            entry(1, FileType::Jpeg, 1, "image_000001.jpg"),
+            entry(2, FileType::Jpeg, 1, "image_000002.jpg"),
+        ];
+        fs::write(output_dir.join("image_000000.jpg"), [0xFF, 0xD8]).unwrap();
+        for (name, date) in [
+            ("image_000001.jpg", "2024:05:01 10:00:00"),
+            ("image_000002.jpg", "2021:12:31 23:59:59"),
+        ] {
+            let jpeg = exif::test_fixtures::jpeg_with_capture_date(date, &[0x01]);
+            fs::write(output_dir.join(name), jpeg).unwrap();
+        }
+
+        // 2. execute
+        let selected = select(entries, &[], Some(SortKey::Date), &output_dir);
+
+        // 3. verify
+        let indexes: Vec<usize> = selected.iter().map(|entry| entry.index).collect();
+        assert_eq!(indexes, vec![2, 1, 0]);
+    }
+}
@@ -1,241 +1,5483 @@
+mod bmff;
+mod bmp;
+mod carve;
+mod cli;
+mod color;
+mod confirm;
+mod console;
+mod crc32;
+mod custom;
+mod declared_size;
+mod decode;
+mod device_info;
+mod dicom;
+mod disk_space;
+mod entropy;
+mod eta;
+mod exif;
+mod fat;
+mod footer;
+mod fragment;
+#[cfg(test)]
+mod fuzz;
+mod gallery;
+mod gzip;
+mod jpeg;
+mod listing;
+mod manifest;
+mod mounts;
+mod mpf;
+mod output;
+mod partition;
+mod preview;
+mod progress;
+mod psd;
+mod raf;
+mod reconcile;
+mod run_log;
+mod sector_map;
+mod selftest;
+mod sha256;
+mod slack;
+mod sniff;
+mod source;
+mod status_server;
+mod strategy;
+#[cfg(test)]
+mod test_util;
+mod tga;
+mod tiff;
+mod trim;
+mod unknown;
+mod webp;
+mod worklist;
+mod x3f;
+mod xmp;
+mod zero_fill;
+mod zip;
+
+use std::collections::HashMap;
 use std::fs::{File, create_dir_all};
-use std::io::{Read, Write};
-use std::path::Path;
-use std::time::Instant;
+use std::io::{self, Cursor, IsTerminal, Read, Seek};
+use std::iter::StepBy;
+use std::ops::{ControlFlow, Range};
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use carve::CarveEnd;
+use cli::Options;
+use jpeg::JpegEndStrategy;
+use manifest::{ManifestEntry, OffsetBase, ReportFormat};
+use output::{OutputPattern, Sink};
+use run_log::log_eprintln;
+use source::{Advice, Source};
+use strategy::ScanStrategy;
+use zero_fill::ZeroFillImage;
+
+const DEVICE_PATH: &str = "/dev/mmcblk0";
+const OUTPUT_DIR: &str = "recovered";
+const JPEG_START: &[u8] = &[0xFF, 0xD8];
+const RW2_START: &[u8] = &[0x49, 0x49, 0x2A, 0x00];
+const ZIP_START: &[u8] = &[0x50, 0x4B, 0x03, 0x04];
+const BMP_START: &[u8] = &[0x42, 0x4D];
+const WEBP_START: &[u8] = b"RIFF";
+// 旧 Canon RAW（CIFF）: II + ヘッダ長 0x1A + "HEAPCCDR"
+const CRW_START: &[u8] = &[
+    0x49, 0x49, 0x1A, 0x00, 0x00, 0x00, 0x48, 0x45, 0x41, 0x50, 0x43, 0x43, 0x44, 0x52,
+];
+// Olympus RAW: TIFF と同じ IFD の並びで、"II" の後ろが 0x2A ではなく "RO"（古い機種は "RS"）
+const ORF_START: &[u8] = b"IIRO";
+const ORF_RS_START: &[u8] = b"IIRS";
+// ビッグエンディアンのTIFF（リトルエンディアンは RW2_START と同じ）
+const TIFF_BE_START: &[u8] = &[0x4D, 0x4D, 0x00, 0x2A];
+// --block-size を指定しないときの、デバイスから1回に読むバイト数
+const READ_BLOCK_SIZE: usize = 512 * 1024;
+// 動画は数GBになるので、ボックスの長さを 4GiB まで信じる
+const MP4_END_SEARCH_LIMIT: u64 = 4 << 30;
+// CRW は10MB前後で、次のシグネチャを探し続けても無駄になる範囲が大きい
+const CRW_END_SEARCH_LIMIT: u64 = 64 << 20;
+// TGA はフッターからこの範囲だけ戻って先頭を探す
+const TGA_END_SEARCH_LIMIT: u64 = 64 << 20;
+// XMP のサイドカーは数十KBで、終わりの印はこの範囲までしか探さない
+const XMP_END_SEARCH_LIMIT: u64 = 4 << 20;
+// 終端を待つ候補のためにバッファに溜めるのは、その種類の上限サイズのこの倍まで
+const BUFFER_CAP_FACTOR: u64 = 2;
+// --fadvise で、読み終えた範囲をページキャッシュから落とすのはこのブロック数ごと
+const FADVISE_BLOCKS: u64 = 64;
+
+// スタートシグネチャ。magic はファイル先頭から offset バイト目にある（ISO-BMFF の ftyp は4バイト目）
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Signature {
+    magic: &'static [u8],
+    offset: usize,
+    // 終端を探す（申告サイズを信じる）範囲の、この形式での上限。None なら --max-file-size
+    end_search_limit: Option<u64>,
+}
+
+impl Signature {
+    // ファイル先頭から magic の終わりまでの長さ
+    fn len(&self) -> usize {
+        self.offset + self.magic.len()
+    }
+
+    // data がファイルの先頭だとしたときに一致するか
+    fn matches(&self, data: &[u8]) -> bool {
+        data.get(self.offset..self.len()) == Some(self.magic)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileType {
+    Jpeg,
+    Rw2,
+    Zip,
+    Bmp,
+    Webp,
+    Dng,
+    Mp4,
+    Crw,
+    Tga,
+    Cr3,
+    Orf,
+    Raf,
+    Psd,
+    Nrw,
+    X3f,
+    Tiff,
+    Xmp,
+    Dicom,
+    // --add-signature で足した種類（ALL には入らない）
+    Custom(&'static custom::CustomSignature),
+}
+
+impl FileType {
+    const ALL: [FileType; 18] = [
+        FileType::Jpeg,
+        FileType::Rw2,
+        FileType::Zip,
+        FileType::Bmp,
+        FileType::Webp,
+        FileType::Dng,
+        FileType::Mp4,
+        FileType::Crw,
+        FileType::Tga,
+        FileType::Cr3,
+        FileType::Orf,
+        FileType::Raf,
+        FileType::Psd,
+        FileType::Nrw,
+        FileType::X3f,
+        FileType::Tiff,
+        FileType::Xmp,
+        FileType::Dicom,
+    ];
+
+    fn parse(name: &str) -> Option<FileType> {
+        match name {
+            "jpg" => Some(FileType::Jpeg),
+            _ => FileType::ALL.into_iter().find(|t| t.name() == name),
+        }
+    }
+
+    // 同じ種類のシグネチャはすべて同じ長さ
+    fn start_signatures(&self) -> &'static [Signature] {
+        match self {
+            FileType::Jpeg => &[Signature {
+                magic: JPEG_START,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::Rw2 => &[Signature {
+                magic: RW2_START,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::Zip => &[Signature {
+                magic: ZIP_START,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::Bmp => &[Signature {
+                magic: BMP_START,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::Webp => &[Signature {
+                magic: WEBP_START,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::Dng => &[
+                Signature {
+                    magic: RW2_START,
+                    offset: 0,
+                    end_search_limit: None,
+                },
+                Signature {
+                    magic: TIFF_BE_START,
+                    offset: 0,
+                    end_search_limit: None,
+                },
+            ],
+            // ftyp の手前はボックスの長さ欄
+            FileType::Mp4 => &[Signature {
+                magic: bmff::FTYP,
+                offset: 4,
+                end_search_limit: Some(MP4_END_SEARCH_LIMIT),
+            }],
+            FileType::Crw => &[Signature {
+                magic: CRW_START,
+                offset: 0,
+                end_search_limit: Some(CRW_END_SEARCH_LIMIT),
+            }],
+            // 先頭にシグネチャがないので、end_signatures のフッターから探す
+            FileType::Tga => &[],
+            // MP4 と同じ ftyp で、メジャーブランドだけが違う
+            FileType::Cr3 => &[Signature {
+                magic: bmff::FTYP,
+                offset: 4,
+                end_search_limit: Some(MP4_END_SEARCH_LIMIT),
+            }],
+            FileType::Raf => &[Signature {
+                magic: raf::MAGIC,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::Psd => &[Signature {
+                magic: psd::MAGIC,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            // RW2 と同じリトルエンディアンの TIFF で、IFD0 の Make で分ける
+            FileType::Nrw => &[Signature {
+                magic: RW2_START,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            FileType::X3f => &[Signature {
+                magic: x3f::MAGIC,
+                offset: 0,
+                end_search_limit: None,
+            }],
+            // RAW 以外の TIFF。先頭は RW2 や DNG と同じなので claims で分ける
+            FileType::Tiff => &[
+                Signature {
+                    magic: RW2_START,
+                    offset: 0,
+                    end_search_limit: None,
+                },
+                Signature {
+                    magic: TIFF_BE_START,
+                    offset: 0,
+                    end_search_limit: None,
+                },
+            ],
+            // パケットの開始の処理命令か、パケットで包まれていない xmpmeta の開始タグ
+            FileType::Xmp => &[
+                Signature {
+                    magic: xmp::PACKET_START,
+                    offset: 0,
+                    end_search_limit: Some(XMP_END_SEARCH_LIMIT),
+                },
+                Signature {
+                    magic: xmp::META_START,
+                    offset: 0,
+                    end_search_limit: Some(XMP_END_SEARCH_LIMIT),
+                },
+            ],
+            // 先頭の 128 バイトはプリアンブル
+            FileType::Dicom => &[Signature {
+                magic: dicom::MAGIC,
+                offset: dicom::PREAMBLE_LEN,
+                end_search_limit: None,
+            }],
+            FileType::Custom(signature) => &signature.start,
+            FileType::Orf => &[
+                Signature {
+                    magic: ORF_START,
+                    offset: 0,
+                    end_search_limit: None,
+                },
+                Signature {
+                    magic: ORF_RS_START,
+                    offset: 0,
+                    end_search_limit: None,
+                },
+            ],
+        }
+    }
+
+    // ファイルの終わりを示すフッター。offset はフッターの先頭から magic までの長さ、
+    // end_search_limit はフッターから先頭を探して戻る範囲
+    fn end_signatures(&self) -> &'static [Signature] {
+        match self {
+            FileType::Tga => &[Signature {
+                magic: tga::FOOTER_MAGIC,
+                offset: tga::FOOTER_MAGIC_OFFSET,
+                end_search_limit: Some(TGA_END_SEARCH_LIMIT),
+            }],
+            _ => &[],
+        }
+    }
+
+    // 先頭のシグネチャを持たない種類は 0
+    fn signature_len(&self) -> usize {
+        self.start_signatures().first().map_or(0, Signature::len)
+    }
+
+    fn end_search_limit(&self) -> Option<u64> {
+        self.start_signatures()
+            .iter()
+            .chain(self.end_signatures())
+            .next()?
+            .end_search_limit
+    }
+
+    // スタートシグネチャが一致した data をこの種類として扱うか
+    // TIFFベースの RW2 と DNG と NRW は先頭が同じなので、IFD0 の DNGVersion タグと Make で分ける
+    // （NRW から変換した DNG も Make は NIKON のままなので、DNGVersion を先に見る）
+    // RAW でない TIFF は、ビッグエンディアンか、IFD0 が複数ページの 1 ページ目（スキャナの出力）のもの
+    // IFD0 がまだバッファにない場合は RW2 として扱い、終端検出が追加の読み込みを待つ間に判別し直す
+    fn claims(&self, data: &[u8]) -> bool {
+        match self {
+            FileType::Rw2 => {
+                tiff::has_dng_version(data) != Ok(true)
+                    && tiff::is_nikon(data) != Ok(true)
+                    && tiff::is_multi_page(data) != Ok(true)
+            }
+            FileType::Nrw => {
+                tiff::has_dng_version(data) != Ok(true) && tiff::is_nikon(data) == Ok(true)
+            }
+            FileType::Dng => match tiff::has_dng_version(data) {
+                Ok(is_dng) => is_dng,
+                // ビッグエンディアンは RW2 が拾わないので、判別できるまで DNG の候補として残す
+                Err(tiff::ParseError::NeedMoreData) => data.starts_with(TIFF_BE_START),
+                Err(tiff::ParseError::Malformed) => false,
+            },
+            FileType::Tiff => {
+                tiff::has_dng_version(data) == Ok(false)
+                    && tiff::is_nikon(data) == Ok(false)
+                    && (data.starts_with(TIFF_BE_START) || tiff::is_multi_page(data) == Ok(true))
+            }
+            FileType::Mp4 => bmff::plausible_ftyp(data) && !bmff::is_cr3(data),
+            // パケットの終わりの処理命令も "<?xpacket " で始まる
+            FileType::Xmp => !data.starts_with(xmp::PACKET_END),
+            FileType::Cr3 => bmff::plausible_ftyp(data) && bmff::is_cr3(data),
+            _ => true,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            FileType::Jpeg => "jpeg",
+            FileType::Rw2 => "rw2",
+            FileType::Zip => "zip",
+            FileType::Bmp => "bmp",
+            FileType::Webp => "webp",
+            FileType::Dng => "dng",
+            FileType::Mp4 => "mp4",
+            FileType::Crw => "crw",
+            FileType::Tga => "tga",
+            FileType::Cr3 => "cr3",
+            FileType::Orf => "orf",
+            FileType::Raf => "raf",
+            FileType::Psd => "psd",
+            FileType::Nrw => "nrw",
+            FileType::X3f => "x3f",
+            FileType::Tiff => "tiff",
+            FileType::Xmp => "xmp",
+            FileType::Dicom => "dicom",
+            FileType::Custom(signature) => signature.name,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            FileType::Jpeg => "jpg",
+            FileType::Rw2 => "rw2",
+            FileType::Zip => "zip",
+            FileType::Bmp => "bmp",
+            FileType::Webp => "webp",
+            FileType::Dng => "dng",
+            FileType::Mp4 => "mp4",
+            FileType::Crw => "crw",
+            FileType::Tga => "tga",
+            FileType::Cr3 => "cr3",
+            FileType::Orf => "orf",
+            FileType::Raf => "raf",
+            FileType::Psd => "psd",
+            FileType::Nrw => "nrw",
+            FileType::X3f => "x3f",
+            FileType::Tiff => "tif",
+            FileType::Xmp => "xmp",
+            FileType::Dicom => "dcm",
+            FileType::Custom(signature) => signature.extension,
+        }
+    }
+}
+
+// デバイス先頭からの絶対オフセットで表した、ファイルとして切り出した領域
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Debug, Default)]
+struct Summary {
+    counter: usize,
+    scanned_bytes: u64,
+    written_bytes: u64,
+    // スパースファイルの穴として読まずに飛ばしたバイト数
+    skipped_hole_bytes: u64,
+    // スタートシグネチャが多すぎてノイズとみなしたブロック数
+    noise_blocks: usize,
+    // --resume で前回の実行から引き継いだファイル数
+    resumed_files: usize,
+    // 読み込み用のバッファが最も大きくなったときのバイト数
+    peak_buffer_bytes: usize,
+    // 読み込み用のバッファの容量が足りず確保し直した回数
+    buffer_allocations: usize,
+    // 切り出しのループで終端検出を呼んだ回数
+    end_checks: usize,
+    // スキャンする範囲のバイト数（分からなければ None）
+    total_bytes: Option<u64>,
+    throughput: eta::Throughput,
+    // --flatten-duplicates-to-hardlinks 用の、SHA-256 から最初に保存したファイル名
+    first_by_hash: HashMap<String, String>,
+    regions: Vec<Region>,
+    // 終端が見つからなかったり成り立たなかったりして読み飛ばした候補（--rescan-failed で見直す）
+    failed_starts: Vec<(u64, FileType)>,
+    // --only-deleted で参照する、ファイルシステムごとのクラスタの使用状況
+    allocation: Vec<fat::Allocation>,
+    // --reassemble-fragments で参照する、チェーンが連続していないファイル
+    fragmented_files: Vec<fat::FragmentedFile>,
+    // --only-deleted で、使用中のクラスタから始まるため保存しなかったファイル数
+    skipped_allocated: usize,
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Default)]
+struct ScanCounts {
+    scanned_bytes: u64,
+    // options.types の順に、見つかったスタートシグネチャの数
+    counts: Vec<(FileType, usize)>,
+}
+
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            log_eprintln!("{}", e);
+            return;
+        }
+    };
+    color::init(options.color);
+
+    if options.list_recovered {
+        if let Err(e) = listing::run(&mut io::stdout(), &options) {
+            log_eprintln!("{}", color::error(format_args!("{}", e)));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if options.selftest {
+        match selftest::run(&mut io::stdout()) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                log_eprintln!("{}", color::error(format_args!("出力エラー: {}", e)));
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let offsets = match &options.worklist {
+        Some(path) => match worklist::load(path) {
+            Ok(list) => {
+                for (line_number, line) in &list.invalid {
+                    log_eprintln!(
+                        "{}",
+                        color::skipped(format_args!(
+                            "{} の {} 行目はオフセットとして解釈できないため読み飛ばします: {}",
+                            path.display(),
+                            line_number,
+                            line
+                        ))
+                    );
+                }
+                Some(list.offsets)
+            }
+            Err(e) => {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!("オフセット一覧を読み込めませんでした: {}", e))
+                );
+                return;
+            }
+        },
+        None => options.carve_at.map(|offset| vec![offset]),
+    };
+
+    if options.device_info {
+        print_device_info(&options);
+        return;
+    }
+
+    // 書き込み中のカードを読むと、途中までしかないファイルが切り出されやすい
+    let mounted = mounts::find_mounted(&options.device_path);
+    if !mounted.is_empty() {
+        for (source, target) in &mounted {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: {} が {} にマウントされています",
+                    source, target
+                ))
+            );
+        }
+        if !options.force {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "アンマウントしてから実行してください（続行する場合は --force を指定してください）"
+                ))
+            );
+            return;
+        }
+    }
+
+    // 保存先ディレクトリは最初に書き込むときに作る。作らない指定なら、保存する実行では先に確かめる
+    if options.no_create_output
+        && !options.dry_run_scan_only
+        && options.preview.is_none()
+        && options.archive.is_none()
+        && !options.output_dir.is_dir()
+    {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "保存先ディレクトリ {} がありません（--no-create-output のため作成しません）",
+                options.output_dir.display()
+            ))
+        );
+        return;
+    }
+
+    let start_time = Instant::now();
+
+    let Some(mut file) = open_device(&options) else {
+        return;
+    };
+    if !confirm_device(&mut file, &options) {
+        return;
+    }
+    if !options.dry_run_scan_only && options.preview.is_none() && options.archive.is_none() {
+        match run_log::open(&options.output_dir, SystemTime::now()) {
+            Ok(path) => println!("ログ: {}", path.display()),
+            Err(e) => log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: ログファイルを作れませんでした（標準エラーにだけ出します）: {}",
+                    e
+                ))
+            ),
+        }
+    }
+
+    if options.partition_scan {
+        let sector_size = device_info::sector_size(&file);
+        let mut reader = reconnecting(file, &options);
+        for (part, summary) in recover_partitions(&mut reader, sector_size, &options) {
+            println!("\nパーティション {} ({}):", part.index, part.label);
+            if let Err(e) =
+                console::write_summary(&mut io::stdout(), &options, &summary, start_time.elapsed())
+            {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!("集計の表示に失敗しました: {}", e))
+                );
+            }
+        }
+        return;
+    }
+
+    match options.partition {
+        Some(index) => {
+            let Some(part) = find_partition(&mut file, index) else {
+                return;
+            };
+            let options = Options {
+                partition_start: part.start,
+                ..options
+            };
+            match source::Limited::new(reconnecting(file, &options), part.start, part.len) {
+                Ok(mut reader) => run(&mut reader, &options, offsets.as_deref(), start_time),
+                Err(e) => log_eprintln!(
+                    "{}",
+                    color::error(format_args!(
+                        "パーティションの先頭に移動できませんでした: {}",
+                        e
+                    ))
+                ),
+            }
+        }
+        None => match strategy::resolve(options.strategy, &file, options.max_memory) {
+            Ok(ScanStrategy::Load) => {
+                println!("デバイス全体をメモリに読み込んでいます...");
+                match strategy::load(&mut file) {
+                    Ok(data) => run(
+                        &mut Cursor::new(data),
+                        &options,
+                        offsets.as_deref(),
+                        start_time,
+                    ),
+                    Err(e) => {
+                        log_eprintln!("{}", color::error(format_args!("読み取りエラー: {}", e)))
+                    }
+                }
+            }
+            Ok(_) => run(
+                &mut reconnecting(file, &options),
+                &options,
+                offsets.as_deref(),
+                start_time,
+            ),
+            Err(e) => log_eprintln!("{}", color::error(format_args!("{}", e))),
+        },
+    }
+}
+
+// --skip-start は読み込み範囲（--partition ならそのパーティション）の先頭から数える
+// 切り出すファイルのオフセットはデバイス先頭からのまま
+fn run<R: Source>(reader: &mut R, options: &Options, offsets: Option<&[u64]>, start_time: Instant) {
+    if options.skip_start == 0 {
+        return run_scan(reader, options, offsets, start_time);
+    }
+    match skip_start(reader, options.skip_start) {
+        Ok(mut skipped) => run_scan(&mut skipped, options, offsets, start_time),
+        Err(e) => log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "先頭から {} バイト先に移動できませんでした: {}",
+                options.skip_start, e
+            ))
+        ),
+    }
+}
+
+fn skip_start<R: Source>(reader: &mut R, skip: u64) -> io::Result<source::Limited<&mut R>> {
+    let start = reader.start_offset() + skip;
+    let len = reader
+        .total_len()
+        .map_or(u64::MAX, |len| len.saturating_sub(skip));
+    source::Limited::new(reader, start, len)
+}
+
+fn run_scan<R: Source>(
+    reader: &mut R,
+    options: &Options,
+    offsets: Option<&[u64]>,
+    start_time: Instant,
+) {
+    if let Some(limit) = options.preview {
+        let candidates = preview::first_candidates(reader, options, limit);
+        if let Err(e) = preview::write_table(&mut io::stdout(), &candidates) {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("候補の表示に失敗しました: {}", e))
+            );
+        }
+        return;
+    }
+
+    if options.dry_run_scan_only {
+        let counts = scan_only(reader, options);
+        let duration = start_time.elapsed();
+        println!(
+            "\nスキャン完了: {} バイトを走査しました",
+            counts.scanned_bytes
+        );
+        for (file_type, count) in &counts.counts {
+            println!("{}: {} 個", file_type.name(), count);
+        }
+        let seconds = duration.as_secs_f64();
+        if seconds > 0.0 {
+            println!(
+                "速度: {:.1} MB/s",
+                counts.scanned_bytes as f64 / (1024.0 * 1024.0) / seconds
+            );
+        }
+        println!("実行時間: {:.2?}", duration);
+        return;
+    }
+
+    if offsets.is_none() && refuse_encrypted(reader, "デバイス", options) {
+        return;
+    }
+
+    let summary = match offsets {
+        Some(offsets) => recover_at_offsets(reader, offsets, options),
+        None => recover(reader, options),
+    };
+
+    let duration = start_time.elapsed();
+    if let Err(e) = console::write_summary(&mut io::stdout(), options, &summary, duration) {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("集計の表示に失敗しました: {}", e))
+        );
+    }
+}
+
+// 暗号化されたカードを全体スキャンしても、偶然一致したシグネチャのゴミが大量にできるだけになる
+// target（デバイスやパーティション）全体が乱数のように見えれば、理由を表示して true
+fn refuse_encrypted<R: Source>(reader: &mut R, target: &str, options: &Options) -> bool {
+    if options.skip_encryption_check {
+        return false;
+    }
+    match entropy::looks_encrypted(reader) {
+        Ok(true) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "{}全体が乱数のように見えるため、暗号化（BitLocker / LUKS など）されている可能性があります。復号してから実行してください（続行する場合は --skip-encryption-check を指定してください）",
+                    target
+                ))
+            );
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: 暗号化されているかを確かめられませんでした: {}",
+                    e
+                ))
+            );
+            false
+        }
+    }
+}
+
+// partition_0/ のようなテーブル順（0始まり）のフォルダに、パーティションごとに切り出す
+// 暗号化されているように見えるパーティションは読み飛ばし、ほかのパーティションは続ける
+fn recover_partitions<R: Source + Read + Seek>(
+    reader: &mut R,
+    sector_size: u64,
+    options: &Options,
+) -> Vec<(partition::Partition, Summary)> {
+    let info = match device_info::inspect(reader, sector_size) {
+        Ok(info) => info,
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "パーティションテーブルを読めませんでした: {}",
+                    e
+                ))
+            );
+            return Vec::new();
+        }
+    };
+    let table = match info.layout() {
+        device_info::Layout::SinglePartition(filesystem) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "先頭が {} のブートセクタで、パーティション単体のイメージのようです（--partition-scan は不要です）",
+                    filesystem
+                ))
+            );
+            return Vec::new();
+        }
+        _ => info.table,
+    };
+    if table.partitions().is_empty() {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "パーティションテーブルにパーティションがありません（--partition-scan は不要です）"
+            ))
+        );
+        return Vec::new();
+    }
+
+    let mut results = Vec::new();
+    for (i, part) in table.partitions().iter().enumerate() {
+        let part_options = Options {
+            output_dir: options.output_dir.join(format!("partition_{}", i)),
+            partition_start: part.start,
+            ..options.clone()
+        };
+        // --skip-start はパーティションごとの先頭から数える
+        let skip = options.skip_start.min(part.len);
+        match source::Limited::new(&mut *reader, part.start + skip, part.len - skip) {
+            Ok(mut limited) => {
+                if refuse_encrypted(
+                    &mut limited,
+                    &format!("パーティション {} ", part.index),
+                    options,
+                ) {
+                    continue;
+                }
+                let summary = recover(&mut limited, &part_options);
+                results.push((part.clone(), summary));
+            }
+            Err(e) => log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "パーティション {} の先頭に移動できませんでした: {}",
+                    part.index, e
+                ))
+            ),
+        }
+    }
+    results
+}
+
+// 番号で指定されたパーティションを探す。見つからなければ理由を表示して None
+fn find_partition(file: &mut File, index: usize) -> Option<partition::Partition> {
+    let sector_size = device_info::sector_size(file);
+    match device_info::inspect(file, sector_size) {
+        Ok(info) => select_partition(&info, index),
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "パーティションテーブルを読めませんでした: {}",
+                    e
+                ))
+            );
+            None
+        }
+    }
+}
+
+// パーティション単体のイメージなら、1番はイメージ全体として扱う（テーブルの位置を当てはめると的外れになる）
+fn select_partition(info: &device_info::DeviceInfo, index: usize) -> Option<partition::Partition> {
+    match info.layout() {
+        device_info::Layout::SinglePartition(filesystem) => {
+            if index != 1 {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!(
+                        "先頭が {} のブートセクタで、パーティション単体のイメージのようです。パーティション {} はありません",
+                        filesystem, index
+                    ))
+                );
+                return None;
+            }
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: 先頭が {} のブートセクタで、パーティション単体のイメージのようです。--partition は不要なのでイメージ全体を読みます",
+                    filesystem
+                ))
+            );
+            return Some(partition::Partition {
+                index: 1,
+                start: 0,
+                len: info.size,
+                label: filesystem.to_string(),
+            });
+        }
+        device_info::Layout::Unknown => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "パーティションテーブルが見つかりません（パーティション自体のデバイスを指定している場合は --partition は不要です）"
+                ))
+            );
+            return None;
+        }
+        device_info::Layout::WholeDisk => {}
+    }
+    let table = &info.table;
+    let found = table
+        .partitions()
+        .iter()
+        .find(|p| p.index == index)
+        .cloned();
+    if found.is_none() {
+        let available: Vec<String> = table
+            .partitions()
+            .iter()
+            .map(|p| p.index.to_string())
+            .collect();
+        log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "パーティション {} はありません（{}: {}）",
+                index,
+                table.name(),
+                available.join(", ")
+            ))
+        );
+    }
+    found
+}
+
+fn open_device(options: &Options) -> Option<File> {
+    match File::open(&options.device_path) {
+        Ok(f) => Some(f),
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("デバイスを開けませんでした: {}", e))
+            );
+            if let Some(hint) = open_error_hint(e.kind()) {
+                log_eprintln!("ヒント: {}", hint);
+            }
+            None
+        }
+    }
+}
+
+// 取り違えたディスクを読み始めないよう、大きなデバイスは対象を表示して確かめる
+fn confirm_device(file: &mut File, options: &Options) -> bool {
+    let target = confirm::Target {
+        path: &options.device_path,
+        size: file.total_len().unwrap_or(0),
+        model: device_info::model(&options.device_path),
+    };
+    match confirm::confirm(
+        &mut io::stdin().lock(),
+        &mut io::stdout(),
+        &target,
+        options.yes,
+        io::stdout().is_terminal(),
+    ) {
+        Ok(true) => true,
+        Ok(false) => {
+            log_eprintln!("中止しました");
+            false
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("確認の入力を読めませんでした: {}", e))
+            );
+            false
+        }
+    }
+}
+
+// USB リーダーが抜けても（ENODEV / ENXIO）、同じパスで認識し直されれば開き直して続ける
+// --scan-beyond-size の本当の終わりでの読み取りエラーは、開き直す前に終わりとして扱う
+fn reconnecting(
+    file: File,
+    options: &Options,
+) -> source::Reconnecting<
+    source::BeyondSize<File>,
+    impl FnMut() -> io::Result<source::BeyondSize<File>> + '_,
+> {
+    let path = &options.device_path;
+    let beyond = options.scan_beyond_size;
+    source::Reconnecting::new(
+        source::BeyondSize::new(file, beyond),
+        move || File::open(path).map(|file| source::BeyondSize::new(file, beyond)),
+        options.reconnect_retries,
+        source::RECONNECT_DELAY,
+    )
+}
+
+fn print_device_info(options: &Options) {
+    let Some(mut file) = open_device(options) else {
+        return;
+    };
+    let sector_size = device_info::sector_size(&file);
+    let result = device_info::inspect(&mut file, sector_size).and_then(|info| {
+        let mounted = mounts::find_mounted(&options.device_path);
+        device_info::write_report(&mut io::stdout(), &options.device_path, &info, &mounted)
+    });
+    if let Err(e) = result {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("デバイス情報の取得に失敗しました: {}", e))
+        );
+    }
+}
+
+fn open_error_hint(kind: io::ErrorKind) -> Option<&'static str> {
+    match kind {
+        io::ErrorKind::PermissionDenied => Some(
+            "デバイスを読む権限がありません。sudo で実行するか、ユーザーを disk グループに追加してください",
+        ),
+        io::ErrorKind::NotFound => {
+            Some("デバイスが見つかりません。lsblk などでデバイスのパスが正しいか確認してください")
+        }
+        _ => None,
+    }
+}
+
+fn open_sink(options: &Options) -> Option<Box<dyn Sink>> {
+    match output::open_sink(
+        &options.output_dir,
+        options.archive.as_deref(),
+        !options.no_create_output,
+    ) {
+        Ok(sink) => Some(sink),
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("出力先を開けませんでした: {}", e))
+            );
+            None
+        }
+    }
+}
+
+fn recover<R: Source>(reader: &mut R, options: &Options) -> Summary {
+    match open_sink(options) {
+        Some(mut sink) => recover_to(reader, options, sink.as_mut()),
+        None => Summary::default(),
+    }
+}
+
+// 保存先を options から開かず、呼び出し側の sink（MemorySink など）に書き出す
+fn recover_to<R: Source>(reader: &mut R, options: &Options, sink: &mut dyn Sink) -> Summary {
+    let started = Instant::now();
+    let mut summary = Summary {
+        counter: options.counter_start,
+        total_bytes: reader.total_len(),
+        ..Summary::default()
+    };
+
+    // 待ち受けられなくても、進捗を外から見られないだけなのでスキャンは続ける
+    let status_server = options.serve_status.and_then(|addr| {
+        match status_server::start(addr, progress::to_json(&summary, started.elapsed(), false)) {
+            Ok(server) => {
+                println!("進捗: http://{}/", server.addr());
+                Some(server)
+            }
+            Err(e) => {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!(
+                        "進捗の HTTP を {} で待ち受けられませんでした: {}",
+                        addr, e
+                    ))
+                );
+                None
+            }
+        }
+    });
+
+    let mut zero_fill = if options.zero_fill_gaps {
+        let created = if options.no_create_output {
+            Ok(())
+        } else {
+            create_dir_all(&options.output_dir)
+        };
+        match created.and_then(|()| ZeroFillImage::create(&options.output_dir)) {
+            Ok(image) => Some(image),
+            Err(e) => {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!("再構成イメージの作成に失敗しました: {}", e))
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let resumable = if options.resume {
+        load_resumable(options)
+    } else {
+        HashMap::new()
+    };
+    if options.only_deleted {
+        summary.allocation = load_allocation(reader);
+    }
+    if options.reassemble_fragments {
+        summary.fragmented_files = load_fragmented_files(reader);
+    }
+    // 伝えられなくても読み方が変わらないだけなので、警告だけしてスキャンする
+    if options.fadvise
+        && let Err(e) = reader.advise(Advice::Sequential, reader.start_offset(), 0)
+    {
+        log_eprintln!(
+            "{}",
+            color::skipped(format_args!(
+                "警告: 順に読むことをカーネルに伝えられませんでした: {}",
+                e
+            ))
+        );
+    }
+    // ここより前はページキャッシュから落とし済み
+    let mut advised_until = reader.start_offset();
+
+    let mut buffer = Vec::new();
+    // buffer[0]（切り出しの途中は buffer[cursor]）がデバイス上のどのオフセットに当たるか
+    let mut buffer_offset: u64 = reader.start_offset();
+    let mut temp = vec![0u8; options.block_size];
+    // 読み飛ばし予定の穴の終わり（次のデータの位置）
+    let mut hole_end: Option<u64> = None;
+    let mut footers = footer::FooterScanner::new(&options.types);
+    // 終端を待っているファイルの先頭と、次に終端検出をやり直すまでに溜める長さ
+    let mut deferred_end: Option<(u64, usize)> = None;
+
+    'scan: loop {
+        let position = buffer_offset + buffer.len() as u64;
+
+        // 穴の中身はすべてゼロなので、手前で保留中のファイルがなければ末尾のゼロだけ残して飛ばす
+        if let Some(next_data) = hole_end.take() {
+            let resume_at = if first_candidate(&buffer, buffer_offset, options).is_none() {
+                summary.skipped_hole_bytes += next_data - position;
+                buffer_offset = next_data - buffer.len() as u64;
+                summary.scanned_bytes += next_data - position;
+                next_data
+            } else {
+                position
+            };
+            if let Err(e) = reader.seek_to(resume_at) {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!("読み取り位置の移動に失敗しました: {}", e))
+                );
+                break;
+            }
+            continue;
+        }
+
+        // 穴の先頭は境界をまたぐシグネチャのためにゼロとして読んだことにする
+        let margin = longest_signature(&options.types);
+        // ノイズとみなしたブロックの終わり（そのブロックは読み足さない）
+        let mut noise_end = None;
+        let eof = match reader.next_data(position) {
+            Some(next_data) if next_data > position + 2 * margin as u64 => {
+                buffer.resize(buffer.len() + margin, 0);
+                summary.scanned_bytes += margin as u64;
+                hole_end = Some(next_data);
+                footers.skip();
+                false
+            }
+            _ => match source::read_block(reader, &mut temp, position) {
+                Ok(0) => true,
+                Ok(n) => {
+                    summary.scanned_bytes += n as u64;
+                    footers.feed(&temp[..n], position);
+                    let candidates = count_starts(&temp[..n], &options.types);
+                    if let Some(limit) = options.max_candidates_per_block
+                        && candidates > limit
+                    {
+                        log_eprintln!(
+                            "{}",
+                            color::skipped(format_args!(
+                                "警告: オフセット {} のブロックに {} 個のスタートシグネチャ候補があるため、ノイズとみなして読み飛ばします",
+                                position, candidates
+                            ))
+                        );
+                        summary.noise_blocks += 1;
+                        noise_end = Some(position + n as u64);
+                        false
+                    } else {
+                        let capacity = buffer.capacity();
+                        buffer.extend_from_slice(&temp[..n]);
+                        if buffer.capacity() != capacity {
+                            summary.buffer_allocations += 1;
+                        }
+                        summary.peak_buffer_bytes = summary.peak_buffer_bytes.max(buffer.len());
+                        if summary
+                            .throughput
+                            .record(started.elapsed(), summary.scanned_bytes)
+                        {
+                            let _ = console::report_progress(&mut io::stdout(), options, &summary);
+                            // 表示と同じく、書けなくてもスキャンは止めない（最後の書き込みで知らせる）
+                            if options.progress_file {
+                                let _ = progress::write(
+                                    &options.output_dir,
+                                    &summary,
+                                    started.elapsed(),
+                                    false,
+                                );
+                            }
+                            if let Some(server) = &status_server {
+                                server.update(progress::to_json(
+                                    &summary,
+                                    started.elapsed(),
+                                    false,
+                                ));
+                            }
+                        }
+                        false
+                    }
+                }
+                Err(e) => {
+                    // 保留中の候補があれば、それが次に保存するファイルになる
+                    let pending = first_candidate(&buffer, buffer_offset, options).is_some();
+                    let error = source::ReadError {
+                        offset: position,
+                        file_index: pending.then_some(summary.counter),
+                        error: e,
+                    };
+                    log_eprintln!("{}", color::error(format_args!("{}", error)));
+                    true
+                }
+            },
+        };
+
+        // ノイズのブロックで途切れた保留中のファイルは、デバイスの終わりと同じくそこまでで切り出す
+        let ended = eof || noise_end.is_some();
+
+        // 切り出し済みの部分は読み取り位置だけ進め、ブロックを読み足す前にまとめて詰める
+        // （ファイルごとに split_off で新しいバッファを確保し直さない）
+        let mut cursor = 0;
+        loop {
+            let window = &buffer[cursor..];
+            let Some((start_idx, file_type)) = first_candidate(window, buffer_offset, options)
+            else {
+                // スタートシグネチャ見つからなければ、末尾だけ残して次ブロックへ
+                let keep_from = window.len().saturating_sub(scan_overlap(options));
+                cursor += keep_from;
+                buffer_offset += keep_from as u64;
+                break;
+            };
+
+            // 前回保存済みのファイルは終端検出もハッシュ計算もせず、記録されたサイズだけ進める
+            if let Some(entry) = resumable.get(&(buffer_offset + start_idx as u64))
+                && entry.file_type == file_type
+                && entry.size > 0
+            {
+                let end_idx = start_idx + entry.size as usize;
+                if end_idx <= window.len() {
+                    let region = Region {
+                        offset: entry.offset,
+                        len: entry.size,
+                    };
+                    if let Some(image) = zero_fill.as_mut()
+                        && let Err(e) =
+                            image.write_region(region.offset, &window[start_idx..end_idx])
+                    {
+                        log_eprintln!(
+                            "{}",
+                            color::error(format_args!("再構成イメージの書き込みエラー: {}", e))
+                        );
+                    }
+                    summary.regions.push(region);
+                    append_journal(sink, options, entry);
+                    summary.entries.push(entry.clone());
+                    summary.written_bytes += entry.stored_size.unwrap_or(entry.size);
+                    summary.counter = summary.counter.max(entry.index + 1);
+                    summary.resumed_files += 1;
+                    cursor += end_idx;
+                    buffer_offset += end_idx as u64;
+                    continue;
+                }
+                if !ended {
+                    cursor += start_idx;
+                    buffer_offset += start_idx as u64;
+                    break;
+                }
+            }
+
+            // 前回 NeedMoreData だったファイルは、決めておいた長さまで読み足してから終端検出をやり直す
+            if !ended
+                && deferred_end.is_some_and(|(offset, until)| {
+                    offset == buffer_offset + start_idx as u64 && window.len() - start_idx < until
+                })
+            {
+                cursor += start_idx;
+                buffer_offset += start_idx as u64;
+                break;
+            }
+
+            summary.end_checks += 1;
+            let end = match carve::find_end(&window[start_idx..], file_type, options, ended) {
+                // 終わりの印を待っていた形式も、途切れた位置までを残す
+                CarveEnd::NeedMoreData if noise_end.is_some() && !options.skip_unterminated => {
+                    CarveEnd::Found(window.len() - start_idx, carve::CONFIDENCE_BUFFER_END)
+                }
+                end => end,
+            };
+            let end_idx = match end {
+                CarveEnd::Found(len, confidence) if len > 0 && start_idx + len <= window.len() => {
+                    let end_idx = start_idx + len;
+                    let data = &window[start_idx..end_idx];
+                    let region = Region {
+                        offset: buffer_offset + start_idx as u64,
+                        len: data.len() as u64,
+                    };
+                    // 再構成イメージと読んだ範囲の記録には、つなぎ直す前の切り出した範囲を使う
+                    let reassembled = reassemble(
+                        reader,
+                        &summary.fragmented_files,
+                        region.offset,
+                        buffer_offset + window.len() as u64,
+                    );
+                    let (saved_region, saved) = match &reassembled {
+                        Some(reassembled) => (
+                            Region {
+                                offset: region.offset,
+                                len: reassembled.len() as u64,
+                            },
+                            reassembled.as_slice(),
+                        ),
+                        None => (region, data),
+                    };
+                    if store_file(
+                        &mut summary,
+                        sink,
+                        options,
+                        file_type,
+                        saved_region,
+                        saved,
+                        confidence,
+                    )
+                    .is_break()
+                    {
+                        break 'scan;
+                    }
+                    if let Some(image) = zero_fill.as_mut()
+                        && let Err(e) = image.write_region(region.offset, data)
+                    {
+                        log_eprintln!(
+                            "{}",
+                            color::error(format_args!("再構成イメージの書き込みエラー: {}", e))
+                        );
+                    }
+                    summary.regions.push(region);
+                    summary.counter += 1;
+                    end_idx
+                }
+                CarveEnd::NeedMoreData
+                    if options.skip_unterminated
+                        && (ended
+                            || (window.len() - start_idx) as u64
+                                >= options.max_file_size_for(file_type)) =>
+                {
+                    log_eprintln!(
+                        "{}",
+                        color::skipped(format_args!(
+                            "警告: オフセット {} の {} は終端が見つからないため読み飛ばします",
+                            buffer_offset + start_idx as u64,
+                            file_type.name()
+                        ))
+                    );
+                    summary
+                        .failed_starts
+                        .push((buffer_offset + start_idx as u64, file_type));
+                    start_idx + file_type.signature_len()
+                }
+                // 終端が見つからないまま溜め続けるとデバイス全体を読み込みかねないので、上限で諦める
+                CarveEnd::NeedMoreData
+                    if (window.len() - start_idx) as u64
+                        >= BUFFER_CAP_FACTOR * options.max_file_size_for(file_type) =>
+                {
+                    log_eprintln!(
+                        "{}",
+                        color::skipped(format_args!(
+                            "警告: オフセット {} の {} は上限サイズの {} 倍読んでも終端が見つからないため読み飛ばします",
+                            buffer_offset + start_idx as u64,
+                            file_type.name(),
+                            BUFFER_CAP_FACTOR
+                        ))
+                    );
+                    summary
+                        .failed_starts
+                        .push((buffer_offset + start_idx as u64, file_type));
+                    start_idx + file_type.signature_len()
+                }
+                CarveEnd::NeedMoreData => {
+                    deferred_end = Some((
+                        buffer_offset + start_idx as u64,
+                        next_end_check(window.len() - start_idx, file_type, options),
+                    ));
+                    cursor += start_idx;
+                    buffer_offset += start_idx as u64;
+                    break;
+                }
+                // ファイルとして成り立たないので偽のスタートシグネチャとみなして読み飛ばす
+                // 終端がバッファの外や先頭を指す場合も、切り出すと落ちるか先へ進めなくなるので同じ扱い
+                CarveEnd::Invalid | CarveEnd::Found(..) => {
+                    summary
+                        .failed_starts
+                        .push((buffer_offset + start_idx as u64, file_type));
+                    start_idx + file_type.signature_len()
+                }
+            };
+
+            // 終端のちょうど次から探し直すので、隙間なく続く別の種類のファイルも取りこぼさない
+            cursor += end_idx;
+            buffer_offset += end_idx as u64;
+        }
+        buffer.drain(..cursor);
+        if let Some(end) = noise_end {
+            buffer.clear();
+            buffer_offset = end;
+        }
+        if options.fadvise
+            && buffer_offset - advised_until >= FADVISE_BLOCKS * options.block_size as u64
+        {
+            let _ = reader.advise(
+                Advice::DontNeed,
+                advised_until,
+                buffer_offset - advised_until,
+            );
+            advised_until = buffer_offset;
+        }
+
+        if eof {
+            break;
+        }
+    }
+    if options.fadvise {
+        let _ = reader.advise(Advice::DontNeed, advised_until, 0);
+    }
+
+    recover_from_footers(
+        reader,
+        &footers.found,
+        options,
+        &mut summary,
+        sink,
+        zero_fill.as_mut(),
+    );
+    if options.rescan_failed {
+        rescan_failed(reader, options, &mut summary, sink, zero_fill.as_mut());
+    }
+
+    if let Some(image) = zero_fill
+        && let Err(e) = image.finish(reader.start_offset() + summary.scanned_bytes)
+    {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("再構成イメージの書き込みエラー: {}", e))
+        );
+    }
+
+    if options.sector_bitmap {
+        write_sector_bitmap(&summary, reader.start_offset(), sink);
+    }
+    if options.include_trailing_slack {
+        write_slack(reader, &summary, sink);
+    }
+    if options.dump_unknown {
+        write_unknown(reader, &summary, options, sink);
+    }
+    if options.verify_against_fs {
+        write_reconciliation(reader, &summary, sink);
+    }
+    write_reports(&summary.entries, sink, options);
+    if options.progress_file
+        && let Err(e) = progress::write(&options.output_dir, &summary, started.elapsed(), true)
+    {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "{} の書き込みエラー: {}",
+                progress::PROGRESS_FILE_NAME,
+                e
+            ))
+        );
+    }
+    if let Some(server) = status_server {
+        server.update(progress::to_json(&summary, started.elapsed(), true));
+        server.shutdown();
+    }
+
+    summary
+}
+
+// スキャンした範囲の終わりまでのセクタについて、保存したファイルに含まれるものを立てる
+fn write_sector_bitmap(summary: &Summary, start_offset: u64, sink: &mut dyn Sink) {
+    let ranges: Vec<(u64, u64)> = summary
+        .entries
+        .iter()
+        .map(|entry| (entry.offset, entry.size))
+        .collect();
+    let device_len = start_offset + summary.scanned_bytes;
+    let bitmap = sector_map::build(&ranges, device_len, partition::DEFAULT_SECTOR_SIZE);
+    if let Err(e) = sink.write_file(sector_map::SECTOR_BITMAP_FILE_NAME, &bitmap) {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("セクタビットマップの書き込みエラー: {}", e))
+        );
+        return;
+    }
+    println!(
+        "セクタビットマップ: {} セクタ中 {} セクタが復旧したファイルに含まれます",
+        device_len.div_ceil(partition::DEFAULT_SECTOR_SIZE),
+        sector_map::count_marked(&bitmap)
+    );
+}
+
+// フッターで終わる形式は、スキャン中に見つけたフッターの手前を読み直して先頭を探す
+// 先頭からのスキャンで切り出したファイルの中にあるフッターは、そのファイルの一部とみなす
+fn recover_from_footers<R: Source>(
+    reader: &mut R,
+    footers: &[(u64, FileType)],
+    options: &Options,
+    summary: &mut Summary,
+    sink: &mut dyn Sink,
+    mut zero_fill: Option<&mut ZeroFillImage>,
+) {
+    for &(footer_end, file_type) in footers {
+        if summary
+            .regions
+            .iter()
+            .any(|region| (region.offset..region.offset + region.len).contains(&(footer_end - 1)))
+        {
+            continue;
+        }
+        let window_start = footer_end
+            .saturating_sub(options.max_file_size_for(file_type))
+            .max(reader.start_offset());
+        let data = match source::read_at(reader, window_start, footer_end - window_start) {
+            Ok(data) => data,
+            Err(e) => {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!(
+                        "オフセット {} の手前の読み取りエラー: {}",
+                        footer_end, e
+                    ))
+                );
+                continue;
+            }
+        };
+        let Some((start_idx, confidence)) = carve::find_start_before(&data, file_type) else {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: オフセット {} で終わる {} のフッターの手前に先頭が見つかりません",
+                    footer_end,
+                    file_type.name()
+                ))
+            );
+            continue;
+        };
+
+        let data = &data[start_idx..];
+        let region = Region {
+            offset: window_start + start_idx as u64,
+            len: data.len() as u64,
+        };
+        if store_file(summary, sink, options, file_type, region, data, confidence).is_break() {
+            break;
+        }
+        if let Some(image) = zero_fill.as_mut()
+            && let Err(e) = image.write_region(region.offset, data)
+        {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("再構成イメージの書き込みエラー: {}", e))
+            );
+        }
+        summary.regions.push(region);
+        summary.counter += 1;
+    }
+}
+
+// 1回目のスキャンで読み飛ばした候補だけを、別の終端検出と緩めた確認で切り出し直す
+fn rescan_failed<R: Source>(
+    reader: &mut R,
+    options: &Options,
+    summary: &mut Summary,
+    sink: &mut dyn Sink,
+    mut zero_fill: Option<&mut ZeroFillImage>,
+) {
+    let relaxed = rescan_options(options);
+    let failed = std::mem::take(&mut summary.failed_starts);
+    let mut recovered = 0;
+    for &(offset, file_type) in &failed {
+        // 後から見つかったファイル（フッターからの復旧など）に含まれていれば見直さない
+        if summary
+            .regions
+            .iter()
+            .any(|region| (region.offset..region.offset + region.len).contains(&offset))
+        {
+            continue;
+        }
+        let Some(file) = carve::carve_at(reader, offset, &relaxed) else {
+            continue;
+        };
+        if file.file_type != file_type {
+            continue;
+        }
+        let region = Region {
+            offset,
+            len: file.data.len() as u64,
+        };
+        if store_file(
+            summary,
+            sink,
+            &relaxed,
+            file_type,
+            region,
+            &file.data,
+            file.confidence,
+        )
+        .is_break()
+        {
+            break;
+        }
+        if let Some(image) = zero_fill.as_mut()
+            && let Err(e) = image.write_region(region.offset, &file.data)
+        {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("再構成イメージの書き込みエラー: {}", e))
+            );
+        }
+        summary.regions.push(region);
+        summary.counter += 1;
+        recovered += 1;
+    }
+    summary.failed_starts = failed;
+    println!(
+        "再スキャン: 読み飛ばした {} 個の候補から {} 個を復旧しました",
+        summary.failed_starts.len(),
+        recovered
+    );
+}
+
+// JPEG は別の終端の決め方にし、終端がなくても次のシグネチャまで切り出し、
+// 確からしさが低いものは捨てずに分けて保存する
+fn rescan_options(options: &Options) -> Options {
+    Options {
+        jpeg_end_strategy: match options.jpeg_end_strategy {
+            JpegEndStrategy::Strict | JpegEndStrategy::First => JpegEndStrategy::Last,
+            JpegEndStrategy::Last => JpegEndStrategy::First,
+        },
+        skip_unterminated: false,
+        min_confidence: options.min_confidence.map(|min| carve::MinConfidence {
+            quarantine: true,
+            ..min
+        }),
+        ..options.clone()
+    }
+}
+
+// スキャンを終えてから、保存した各ファイルの後ろのスラックを読み直して書き出す
+fn write_slack<R: Source>(reader: &mut R, summary: &Summary, sink: &mut dyn Sink) {
+    let filesystems = match slack::detect(reader) {
+        Ok(filesystems) if !filesystems.is_empty() => filesystems,
+        Ok(_) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: クラスタの大きさが分かるファイルシステムが見つからないため、スラックは書き出しません"
+                ))
+            );
+            return;
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("ブートセクタの読み取りエラー: {}", e))
+            );
+            return;
+        }
+    };
+
+    let mut written = 0;
+    for entry in &summary.entries {
+        let Some((offset, len)) = slack::region(&filesystems, entry.offset + entry.size) else {
+            continue;
+        };
+        let stem = entry
+            .filename
+            .rsplit_once('.')
+            .map_or(entry.filename.as_str(), |(stem, _)| stem);
+        let name = format!("{}.{}", stem, slack::SLACK_EXTENSION);
+        let result =
+            source::read_at(reader, offset, len).and_then(|data| sink.write_file(&name, &data));
+        match result {
+            Ok(_) => written += 1,
+            Err(e) => log_eprintln!(
+                "{}",
+                color::error(format_args!("スラックの書き込みエラー: {}", e))
+            ),
+        }
+    }
+    println!("スラック: {} 個のファイルの後ろを書き出しました", written);
+}
+
+// 見つかった FAT32 のディレクトリをすべてたどり、切り出した結果と突き合わせる
+fn write_reconciliation<R: Source>(reader: &mut R, summary: &Summary, sink: &mut dyn Sink) {
+    let listed: io::Result<Vec<Vec<fat::FsFile>>> = slack::detect(reader).and_then(|filesystems| {
+        filesystems
+            .iter()
+            .filter_map(|fs| fat::list_files(reader, fs.start).transpose())
+            .collect()
+    });
+    let files: Vec<fat::FsFile> = match listed {
+        Ok(listed) if !listed.is_empty() => listed.into_iter().flatten().collect(),
+        Ok(_) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: FAT32 が見つからないため、ファイルシステムとの突き合わせはしません"
+                ))
+            );
+            return;
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("ディレクトリの読み取りエラー: {}", e))
+            );
+            return;
+        }
+    };
+
+    let reconciliation = reconcile::reconcile(&summary.entries, &files);
+    let mut report = Vec::new();
+    let result = reconcile::write_report(&mut report, &reconciliation)
+        .and_then(|_| sink.write_file(reconcile::RECONCILIATION_FILE_NAME, &report));
+    if let Err(e) = result {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("突き合わせ結果の書き込みエラー: {}", e))
+        );
+    }
+    println!(
+        "突き合わせ: 削除済みから復旧 {} 件 / ファイルシステムに残っている {} 件 / 取りこぼし {} 件",
+        reconciliation.deleted.len(),
+        reconciliation.live.len(),
+        reconciliation.missed.len()
+    );
+}
+
+// 切り出した領域の隙間を読み直して書き出す
+// すべてゼロの部分（未使用の領域やスパースファイルの穴）は調べる意味がないので書き出さない
+fn write_unknown<R: Source>(
+    reader: &mut R,
+    summary: &Summary,
+    options: &Options,
+    sink: &mut dyn Sink,
+) {
+    let start = reader.start_offset();
+    let carved: Vec<(u64, u64)> = summary
+        .regions
+        .iter()
+        .map(|region| (region.offset, region.len))
+        .collect();
+    let gaps = unknown::gaps(
+        &carved,
+        start,
+        start + summary.scanned_bytes,
+        options.unknown_min_size,
+    );
+
+    let mut written = 0;
+    for gap in gaps {
+        for (offset, len) in unknown::pieces(gap, options.max_file_size) {
+            let result = source::read_at(reader, offset, len).and_then(|data| {
+                if data.iter().all(|&b| b == 0) {
+                    return Ok(false);
+                }
+                sink.write_file(&unknown::file_name(offset), &data)
+                    .map(|_| true)
+            });
+            match result {
+                Ok(true) => written += 1,
+                Ok(false) => {}
+                Err(e) => log_eprintln!(
+                    "{}",
+                    color::error(format_args!(
+                        "オフセット {} の不明な領域の書き込みエラー: {}",
+                        offset, e
+                    ))
+                ),
+            }
+        }
+    }
+    println!(
+        "不明な領域: {} 個を {}/ に書き出しました",
+        written,
+        unknown::UNKNOWN_DIR
+    );
+}
+
+// 終端検出・ハッシュ・書き込みを一切せず、スタートシグネチャの数だけを数える
+fn scan_only<R: Source>(reader: &mut R, options: &Options) -> ScanCounts {
+    let mut result = ScanCounts {
+        scanned_bytes: 0,
+        counts: options.types.iter().map(|t| (*t, 0)).collect(),
+    };
+    let overlap = scan_overlap(options);
+
+    let mut buffer = Vec::new();
+    let mut temp = vec![0u8; options.block_size];
+
+    loop {
+        let position = reader.start_offset() + result.scanned_bytes;
+        let n = match source::read_block(reader, &mut temp, position) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                let error = source::ReadError {
+                    offset: position,
+                    file_index: None,
+                    error: e,
+                };
+                log_eprintln!("{}", color::error(format_args!("{}", error)));
+                break;
+            }
+        };
+        result.scanned_bytes += n as u64;
+
+        // 前のブロックから持ち越した部分だけに収まるシグネチャは数え済み
+        let carried = buffer.len();
+        buffer.extend_from_slice(&temp[..n]);
+        let buffer_offset = reader.start_offset() + result.scanned_bytes - buffer.len() as u64;
+        // 一致は magic の先頭がある範囲で数えるので、範囲の境目をまたいでも1回だけになる
+        let threads = options.threads.clamp(1, buffer.len().max(1));
+        let chunk = buffer.len().div_ceil(threads);
+        let ranges = (0..threads).map(|i| i * chunk..((i + 1) * chunk).min(buffer.len()));
+        let counts: Vec<Vec<usize>> = if threads == 1 {
+            ranges
+                .map(|range| count_signatures(&buffer, carried, buffer_offset, options, range))
+                .collect()
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = ranges
+                    .map(|range| {
+                        let buffer = &buffer;
+                        scope.spawn(move || {
+                            count_signatures(buffer, carried, buffer_offset, options, range)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .expect("シグネチャを数えるスレッドが異常終了しました")
+                    })
+                    .collect()
+            })
+        };
+        for range_counts in counts {
+            for ((_, count), found) in result.counts.iter_mut().zip(range_counts) {
+                *count += found;
+            }
+        }
+
+        let keep_from = buffer.len().saturating_sub(overlap);
+        buffer.drain(..keep_from);
+    }
+
+    result
+}
+
+// buffer のうち magic の先頭が range にあるシグネチャを、options.types の順に数える
+// 前のブロックから持ち越した carried バイトだけに収まるものは数え済みなので除く
+fn count_signatures(
+    buffer: &[u8],
+    carried: usize,
+    buffer_offset: u64,
+    options: &Options,
+    range: Range<usize>,
+) -> Vec<usize> {
+    let step = options.scan_step as u64;
+    options
+        .types
+        .iter()
+        .map(|file_type| {
+            // フッターで終わる形式はフッターを数える
+            file_type
+                .start_signatures()
+                .iter()
+                .chain(file_type.end_signatures())
+                .map(|signature| {
+                    range
+                        .clone()
+                        .filter(|&idx| {
+                            idx + signature.magic.len() > carried
+                                && buffer.get(idx..idx + signature.magic.len())
+                                    == Some(signature.magic)
+                                && idx >= signature.offset
+                                && (buffer_offset + (idx - signature.offset) as u64)
+                                    .is_multiple_of(step)
+                                && file_type.claims(&buffer[idx - signature.offset..])
+                        })
+                        .count()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// 指定されたオフセットのファイルだけを切り出す
+fn recover_at_offsets<R: Source>(reader: &mut R, offsets: &[u64], options: &Options) -> Summary {
+    let mut summary = Summary {
+        counter: options.counter_start,
+        ..Summary::default()
+    };
+    let Some(mut sink) = open_sink(options) else {
+        return summary;
+    };
+    if options.only_deleted {
+        summary.allocation = load_allocation(reader);
+    }
+
+    for &offset in offsets {
+        let Some(carved) = carve::carve_at(reader, offset, options) else {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "オフセット {} からファイルを切り出せませんでした",
+                    offset
+                ))
+            );
+            continue;
+        };
+        let region = Region {
+            offset,
+            len: carved.data.len() as u64,
+        };
+        if store_file(
+            &mut summary,
+            sink.as_mut(),
+            options,
+            carved.file_type,
+            region,
+            &carved.data,
+            carved.confidence,
+        )
+        .is_break()
+        {
+            break;
+        }
+        summary.regions.push(region);
+        summary.counter += 1;
+    }
+
+    write_reports(&summary.entries, sink.as_mut(), options);
+
+    summary
+}
+
+// --only-deleted 用に、見つかった FAT32 / exFAT のクラスタの使用状況を読み、読み取り位置を先頭に戻す
+// 見つからなければ空のまま返し、使用中かどうかを区別せずに保存する
+fn load_allocation<R: Source>(reader: &mut R) -> Vec<fat::Allocation> {
+    let loaded: io::Result<Vec<fat::Allocation>> = slack::detect(reader).and_then(|filesystems| {
+        filesystems
+            .iter()
+            .filter_map(|fs| fat::allocation(reader, fs.start).transpose())
+            .collect()
+    });
+    let allocation = match loaded {
+        Ok(allocation) if !allocation.is_empty() => allocation,
+        Ok(_) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: FAT32 / exFAT が見つからないため、使用中のクラスタのファイルも保存します"
+                ))
+            );
+            Vec::new()
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("割り当て情報の読み取りエラー: {}", e))
+            );
+            Vec::new()
+        }
+    };
+    if let Err(e) = reader.seek_to(reader.start_offset()) {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("読み取り位置の移動に失敗しました: {}", e))
+        );
+    }
+    allocation
+}
+
+// --reassemble-fragments 用に、見つかった FAT32 のチェーンが連続していないファイルを集め、読み取り位置を先頭に戻す
+fn load_fragmented_files<R: Source>(reader: &mut R) -> Vec<fat::FragmentedFile> {
+    let loaded: io::Result<Vec<Vec<fat::FragmentedFile>>> =
+        slack::detect(reader).and_then(|filesystems| {
+            filesystems
+                .iter()
+                .filter_map(|fs| fat::fragmented_files(reader, fs.start).transpose())
+                .collect()
+        });
+    let files = match loaded {
+        Ok(listed) if !listed.is_empty() => listed.into_iter().flatten().collect(),
+        Ok(_) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: FAT32 が見つからないため、断片化したファイルはつなぎ直しません"
+                ))
+            );
+            Vec::new()
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("FAT の読み取りエラー: {}", e))
+            );
+            Vec::new()
+        }
+    };
+    if let Err(e) = reader.seek_to(reader.start_offset()) {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("読み取り位置の移動に失敗しました: {}", e))
+        );
+    }
+    files
+}
+
+// offset から始まるファイルのチェーンが連続していなければ、チェーンの順に読み直した中身を返す
+// 読み終えたら、スキャンを続けられるよう読み取り位置を resume_at に戻す
+fn reassemble<R: Source>(
+    reader: &mut R,
+    files: &[fat::FragmentedFile],
+    offset: u64,
+    resume_at: u64,
+) -> Option<Vec<u8>> {
+    let file = files.iter().find(|file| file.offset == offset)?;
+    let data = fat::read_fragments(reader, file);
+    if let Err(e) = reader.seek_to(resume_at) {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("読み取り位置の移動に失敗しました: {}", e))
+        );
+    }
+    match data {
+        Ok(data) => {
+            println!(
+                "オフセット {} のファイルを FAT のチェーンから {} か所をつなぎ直しました",
+                offset,
+                file.extents.len()
+            );
+            Some(data)
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: オフセット {} のファイルをつなぎ直せないため、切り出したまま保存します: {}",
+                    offset, e
+                ))
+            );
+            None
+        }
+    }
+}
+
+// 保存を続けられない（上限到達や空き容量不足）ときは Break を返す
+fn store_file(
+    summary: &mut Summary,
+    sink: &mut dyn Sink,
+    options: &Options,
+    file_type: FileType,
+    region: Region,
+    data: &[u8],
+    confidence: f64,
+) -> ControlFlow<()> {
+    // 先頭のクラスタがまだ使われているものは、ファイルシステムからそのまま読めるので保存しない
+    if options.only_deleted
+        && summary
+            .allocation
+            .iter()
+            .any(|allocation| allocation.is_allocated(region.offset) == Some(true))
+    {
+        summary.skipped_allocated += 1;
+        return ControlFlow::Continue(());
+    }
+    if let Some(limit) = options.max_total_size
+        && summary.written_bytes + region.len > limit
+    {
+        println!(
+            "出力サイズの上限 ({} バイト) に達したため、スキャンを終了します",
+            limit
+        );
+        return ControlFlow::Break(());
+    }
+    // 途中で write_all が失敗する前に、空き容量が足りなければ中止する
+    if let Some(location) = sink.location()
+        && let Some(available) = disk_space::available_bytes(location)
+        && available < region.len
+    {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "保存先の空き容量が不足しています（必要: {} バイト, 空き: {} バイト）。スキャンを中止します",
+                region.len, available
+            ))
+        );
+        return ControlFlow::Break(());
+    }
+
+    let quarantined = match options.min_confidence {
+        Some(min) if confidence < min.threshold && !min.quarantine => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: オフセット {} の {} は確からしさ {:.2} がしきい値 {:.2} を下回るため保存しません",
+                    region.offset,
+                    file_type.name(),
+                    confidence,
+                    min.threshold
+                ))
+            );
+            return ControlFlow::Continue(());
+        }
+        Some(min) => confidence < min.threshold,
+        None => false,
+    };
+
+    let extension = file_extension(options, file_type, region, data);
+    let fragmented = fragment::looks_fragmented(file_type, data);
+    if fragmented {
+        log_eprintln!(
+            "{}",
+            color::skipped(format_args!(
+                "警告: オフセット {} のファイルは断片化している可能性があるため {}/ に保存します",
+                region.offset,
+                fragment::FRAGMENTED_DIR
+            ))
+        );
+    }
+    let compress = gzip::should_compress(options, file_type, extension);
+    // 重複をまとめるには書き込む前にハッシュが要る。それ以外は書き込みながら求める
+    // gzip にするときは、書き込む .gz ではなく切り出した中身のハッシュを残す
+    let known_hash =
+        (options.flatten_duplicates_to_hardlinks || compress).then(|| sha256::sha256_hex(data));
+    let original = known_hash
+        .as_ref()
+        .and_then(|hash| summary.first_by_hash.get(hash).cloned());
+    let decode_failure = if options.quarantine_on_decode_failure {
+        decode::check(file_type, data).err()
+    } else {
+        None
+    };
+    if let Some(reason) = decode_failure {
+        log_eprintln!(
+            "{}",
+            color::skipped(format_args!(
+                "警告: オフセット {} の {} は画像として開けないため {}/ に保存します（{}）",
+                region.offset,
+                file_type.name(),
+                decode::FAILED_DIR,
+                reason
+            ))
+        );
+    }
+    let raw_rejection = if options.validate_raw
+        && matches!(
+            file_type,
+            FileType::Rw2 | FileType::Dng | FileType::Orf | FileType::Nrw
+        ) {
+        tiff::validate(data).err()
+    } else {
+        None
+    };
+    if let Some(reason) = raw_rejection {
+        log_eprintln!(
+            "{}",
+            color::skipped(format_args!(
+                "警告: オフセット {} の {} は IFD を最後までたどれないため {}/ に保存します（{}）",
+                region.offset,
+                file_type.name(),
+                tiff::REJECTED_DIR,
+                reason
+            ))
+        );
+    }
+    // 開けないと分かったもの、IFD が壊れた RAW、確からしさが低いもの、断片化の疑いがあるものの順に優先して分ける
+    let folder = if decode_failure.is_some() {
+        Some(decode::FAILED_DIR)
+    } else if raw_rejection.is_some() {
+        Some(tiff::REJECTED_DIR)
+    } else if quarantined {
+        Some(carve::LOW_CONFIDENCE_DIR)
+    } else {
+        fragmented.then_some(fragment::FRAGMENTED_DIR)
+    };
+    let mut name = file_name(options, file_type, data, region, summary.counter, extension);
+    // フォルダのディレクトリはファイルを書くときに作るので、使わない日付のフォルダはできない
+    if let Some(OutputPattern::Date) = options.output_pattern {
+        let date_dir = match exif::capture_date(file_type, data) {
+            Some(date) => format!("{:04}/{:02}/{:02}", date.year, date.month, date.day),
+            None => output::UNDATED_DIR.to_string(),
+        };
+        name = format!("{}/{}", date_dir, name);
+    }
+    if let Some(folder) = folder {
+        name = format!("{}/{}", folder, name);
+    }
+    let compressed = compress.then(|| gzip::compress(data));
+    if compressed.is_some() {
+        name.push_str(gzip::EXTENSION_SUFFIX);
+    }
+    let stored = compressed.as_deref().unwrap_or(data);
+    let saved = match save_file(sink, stored, name, original.as_deref()) {
+        Ok(saved) => Some(saved),
+        // 以降の書き込みもすべて失敗するので、途中までのファイルを消して中止する
+        Err(e) if output::is_disk_full(&e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!(
+                    "保存先の空き容量がなくなりました（{}）。書きかけのファイルを削除してスキャンを中止します",
+                    e
+                ))
+            );
+            return ControlFlow::Break(());
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("ファイル書き込みエラー: {}", e))
+            );
+            None
+        }
+    };
+    if let Some((filename, written_hash)) = saved {
+        // リンクにした場合は書き込んでいないので、先に求めたハッシュを使う
+        let sha256 = known_hash.or(written_hash).unwrap_or_default();
+        if options.flatten_duplicates_to_hardlinks && original.is_none() {
+            summary
+                .first_by_hash
+                .insert(sha256.clone(), filename.clone());
+        }
+        let entry = ManifestEntry {
+            index: summary.counter,
+            file_type,
+            offset: region.offset,
+            size: region.len,
+            sha256,
+            confidence,
+            filename: filename.clone(),
+            fragmented,
+            gps: exif::gps(file_type, data),
+            decode_failure: decode_failure.map(str::to_string),
+            stored_size: compressed
+                .as_ref()
+                .map(|compressed| compressed.len() as u64),
+        };
+        append_journal(sink, options, &entry);
+        summary.entries.push(entry);
+        // ハードリンクは容量を使わない
+        if original.is_none() {
+            summary.written_bytes += stored.len() as u64;
+        }
+        // 表示先が閉じられていても復旧は続ける
+        let _ = console::report_saved(
+            &mut io::stdout(),
+            options,
+            summary,
+            &sink.display_name(&filename),
+        );
+
+        if options.extract_preview
+            && file_type == FileType::Rw2
+            && let Some(preview) = tiff::find_preview_jpeg(data)
+        {
+            save_preview(sink, options, summary, &filename, preview);
+        }
+    }
+
+    ControlFlow::Continue(())
+}
+
+fn file_extension(
+    options: &Options,
+    file_type: FileType,
+    region: Region,
+    data: &[u8],
+) -> &'static str {
+    if options.sniff
+        && let Some(sniffed) = sniff::mismatch(file_type, data)
+    {
+        log_eprintln!(
+            "{}",
+            color::skipped(format_args!(
+                "警告: オフセット {} は {} として切り出しましたが、中身は {} のようです（拡張子を .{} にします）",
+                region.offset,
+                file_type.name(),
+                sniffed.name,
+                sniffed.extension
+            ))
+        );
+        return sniffed.extension;
+    }
+
+    // ZIPは中身を見て docx / xlsx などの、ISO-BMFF はブランドを見て heic / mov などの拡張子にする
+    // 複数の画像をまとめて切り出したJPEGは mpo にする
+    match file_type {
+        FileType::Jpeg => mpf::extension(data),
+        FileType::Zip => zip::document_extension(data),
+        FileType::Mp4 => bmff::extension(data),
+        _ => file_type.extension(),
+    }
+}
+
+// image_000001.jpg（--rename-by-dimensions なら 6000x4000_000001.jpg）
+fn file_name(
+    options: &Options,
+    file_type: FileType,
+    data: &[u8],
+    region: Region,
+    counter: usize,
+    extension: &str,
+) -> String {
+    let serial = if options.stable_names {
+        format!("{:012}", reported_offset(options, region.offset))
+    } else {
+        format!("{:06}", counter)
+    };
+    let dimensions = if options.rename_by_dimensions {
+        image_dimensions(file_type, data)
+    } else {
+        None
+    };
+    match dimensions {
+        Some((width, height)) => format!("{}x{}_{}.{}", width, height, serial, extension),
+        None => format!("image_{}.{}", serial, extension),
+    }
+}
+
+// ヘッダから読める (幅, 高さ)。RAW や動画は読まない
+fn image_dimensions(file_type: FileType, data: &[u8]) -> Option<(u32, u32)> {
+    match file_type {
+        FileType::Jpeg => jpeg::dimensions(data),
+        FileType::Bmp => bmp::dimensions(data),
+        FileType::Webp => webp::dimensions(data),
+        _ => None,
+    }
+}
+
+// image_000001.rw2 のプレビューは image_000001.preview.jpg
+fn save_preview(
+    sink: &mut dyn Sink,
+    options: &Options,
+    summary: &Summary,
+    filename: &str,
+    preview: &[u8],
+) {
+    let filename = filename
+        .strip_suffix(gzip::EXTENSION_SUFFIX)
+        .unwrap_or(filename);
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let name = format!("{}.preview.jpg", stem);
+    match sink.write_file(&name, preview) {
+        Ok(_) => {
+            let _ = console::report_saved(
+                &mut io::stdout(),
+                options,
+                summary,
+                &sink.display_name(&name),
+            );
+        }
+        Err(e) => log_eprintln!(
+            "{}",
+            color::error(format_args!("プレビューの書き込みエラー: {}", e))
+        ),
+    }
+}
+
+// 前回の manifest.json のうち、出力先に記録どおりのサイズで残っているものをオフセットで引けるようにする
+// .gz にしたものは切り出したサイズではなく、保存したサイズと比べる
+// 消えていたり書きかけで短かったりするファイルは載せず、通常どおり切り出し直す
+fn load_resumable(options: &Options) -> HashMap<u64, ManifestEntry> {
+    let path = options.output_dir.join(manifest::MANIFEST_FILE_NAME);
+    let journal = options.output_dir.join(manifest::JOURNAL_FILE_NAME);
+    let entries = match std::fs::read_to_string(&path) {
+        Ok(text) => manifest::from_json(&text),
+        // 前回の実行が途中で止まっていれば、manifest.json はなく途中経過だけが残っている
+        Err(_) if let Ok(text) = std::fs::read_to_string(&journal) => {
+            Some(manifest::from_json_lines(&text))
+        }
+        Err(e) => {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: {} を読めないため最初から復旧します: {}",
+                    path.display(),
+                    e
+                ))
+            );
+            return HashMap::new();
+        }
+    };
+    let Some(entries) = entries else {
+        log_eprintln!(
+            "{}",
+            color::skipped(format_args!(
+                "警告: {} の形式が正しくないため最初から復旧します",
+                path.display()
+            ))
+        );
+        return HashMap::new();
+    };
+
+    entries
+        .into_iter()
+        .filter(|entry| {
+            std::fs::metadata(options.output_dir.join(&entry.filename))
+                .is_ok_and(|metadata| metadata.len() == entry.stored_size.unwrap_or(entry.size))
+        })
+        .map(|entry| {
+            let offset = match options.offset_base {
+                OffsetBase::Device => entry.offset,
+                OffsetBase::Partition => options.partition_start + entry.offset,
+            };
+            (offset, ManifestEntry { offset, ..entry })
+        })
+        .collect()
+}
+
+// manifest.json とファイル名に書くオフセット（Summary の中ではデバイス先頭からのまま持つ）
+fn reported_offset(options: &Options, offset: u64) -> u64 {
+    match options.offset_base {
+        OffsetBase::Device => offset,
+        OffsetBase::Partition => offset - options.partition_start,
+    }
+}
+
+fn reported_entry(options: &Options, entry: &ManifestEntry) -> ManifestEntry {
+    ManifestEntry {
+        offset: reported_offset(options, entry.offset),
+        ..entry.clone()
+    }
+}
+
+// 書けなくても復旧は続ける（最後に manifest.json を書ければ途中経過は要らない）
+fn append_journal(sink: &mut dyn Sink, options: &Options, entry: &ManifestEntry) {
+    let entry = reported_entry(options, entry);
+    if let Err(e) = sink.append_journal(&manifest::to_json_line(&entry)) {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!(
+                "{} の書き込みエラー: {}",
+                manifest::JOURNAL_FILE_NAME,
+                e
+            ))
+        );
+    }
+}
+
+// アーカイブに出力する場合はマニフェストもメンバーとして格納する
+// manifest.json を書き終えるまでは、途中経過の manifest.jsonl を消さない
+fn write_reports(entries: &[ManifestEntry], sink: &mut dyn Sink, options: &Options) {
+    let entries: Vec<ManifestEntry> = entries
+        .iter()
+        .map(|entry| reported_entry(options, entry))
+        .collect();
+    let entries = entries.as_slice();
+    let manifest = manifest::to_json(entries);
+    match sink.write_file(manifest::MANIFEST_FILE_NAME, manifest.as_bytes()) {
+        Ok(_) => {
+            if let Err(e) = sink.remove_journal() {
+                log_eprintln!(
+                    "{}",
+                    color::error(format_args!(
+                        "{} を削除できませんでした: {}",
+                        manifest::JOURNAL_FILE_NAME,
+                        e
+                    ))
+                );
+            }
+        }
+        Err(e) => log_eprintln!(
+            "{}",
+            color::error(format_args!("マニフェストの書き込みエラー: {}", e))
+        ),
+    }
+
+    if options.report == Some(ReportFormat::Csv) {
+        let report = manifest::to_csv(entries);
+        if let Err(e) = sink.write_file(manifest::CSV_REPORT_FILE_NAME, report.as_bytes()) {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("レポートの書き込みエラー: {}", e))
+            );
+        }
+    }
+
+    if options.html_report {
+        let html = gallery::to_html(entries);
+        if let Err(e) = sink.write_file(gallery::GALLERY_FILE_NAME, html.as_bytes()) {
+            log_eprintln!(
+                "{}",
+                color::error(format_args!("HTMLレポートの書き込みエラー: {}", e))
+            );
+        }
+    }
+
+    if let Err(e) = sink.finish() {
+        log_eprintln!(
+            "{}",
+            color::error(format_args!("出力先の書き込みエラー: {}", e))
+        );
+    }
+}
+
+fn find_signature(buffer: &[u8], signature: &[u8]) -> Option<usize> {
+    buffer.windows(signature.len()).position(|window| window == signature)
+}
+
+// ファイルの境界を探すときは、抽出対象外の種類も含めてすべてのシグネチャを使う
+fn find_all_starts(buffer: &[u8]) -> Vec<(usize, FileType)> {
+    find_starts(buffer, &FileType::ALL)
+}
+
+// ブロック境界をまたぐシグネチャ（フッターも含む）を見逃さないために持ち越す長さ
+fn longest_signature(types: &[FileType]) -> usize {
+    types
+        .iter()
+        .flat_map(|t| t.start_signatures().iter().chain(t.end_signatures()))
+        .map(Signature::len)
+        .max()
+        .unwrap_or(0)
+}
+
+// --overlap を指定しなければ、最も長いシグネチャが1バイトだけ欠けて残る長さ
+fn scan_overlap(options: &Options) -> usize {
+    options
+        .overlap
+        .unwrap_or_else(|| longest_signature(&options.types).saturating_sub(1))
+}
+
+// 種類をまたいで同じシグネチャ（RW2 と DNG の II*\0）は1回だけ数える
+fn count_starts(buffer: &[u8], types: &[FileType]) -> usize {
+    let mut signatures: Vec<Signature> = Vec::new();
+    for signature in types.iter().flat_map(|t| t.start_signatures()) {
+        if !signatures.contains(signature) {
+            signatures.push(*signature);
+        }
+    }
+    signatures
+        .iter()
+        .map(|signature| {
+            buffer
+                .windows(signature.magic.len())
+                .filter(|window| *window == signature.magic)
+                .count()
+        })
+        .sum()
+}
+
+// file_type として扱える最初のファイル先頭の位置（magic の位置から offset だけ戻ったところ）
+// before より後ろから始まるものは探さない
+fn find_start(buffer: &[u8], file_type: FileType, before: usize) -> Option<usize> {
+    file_type
+        .start_signatures()
+        .iter()
+        .filter_map(|signature| {
+            let searched = buffer
+                .len()
+                .min(before.saturating_add(signature.offset + signature.magic.len()));
+            let mut from = 0;
+            while let Some(idx) = find_signature(&buffer[from..searched], signature.magic) {
+                let magic_pos = from + idx;
+                from = magic_pos + 1;
+                // ファイル先頭がバッファより前にある
+                let Some(start) = magic_pos.checked_sub(signature.offset) else {
+                    continue;
+                };
+                if start >= before {
+                    break;
+                }
+                if file_type.claims(&buffer[start..]) {
+                    return Some(start);
+                }
+            }
+            None
+        })
+        .min()
+}
+
+// buffer_offset は buffer[0] のデバイス上のオフセット（--step の境界はデバイス先頭から数える）
+fn first_candidate(
+    buffer: &[u8],
+    buffer_offset: u64,
+    options: &Options,
+) -> Option<(usize, FileType)> {
+    if options.scan_step > 1 {
+        first_start_stepped(buffer, &options.types, buffer_offset, options.scan_step)
+    } else {
+        first_start(buffer, &options.types)
+    }
+}
+
+// 終端を待つファイルは、溜めた長さが倍になるか上限に届くまで終端検出をやり直さない
+// 終端検出は毎回ファイルの先頭から読み直すので、ブロックごとにやり直すとファイルの大きさの2乗かかる
+// 終わりはどの長さで調べても同じ位置に決まる（--block-size で変わらない）ので、結果は変わらない
+fn next_end_check(available: usize, file_type: FileType, options: &Options) -> usize {
+    let limit = options.max_file_size_for(file_type);
+    let until = [limit, limit.saturating_mul(BUFFER_CAP_FACTOR)]
+        .into_iter()
+        .filter(|&threshold| threshold > available as u64)
+        .fold(available.saturating_mul(2) as u64, u64::min);
+    usize::try_from(until).unwrap_or(usize::MAX)
+}
+
+// base_offset + idx が step の倍数になる buffer 内の位置
+fn stepped_positions(buffer_len: usize, base_offset: u64, step: usize) -> StepBy<Range<usize>> {
+    let first = (step as u64 - base_offset % step as u64) % step as u64;
+    (first as usize..buffer_len).step_by(step)
+}
+
+// data の先頭から始まる、types のうち最初の種類
+fn start_here(data: &[u8], types: &[FileType]) -> Option<FileType> {
+    types.iter().copied().find(|file_type| {
+        file_type
+            .start_signatures()
+            .iter()
+            .any(|signature| signature.matches(data))
+            && file_type.claims(data)
+    })
+}
+
+// first_start と同じ結果を、stepped_positions の位置だけを調べて返す
+fn first_start_stepped(
+    buffer: &[u8],
+    types: &[FileType],
+    base_offset: u64,
+    step: usize,
+) -> Option<(usize, FileType)> {
+    stepped_positions(buffer.len(), base_offset, step)
+        .find_map(|pos| start_here(&buffer[pos..], types).map(|file_type| (pos, file_type)))
+}
+
+fn find_starts(buffer: &[u8], types: &[FileType]) -> Vec<(usize, FileType)> {
+    let mut results: Vec<(usize, FileType)> = types
+        .iter()
+        .filter_map(|t| find_start(buffer, *t, buffer.len()).map(|idx| (idx, *t)))
+        .collect();
+
+    results.sort_by_key(|k| k.0);
+    results
+}
+
+// find_starts の先頭と同じもの。見つかった位置より後ろは、残りの種類で探さない
+// 終端を待つファイルを読み足したバッファは先頭がそのファイルなので、バッファ全体は探さない
+fn first_start(buffer: &[u8], types: &[FileType]) -> Option<(usize, FileType)> {
+    if let Some(file_type) = start_here(buffer, types) {
+        return Some((0, file_type));
+    }
+    types.iter().fold(None, |first, &file_type| {
+        let before = first.map_or(buffer.len(), |(idx, _)| idx);
+        find_start(buffer, file_type, before)
+            .map(|idx| (idx, file_type))
+            .or(first)
+    })
+}
+
+// 保存できたらファイル名と、書き込みながら求めた SHA-256 を返す
+// 保存先がいっぱいで失敗したときは、書きかけのファイルを消してから返す
+// original があれば、中身が同じその保存済みファイルへのリンクとして置く（ハッシュは求めない）
+fn save_file(
+    sink: &mut dyn Sink,
+    data: &[u8],
+    name: String,
+    original: Option<&str>,
+) -> io::Result<(String, Option<String>)> {
+    let result = match original {
+        Some(original) => sink.write_duplicate(&name, original, data).map(|()| None),
+        None => sink.write_file(&name, data).map(Some),
+    };
+    match result {
+        Ok(digest) => Ok((name, digest)),
+        Err(e) => {
+            if output::is_disk_full(&e) {
+                let _ = sink.discard(&name);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use test_util::{minimal_jpeg, temp_output_dir};
+
+    const JPEG_START: &[u8] = &[0xFF, 0xD8];
+
+    // ---------------------------
+    // Tests for find_signature
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_correct_index_when_signature_is_found() {
+        // 1. setup
+        let buffer = [0x00, 0x11, 0x22, 0xFF, 0xD8, 0x33, 0x44];
+        let signature = JPEG_START;
+
+        // 2. execute
+        let result = find_signature(&buffer, signature);
+        println!("{:?}", result);
+
+        // 3. verify
+        assert_eq!(result, Some(3));
+    }
+
+    #[test]
+    fn test_should_return_none_when_signature_is_not_found() {
+        // 1. setup
+        let buffer = [0x00, 0x11, 0x22, 0x33, 0x44];
+        let signature = JPEG_START;
+
+        // 2. execute
+        let result = find_signature(&buffer, signature);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_should_return_first_occurrence_when_multiple_matches_exist() {
+        // 1. setup
+        let buffer = [0xFF, 0xD8, 0x00, 0xFF, 0xD8, 0x01];
+        let signature = JPEG_START;
+
+        // 2. execute
+        let result = find_signature(&buffer, signature);
+
+        // 3. verify
+        assert_eq!(result, Some(0)); // 最初の0番目のマッチを返す
+    }
+
+    // ---------------------------
+    // Tests for first_start_stepped
+    // ---------------------------
+
+    #[test]
+    fn test_should_find_only_aligned_jpeg_when_step_is_512() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut buffer = vec![0x00; 64 * 1024];
+        buffer[1000..1000 + jpeg.len()].copy_from_slice(&jpeg); // 境界からずれている
+        buffer[4096..4096 + jpeg.len()].copy_from_slice(&jpeg);
+
+        // 2. execute
+        let result = first_start_stepped(&buffer, &[FileType::Jpeg], 0, 512);
+        let examined = stepped_positions(buffer.len(), 0, 512).count();
+
+        // 3. verify
+        assert_eq!(result, Some((4096, FileType::Jpeg)));
+        assert_eq!(examined, 128);
+        assert!(examined < buffer.len());
+    }
+
+    #[test]
+    fn test_should_align_positions_to_device_offset_when_buffer_starts_mid_block() {
+        // 1. setup
+        let base_offset = 512 * 3 + 100;
+
+        // 2. execute
+        let positions: Vec<usize> = stepped_positions(1024, base_offset, 512).collect();
+
+        // 3. verify
+        assert_eq!(positions, vec![412, 924]);
+    }
+
+    // ---------------------------
+    // Tests for find_all_starts
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_jpeg_and_rw2_when_both_signatures_exist() {
+        // 1. setup
+        let buffer = [
+            0x00, 0xFF, 0xD8, 0x01, 0x49, 0x49, 0x2A, 0x00, 0x02,
+        ];
+
+        // 2. execute
+        let results = find_all_starts(&buffer);
+
+        // 3. verify
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], (1, FileType::Jpeg));
+        assert_eq!(results[1], (4, FileType::Rw2));
+    }
+
+    #[test]
+    fn test_should_return_only_jpeg_when_only_jpeg_signature_exists() {
+        // 1. setup
+        let buffer = [0xFF, 0xD8, 0x10, 0x20];
+
+        // 2. execute
+        let results = find_all_starts(&buffer);
+
+        // 3. verify
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0], (0, FileType::Jpeg));
+    }
+
+    #[test]
+    fn test_should_return_only_rw2_when_only_rw2_signature_exists() {
+        // 1. setup
+        let buffer = [0x49, 0x49, 0x2A, 0x00, 0xAA, 0xBB];
+
+        // 2. execute
+        let results = find_all_starts(&buffer);
+
+        // 3. verify
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0], (0, FileType::Rw2));
+    }
+
+    #[test]
+    fn test_should_match_first_of_find_starts_when_first_start_is_used() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let rw2 = [0x49, 0x49, 0x2A, 0x00, 0xAA, 0xBB];
+        let buffer = [&[0x00; 3][..], &rw2, &[0x00; 5], &jpeg, &rw2, &jpeg].concat();
+
+        for from in 0..buffer.len() {
+            // 2. execute
+            let result = first_start(&buffer[from..], &FileType::ALL);
+
+            // 3. verify
+            assert_eq!(
+                result,
+                find_all_starts(&buffer[from..]).first().copied(),
+                "from={}",
+                from
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_return_empty_when_no_signatures_exist() {
+        // 1. setup
+        let buffer = [0x00, 0x01, 0x02, 0x03, 0x04];
+
+        // 2. execute
+        let results = find_all_starts(&buffer);
+
+        // 3. verify
+        assert_eq!(results.len(), 0);
+    }
+
+    #[test]
+    fn test_should_scan_to_the_end_when_each_signature_is_at_the_device_tail() {
+        // 1. setup
+        let output_dir = temp_output_dir("signature_at_tail");
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+        let signatures: Vec<&[u8]> = FileType::ALL
+            .iter()
+            .flat_map(|t| t.start_signatures().iter().map(|s| s.magic))
+            .collect();
+
+        for signature in signatures {
+            // 読み込みブロックの終わりちょうどと、半端な長さの両方
+            for padding in [READ_BLOCK_SIZE - signature.len(), 1000] {
+                let mut device = vec![0x00; padding];
+                device.extend_from_slice(signature);
+
+                // 2. execute
+                let summary = recover(&mut Cursor::new(device.clone()), &options);
+
+                // 3. verify
+                assert_eq!(summary.scanned_bytes, device.len() as u64);
+            }
+        }
+    }
+
+    // ---------------------------
+    // Tests for open_error_hint
+    // ---------------------------
+
+    #[test]
+    fn test_should_suggest_elevated_privileges_when_permission_is_denied() {
+        // 1. setup
+        let kind = io::ErrorKind::PermissionDenied;
+
+        // 2. execute
+        let hint = open_error_hint(kind).unwrap();
+
+        // 3. verify
+        assert!(hint.contains("sudo"));
+        assert!(hint.contains("disk グループ"));
+    }
+
+    #[test]
+    fn test_should_suggest_checking_device_path_when_device_is_not_found() {
+        // 1. setup
+        let kind = io::ErrorKind::NotFound;
+
+        // 2. execute
+        let hint = open_error_hint(kind).unwrap();
+
+        // 3. verify
+        assert!(hint.contains("パス"));
+        assert!(!hint.contains("sudo"));
+    }
+
+    #[test]
+    fn test_should_return_no_hint_when_error_kind_is_unrelated() {
+        // 1. setup
+        let kind = io::ErrorKind::Interrupted;
+
+        // 2. execute
+        let hint = open_error_hint(kind);
+
+        // 3. verify
+        assert_eq!(hint, None);
+    }
+
+    // ---------------------------
+    // Tests for recover
+    // ---------------------------
+
+    #[test]
+    fn test_should_zero_everything_outside_carved_regions_when_zero_fill_gaps_is_enabled() {
+        // 1. setup
+        let output_dir = temp_output_dir("zero_fill_gaps");
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut device = vec![0x11; 100];
+        device.extend_from_slice(&jpeg);
+        device.extend_from_slice(&[0x22; 50]);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            zero_fill_gaps: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device.clone()), &options);
+
+        // 3. verify
+        let expected = Region {
+            offset: 100,
+            len: jpeg.len() as u64,
+        };
+        assert_eq!(summary.regions, vec![expected]);
+        let image = std::fs::read(output_dir.join(zero_fill::RECONSTRUCTED_FILE_NAME)).unwrap();
+        assert_eq!(image.len(), device.len());
+        assert!(image[..100].iter().all(|&b| b == 0));
+        assert_eq!(&image[100..100 + jpeg.len()], &jpeg);
+        assert!(image[100 + jpeg.len()..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_should_write_csv_report_with_one_row_per_carved_file_when_report_is_csv() {
+        // 1. setup
+        let output_dir = temp_output_dir("report_csv");
+        let mut device = minimal_jpeg(&[0x01]);
+        device.extend_from_slice(&[0x00; 16]);
+        device.extend_from_slice(&minimal_jpeg(&[0x02]));
+        let options = Options {
+            output_dir: output_dir.clone(),
+            report: Some(ReportFormat::Csv),
+            ..Options::default()
+        };
+
+        // 2. execute
+        recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        let csv = std::fs::read_to_string(output_dir.join(manifest::CSV_REPORT_FILE_NAME)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "index,type,offset,size,sha256,confidence,filename"
+        );
+        assert!(lines[1].starts_with("0,jpeg,0,10,"));
+        assert!(lines[2].starts_with("1,jpeg,26,10,"));
+        assert!(lines[2].ends_with(",1.00,image_000001.jpg"));
+    }
+
+    #[test]
+    fn test_should_skip_manifested_offsets_and_recarve_missing_files_when_resuming() {
+        // 1. setup
+        let output_dir = temp_output_dir("resume");
+        let mut device = minimal_jpeg(&[0x01]);
+        device.extend_from_slice(&[0x00; 16]);
+        device.extend_from_slice(&minimal_jpeg(&[0x02]));
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+        recover(&mut Cursor::new(device.clone()), &options);
+        // 1つ目は中身を差し替えて切り出し直されないことを見分け、2つ目は消しておく
+        std::fs::write(output_dir.join("image_000000.jpg"), [0xAA; 10]).unwrap();
+        std::fs::remove_file(output_dir.join("image_000001.jpg")).unwrap();
+        let resume_options = Options {
+            resume: true,
+            ..options
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &resume_options);
+
+        // 3. verify
+        assert_eq!(summary.resumed_files, 1);
+        assert_eq!(summary.counter, 2);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.jpg")).unwrap(),
+            vec![0xAA; 10]
+        );
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000001.jpg")).unwrap(),
+            minimal_jpeg(&[0x02])
+        );
+        let manifest =
+            std::fs::read_to_string(output_dir.join(manifest::MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!(manifest::from_json(&manifest).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_should_skip_compressed_files_when_resuming_with_compress() {
+        // 1. setup
+        let output_dir = temp_output_dir("resume_compress");
+        let bmp = bmp::test_fixtures::minimal_bmp(4096);
+        let mut device = bmp.clone();
+        device.extend_from_slice(&minimal_jpeg(&[0x02]));
+        let options = Options {
+            output_dir: output_dir.clone(),
+            compress: true,
+            ..Options::default()
+        };
+        recover(&mut Cursor::new(device.clone()), &options);
+        let gz = output_dir.join("image_000000.bmp.gz");
+        let saved = std::fs::read(&gz).unwrap();
+        // 切り出し直されないことを見分けるため、同じ長さの別の中身に差し替える
+        std::fs::write(&gz, vec![0xAA; saved.len()]).unwrap();
+        let resume_options = Options {
+            resume: true,
+            ..options
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &resume_options);
+
+        // 3. verify
+        assert!(saved.len() < bmp.len());
+        assert_eq!(summary.resumed_files, 2);
+        assert_eq!(std::fs::read(&gz).unwrap(), vec![0xAA; saved.len()]);
+        assert_eq!(summary.entries[0].size, bmp.len() as u64);
+        assert_eq!(summary.entries[0].stored_size, Some(saved.len() as u64));
+    }
+
+    // limit バイト目より先を読もうとするとパニックする（途中で止まった実行の代わり）
+    struct CrashingReader {
+        inner: Cursor<Vec<u8>>,
+        limit: u64,
+    }
+
+    impl Read for CrashingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            assert!(self.inner.position() < self.limit, "読み込み中に停止");
+            self.inner.read(buf)
+        }
+    }
+
+    impl Source for CrashingReader {}
+
+    // stall_at バイト目に着いたとき、一度だけ終わりでもないのに Ok(0) を返す（長さは分からない）
+    struct StallingReader {
+        inner: Cursor<Vec<u8>>,
+        stall_at: u64,
+        stalled: bool,
+    }
+
+    impl Read for StallingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.stalled && self.inner.position() == self.stall_at {
+                self.stalled = true;
+                return Ok(0);
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Source for StallingReader {}
+
+    // snapshot_at を読み始めるときに、その時点の progress.json を控えておく
+    struct SnapshotReader {
+        inner: Cursor<Vec<u8>>,
+        snapshot_at: u64,
+        progress_path: std::path::PathBuf,
+        snapshot: Option<String>,
+    }
+
+    impl Read for SnapshotReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.snapshot.is_none() && self.inner.position() == self.snapshot_at {
+                self.snapshot = std::fs::read_to_string(&self.progress_path).ok();
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Source for SnapshotReader {}
+
+    // 読んだバイト数を数える
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        read_bytes: u64,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read_bytes += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Source for CountingReader {
+        fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+            self.inner.seek_to(offset)
+        }
+
+        fn total_len(&mut self) -> Option<u64> {
+            self.inner.total_len()
+        }
+    }
+
+    // 伝えられた読み方を控えておく
+    struct AdviceRecorder {
+        inner: Cursor<Vec<u8>>,
+        advices: Vec<(Advice, u64, u64)>,
+    }
+
+    impl Read for AdviceRecorder {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Source for AdviceRecorder {
+        fn advise(&mut self, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+            self.advices.push((advice, offset, len));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_should_advise_sequential_then_drop_scanned_ranges_when_fadvise_is_enabled() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01]);
+        let mut device = vec![0x00; 512 * 150];
+        device[1000..1000 + jpeg.len()].copy_from_slice(&jpeg);
+        let options = Options {
+            block_size: 512,
+            fadvise: true,
+            ..Options::default()
+        };
+        let mut reader = AdviceRecorder {
+            inner: Cursor::new(device),
+            advices: Vec::new(),
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut reader, &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        let advices = reader.advices;
+        assert_eq!(advices[0], (Advice::Sequential, 0, 0));
+        assert!(advices.len() >= 3);
+        // 落とす範囲は隙間も重なりもなく続き、最後は終わりまで
+        let mut next = 0;
+        for &(advice, offset, len) in &advices[1..advices.len() - 1] {
+            assert_eq!((advice, offset), (Advice::DontNeed, next));
+            assert!(len >= FADVISE_BLOCKS * 512);
+            next = offset + len;
+        }
+        assert_eq!(advices[advices.len() - 1], (Advice::DontNeed, next, 0));
+    }
+
+    #[test]
+    fn test_should_update_progress_file_while_scanning_when_progress_file_is_enabled() {
+        // 1. setup
+        let output_dir = temp_output_dir("progress_file");
+        let jpeg = minimal_jpeg(&[0x01]);
+        let mut device = jpeg.clone();
+        device.resize(64, 0x00);
+        device.extend_from_slice(&jpeg);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            block_size: 32,
+            progress_file: true,
+            ..Options::default()
+        };
+        let mut reader = SnapshotReader {
+            inner: Cursor::new(device),
+            snapshot_at: 32,
+            progress_path: output_dir.join(progress::PROGRESS_FILE_NAME),
+            snapshot: None,
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut reader, &options, &mut sink);
+
+        // 3. verify
+        let during = reader.snapshot.unwrap();
+        assert!(during.starts_with("{\"scanned_bytes\": 32, \"total_bytes\": "));
+        assert!(during.contains("\"finished\": false"));
+        let after = std::fs::read_to_string(output_dir.join(progress::PROGRESS_FILE_NAME)).unwrap();
+        assert!(after.contains("\"files_found\": 2"));
+        assert!(after.contains("\"eta_seconds\": 0, \"finished\": true"));
+    }
+
+    #[test]
+    fn test_should_keep_scanning_when_reader_returns_spurious_zero_read() {
+        // 1. setup
+        let first = minimal_jpeg(&[0x01]);
+        let second = minimal_jpeg(&[0x02]);
+        let mut device = first.clone();
+        device.resize(64, 0x00);
+        device.extend_from_slice(&second);
+        let options = Options {
+            block_size: 32,
+            ..Options::default()
+        };
+        let mut reader = StallingReader {
+            inner: Cursor::new(device),
+            stall_at: 32,
+            stalled: false,
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut reader, &options, &mut sink);
+
+        // 3. verify
+        assert!(reader.stalled);
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.entries[1].offset, 64);
+        assert_eq!(sink.files[1].1, second);
+    }
+
+    #[test]
+    fn test_should_keep_every_saved_entry_in_journal_when_run_is_cut_short() {
+        // 1. setup
+        let output_dir = temp_output_dir("journal_crash");
+        let mut device = minimal_jpeg(&[0x01]);
+        device.extend_from_slice(&minimal_jpeg(&[0x02]));
+        device.resize(4 * 4096, 0x00);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            block_size: 4096,
+            ..Options::default()
+        };
+        let mut reader = CrashingReader {
+            inner: Cursor::new(device),
+            limit: 2 * 4096,
+        };
+
+        // 2. execute
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            recover(&mut reader, &options)
+        }));
+
+        // 3. verify
+        assert!(result.is_err());
+        assert!(!output_dir.join(manifest::MANIFEST_FILE_NAME).exists());
+        let journal =
+            std::fs::read_to_string(output_dir.join(manifest::JOURNAL_FILE_NAME)).unwrap();
+        let entries = manifest::from_json_lines(&journal);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].filename, "image_000001.jpg");
+    }
+
+    #[test]
+    fn test_should_remove_journal_when_manifest_is_written_on_clean_exit() {
+        // 1. setup
+        let output_dir = temp_output_dir("journal_clean");
+        let device = minimal_jpeg(&[0x01]);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert!(output_dir.join(manifest::MANIFEST_FILE_NAME).exists());
+        assert!(!output_dir.join(manifest::JOURNAL_FILE_NAME).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_should_hard_link_duplicate_to_first_copy_when_flattening_duplicates() {
+        use std::os::unix::fs::MetadataExt;
+
+        // 1. setup
+        let output_dir = temp_output_dir("hardlink_duplicates");
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let device = [
+            jpeg.clone(),
+            vec![0x00; 16],
+            minimal_jpeg(&[0x03]),
+            vec![0x00; 16],
+            jpeg.clone(),
+        ]
+        .concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            flatten_duplicates_to_hardlinks: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 3);
+        let first = std::fs::metadata(output_dir.join("image_000000.jpg")).unwrap();
+        let duplicate = std::fs::metadata(output_dir.join("image_000002.jpg")).unwrap();
+        let other = std::fs::metadata(output_dir.join("image_000001.jpg")).unwrap();
+        assert_eq!(duplicate.ino(), first.ino());
+        assert_ne!(other.ino(), first.ino());
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000002.jpg")).unwrap(),
+            jpeg
+        );
+    }
+
+    #[test]
+    fn test_should_write_html_gallery_referencing_each_file_when_html_report_is_enabled() {
+        // 1. setup
+        let output_dir = temp_output_dir("html_report");
+        let bmp = bmp::test_fixtures::minimal_bmp(16);
+        let device = [minimal_jpeg(&[0x01]), vec![0x00; 16], bmp].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            html_report: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        let html = std::fs::read_to_string(output_dir.join(gallery::GALLERY_FILE_NAME)).unwrap();
+        assert_eq!(summary.entries.len(), 2);
+        for entry in &summary.entries {
+            assert!(html.contains(&format!("<img src=\"{}\"", entry.filename)));
+        }
+    }
+
+    #[test]
+    fn test_should_carve_only_inside_partition_when_reader_is_limited() {
+        // 1. setup
+        let output_dir = temp_output_dir("partition_only");
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let mut device = partition::test_fixtures::mbr(&[(0x0C, 8, 16)]);
+        device.resize(32 * 512, 0x00);
+        device[2 * 512..2 * 512 + jpeg.len()].copy_from_slice(&jpeg); // パーティションの手前
+        device[8 * 512 + 100..8 * 512 + 100 + jpeg.len()].copy_from_slice(&jpeg);
+        device[24 * 512 + 10..24 * 512 + 10 + jpeg.len()].copy_from_slice(&jpeg); // パーティションの後ろ
+        let table = partition::parse(&device, 512);
+        let part = table.partitions()[0].clone();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let mut reader = source::Limited::new(Cursor::new(device), part.start, part.len).unwrap();
+        let summary = recover(&mut reader, &options);
+
+        // 3. verify
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![8 * 512 + 100]);
+        assert_eq!(summary.scanned_bytes, 16 * 512);
+    }
+
+    #[test]
+    fn test_should_carve_across_disconnect_when_device_is_reopened() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01; 200]);
+        let mut device = vec![0x00; 8192];
+        device[1000..1000 + jpeg.len()].copy_from_slice(&jpeg);
+        device[3000 - 50..3000 - 50 + jpeg.len()].copy_from_slice(&jpeg);
+        device[6000..6000 + jpeg.len()].copy_from_slice(&jpeg);
+        let disconnecting = source::test_fixtures::DisconnectingReader {
+            inner: Cursor::new(device.clone()),
+            fail_at: 3000,
+        };
+        let reopen = || {
+            Ok(source::test_fixtures::DisconnectingReader {
+                inner: Cursor::new(device.clone()),
+                fail_at: u64::MAX,
+            })
+        };
+        let mut reader =
+            source::Reconnecting::new(disconnecting, reopen, 1, std::time::Duration::ZERO);
+        let options = Options {
+            block_size: 512,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut reader, &options, &mut sink);
+
+        // 3. verify
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![1000, 2950, 6000]);
+        assert_eq!(summary.scanned_bytes, 8192);
+    }
+
+    #[test]
+    fn test_should_recover_past_declared_size_when_scan_beyond_size_is_enabled() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01; 200]);
+        let mut device = vec![0x00; 8192];
+        device[1000..1000 + jpeg.len()].copy_from_slice(&jpeg);
+        device[5000..5000 + jpeg.len()].copy_from_slice(&jpeg);
+        let options = Options {
+            block_size: 512,
+            ..Options::default()
+        };
+        let reopened = std::cell::Cell::new(0);
+        let scan = |beyond| {
+            let underreporting = source::test_fixtures::UnderreportingReader {
+                inner: Cursor::new(device.clone()),
+                declared: 4096,
+                readable: 8192,
+                probed: false,
+            };
+            let reopen = || {
+                reopened.set(reopened.get() + 1);
+                Err(io::Error::other("No such device"))
+            };
+            let mut reader = source::Reconnecting::new(
+                source::BeyondSize::new(underreporting, beyond),
+                reopen,
+                1,
+                std::time::Duration::ZERO,
+            );
+            recover_to(&mut reader, &options, &mut output::MemorySink::default())
+        };
+
+        // 2. execute
+        let beyond = scan(true);
+        let declared = scan(false);
+
+        // 3. verify
+        let offsets =
+            |summary: &Summary| -> Vec<u64> { summary.entries.iter().map(|e| e.offset).collect() };
+        assert_eq!(offsets(&beyond), vec![1000, 5000]);
+        assert_eq!(beyond.scanned_bytes, 8192);
+        assert_eq!(offsets(&declared), vec![1000]);
+        assert_eq!(declared.scanned_bytes, 4096);
+        // 本当の終わりの読み取りエラーで、抜けたとみなして開き直そうとしない
+        assert_eq!(reopened.get(), 0);
+    }
+
+    #[test]
+    fn test_should_write_partition_relative_offsets_when_offset_base_is_partition() {
+        // 1. setup
+        let output_dir = temp_output_dir("offset_base_partition");
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let mut device = partition::test_fixtures::mbr(&[(0x0C, 8, 16)]);
+        device.resize(32 * 512, 0x00);
+        device[8 * 512 + 100..8 * 512 + 100 + jpeg.len()].copy_from_slice(&jpeg);
+        let table = partition::parse(&device, 512);
+        let part = table.partitions()[0].clone();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            stable_names: true,
+            offset_base: OffsetBase::Partition,
+            partition_start: part.start,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let mut reader = source::Limited::new(Cursor::new(device), part.start, part.len).unwrap();
+        let summary = recover(&mut reader, &options);
+
+        // 3. verify
+        let manifest =
+            std::fs::read_to_string(output_dir.join(manifest::MANIFEST_FILE_NAME)).unwrap();
+        let written = manifest::from_json(&manifest).unwrap();
+        assert_eq!(written[0].offset, summary.entries[0].offset - part.start);
+        assert_eq!(written[0].offset, 100);
+        assert_eq!(written[0].filename, "image_000000000100.jpg");
+        assert!(output_dir.join("image_000000000100.jpg").exists());
+    }
+
+    // ---------------------------
+    // Tests for recover_at_offsets
+    // ---------------------------
+
+    #[test]
+    fn test_should_carve_listed_offsets_when_worklist_has_invalid_lines() {
+        // 1. setup
+        let output_dir = temp_output_dir("worklist");
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let mut device = vec![0x00; 8192];
+        device[100..100 + jpeg.len()].copy_from_slice(&jpeg);
+        device[5000..5000 + jpeg.len()].copy_from_slice(&jpeg);
+        let worklist = worklist::parse("100\nbogus\n0x1388\n999\n");
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover_at_offsets(&mut Cursor::new(device), &worklist.offsets, &options);
+
+        // 3. verify
+        assert_eq!(worklist.invalid, vec![(2, "bogus".to_string())]);
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![100, 5000]);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000001.jpg")).unwrap(),
+            jpeg
+        );
+    }
+
+    // ---------------------------
+    // Tests for scan_only
+    // ---------------------------
+
+    #[test]
+    fn test_should_count_each_start_signature_once_when_signatures_straddle_blocks() {
+        // 1. setup
+        let mut device = vec![0x00; READ_BLOCK_SIZE * 3];
+        for offset in [100, 5000, READ_BLOCK_SIZE - 1] {
+            device[offset..offset + JPEG_START.len()].copy_from_slice(JPEG_START);
+        }
+        for offset in [20000, READ_BLOCK_SIZE * 2 - 2] {
+            device[offset..offset + RW2_START.len()].copy_from_slice(RW2_START);
+        }
+
+        // 2. execute
+        let result = scan_only(&mut Cursor::new(device), &Options::default());
+
+        // 3. verify
+        assert_eq!(result.scanned_bytes, (READ_BLOCK_SIZE * 3) as u64);
+        assert_eq!(
+            result.counts,
+            vec![
+                (FileType::Jpeg, 3),
+                (FileType::Rw2, 2),
+                (FileType::Zip, 0),
+                (FileType::Bmp, 0),
+                (FileType::Webp, 0),
+                (FileType::Dng, 0),
+                (FileType::Mp4, 0),
+                (FileType::Crw, 0),
+                (FileType::Tga, 0),
+                (FileType::Cr3, 0),
+                (FileType::Orf, 0),
+                (FileType::Raf, 0),
+                (FileType::Psd, 0),
+                (FileType::Nrw, 0),
+                (FileType::X3f, 0),
+                (FileType::Tiff, 0),
+                (FileType::Xmp, 0),
+                (FileType::Dicom, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_count_same_signatures_when_thread_count_differs() {
+        // 1. setup
+        let mut device = vec![0x00; 3 * 4096 + 100];
+        for offset in (0..device.len() - 8).step_by(509) {
+            device[offset..offset + JPEG_START.len()].copy_from_slice(JPEG_START);
+            device[offset + 4..offset + 4 + RW2_START.len()].copy_from_slice(RW2_START);
+        }
+        let single = Options {
+            block_size: 4096,
+            threads: 1,
+            ..Options::default()
+        };
+        let parallel = Options {
+            threads: 7,
+            ..single.clone()
+        };
+
+        // 2. execute
+        let single_counts = scan_only(&mut Cursor::new(device.clone()), &single);
+        let parallel_counts = scan_only(&mut Cursor::new(device), &parallel);
+
+        // 3. verify
+        assert_eq!(parallel_counts.counts, single_counts.counts);
+        assert_eq!(single_counts.counts[0], (FileType::Jpeg, 25));
+    }
+
+    #[test]
+    fn test_should_miss_straddling_signature_when_overlap_is_shorter_than_signature() {
+        // 1. setup
+        let mut device = vec![0x00; 64];
+        device[15..15 + JPEG_START.len()].copy_from_slice(JPEG_START);
+        let base = Options {
+            types: vec![FileType::Jpeg],
+            block_size: 16,
+            ..Options::default()
+        };
+        let short = Options {
+            overlap: Some(0),
+            ..base.clone()
+        };
+
+        // 2. execute
+        let default_counts = scan_only(&mut Cursor::new(device.clone()), &base).counts;
+        let short_counts = scan_only(&mut Cursor::new(device), &short).counts;
+
+        // 3. verify
+        assert_eq!(default_counts, vec![(FileType::Jpeg, 1)]);
+        assert_eq!(short_counts, vec![(FileType::Jpeg, 0)]);
+    }
+
+    #[test]
+    fn test_should_leave_filesystem_untouched_when_only_scanning() {
+        // 1. setup
+        let output_dir = temp_output_dir("scan_only_untouched").join("recovered");
+        let device = [minimal_jpeg(&[0x01]), vec![0x00; 16]].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            dry_run_scan_only: true,
+            no_create_output: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        run(&mut Cursor::new(device), &options, None, Instant::now());
+
+        // 3. verify
+        assert!(!output_dir.exists());
+    }
+
+    #[test]
+    fn test_should_stream_carved_files_and_manifest_into_tar_when_archive_is_given() {
+        // 1. setup
+        let output_dir = temp_output_dir("archive_tar");
+        let archive = output_dir.join("recovered.tar");
+        let first = minimal_jpeg(&[0x01]);
+        let second = minimal_jpeg(&[0x02, 0x03]);
+        let device = [first.clone(), vec![0x00; 8], second.clone()].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            archive: Some(archive.clone()),
+            ..Options::default()
+        };
+
+        // 2. execute
+        recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        let members = output::test_readers::tar_members(&std::fs::read(&archive).unwrap());
+        let names: Vec<&str> = members.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "image_000000.jpg",
+                "image_000001.jpg",
+                manifest::MANIFEST_FILE_NAME
+            ]
+        );
+        assert_eq!(members[0].1, first);
+        assert_eq!(members[1].1, second);
+        assert!(!output_dir.join("image_000000.jpg").exists());
+    }
+
+    #[test]
+    fn test_should_stop_writing_when_max_total_size_would_be_exceeded() {
+        // 1. setup
+        let output_dir = temp_output_dir("max_total_size");
+        let jpeg = minimal_jpeg(&[0x01]);
+        let device = [jpeg.clone(), jpeg.clone(), jpeg.clone()].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            max_total_size: Some(jpeg.len() as u64 * 2 + 1),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.written_bytes, jpeg.len() as u64 * 2);
+        assert!(output_dir.join("image_000001.jpg").exists());
+        assert!(!output_dir.join("image_000002.jpg").exists());
+        let manifest =
+            std::fs::read_to_string(output_dir.join(manifest::MANIFEST_FILE_NAME)).unwrap();
+        assert!(manifest.contains("image_000001.jpg"));
+    }
+
+    // 暗号化の見積もりは等間隔の標本、候補の多いブロックの判定は個数だけで決まり、乱数を使う見積もりはない
+    // 同じ入力からは種を指定しなくても毎回同じ結果になること
+    #[test]
+    fn test_should_produce_identical_results_when_same_input_is_scanned_twice() {
+        // 1. setup
+        let mut device = [0xFF, 0xD8].repeat(4096);
+        device.extend_from_slice(&minimal_jpeg(&[0x01]));
+        let noise: Vec<u8> = (0..1u32 << 18)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        device.extend_from_slice(&noise);
+        device.extend_from_slice(&minimal_jpeg(&[0x02]));
+        let options = Options {
+            block_size: 8192,
+            max_candidates_per_block: Some(1000),
+            ..Options::default()
+        };
+        let run = || {
+            let mut sink = output::MemorySink::default();
+            let summary = recover_to(&mut Cursor::new(device.clone()), &options, &mut sink);
+            let encrypted = entropy::looks_encrypted(&mut Cursor::new(device.clone())).unwrap();
+            (summary.noise_blocks, summary.entries, sink.files, encrypted)
+        };
+
+        // 2. execute
+        let first = run();
+        let second = run();
+
+        // 3. verify
+        assert_eq!(first.0, 1);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_should_skip_block_as_noise_when_it_has_too_many_candidates() {
+        // 1. setup
+        let output_dir = temp_output_dir("candidate_storm");
+        let mut device = [0xFF, 0xD8].repeat(READ_BLOCK_SIZE / 2);
+        device.extend_from_slice(&minimal_jpeg(&[0x01]));
+        let options = Options {
+            output_dir: output_dir.clone(),
+            max_candidates_per_block: Some(1000),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.noise_blocks, 1);
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].offset, READ_BLOCK_SIZE as u64);
+    }
+
+    #[test]
+    fn test_should_keep_pending_file_up_to_noise_when_noise_block_interrupts_it() {
+        // 1. setup
+        let pending = minimal_jpeg(&[0x11; 20000]);
+        let after = minimal_jpeg(&[0x02]);
+        let device = [
+            &pending[..8192],
+            &[0xFF, 0xD8].repeat(4096),
+            after.as_slice(),
+        ]
+        .concat();
+        let options = Options {
+            block_size: 8192,
+            max_candidates_per_block: Some(1000),
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.noise_blocks, 1);
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![0, 16384]);
+        assert_eq!(
+            sink.files[0],
+            ("image_000000.jpg".to_string(), pending[..8192].to_vec())
+        );
+        assert_eq!(sink.files[1], ("image_000001.jpg".to_string(), after));
+    }
+
+    #[test]
+    fn test_should_anchor_mp4_at_box_start_when_ftyp_is_at_offset_four() {
+        // 1. setup
+        let output_dir = temp_output_dir("mp4_box_start");
+        let mp4 = bmff::test_fixtures::minimal_mp4(b"isom", 64);
+        let device = [vec![0x00; 100], mp4.clone(), minimal_jpeg(&[0x01])].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].file_type, FileType::Mp4);
+        assert_eq!(summary.entries[0].offset, 100);
+        assert_eq!(summary.entries[1].offset, 100 + mp4.len() as u64);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.mp4")).unwrap(),
+            mp4
+        );
+    }
+
+    #[test]
+    fn test_should_save_as_dng_when_ifd_has_dng_version_tag() {
+        // 1. setup
+        let output_dir = temp_output_dir("dng");
+        let dng = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 100),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+                (tiff::TAG_DNG_VERSION, 1, 4, 0x0000_0401),
+            ],
+            150,
+        );
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        let device = [dng.clone(), rw2].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        let types: Vec<FileType> = summary.entries.iter().map(|e| e.file_type).collect();
+        assert_eq!(types, vec![FileType::Dng, FileType::Rw2]);
+        assert_eq!(summary.entries[1].offset, 150);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.dng")).unwrap(),
+            dng
+        );
+    }
+
+    #[test]
+    fn test_should_not_carve_rw2_when_rw2_is_ignored() {
+        // 1. setup
+        let output_dir = temp_output_dir("ignore_types_rw2");
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        let device = [rw2, minimal_jpeg(&[0x01])].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..cli::parse_args(["--ignore-types".to_string(), "rw2".to_string()]).unwrap()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].file_type, FileType::Jpeg);
+        assert_eq!(summary.entries[0].offset, 50);
+    }
+
+    #[test]
+    fn test_should_carve_rw2_by_ifd_size_when_ifd_describes_strip_data() {
+        // 1. setup
+        let output_dir = temp_output_dir("rw2_ifd_size");
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 100),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            150,
+        );
+        let mut device = rw2.clone();
+        device.extend_from_slice(&[0x00; 30]); // 次のファイルまでの隙間
+        device.extend_from_slice(&minimal_jpeg(&[0x01]));
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.entries[0].size, 150);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(summary.entries[1].offset, 180);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.rw2")).unwrap(),
+            rw2
+        );
+    }
+
+    #[test]
+    fn test_should_save_with_sniffed_extension_when_sniff_disagrees_with_signature() {
+        // 1. setup
+        let output_dir = temp_output_dir("sniff_mismatch");
+        // RW2のスタートシグネチャに一致するが、中身は素のTIFF
+        let tiff = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 100),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            150,
+        );
+        let options = Options {
+            output_dir: output_dir.clone(),
+            sniff: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(tiff), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].file_type, FileType::Rw2);
+        assert_eq!(summary.entries[0].filename, "image_000000.tif");
+        assert!(output_dir.join("image_000000.tif").exists());
+    }
+
+    #[test]
+    fn test_should_route_jpeg_to_fragmented_folder_when_scan_data_has_zero_gap() {
+        // 1. setup
+        let output_dir = temp_output_dir("fragmented_jpeg");
+        let mut scan = vec![0x5A; 2048];
+        scan.extend_from_slice(&[0x00; 8192]); // 別ファイルのクラスタの代わりのゼロ
+        scan.extend_from_slice(&[0xA5; 2048]);
+        let jpeg = minimal_jpeg(&scan);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(jpeg.clone()), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert!(summary.entries[0].fragmented);
+        assert_eq!(summary.entries[0].filename, "fragmented/image_000000.jpg");
+        assert_eq!(
+            std::fs::read(output_dir.join("fragmented").join("image_000000.jpg")).unwrap(),
+            jpeg
+        );
+    }
+
+    #[test]
+    fn test_should_carve_files_with_inline_signature_when_add_signature_is_given() {
+        // 1. setup
+        let options = cli::parse_args(
+            [
+                "--add-signature",
+                "png:89504E470D0A1A0A:49454E44AE426082:png",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+        let png = [
+            &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A][..],
+            &[0x11; 20],
+            &[0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82],
+        ]
+        .concat();
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let device = [&[0x00; 30][..], &png, &[0x00; 7], &jpeg, &png].concat();
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let types: Vec<&str> = summary.entries.iter().map(|e| e.file_type.name()).collect();
+        assert_eq!(types, vec!["png", "jpeg", "png"]);
+        assert_eq!(summary.entries[0].offset, 30);
+        assert_eq!(sink.files[0], ("image_000000.png".to_string(), png.clone()));
+        assert_eq!(sink.files[2], ("image_000002.png".to_string(), png));
+    }
+
+    #[test]
+    fn test_should_group_files_into_date_folders_when_output_pattern_is_date() {
+        // 1. setup
+        let output_dir = temp_output_dir("output_pattern_date");
+        let summer = exif::test_fixtures::jpeg_with_capture_date("2023:07:14 09:30:00", &[0x01]);
+        let new_year = exif::test_fixtures::jpeg_with_capture_date("2024:01:02 00:00:01", &[0x02]);
+        let undated = minimal_jpeg(&[0x03]);
+        let device = [summer.clone(), new_year.clone(), undated.clone()].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            output_pattern: Some(OutputPattern::Date),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        let names: Vec<&str> = summary
+            .entries
+            .iter()
+            .map(|e| e.filename.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "2023/07/14/image_000000.jpg",
+                "2024/01/02/image_000001.jpg",
+                "undated/image_000002.jpg"
+            ]
+        );
+        for (name, data) in names.iter().zip([summer, new_year, undated]) {
+            assert_eq!(std::fs::read(output_dir.join(name)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_should_collect_carved_files_in_memory_when_recovering_to_memory_sink() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let webp = webp::test_fixtures::minimal_webp(16);
+        let device = [vec![0x00; 100], jpeg.clone(), webp.clone()].concat();
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(sink.files[0], ("image_000000.jpg".to_string(), jpeg));
+        assert_eq!(sink.files[1], ("image_000001.webp".to_string(), webp));
+        assert_eq!(sink.files[2].0, manifest::MANIFEST_FILE_NAME);
+    }
+
+    // capacity バイトまでしか書けず、超えた分は途中まで書いてから StorageFull を返す
+    struct FullDiskSink {
+        capacity: usize,
+        files: Vec<(String, usize)>,
+        attempts: usize,
+    }
+
+    impl Sink for FullDiskSink {
+        fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<String> {
+            self.attempts += 1;
+            let used: usize = self.files.iter().map(|(_, len)| len).sum();
+            let len = data.len().min(self.capacity - used);
+            self.files.push((name.to_string(), len));
+            if len < data.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    "No space left on device",
+                ));
+            }
+            Ok(sha256::sha256_hex(data))
+        }
+
+        fn display_name(&self, name: &str) -> String {
+            name.to_string()
+        }
+
+        fn location(&self) -> Option<&std::path::Path> {
+            None
+        }
+
+        fn discard(&mut self, name: &str) -> io::Result<()> {
+            self.files.retain(|(file, _)| file != name);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_should_remove_partial_file_and_stop_when_disk_becomes_full() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x5A; 1000]);
+        let device = [jpeg.clone(), jpeg.clone(), jpeg.clone()].concat();
+        let mut sink = FullDiskSink {
+            capacity: 1500,
+            files: Vec::new(),
+            attempts: 0,
+        };
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        // 3つ目は書こうとせず、manifest はそれまでの分で書く
+        assert_eq!(sink.attempts, 3);
+        assert_eq!(
+            sink.files,
+            vec![
+                ("image_000000.jpg".to_string(), jpeg.len()),
+                (
+                    manifest::MANIFEST_FILE_NAME.to_string(),
+                    manifest::to_json(&summary.entries).len()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_recover_crw_up_to_next_file_when_header_is_canon_ciff() {
+        // 1. setup
+        let mut crw = CRW_START.to_vec();
+        crw.resize(4096, 0x33);
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [crw.clone(), jpeg.clone()].concat();
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.entries[0].file_type, FileType::Crw);
+        assert_eq!(sink.files[0], ("image_000000.crw".to_string(), crw));
+        assert_eq!(sink.files[1], ("image_000001.jpg".to_string(), jpeg));
+    }
+
+    #[test]
+    fn test_should_write_sector_bitmap_from_carved_ranges_when_enabled() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x5A; 600]);
+        let mut device = vec![0x00; 8 * 512];
+        device[1024..1024 + jpeg.len()].copy_from_slice(&jpeg);
+        let options = Options {
+            sector_bitmap: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let (_, bitmap) = sink
+            .files
+            .iter()
+            .find(|(name, _)| name == sector_map::SECTOR_BITMAP_FILE_NAME)
+            .unwrap();
+        // 1024 から 611 バイトはセクタ2と3
+        assert_eq!(bitmap, &vec![0b0000_1100]);
+    }
+
+    #[test]
+    fn test_should_write_bytes_up_to_cluster_boundary_when_trailing_slack_is_enabled() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        // 4096 バイトのクラスタ、クラスタヒープは (32 + 2 * 1) セクタ目から
+        let mut device = partition::test_fixtures::fat32_boot_sector(8, 1);
+        let file_start = 34 * 512 + 4096;
+        device.resize(file_start + 3 * 4096, 0x00);
+        device[file_start..file_start + jpeg.len()].copy_from_slice(&jpeg);
+        let slack_end = file_start + 4096;
+        device[file_start + jpeg.len()..slack_end].fill(0xAB);
+        let options = Options {
+            include_trailing_slack: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let (_, slack) = sink
+            .files
+            .iter()
+            .find(|(name, _)| name == "image_000000.slack")
+            .unwrap();
+        assert_eq!(slack, &vec![0xAB; 4096 - jpeg.len()]);
+    }
+
+    #[test]
+    fn test_should_save_only_files_in_free_clusters_when_only_deleted_is_enabled() {
+        // 1. setup
+        let live = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let deleted = minimal_jpeg(&[0x04, 0x05, 0x06]);
+        let device = fat::test_fixtures::fat32_image(&[
+            ("IMG_0001.JPG", &live, false),
+            ("IMG_0002.JPG", &deleted, true),
+        ]);
+        let options = Options {
+            only_deleted: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let deleted_offset =
+            (fat::test_fixtures::HEAP_OFFSET + 2 * fat::test_fixtures::SECTOR) as u64;
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![deleted_offset]);
+        assert_eq!(summary.skipped_allocated, 1);
+        assert_eq!(sink.files[0].1, deleted);
+    }
+
+    #[test]
+    fn test_should_join_fat_chain_fragments_when_reassemble_fragments_is_enabled() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x5A; 900]);
+        let after = minimal_jpeg(&[0x01, 0x02]);
+        let image = fat::test_fixtures::fragmented_fat32_image(&jpeg, 1, &[0x11; 600]);
+        let device = [image.as_slice(), &after].concat();
+        let options = Options {
+            block_size: 512,
+            reassemble_fragments: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(
+            summary.entries[0].offset,
+            fat::test_fixtures::HEAP_OFFSET as u64 + 512
+        );
+        assert_eq!(summary.entries[0].size, jpeg.len() as u64);
+        assert_eq!(sink.files[0].1, jpeg);
+        assert_eq!(sink.files[1].1, after);
+    }
+
+    #[test]
+    fn test_should_separate_deleted_live_and_missed_files_when_verify_against_fs_is_enabled() {
+        // 1. setup
+        let live = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let deleted = minimal_jpeg(&[0x04, 0x05, 0x06]);
+        let device = fat::test_fixtures::fat32_image(&[
+            ("IMG_0001.JPG", &live, false),
+            ("IMG_0002.JPG", &deleted, true),
+            ("NOTES.TXT", b"hello", false),
+        ]);
+        let options = Options {
+            verify_against_fs: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let (_, report) = sink
+            .files
+            .iter()
+            .find(|(name, _)| name == reconcile::RECONCILIATION_FILE_NAME)
+            .unwrap();
+        let report = String::from_utf8(report.clone()).unwrap();
+        let heap = fat::test_fixtures::HEAP_OFFSET;
+        let sector = fat::test_fixtures::SECTOR;
+        assert_eq!(
+            report,
+            format!(
+                "# 削除済みから復旧: 1 件\n{:#x}\t{}\timage_000001.jpg\n\
+                 # ファイルシステムに残っている: 1 件\n{:#x}\t{}\timage_000000.jpg\tIMG_0001.JPG\n\
+                 # 取りこぼし: 1 件\n{:#x}\t5\tNOTES.TXT\n",
+                heap + 2 * sector,
+                deleted.len(),
+                heap + sector,
+                live.len(),
+                heap + 3 * sector,
+            )
+        );
+    }
+
+    #[test]
+    fn test_should_dump_gap_between_carved_files_when_dump_unknown_is_enabled() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let gap: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8 | 0x01).collect();
+        let device = [jpeg.as_slice(), &gap, &jpeg].concat();
+        let options = Options {
+            dump_unknown: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let name = format!("unknown/region_{}.bin", jpeg.len());
+        let (_, dumped) = sink.files.iter().find(|(n, _)| *n == name).unwrap();
+        assert_eq!(dumped, &gap);
+    }
+
+    #[test]
+    fn test_should_number_first_file_from_counter_start_when_counter_start_is_given() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [jpeg.as_slice(), &jpeg].concat();
+        let options = Options {
+            counter_start: 10000,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].filename, "image_010000.jpg");
+        assert_eq!(summary.entries[1].filename, "image_010001.jpg");
+        assert_eq!(summary.entries[0].index, 10000);
+    }
+
+    #[test]
+    fn test_should_recover_tga_by_walking_back_from_footer_when_header_has_no_magic() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let tga = tga::test_fixtures::minimal_tga(16, 16);
+        let mut device = [jpeg.as_slice(), &[0x00; 100], &tga].concat();
+        device.resize(3 * 4096, 0x00);
+        let options = Options {
+            block_size: 4096,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        let entry = &summary.entries[1];
+        assert_eq!(entry.file_type, FileType::Tga);
+        assert_eq!(entry.offset, (jpeg.len() + 100) as u64);
+        assert_eq!(entry.confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(sink.files[1], ("image_000001.tga".to_string(), tga));
+    }
+
+    #[test]
+    fn test_should_gzip_bmp_but_not_jpeg_when_compress_is_enabled() {
+        // 1. setup
+        let bmp = bmp::test_fixtures::minimal_bmp(4096);
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [bmp.as_slice(), &jpeg].concat();
+        let options = Options {
+            compress: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(sink.files[0].0, "image_000000.bmp.gz");
+        assert!(sink.files[0].1.len() < bmp.len());
+        assert_eq!(gzip::test_fixtures::decompress(&sink.files[0].1), bmp);
+        assert_eq!(summary.entries[0].sha256, sha256::sha256_hex(&bmp));
+        assert_eq!(summary.entries[0].size, bmp.len() as u64);
+        assert_eq!(sink.files[1], ("image_000001.jpg".to_string(), jpeg));
+    }
+
+    #[test]
+    fn test_should_route_undecodable_jpeg_to_failed_when_quarantine_on_decode_failure_is_enabled() {
+        // 1. setup
+        let mut good = vec![0xFF, 0xD8];
+        // SOF0: 高さ 8, 幅 8
+        good.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01]);
+        good.extend_from_slice(&[0x01, 0x11, 0x00]);
+        good.extend_from_slice(&minimal_jpeg(&[0x01, 0x02])[2..]);
+        // SOF がない
+        let corrupt = minimal_jpeg(&[0x03, 0x04]);
+        let device = [good.as_slice(), &corrupt].concat();
+        let options = Options {
+            quarantine_on_decode_failure: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(sink.files[0].0, "image_000000.jpg");
+        assert_eq!(sink.files[1].0, "failed/image_000001.jpg");
+        assert_eq!(summary.entries[0].decode_failure, None);
+        assert_eq!(
+            summary.entries[1].decode_failure.as_deref(),
+            Some("SOF から画素数を読めません")
+        );
+        assert!(
+            manifest::to_json(&summary.entries)
+                .contains("\"decode_failure\": \"SOF から画素数を読めません\"")
+        );
+    }
+
+    #[test]
+    fn test_should_recover_jpeg_missed_by_strict_pass_when_rescan_failed_is_enabled() {
+        // 1. setup
+        // SOI の直後がマーカーではないので、strict では成り立たない
+        let broken = vec![0xFF, 0xD8, 0x12, 0x34, 0x56, 0xFF, 0xD9];
+        let jpeg = minimal_jpeg(&[0x01]);
+        let device = [&[0x00; 16][..], &broken, &[0x00; 16], &jpeg].concat();
+        let options = Options {
+            rescan_failed: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let strict = recover_to(
+            &mut Cursor::new(device.clone()),
+            &Options::default(),
+            &mut output::MemorySink::default(),
+        );
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(strict.entries.len(), 1);
+        assert_eq!(strict.failed_starts, vec![(16, FileType::Jpeg)]);
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![39, 16]);
+        assert_eq!(sink.files[1], ("image_000001.jpg".to_string(), broken));
+    }
+
+    #[test]
+    fn test_should_not_read_to_device_end_when_rescanning_unterminated_start() {
+        // 1. setup
+        // SOI と SOS の後ろがゼロのまま終わらない JPEG
+        let mut device = vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x03, 0x01];
+        device.resize(1 << 20, 0x00);
+        let options = Options {
+            block_size: 512,
+            rescan_failed: true,
+            max_file_size_by_type: vec![(FileType::Jpeg, 4096)],
+            ..Options::default()
+        };
+        let mut reader = CountingReader {
+            inner: Cursor::new(device.clone()),
+            read_bytes: 0,
+        };
+
+        // 2. execute
+        let summary = recover_to(&mut reader, &options, &mut output::MemorySink::default());
+
+        // 3. verify
+        assert!(summary.entries.is_empty());
+        assert_eq!(summary.failed_starts, vec![(0, FileType::Jpeg)]);
+        // 全体を1回読んだ後の見直しは、上限の BUFFER_CAP_FACTOR 倍と1ブロックまで
+        assert!(
+            reader.read_bytes <= device.len() as u64 + BUFFER_CAP_FACTOR * 4096 + 512,
+            "{}",
+            reader.read_bytes
+        );
+    }
+
+    #[test]
+    fn test_should_route_truncated_rw2_to_rejected_when_validate_raw_is_enabled() {
+        // 1. setup
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        // ストリップの途中でデバイスが終わる
+        let truncated = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 200),
+            ],
+            100,
+        );
+        let device = [rw2.as_slice(), &truncated].concat();
+        let options = Options {
+            validate_raw: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(sink.files[0], ("image_000000.rw2".to_string(), rw2));
+        assert_eq!(
+            sink.files[1],
+            ("rejected/image_000001.rw2".to_string(), truncated)
+        );
+    }
+
+    #[test]
+    fn test_should_end_jpeg_at_eoi_without_ff_padding_when_jpeg_eoi_run_is_enabled() {
+        // 1. setup
+        let padded = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let repeated = [minimal_jpeg(&[0x04]).as_slice(), &[0xFF, 0xFF, 0xD9]].concat();
+        let device = [
+            padded.as_slice(),
+            &[0xFF, 0xFF],
+            &repeated,
+            &[0xFF, 0xFF, 0x00],
+        ]
+        .concat();
+        let options = Options {
+            jpeg_eoi_run: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(sink.files[0], ("image_000000.jpg".to_string(), padded));
+        assert_eq!(sink.files[1], ("image_000001.jpg".to_string(), repeated));
+    }
+
+    #[test]
+    fn test_should_trim_zero_padding_after_rw2_when_trim_trailing_is_enabled() {
+        // 1. setup
+        // IFD を読めないので次のスタートシグネチャまでになる RW2
+        let mut rw2 = tiff::test_fixtures::little_endian_tiff(&[], 40);
+        rw2[4..8].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+        rw2[39] = 0x5A;
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [rw2.as_slice(), &[0x00; 3000], &jpeg].concat();
+        let options = Options {
+            trim_trailing: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].file_type, FileType::Rw2);
+        assert_eq!(sink.files[0], ("image_000000.rw2".to_string(), rw2));
+        assert_eq!(sink.files[1], ("image_000001.jpg".to_string(), jpeg));
+    }
+
+    #[test]
+    fn test_should_recover_every_file_when_rw2_jpeg_and_rw2_are_back_to_back() {
+        // 1. setup
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        // IFD を読めないので次のスタートシグネチャまでになる RW2
+        let mut unparsed_rw2 = tiff::test_fixtures::little_endian_tiff(&[], 40);
+        unparsed_rw2[4..8].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let files = [
+            unparsed_rw2.clone(),
+            jpeg.clone(),
+            rw2.clone(),
+            jpeg.clone(),
+            rw2.clone(),
+        ];
+        let device = files.concat();
+        let options = Options {
+            block_size: 64,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let recovered: Vec<Vec<u8>> = sink
+            .files
+            .into_iter()
+            .filter(|(name, _)| name.starts_with("image_"))
+            .map(|(_, data)| data)
+            .collect();
+        assert_eq!(recovered, files.to_vec());
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        let expected: Vec<u64> = files
+            .iter()
+            .scan(0, |offset, file| {
+                let start = *offset;
+                *offset += file.len() as u64;
+                Some(start)
+            })
+            .collect();
+        assert_eq!(offsets, expected);
+    }
+
+    #[test]
+    fn test_should_not_carve_files_starting_before_skip_point_when_skip_start_is_given() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut device = vec![0x00; 3 * 4096];
+        device[..jpeg.len()].copy_from_slice(&jpeg);
+        // 読み飛ばす位置をまたぐ
+        device[4090..4090 + jpeg.len()].copy_from_slice(&jpeg);
+        device[8192..8192 + jpeg.len()].copy_from_slice(&jpeg);
+        let options = Options::default();
+        let mut sink = output::MemorySink::default();
+        let mut reader = Cursor::new(device);
+
+        // 2. execute
+        let summary = recover_to(
+            &mut skip_start(&mut reader, 4096).unwrap(),
+            &options,
+            &mut sink,
+        );
+
+        // 3. verify
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![8192]);
+        assert_eq!(sink.files[0], ("image_000000.jpg".to_string(), jpeg));
+    }
+
+    #[test]
+    fn test_should_cut_psd_at_end_of_image_data_when_header_sections_are_valid() {
+        // 1. setup
+        let psd = psd::test_fixtures::minimal_psd(16, 4, true);
+        let jpeg = minimal_jpeg(&[0x01]);
+        // PSD のすぐ後ろに別ファイルの中身が続く
+        let device = [psd.as_slice(), &[0x33; 40], &jpeg].concat();
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].file_type, FileType::Psd);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(sink.files[0], ("image_000000.psd".to_string(), psd.clone()));
+        assert_eq!(summary.entries[1].offset, psd.len() as u64 + 40);
+    }
 
-const DEVICE_PATH: &str = "/dev/mmcblk0";
-const OUTPUT_DIR: &str = "recovered";
-const JPEG_START: &[u8] = &[0xFF, 0xD8];
-const JPEG_END: &[u8] = &[0xFF, 0xD9];
-const RW2_START: &[u8] = &[0x49, 0x49, 0x2A, 0x00];
-const READ_BLOCK_SIZE: usize = 512 * 1024;
+    #[test]
+    fn test_should_cut_raf_at_declared_raw_end_when_header_offsets_are_valid() {
+        // 1. setup
+        let raf = raf::test_fixtures::minimal_raf([(0x80, 0x40), (0xC0, 0x10), (0xD0, 0x30)]);
+        let jpeg = minimal_jpeg(&[0x01]);
+        // RAF のすぐ後ろに別ファイルの中身が続く
+        let device = [raf.as_slice(), &[0x33; 40], &jpeg].concat();
+        let mut sink = output::MemorySink::default();
 
-#[derive(Debug, PartialEq)]
-enum FileType {
-    Jpeg,
-    Rw2,
-}
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
 
-fn main() {
-    if !Path::new(OUTPUT_DIR).exists() {
-        if let Err(e) = create_dir_all(OUTPUT_DIR) {
-            eprintln!("保存先ディレクトリの作成に失敗しました: {}", e);
-            return;
-        }
+        // 3. verify
+        assert_eq!(raf.len(), 0x100);
+        assert_eq!(summary.entries[0].file_type, FileType::Raf);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(sink.files[0], ("image_000000.raf".to_string(), raf));
+        assert_eq!(summary.entries[1].offset, 0x100 + 40);
     }
 
-    let start_time = Instant::now();
-    let mut counter = 0;
+    #[test]
+    fn test_should_save_nrw_with_nrw_extension_when_make_is_nikon() {
+        // 1. setup
+        let mut nrw = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_MAKE, 2, 6, 50),
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 56),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 20),
+            ],
+            76,
+        );
+        nrw[50..56].copy_from_slice(b"NIKON\0");
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        let device = [nrw.as_slice(), &rw2, &[0x00; 16]].concat();
+        let mut sink = output::MemorySink::default();
 
-    let mut file = match File::open(DEVICE_PATH) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("デバイスを開けませんでした: {}", e);
-            return;
-        }
-    };
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
 
-    let mut buffer = Vec::new();
-    let mut temp = vec![0u8; READ_BLOCK_SIZE];
+        // 3. verify
+        let types: Vec<FileType> = summary.entries.iter().map(|e| e.file_type).collect();
+        assert_eq!(types, vec![FileType::Nrw, FileType::Rw2]);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(sink.files[0], ("image_000000.nrw".to_string(), nrw));
+    }
 
-    loop {
-        match file.read(&mut temp) {
-            Ok(0) => break,
-            Ok(n) => buffer.extend_from_slice(&temp[..n]),
-            Err(e) => {
-                eprintln!("読み取りエラー: {}", e);
-                break;
-            }
-        }
+    #[test]
+    fn test_should_cut_x3f_after_directory_pointer_when_magic_is_fovb() {
+        // 1. setup
+        let x3f = x3f::test_fixtures::minimal_x3f(200);
+        let jpeg = minimal_jpeg(&[0x01]);
+        let device = [x3f.as_slice(), &[0x33; 40], &jpeg].concat();
+        let mut sink = output::MemorySink::default();
 
-        loop {
-            let candidates = find_all_starts(&buffer);
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
 
-            if candidates.is_empty() {
-                // スタートシグネチャ見つからなければ、末尾だけ残して次ブロックへ
-                buffer = buffer.split_off(buffer.len().saturating_sub(RW2_START.len()));
-                break;
-            }
+        // 3. verify
+        assert_eq!(summary.entries[0].file_type, FileType::X3f);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(sink.files[0], ("image_000000.x3f".to_string(), x3f.clone()));
+        assert_eq!(summary.entries[1].offset, x3f.len() as u64 + 40);
+    }
 
-            let (start_idx, file_type) = &candidates[0];
+    #[test]
+    fn test_should_save_every_page_as_tif_when_tiff_has_two_page_ifd_chain() {
+        // 1. setup
+        let tiff = tiff::test_fixtures::two_page_tiff();
+        let jpeg = minimal_jpeg(&[0x01]);
+        let device = [tiff.as_slice(), &[0x33; 40], &jpeg].concat();
+        let mut sink = output::MemorySink::default();
 
-            // JPEGの場合、エンドマーカーを探して保存
-            if *file_type == FileType::Jpeg {
-                match find_signature(&buffer[*start_idx + JPEG_START.len()..], JPEG_END) {
-                    Some(offset) => {
-                        let end_idx = *start_idx + JPEG_START.len() + offset + JPEG_END.len();
-                        save_file(&buffer[*start_idx..end_idx], counter, file_type);
-                        counter += 1;
-                        buffer = buffer.split_off(end_idx);
-                    }
-                    None => {
-                        buffer = buffer.split_off(*start_idx);
-                        break;
-                    }
-                }
-            }
-            // RW2の場合、次のスタートシグネチャまでを保存
-            else {
-                let next_candidates = find_all_starts(&buffer[*start_idx + 4..]);
-                let end_idx = match next_candidates.first() {
-                    Some((next_idx, _)) => *start_idx + 4 + *next_idx,
-                    None => buffer.len(),
-                };
-                save_file(&buffer[*start_idx..end_idx], counter, file_type);
-                counter += 1;
-                buffer = buffer.split_off(end_idx);
-            }
-        }
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].file_type, FileType::Tiff);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(
+            sink.files[0],
+            ("image_000000.tif".to_string(), tiff.clone())
+        );
+        assert_eq!(summary.entries[1].offset, tiff.len() as u64 + 40);
     }
 
-    let duration = start_time.elapsed();
-    println!("\n復旧完了: {} 個のファイルを保存しました", counter);
-    println!("実行時間: {:.2?}", duration);
-}
+    #[test]
+    fn test_should_save_xmp_up_to_packet_end_when_sidecar_follows_raw() {
+        // 1. setup
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        let xmp = xmp::test_fixtures::xmp_packet(5);
+        let device = [rw2.as_slice(), &xmp, &[0x20; 64], &[0x00; 64]].concat();
+        let mut sink = output::MemorySink::default();
 
-fn find_signature(buffer: &[u8], signature: &[u8]) -> Option<usize> {
-    buffer.windows(signature.len()).position(|window| window == signature)
-}
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
 
-fn find_all_starts(buffer: &[u8]) -> Vec<(usize, FileType)> {
-    let mut results = Vec::new();
+        // 3. verify
+        let types: Vec<FileType> = summary.entries.iter().map(|e| e.file_type).collect();
+        assert_eq!(types, vec![FileType::Rw2, FileType::Xmp]);
+        assert_eq!(summary.entries[1].offset, rw2.len() as u64);
+        assert_eq!(summary.entries[1].confidence, carve::CONFIDENCE_END_MARKER);
+        assert_eq!(sink.files[1], ("image_000001.xmp".to_string(), xmp));
+    }
+
+    #[test]
+    fn test_should_save_dicom_from_preamble_when_dicm_is_at_offset_128() {
+        // 1. setup
+        let dicom = dicom::test_fixtures::minimal_dicom(300, false);
+        let jpeg = minimal_jpeg(&[0x01]);
+        let device = [&[0x00; 512][..], &dicom, &[0x00; 256], &jpeg].concat();
+        let mut sink = output::MemorySink::default();
 
-    if let Some(idx) = find_signature(buffer, JPEG_START) {
-        results.push((idx, FileType::Jpeg));
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].file_type, FileType::Dicom);
+        assert_eq!(summary.entries[0].offset, 512);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(
+            sink.files[0],
+            ("image_000000.dcm".to_string(), dicom.clone())
+        );
+        assert_eq!(summary.entries[1].file_type, FileType::Jpeg);
     }
-    if let Some(idx) = find_signature(buffer, RW2_START) {
-        results.push((idx, FileType::Rw2));
+
+    #[test]
+    fn test_should_save_orf_with_orf_extension_when_header_has_olympus_magic() {
+        // 1. setup
+        let mut orf = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        orf[..4].copy_from_slice(ORF_START);
+        let mut old_orf = orf.clone();
+        old_orf[..4].copy_from_slice(ORF_RS_START);
+        let device = [orf.as_slice(), &old_orf, &[0x00; 16]].concat();
+        let options = Options::default();
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        let types: Vec<FileType> = summary.entries.iter().map(|e| e.file_type).collect();
+        assert_eq!(types, vec![FileType::Orf, FileType::Orf]);
+        assert_eq!(summary.entries[0].confidence, carve::CONFIDENCE_STRUCTURE);
+        assert_eq!(sink.files[0], ("image_000000.orf".to_string(), orf));
+        assert_eq!(sink.files[1], ("image_000001.orf".to_string(), old_orf));
     }
 
-    results.sort_by_key(|k| k.0);
-    results
-}
+    #[test]
+    fn test_should_save_cr3_with_cr3_extension_when_ftyp_brand_is_crx() {
+        // 1. setup
+        let cr3 = bmff::test_fixtures::minimal_mp4(b"crx ", 64);
+        let mp4 = bmff::test_fixtures::minimal_mp4(b"isom", 64);
+        let device = [cr3.as_slice(), &[0xFF; 16], &mp4].concat();
+        let options = Options::default();
+        let mut sink = output::MemorySink::default();
 
-fn save_file(data: &[u8], counter: usize, file_type: &FileType) {
-    let ext = match file_type {
-        FileType::Jpeg => "jpg",
-        FileType::Rw2 => "rw2",
-    };
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
 
-    let filename = format!("{}/image_{:06}.{}", OUTPUT_DIR, counter, ext);
-    match File::create(&filename) {
-        Ok(mut out_file) => {
-            if let Err(e) = out_file.write_all(data) {
-                eprintln!("ファイル書き込みエラー: {}", e);
-            } else {
-                println!("Saved: {}", filename);
-            }
+        // 3. verify
+        let types: Vec<FileType> = summary.entries.iter().map(|e| e.file_type).collect();
+        assert_eq!(types, vec![FileType::Cr3, FileType::Mp4]);
+        assert_eq!(sink.files[0], ("image_000000.cr3".to_string(), cr3));
+        assert_eq!(sink.files[1].0, "image_000001.mp4");
+    }
+
+    #[test]
+    fn test_should_name_images_by_dimensions_when_rename_by_dimensions_is_enabled() {
+        // 1. setup
+        let mut jpeg = vec![0xFF, 0xD8];
+        // SOF0: 高さ 4000, 幅 6000
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x0F, 0xA0, 0x17, 0x70, 0x01]);
+        jpeg.extend_from_slice(&[0x01, 0x11, 0x00]);
+        jpeg.extend_from_slice(&minimal_jpeg(&[0x01, 0x02])[2..]);
+        let rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[
+                (tiff::TAG_STRIP_OFFSETS, 4, 1, 40),
+                (tiff::TAG_STRIP_BYTE_COUNTS, 4, 1, 10),
+            ],
+            50,
+        );
+        let device = [jpeg.clone(), rw2].concat();
+        let options = Options {
+            rename_by_dimensions: true,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+        // 3. verify
+        assert_eq!(summary.entries[0].filename, "6000x4000_000000.jpg");
+        assert_eq!(sink.files[0], ("6000x4000_000000.jpg".to_string(), jpeg));
+        // 画素数を読めない形式は通常の名前のまま
+        assert_eq!(summary.entries[1].filename, "image_000001.rw2");
+    }
+
+    #[test]
+    fn test_should_produce_identical_names_when_block_sizes_differ_with_stable_names() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut device = vec![0x00; 16 * 1024];
+        // 小さいブロックでは境界をまたぐ位置にも置く
+        for offset in [100, 4090, 9000] {
+            device[offset..offset + jpeg.len()].copy_from_slice(&jpeg);
         }
-        Err(e) => eprintln!("ファイル作成エラー: {}", e),
+        let names = |block_size: usize| {
+            let options = Options {
+                block_size,
+                stable_names: true,
+                ..Options::default()
+            };
+            let mut sink = output::MemorySink::default();
+            let summary = recover_to(&mut Cursor::new(device.clone()), &options, &mut sink);
+            summary
+                .entries
+                .into_iter()
+                .map(|entry| entry.filename)
+                .collect::<Vec<_>>()
+        };
+
+        // 2. execute
+        let small = names(4096);
+        let large = names(READ_BLOCK_SIZE);
+
+        // 3. verify
+        assert_eq!(small, large);
+        assert_eq!(
+            small,
+            vec![
+                "image_000000000100.jpg",
+                "image_000000004090.jpg",
+                "image_000000009000.jpg"
+            ]
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn test_should_not_reallocate_buffer_per_file_when_many_small_files_are_carved() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device: Vec<u8> = (0..2000).flat_map(|_| jpeg.clone()).collect();
+        // 1 ブロックに 5 個ずつ入り、ブロックの境目をまたぐものもある
+        let options = Options {
+            block_size: 64,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
 
-    use super::*;
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
 
-    const JPEG_START: &[u8] = &[0xFF, 0xD8];
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2000);
+        assert!(summary.buffer_allocations <= 2);
+    }
 
-    // ---------------------------
-    // Tests for find_signature
-    // ---------------------------
+    #[test]
+    fn test_should_carve_identical_files_when_block_size_changes_where_cursor_stops() {
+        // 1. setup
+        let small = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let large = minimal_jpeg(&[0x22; 90]);
+        let device = [
+            small.as_slice(),
+            &large,
+            &[0x00; 7],
+            &small,
+            &small,
+            &[0x11; 100],
+            &large,
+        ]
+        .concat();
+        let carve = |block_size: usize| {
+            let options = Options {
+                block_size,
+                ..Options::default()
+            };
+            let mut sink = output::MemorySink::default();
+            recover_to(&mut Cursor::new(device.clone()), &options, &mut sink);
+            sink.files
+        };
+
+        // 2. execute
+        let results = [carve(16), carve(37), carve(READ_BLOCK_SIZE)];
+
+        // 3. verify
+        let contents: Vec<&[u8]> = results[2]
+            .iter()
+            .filter(|(name, _)| name.ends_with(".jpg"))
+            .map(|(_, data)| data.as_slice())
+            .collect();
+        assert_eq!(
+            contents,
+            vec![small.as_slice(), &large, &small, &small, &large]
+        );
+        assert_eq!(results[0], results[2]);
+        assert_eq!(results[1], results[2]);
+    }
 
     #[test]
-    fn test_should_return_correct_index_when_signature_is_found() {
+    fn test_should_carve_same_unparsable_rw2_when_block_size_changes() {
         // 1. setup
-        let buffer = [0x00, 0x11, 0x22, 0xFF, 0xD8, 0x33, 0x44];
-        let signature = JPEG_START;
+        let mut rw2 = tiff::test_fixtures::little_endian_tiff(&[], 40);
+        rw2[4..8].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+        let rw2 = [rw2.as_slice(), &[0x5A; 3000]].concat();
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [rw2.as_slice(), &jpeg].concat();
+        let carve = |block_size: usize| {
+            let options = Options {
+                block_size,
+                ..Options::default()
+            };
+            let mut sink = output::MemorySink::default();
+            recover_to(&mut Cursor::new(device.clone()), &options, &mut sink);
+            sink.files
+        };
 
         // 2. execute
-        let result = find_signature(&buffer, signature);
-        println!("{:?}", result);
+        let results = [carve(512), carve(1000), carve(READ_BLOCK_SIZE)];
 
         // 3. verify
-        assert_eq!(result, Some(3));
+        assert_eq!(results[2][0], ("image_000000.rw2".to_string(), rw2));
+        assert_eq!(results[0], results[2]);
+        assert_eq!(results[1], results[2]);
     }
 
     #[test]
-    fn test_should_return_none_when_signature_is_not_found() {
+    fn test_should_not_rerun_end_detection_every_block_when_file_waits_for_its_end() {
         // 1. setup
-        let buffer = [0x00, 0x11, 0x22, 0x33, 0x44];
-        let signature = JPEG_START;
+        let large = minimal_jpeg(&[0x11; 256 * 1024]);
+        let mp4 = bmff::test_fixtures::minimal_mp4(b"isom", 256 * 1024);
+        let device = [large.as_slice(), &mp4].concat();
+        let options = Options {
+            block_size: 512,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
 
         // 2. execute
-        let result = find_signature(&buffer, signature);
+        let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
 
         // 3. verify
-        assert_eq!(result, None);
+        // 512 バイトずつ 1000 ブロック以上読み足すが、終端検出は溜めた長さが倍になるたびに限る
+        assert_eq!(sink.files[0], ("image_000000.jpg".to_string(), large));
+        assert_eq!(sink.files[1], ("image_000001.mp4".to_string(), mp4));
+        assert!(summary.end_checks <= 2 * 12, "{}", summary.end_checks);
     }
 
     #[test]
-    fn test_should_return_first_occurrence_when_multiple_matches_exist() {
+    fn test_should_keep_buffer_small_when_stream_has_no_signatures() {
         // 1. setup
-        let buffer = [0xFF, 0xD8, 0x00, 0xFF, 0xD8, 0x01];
-        let signature = JPEG_START;
+        let device = vec![0x11; READ_BLOCK_SIZE * 8];
+        let mut sink = output::MemorySink::default();
 
         // 2. execute
-        let result = find_signature(&buffer, signature);
+        let summary = recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
 
         // 3. verify
-        assert_eq!(result, Some(0)); // 最初の0番目のマッチを返す
+        assert!(summary.entries.is_empty());
+        assert!(summary.peak_buffer_bytes <= READ_BLOCK_SIZE + longest_signature(&FileType::ALL));
     }
 
-    // ---------------------------
-    // Tests for find_all_starts
-    // ---------------------------
+    #[test]
+    fn test_should_give_up_unterminated_jpeg_when_buffer_reaches_cap() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        // 終端のないJPEGのあとに、SOI も EOI も含まない長いデータと本物のJPEG
+        let mut device = vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x03, 0x01];
+        device.resize(READ_BLOCK_SIZE * 6, 0x11);
+        device.extend_from_slice(&jpeg);
+        let options = Options {
+            max_file_size: READ_BLOCK_SIZE as u64,
+            ..Options::default()
+        };
+        let mut sink = output::MemorySink::default();
+
+        // 2. execute
+        let summary = recover_to(&mut Cursor::new(device.clone()), &options, &mut sink);
+
+        // 3. verify
+        assert!(summary.peak_buffer_bytes <= READ_BLOCK_SIZE * 3);
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(
+            summary.entries[0].offset,
+            (device.len() - jpeg.len()) as u64
+        );
+    }
 
     #[test]
-    fn test_should_return_jpeg_and_rw2_when_both_signatures_exist() {
+    fn test_should_write_gps_coordinates_to_manifest_when_jpeg_is_geotagged() {
         // 1. setup
-        let buffer = [
-            0x00, 0xFF, 0xD8, 0x01, 0x49, 0x49, 0x2A, 0x00, 0x02,
-        ];
+        // 北緯 35°39'31.50" 東経 139°42'3.00"
+        let tagged = exif::test_fixtures::jpeg_with_gps(
+            ('N', [35, 39, 3150]),
+            ('E', [139, 42, 300]),
+            &[0x01],
+        );
+        let plain = minimal_jpeg(&[0x02]);
+        let device = [tagged, plain].concat();
+        let mut sink = output::MemorySink::default();
 
         // 2. execute
-        let results = find_all_starts(&buffer);
+        recover_to(&mut Cursor::new(device), &Options::default(), &mut sink);
+
+        // 3. verify
+        let (_, manifest) = sink
+            .files
+            .iter()
+            .find(|(name, _)| name == manifest::MANIFEST_FILE_NAME)
+            .unwrap();
+        let manifest = String::from_utf8(manifest.clone()).unwrap();
+        let lines: Vec<&str> = manifest.lines().collect();
+        assert!(lines[1].contains("\"latitude\": 35.658750, \"longitude\": 139.700833"));
+        assert!(!lines[2].contains("latitude"));
+    }
+
+    #[test]
+    fn test_should_carve_each_partition_into_own_folder_when_partition_scan_is_enabled() {
+        // 1. setup
+        let output_dir = temp_output_dir("partition_scan");
+        let first = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let second = minimal_jpeg(&[0x04, 0x05, 0x06]);
+        let mut device = partition::test_fixtures::mbr(&[(0x0C, 8, 64), (0x83, 72, 64)]);
+        device.resize(136 * 512, 0x00);
+        device[8 * 512..8 * 512 + first.len()].copy_from_slice(&first);
+        device[72 * 512..72 * 512 + second.len()].copy_from_slice(&second);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            partition_scan: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let results = recover_partitions(&mut Cursor::new(device), 512, &options);
 
         // 3. verify
         assert_eq!(results.len(), 2);
-        assert_eq!(results[0], (1, FileType::Jpeg));
-        assert_eq!(results[1], (4, FileType::Rw2));
+        assert_eq!(results[1].1.entries[0].offset, 72 * 512);
+        assert_eq!(
+            std::fs::read(output_dir.join("partition_0").join("image_000000.jpg")).unwrap(),
+            first
+        );
+        assert_eq!(
+            std::fs::read(output_dir.join("partition_1").join("image_000000.jpg")).unwrap(),
+            second
+        );
     }
 
     #[test]
-    fn test_should_return_only_jpeg_when_only_jpeg_signature_exists() {
+    fn test_should_skip_encrypted_partition_when_partition_scan_is_enabled() {
         // 1. setup
-        let buffer = [0xFF, 0xD8, 0x10, 0x20];
+        let output_dir = temp_output_dir("partition_scan_encrypted");
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut device = partition::test_fixtures::mbr(&[(0x0C, 8, 256), (0x0C, 264, 64)]);
+        device.resize(328 * 512, 0x00);
+        // 1つ目は BitLocker / LUKS のように全体が乱数
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        for byte in &mut device[8 * 512..264 * 512] {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = (state >> 32) as u8;
+        }
+        device[264 * 512..264 * 512 + jpeg.len()].copy_from_slice(&jpeg);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            partition_scan: true,
+            ..Options::default()
+        };
+        let forced = Options {
+            output_dir: temp_output_dir("partition_scan_encrypted_forced"),
+            skip_encryption_check: true,
+            ..options.clone()
+        };
 
         // 2. execute
-        let results = find_all_starts(&buffer);
+        let results = recover_partitions(&mut Cursor::new(device.clone()), 512, &options);
+        let forced_results = recover_partitions(&mut Cursor::new(device), 512, &forced);
 
         // 3. verify
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0], (0, FileType::Jpeg));
+        assert_eq!(results[0].0.start, 264 * 512);
+        assert_eq!(results[0].1.entries[0].offset, 264 * 512);
+        assert!(!output_dir.join("partition_0").exists());
+        assert_eq!(forced_results.len(), 2);
     }
 
     #[test]
-    fn test_should_return_only_rw2_when_only_rw2_signature_exists() {
+    fn test_should_select_partition_from_table_when_image_is_whole_disk() {
         // 1. setup
-        let buffer = [0x49, 0x49, 0x2A, 0x00, 0xAA, 0xBB];
+        let mut device = partition::test_fixtures::mbr(&[(0x0C, 8, 64), (0x83, 72, 64)]);
+        device.resize(136 * 512, 0x00);
+        let info = device_info::inspect(&mut Cursor::new(device), 512).unwrap();
 
         // 2. execute
-        let results = find_all_starts(&buffer);
+        let part = select_partition(&info, 2).unwrap();
 
         // 3. verify
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0], (0, FileType::Rw2));
+        assert_eq!((part.start, part.len), (72 * 512, 64 * 512));
     }
 
     #[test]
-    fn test_should_return_empty_when_no_signatures_exist() {
+    fn test_should_use_whole_image_as_partition_when_image_starts_with_boot_sector() {
         // 1. setup
-        let buffer = [0x00, 0x01, 0x02, 0x03, 0x04];
+        let mut device = partition::test_fixtures::fat32_boot_sector(8, 16);
+        device.resize(64 * 512, 0x00);
+        let info = device_info::inspect(&mut Cursor::new(device), 512).unwrap();
 
         // 2. execute
-        let results = find_all_starts(&buffer);
+        let first = select_partition(&info, 1).unwrap();
+        let second = select_partition(&info, 2);
 
         // 3. verify
-        assert_eq!(results.len(), 0);
+        assert_eq!((first.start, first.len), (0, 64 * 512));
+        assert_eq!(first.label, "FAT32");
+        assert_eq!(second, None);
+    }
+
+    #[test]
+    fn test_should_save_mpo_as_one_file_when_jpeg_has_mpf_index() {
+        // 1. setup
+        let (mpo, first_len) = mpf::test_fixtures::two_image_mpo();
+        let split_options = Options {
+            split_mpo: true,
+            ..Options::default()
+        };
+        let mut merged = output::MemorySink::default();
+        let mut split = output::MemorySink::default();
+
+        // 2. execute
+        recover_to(
+            &mut Cursor::new(mpo.clone()),
+            &Options::default(),
+            &mut merged,
+        );
+        recover_to(&mut Cursor::new(mpo.clone()), &split_options, &mut split);
+
+        // 3. verify
+        assert_eq!(
+            merged.files[0],
+            ("image_000000.mpo".to_string(), mpo.clone())
+        );
+        assert_eq!(split.files[0].0, "image_000000.jpg");
+        assert_eq!(split.files[0].1, &mpo[..first_len]);
+        assert_eq!(split.files[1].0, "image_000001.jpg");
+        assert_eq!(split.files[1].1, &mpo[first_len..]);
+    }
+
+    #[test]
+    fn test_should_recover_aligned_jpeg_when_step_is_512() {
+        // 1. setup
+        let output_dir = temp_output_dir("scan_step");
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut device = vec![0x00; 8192];
+        device[700..700 + jpeg.len()].copy_from_slice(&jpeg);
+        device[2048..2048 + jpeg.len()].copy_from_slice(&jpeg);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            scan_step: 512,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].offset, 2048);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.jpg")).unwrap(),
+            jpeg
+        );
+    }
+
+    #[test]
+    fn test_should_write_only_confident_carves_when_min_confidence_is_set() {
+        // 1. setup
+        let output_dir = temp_output_dir("min_confidence");
+        // 申告サイズがありえない BMP は次のシグネチャまでの切り出しになる
+        let mut bmp = bmp::test_fixtures::minimal_bmp(16);
+        bmp[2..6].copy_from_slice(&u32::MAX.to_le_bytes());
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [bmp.as_slice(), &jpeg].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            min_confidence: Some(carve::MinConfidence {
+                threshold: 0.75,
+                quarantine: false,
+            }),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].file_type, FileType::Jpeg);
+        assert_eq!(summary.entries[0].offset, bmp.len() as u64);
+        assert!(!output_dir.join("image_000000.bmp").exists());
+        assert!(output_dir.join("image_000001.jpg").exists());
+    }
+
+    #[test]
+    fn test_should_route_low_confidence_carves_to_folder_when_quarantine_is_given() {
+        // 1. setup
+        let output_dir = temp_output_dir("min_confidence_quarantine");
+        let mut bmp = bmp::test_fixtures::minimal_bmp(16);
+        bmp[2..6].copy_from_slice(&u32::MAX.to_le_bytes());
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let device = [bmp.as_slice(), &jpeg].concat();
+        let options = Options {
+            output_dir: output_dir.clone(),
+            min_confidence: Some(carve::MinConfidence {
+                threshold: 0.75,
+                quarantine: true,
+            }),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(
+            summary.entries[0].filename,
+            "low-confidence/image_000000.bmp"
+        );
+        assert_eq!(
+            std::fs::read(output_dir.join("low-confidence").join("image_000000.bmp")).unwrap(),
+            bmp
+        );
+        assert_eq!(summary.entries[1].filename, "image_000001.jpg");
+    }
+
+    #[test]
+    fn test_should_save_preview_next_to_rw2_when_extract_preview_is_enabled() {
+        // 1. setup
+        let output_dir = temp_output_dir("rw2_preview");
+        let preview = minimal_jpeg(&[0x11, 0x22, 0x33]);
+        let mut rw2 = tiff::test_fixtures::little_endian_tiff(
+            &[(
+                tiff::TAG_PANASONIC_JPG_FROM_RAW,
+                7,
+                preview.len() as u32,
+                100,
+            )],
+            100 + preview.len(),
+        );
+        rw2[100..].copy_from_slice(&preview);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            extract_preview: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(rw2.clone()), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.rw2")).unwrap(),
+            rw2
+        );
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.preview.jpg")).unwrap(),
+            preview
+        );
+    }
+
+    #[test]
+    fn test_should_recover_real_jpeg_when_false_start_has_no_end_and_skip_unterminated_is_enabled()
+    {
+        // 1. setup
+        // 偽の FF D8 の後ろに、デバイスの終わりを越える長さのセグメントが続く
+        let mut device = vec![0xFF, 0xD8, 0xFF, 0xE1, 0xFF, 0xFF];
+        device.extend_from_slice(&[0x00; 16]);
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        device.extend_from_slice(&jpeg);
+        device.extend_from_slice(&[0x00; 64]);
+        let stalled_options = Options {
+            output_dir: temp_output_dir("unterminated_default"),
+            ..Options::default()
+        };
+        let options = Options {
+            output_dir: temp_output_dir("unterminated_skip"),
+            skip_unterminated: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let stalled = recover(&mut Cursor::new(device.clone()), &stalled_options);
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert!(stalled.entries.is_empty());
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].offset, 22);
+        assert_eq!(
+            std::fs::read(options.output_dir.join("image_000000.jpg")).unwrap(),
+            jpeg
+        );
+    }
+
+    #[test]
+    fn test_should_carve_bmp_by_declared_size_when_header_is_valid() {
+        // 1. setup
+        let output_dir = temp_output_dir("bmp_size");
+        let bmp = bmp::test_fixtures::minimal_bmp(12);
+        let mut device = vec![0x00; 16];
+        device.extend_from_slice(b"BM not a bitmap"); // 偶然の "BM"
+        device.extend_from_slice(&bmp);
+        device.extend_from_slice(&[0x00; 16]);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].offset, 31);
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.bmp")).unwrap(),
+            bmp
+        );
+    }
+
+    #[test]
+    fn test_should_carve_whole_docx_when_zip_has_several_local_headers() {
+        // 1. setup
+        let output_dir = temp_output_dir("zip_docx");
+        let docx = zip::test_fixtures::minimal_zip(
+            &[
+                ("[Content_Types].xml", b"<Types/>"),
+                ("word/document.xml", b"<w:document/>"),
+                ("docProps/core.xml", b"<cp:coreProperties/>"),
+            ],
+            b"",
+        );
+        let mut device = vec![0x00; 64];
+        device.extend_from_slice(&docx);
+        device.extend_from_slice(&[0x00; 64]);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut Cursor::new(device), &options);
+
+        // 3. verify
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.entries[0].offset, 64);
+        assert_eq!(summary.entries[0].filename, "image_000000.docx");
+        assert_eq!(
+            std::fs::read(output_dir.join("image_000000.docx")).unwrap(),
+            docx
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_should_skip_holes_and_keep_offsets_when_input_is_sparse_file() {
+        // 1. setup
+        use std::io::{Seek, SeekFrom, Write};
+        let output_dir = temp_output_dir("sparse_file");
+        let image_path = output_dir.join("sparse.img");
+        let jpeg = minimal_jpeg(&[0x01, 0x02]);
+        let second_offset: u64 = 64 * 1024 * 1024;
+        let mut image = File::create(&image_path).unwrap();
+        image.write_all(&jpeg).unwrap();
+        image.seek(SeekFrom::Start(second_offset)).unwrap();
+        image.write_all(&jpeg).unwrap();
+        image.set_len(second_offset * 2).unwrap();
+        drop(image);
+        let options = Options {
+            output_dir: output_dir.clone(),
+            ..Options::default()
+        };
+
+        // 2. execute
+        let summary = recover(&mut File::open(&image_path).unwrap(), &options);
+
+        // 3. verify
+        let offsets: Vec<u64> = summary.entries.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![0, second_offset]);
+        assert!(summary.skipped_hole_bytes > 0);
+        assert_eq!(summary.scanned_bytes, second_offset * 2);
     }
-}
\ No newline at end of file
+}
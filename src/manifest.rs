@@ -0,0 +1,426 @@
+// 復旧したファイルの一覧（manifest.json）と、表計算向けのCSVレポート
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::FileType;
+use crate::exif::GpsCoordinates;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+pub const CSV_REPORT_FILE_NAME: &str = "report.csv";
+// 1ファイル保存するたびに1行ずつ追記する manifest.json の途中経過（正常に終われば消す）
+pub const JOURNAL_FILE_NAME: &str = "manifest.jsonl";
+
+const CSV_HEADER: &str = "index,type,offset,size,sha256,confidence,filename";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub index: usize,
+    pub file_type: FileType,
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+    pub confidence: f64,
+    pub filename: String,
+    // 断片化していてカービングでは正しく復元できない可能性が高い
+    pub fragmented: bool,
+    // EXIF の GPS IFD にあった撮影地点（なければ manifest.json にも書かない）
+    pub gps: Option<GpsCoordinates>,
+    // --quarantine-on-decode-failure で failed/ に分けた理由
+    pub decode_failure: Option<String>,
+    // --compress で .gz にしたときの保存したバイト数（size は切り出した中身のバイト数のまま）
+    pub stored_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+}
+
+impl ReportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "csv" => Some(ReportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+// manifest.json などに書くオフセットをどこから数えるか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OffsetBase {
+    Device,
+    // --partition / --partition-scan で選んだパーティションの先頭から
+    Partition,
+}
+
+impl OffsetBase {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "device" => Some(OffsetBase::Device),
+            "partition" => Some(OffsetBase::Partition),
+            _ => None,
+        }
+    }
+}
+
+pub fn to_json(entries: &[ManifestEntry]) -> String {
+    let mut json = String::from("[\n");
+    for (i, entry) in entries.iter().enumerate() {
+        json.push_str("  ");
+        json.push_str(&to_json_line(entry));
+        json.push_str(if i + 1 < entries.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+// manifest.json の1要素（改行を含まない）
+pub fn to_json_line(entry: &ManifestEntry) -> String {
+    let mut json = format!(
+        "{{\"index\": {}, \"type\": \"{}\", \"offset\": {}, \"size\": {}, \"sha256\": \"{}\", \"confidence\": {:.2}, \"filename\": {}, \"fragmented\": {}",
+        entry.index,
+        entry.file_type.name(),
+        entry.offset,
+        entry.size,
+        entry.sha256,
+        entry.confidence,
+        json_string(&entry.filename),
+        entry.fragmented,
+    );
+    if let Some(gps) = entry.gps {
+        json.push_str(&format!(
+            ", \"latitude\": {:.6}, \"longitude\": {:.6}",
+            gps.latitude, gps.longitude
+        ));
+    }
+    if let Some(reason) = &entry.decode_failure {
+        json.push_str(&format!(", \"decode_failure\": {}", json_string(reason)));
+    }
+    if let Some(stored_size) = entry.stored_size {
+        json.push_str(&format!(", \"stored_size\": {}", stored_size));
+    }
+    json.push('}');
+    json
+}
+
+pub fn to_csv(entries: &[ManifestEntry]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for entry in entries {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{:.2},{}\n",
+            entry.index,
+            entry.file_type.name(),
+            entry.offset,
+            entry.size,
+            entry.sha256,
+            entry.confidence,
+            csv_field(&entry.filename),
+        ));
+    }
+    csv
+}
+
+#[derive(Debug, PartialEq)]
+enum JsonValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+// to_json が書き出した形式（値が文字列・数値・真偽値だけのオブジェクトの配列）を読む
+// 前回の実行の manifest.json を --resume で読み込むためのもので、それ以外のJSONは想定しない
+pub fn from_json(text: &str) -> Option<Vec<ManifestEntry>> {
+    let mut chars = text.chars().peekable();
+    let mut entries = Vec::new();
+
+    expect(&mut chars, '[')?;
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&']') {
+        return Some(entries);
+    }
+    loop {
+        let object = parse_object(&mut chars)?;
+        entries.push(entry_from_object(&object)?);
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            ']' => return Some(entries),
+            _ => return None,
+        }
+    }
+}
+
+// JOURNAL_FILE_NAME を読む。途中で止まったときに書きかけだった行は読めないので捨てる
+pub fn from_json_lines(text: &str) -> Vec<ManifestEntry> {
+    text.lines()
+        .filter_map(|line| {
+            let object = parse_object(&mut line.chars().peekable())?;
+            entry_from_object(&object)
+        })
+        .collect()
+}
+
+fn entry_from_object(object: &HashMap<String, JsonValue>) -> Option<ManifestEntry> {
+    let number = |key: &str| match object.get(key) {
+        Some(JsonValue::Number(n)) => Some(*n),
+        _ => None,
+    };
+    let string = |key: &str| match object.get(key) {
+        Some(JsonValue::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+
+    Some(ManifestEntry {
+        index: number("index")? as usize,
+        file_type: FileType::parse(&string("type")?)?,
+        offset: number("offset")? as u64,
+        size: number("size")? as u64,
+        sha256: string("sha256")?,
+        confidence: number("confidence")?,
+        filename: string("filename")?,
+        fragmented: matches!(object.get("fragmented"), Some(JsonValue::Bool(true))),
+        gps: match (number("latitude"), number("longitude")) {
+            (Some(latitude), Some(longitude)) => Some(GpsCoordinates {
+                latitude,
+                longitude,
+            }),
+            _ => None,
+        },
+        decode_failure: string("decode_failure"),
+        stored_size: number("stored_size").map(|n| n as u64),
+    })
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Option<()> {
+    skip_whitespace(chars);
+    (chars.next()? == expected).then_some(())
+}
+
+fn parse_object(chars: &mut Peekable<Chars>) -> Option<HashMap<String, JsonValue>> {
+    let mut object = HashMap::new();
+    expect(chars, '{')?;
+    loop {
+        skip_whitespace(chars);
+        let key = parse_string(chars)?;
+        expect(chars, ':')?;
+        skip_whitespace(chars);
+        let value = match chars.peek()? {
+            '"' => JsonValue::String(parse_string(chars)?),
+            't' | 'f' => {
+                let word: String =
+                    std::iter::from_fn(|| chars.next_if(|c| c.is_alphabetic())).collect();
+                match word.as_str() {
+                    "true" => JsonValue::Bool(true),
+                    "false" => JsonValue::Bool(false),
+                    _ => return None,
+                }
+            }
+            _ => {
+                let number: String = std::iter::from_fn(|| {
+                    chars
+                        .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                })
+                .collect();
+                JsonValue::Number(number.parse().ok()?)
+            }
+        };
+        object.insert(key, value);
+
+        skip_whitespace(chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => return Some(object),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                    value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn entry(index: usize, file_type: FileType, filename: &str) -> ManifestEntry {
+        ManifestEntry {
+            index,
+            file_type,
+            offset: 512 * index as u64,
+            size: 100,
+            sha256: "ab".repeat(32),
+            confidence: 1.0,
+            filename: filename.to_string(),
+            fragmented: false,
+            gps: None,
+            decode_failure: None,
+            stored_size: None,
+        }
+    }
+
+    // ---------------------------
+    // Tests for to_csv
+    // ---------------------------
+
+    #[test]
+    fn test_should_write_header_and_one_row_per_file_when_entries_exist() {
+        // 1. setup
+        let entries = vec![
+            entry(0, FileType::Jpeg, "image_000000.jpg"),
+            entry(1, FileType::Rw2, "image_000001.rw2"),
+        ];
+
+        // 2. execute
+        let csv = to_csv(&entries);
+
+        // 3. verify
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "index,type,offset,size,sha256,confidence,filename"
+        );
+        assert_eq!(
+            lines[2],
+            format!("1,rw2,512,100,{},1.00,image_000001.rw2", "ab".repeat(32))
+        );
+    }
+
+    #[test]
+    fn test_should_quote_field_when_it_contains_a_comma() {
+        // 1. setup
+        let value = "a,b\"c";
+
+        // 2. execute
+        let result = csv_field(value);
+
+        // 3. verify
+        assert_eq!(result, "\"a,b\"\"c\"");
+    }
+
+    // ---------------------------
+    // Tests for to_json
+    // ---------------------------
+
+    #[test]
+    fn test_should_read_back_entries_when_json_was_written_by_to_json() {
+        // 1. setup
+        let mut entries = vec![
+            entry(0, FileType::Jpeg, "image_000000.jpg"),
+            entry(1, FileType::Rw2, "fragmented/\"odd\" name.rw2"),
+        ];
+        entries[1].fragmented = true;
+        entries[1].confidence = 0.5;
+        entries[1].decode_failure = Some("EOI で終わっていません".to_string());
+        entries[0].gps = Some(GpsCoordinates {
+            latitude: -33.867778,
+            longitude: 151.21,
+        });
+
+        // 2. execute
+        let result = from_json(&to_json(&entries));
+
+        // 3. verify
+        assert_eq!(result, Some(entries));
+    }
+
+    #[test]
+    fn test_should_return_empty_when_json_array_is_empty() {
+        // 1. setup
+        let json = to_json(&[]);
+
+        // 2. execute
+        let result = from_json(&json);
+
+        // 3. verify
+        assert_eq!(result, Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_should_write_array_of_objects_when_entries_exist() {
+        // 1. setup
+        let entries = vec![entry(0, FileType::Jpeg, "image_000000.jpg")];
+
+        // 2. execute
+        let json = to_json(&entries);
+
+        // 3. verify
+        assert!(json.starts_with("[\n"));
+        assert!(json.ends_with("]\n"));
+        assert!(json.contains("\"type\": \"jpeg\""));
+        assert!(json.contains("\"filename\": \"image_000000.jpg\""));
+        assert!(json.contains("\"fragmented\": false"));
+    }
+
+    // ---------------------------
+    // Tests for from_json_lines
+    // ---------------------------
+
+    #[test]
+    fn test_should_drop_partial_last_line_when_journal_was_cut_short() {
+        // 1. setup
+        let first = to_json_line(&entry(0, FileType::Jpeg, "image_000000.jpg"));
+        let second = to_json_line(&entry(1, FileType::Rw2, "image_000001.rw2"));
+        let text = format!("{}\n{}", first, &second[..second.len() / 2]);
+
+        // 2. execute
+        let result = from_json_lines(&text);
+
+        // 3. verify
+        assert_eq!(result, vec![entry(0, FileType::Jpeg, "image_000000.jpg")]);
+    }
+}
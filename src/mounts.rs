@@ -0,0 +1,113 @@
+// 対象デバイス（とそのパーティション）がマウントされていないかの確認
+use std::fs;
+use std::path::Path;
+
+pub const PROC_MOUNTS: &str = "/proc/mounts";
+
+// /dev/mmcblk0 に対する /dev/mmcblk0p1、/dev/sdb に対する /dev/sdb1 のようなパーティション
+// （名前が数字で終わるデバイスは間に p が入る）
+fn is_same_or_partition(source: &str, device: &str) -> bool {
+    let Some(rest) = source.strip_prefix(device) else {
+        return false;
+    };
+    if rest.is_empty() {
+        return true;
+    }
+    let digits = if device.ends_with(|c: char| c.is_ascii_digit()) {
+        match rest.strip_prefix('p') {
+            Some(digits) => digits,
+            None => return false,
+        }
+    } else {
+        rest
+    };
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+// mounts は /proc/mounts の形式（1行に「マウント元 マウント先 ...」）
+// 該当する (マウント元, マウント先) を返す
+pub fn mounted_entries(mounts: &str, device: &Path) -> Vec<(String, String)> {
+    let device = device.to_string_lossy();
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = fields.next()?;
+            let target = fields.next()?;
+            is_same_or_partition(source, &device).then(|| (source.to_string(), target.to_string()))
+        })
+        .collect()
+}
+
+// /proc/mounts が読めない環境（Linux 以外など）では空を返す
+pub fn find_mounted(device: &Path) -> Vec<(String, String)> {
+    let Ok(mounts) = fs::read_to_string(PROC_MOUNTS) else {
+        return Vec::new();
+    };
+    // /dev/disk/by-id/... のようなシンボリックリンクも実体のパスで比べる
+    let device = fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+    mounted_entries(&mounts, &device)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const MOUNTS: &str = "\
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+/dev/mmcblk0p1 /media/user/SDCARD vfat rw,nosuid,nodev 0 0
+/dev/mmcblk01 /mnt/other vfat rw 0 0
+/dev/sdb1 /mnt/usb exfat rw 0 0
+";
+
+    // ---------------------------
+    // Tests for mounted_entries
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_partition_when_device_partition_is_mounted() {
+        // 1. setup
+        let device = Path::new("/dev/mmcblk0");
+
+        // 2. execute
+        let result = mounted_entries(MOUNTS, device);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            vec![(
+                "/dev/mmcblk0p1".to_string(),
+                "/media/user/SDCARD".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_should_return_numbered_partition_when_device_name_ends_with_letter() {
+        // 1. setup
+        let device = Path::new("/dev/sdb");
+
+        // 2. execute
+        let result = mounted_entries(MOUNTS, device);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            vec![("/dev/sdb1".to_string(), "/mnt/usb".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_should_return_empty_when_device_is_not_mounted() {
+        // 1. setup
+        let device = Path::new("/dev/sdc");
+
+        // 2. execute
+        let result = mounted_entries(MOUNTS, device);
+
+        // 3. verify
+        assert!(result.is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+// MPF（マルチピクチャフォーマット）: APP2 の索引で複数のJPEGを1ファイルにまとめた MPO
+// スマートフォンの2眼写真などでは、1枚目の EOI の後ろに2枚目以降のJPEGが続く
+use crate::tiff::TiffReader;
+
+const MARKER_PREFIX: u8 = 0xFF;
+const MARKER_APP2: u8 = 0xE2;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_EOI: u8 = 0xD9;
+const MPF_IDENTIFIER: &[u8] = b"MPF\0";
+const TAG_MP_ENTRY: u16 = 0xB002;
+const MP_ENTRY_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpImage {
+    // ファイル先頭（1枚目の SOI）からの位置
+    pub offset: usize,
+    pub size: usize,
+}
+
+// data は SOI から始まること。SOS より前の APP2 に MPF の索引があれば、その画像一覧を返す
+pub fn images(data: &[u8]) -> Option<Vec<MpImage>> {
+    let (tiff_start, end) = find_mpf_payload(data)?;
+    let tiff = TiffReader::new(data.get(tiff_start..end)?).ok()?;
+    let ifd = tiff.read_ifd(tiff.first_ifd().ok()?).ok()?;
+    let entry = ifd.find(TAG_MP_ENTRY)?;
+    let count = entry.count as usize / MP_ENTRY_LEN;
+    let (entries_pos, _) = tiff.value_location(entry)?;
+
+    (0..count)
+        .map(|i| {
+            let pos = entries_pos + i * MP_ENTRY_LEN;
+            let size = tiff.u32_at(pos + 4).ok()? as usize;
+            // 1枚目のオフセットは 0、2枚目以降は MPF の TIFF ヘッダからの位置
+            let offset = match tiff.u32_at(pos + 8).ok()? as usize {
+                0 => 0,
+                offset => tiff_start.checked_add(offset)?,
+            };
+            Some(MpImage { offset, size })
+        })
+        .collect()
+}
+
+// 索引のすべての画像を含む長さ。2枚以上なければ None
+pub fn total_len(images: &[MpImage]) -> Option<usize> {
+    if images.len() < 2 {
+        return None;
+    }
+    images
+        .iter()
+        .map(|image| image.offset.checked_add(image.size))
+        .try_fold(0, |end, image_end| Some(end.max(image_end?)))
+}
+
+// 索引のすべての画像をちょうど含んでいれば mpo
+pub fn extension(data: &[u8]) -> &'static str {
+    match images(data).as_deref().and_then(total_len) {
+        Some(total) if total == data.len() => "mpo",
+        _ => "jpg",
+    }
+}
+
+// MPF の TIFF ヘッダの位置とセグメントの終わり
+fn find_mpf_payload(data: &[u8]) -> Option<(usize, usize)> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != MARKER_PREFIX {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == MARKER_SOS || marker == MARKER_EOI {
+            return None;
+        }
+        let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+        let body = pos + 4;
+        let end = pos + 2 + length;
+        if marker == MARKER_APP2 && data.get(body..body + MPF_IDENTIFIER.len())? == MPF_IDENTIFIER {
+            return (end <= data.len()).then_some((body + MPF_IDENTIFIER.len(), end));
+        }
+        pos = end;
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use crate::test_util::minimal_jpeg;
+
+    // 1枚目に MPF の索引を持ち、2枚目を直後に続けた MPO
+    // 戻り値: (データ, 1枚目の長さ)
+    pub fn two_image_mpo() -> (Vec<u8>, usize) {
+        let second = minimal_jpeg(&[0x21, 0x22, 0x23, 0x24]);
+
+        // TIFF ヘッダ + IFD（MPEntry の1エントリ）+ MP Entry 2件
+        let mut tiff = vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00];
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&0xB002u16.to_le_bytes());
+        tiff.extend_from_slice(&7u16.to_le_bytes()); // UNDEFINED
+        tiff.extend_from_slice(&32u32.to_le_bytes());
+        tiff.extend_from_slice(&26u32.to_le_bytes()); // エントリの位置
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        let entries_pos = tiff.len();
+
+        let app2_len = 2 + 4 + entries_pos + 32;
+        let first_scan = [0x11, 0x12, 0x13];
+        let first_len = 2 + 2 + app2_len + 5 + first_scan.len() + 2;
+        // 2枚目の位置は MPF の TIFF ヘッダ（FF D8 FF E2 長さ "MPF\0" の直後）から数える
+        let tiff_start = 2 + 4 + 4;
+
+        for (size, offset) in [(first_len, 0), (second.len(), first_len - tiff_start)] {
+            tiff.extend_from_slice(&0u32.to_le_bytes()); // 属性
+            tiff.extend_from_slice(&(size as u32).to_le_bytes());
+            tiff.extend_from_slice(&(offset as u32).to_le_bytes());
+            tiff.extend_from_slice(&[0; 4]);
+        }
+
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE2];
+        data.extend_from_slice(&(app2_len as u16).to_be_bytes());
+        data.extend_from_slice(b"MPF\0");
+        data.extend_from_slice(&tiff);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x03, 0x01]);
+        data.extend_from_slice(&first_scan);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        assert_eq!(data.len(), first_len);
+        data.extend_from_slice(&second);
+        (data, first_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::two_image_mpo;
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+
+    // ---------------------------
+    // Tests for images
+    // ---------------------------
+
+    #[test]
+    fn test_should_list_both_images_when_app2_has_mpf_index() {
+        // 1. setup
+        let (data, first_len) = two_image_mpo();
+
+        // 2. execute
+        let result = images(&data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            Some(vec![
+                MpImage {
+                    offset: 0,
+                    size: first_len,
+                },
+                MpImage {
+                    offset: first_len,
+                    size: data.len() - first_len,
+                },
+            ])
+        );
+        assert_eq!(total_len(&result.unwrap()), Some(data.len()));
+    }
+
+    #[test]
+    fn test_should_return_none_when_jpeg_has_no_app2() {
+        // 1. setup
+        let data = minimal_jpeg(&[0x01, 0x02]);
+
+        // 2. execute
+        let result = images(&data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
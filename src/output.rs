@@ -0,0 +1,658 @@
+// 切り出したファイルの書き出し先（ディレクトリ、またはtar/zipアーカイブ）
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::sha256::{self, HashingWriter};
+use crate::{crc32, manifest};
+
+const TAR_BLOCK_SIZE: usize = 512;
+// --output-pattern date で撮影日の分からないファイルを置くフォルダ
+pub const UNDATED_DIR: &str = "undated";
+
+pub trait Sink {
+    // name は書き出し先の中での名前（例: image_000000.jpg）
+    // 書き込みながら求めた中身の SHA-256 を返す
+    fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<String>;
+
+    // 画面表示用の保存先
+    fn display_name(&self, name: &str) -> String;
+
+    // 空き容量を確認するディレクトリ（ディスクに書かないなら None）
+    fn location(&self) -> Option<&Path>;
+
+    // 保存済みの original と同じ中身を name として置く（既定では中身をもう一度書く）
+    fn write_duplicate(&mut self, name: &str, _original: &str, data: &[u8]) -> io::Result<()> {
+        self.write_file(name, data).map(|_| ())
+    }
+
+    // 書き込みに失敗した name の途中までの中身を取り除く（アーカイブからは取り除けないので既定では何もしない）
+    fn discard(&mut self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    // 保存したファイルのマニフェストの1行を、途中で止まっても残るようにすぐ書き足す
+    // （アーカイブは最後まで書かないと読めないので、既定では何もしない）
+    fn append_journal(&mut self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    // マニフェストを書き終えたら途中経過は要らない
+    fn remove_journal(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// 保存先がいっぱいで、これ以上書いても失敗し続けるエラー
+pub fn is_disk_full(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::WriteZero
+    )
+}
+
+// 保存先の中でのフォルダ分け
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputPattern {
+    // EXIF の撮影日で YYYY/MM/DD/ に分ける
+    Date,
+}
+
+impl OutputPattern {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "date" => Some(OutputPattern::Date),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    // 拡張子から形式を決める
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "tar" => Some(ArchiveFormat::Tar),
+            "zip" => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+}
+
+// create_dirs が false なら、保存先ディレクトリ（と fragmented/ など）を作らない
+pub fn open_sink(
+    output_dir: &Path,
+    archive: Option<&Path>,
+    create_dirs: bool,
+) -> io::Result<Box<dyn Sink>> {
+    match archive {
+        None => Ok(Box::new(DirSink {
+            dir: output_dir.to_path_buf(),
+            create_dirs,
+            journal: None,
+        })),
+        Some(path) => match ArchiveFormat::from_path(path) {
+            Some(ArchiveFormat::Tar) => Ok(Box::new(TarSink::create(path)?)),
+            Some(ArchiveFormat::Zip) => Ok(Box::new(ZipSink::create(path)?)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "アーカイブの拡張子は .tar か .zip にしてください",
+            )),
+        },
+    }
+}
+
+// 保存先ディレクトリは最初のファイルを書くときに作る（何も書かない実行では作らない）
+pub struct DirSink {
+    dir: PathBuf,
+    create_dirs: bool,
+    // 最初に追記するときに作り直す（前回の実行が残したものは --resume で読み終えている）
+    journal: Option<File>,
+}
+
+impl DirSink {
+    // fragmented/ のようなサブフォルダ付きの名前もある
+    fn prepare(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.dir.join(name);
+        if self.create_dirs
+            && let Some(parent) = path.parent()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+}
+
+impl Sink for DirSink {
+    fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<String> {
+        let path = self.prepare(name)?;
+        let mut writer = HashingWriter::new(File::create(path)?);
+        writer.write_all(data)?;
+        Ok(writer.finish())
+    }
+
+    // 同じファイルシステム内ならハードリンクにし、作れなければコピーする
+    fn write_duplicate(&mut self, name: &str, original: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.prepare(name)?;
+        // 前回の実行で残ったファイルへ書き込むと、リンク先の中身まで変わってしまう
+        let _ = fs::remove_file(&path);
+        match fs::hard_link(self.dir.join(original), &path) {
+            Ok(()) => Ok(()),
+            Err(_) => self.write_file(name, data).map(|_| ()),
+        }
+    }
+
+    fn display_name(&self, name: &str) -> String {
+        self.dir.join(name).display().to_string()
+    }
+
+    fn discard(&mut self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.dir.join(name))
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    fn append_journal(&mut self, line: &str) -> io::Result<()> {
+        let journal = match &mut self.journal {
+            Some(journal) => journal,
+            None => {
+                let path = self.prepare(manifest::JOURNAL_FILE_NAME)?;
+                self.journal.insert(File::create(path)?)
+            }
+        };
+        journal.write_all(format!("{}\n", line).as_bytes())
+    }
+
+    fn remove_journal(&mut self) -> io::Result<()> {
+        if self.journal.take().is_none() {
+            return Ok(());
+        }
+        fs::remove_file(self.dir.join(manifest::JOURNAL_FILE_NAME))
+    }
+}
+
+// アーカイブ自体が置かれるディレクトリ
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+pub struct TarSink {
+    path: PathBuf,
+    dir: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl TarSink {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(TarSink {
+            path: path.to_path_buf(),
+            dir: parent_dir(path),
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+impl Sink for TarSink {
+    fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<String> {
+        self.writer
+            .write_all(&tar_header(name, data.len() as u64)?)?;
+        let mut writer = HashingWriter::new(&mut self.writer);
+        writer.write_all(data)?;
+        let digest = writer.finish();
+        let padding = (TAR_BLOCK_SIZE - data.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+        self.writer.write_all(&[0u8; TAR_BLOCK_SIZE][..padding])?;
+        Ok(digest)
+    }
+
+    fn display_name(&self, name: &str) -> String {
+        format!("{}:{}", self.path.display(), name)
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    // 終端は空のブロック2つ
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.write_all(&[0u8; TAR_BLOCK_SIZE * 2])?;
+        self.writer.flush()
+    }
+}
+
+// ustar のサイズ欄（8進数11桁）に書ける大きさの上限（8GiB）
+const TAR_OCTAL_SIZE_LIMIT: u64 = 1 << 33;
+
+// ustar 形式のヘッダ
+fn tar_header(name: &str, size: u64) -> io::Result<[u8; TAR_BLOCK_SIZE]> {
+    if name.len() > 100 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("tarに格納できない長さのファイル名です: {}", name),
+        ));
+    }
+
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    if size < TAR_OCTAL_SIZE_LIMIT {
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    } else {
+        // 8進数11桁に収まらない大きさは、GNU tar と同じく先頭ビットを立てた 256 進数で書く
+        header[124] = 0x80;
+        header[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // チェックサム欄を空白とみなして合計する
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    header_offset: u32,
+}
+
+// 無圧縮（stored）のZIP。ZIP64 には対応しない
+pub struct ZipSink {
+    path: PathBuf,
+    dir: PathBuf,
+    writer: BufWriter<File>,
+    entries: Vec<ZipEntry>,
+    written: u64,
+}
+
+impl ZipSink {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(ZipSink {
+            path: path.to_path_buf(),
+            dir: parent_dir(path),
+            writer: BufWriter::new(File::create(path)?),
+            entries: Vec::new(),
+            written: 0,
+        })
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+// ZIP64 なしで書けるエントリ数とオフセットの上限
+// 0xFFFF と 0xFFFFFFFF は ZIP64 の印として読まれるので、その手前まで
+const ZIP_MAX_ENTRIES: usize = u16::MAX as usize - 1;
+const ZIP_MAX_OFFSET: u64 = u32::MAX as u64 - 1;
+
+fn zip64_required() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "4GBを超えるZIPには対応していません（.tar を使ってください）",
+    )
+}
+
+impl Sink for ZipSink {
+    // 上限を超えるファイルは書く前に断るので、それまでに書いたものは finish で読める ZIP になる
+    fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<String> {
+        if self.entries.len() >= ZIP_MAX_ENTRIES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "ZIPに格納できるのは {} 個までです（.tar を使ってください）: {}",
+                    ZIP_MAX_ENTRIES, name
+                ),
+            ));
+        }
+        // このファイルの後ろにセントラルディレクトリが始まる位置も、オフセット欄に収まること
+        if self.written + 30 + name.len() as u64 + data.len() as u64 > ZIP_MAX_OFFSET {
+            return Err(zip64_required());
+        }
+        let size = data.len() as u32;
+        let header_offset = self.written as u32;
+        let crc = crc32::crc32(data);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // 展開に必要なバージョン
+        header.extend_from_slice(&0u16.to_le_bytes()); // フラグ
+        header.extend_from_slice(&0u16.to_le_bytes()); // 無圧縮
+        header.extend_from_slice(&0u16.to_le_bytes()); // 時刻
+        header.extend_from_slice(&0x0021u16.to_le_bytes()); // 1980-01-01
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.write_raw(&header)?;
+        let mut writer = HashingWriter::new(&mut self.writer);
+        writer.write_all(data)?;
+        let digest = writer.finish();
+        self.written += data.len() as u64;
+
+        self.entries.push(ZipEntry {
+            name: name.to_string(),
+            crc,
+            size,
+            header_offset,
+        });
+        Ok(digest)
+    }
+
+    fn display_name(&self, name: &str) -> String {
+        format!("{}:{}", self.path.display(), name)
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    // セントラルディレクトリと終端レコード
+    fn finish(&mut self) -> io::Result<()> {
+        let directory_offset = u32::try_from(self.written).map_err(|_| zip64_required())?;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&20u16.to_le_bytes()); // 作成バージョン
+            directory.extend_from_slice(&20u16.to_le_bytes()); // 展開に必要なバージョン
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&0x0021u16.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0u8; 12]); // 拡張フィールド長〜外部属性
+            directory.extend_from_slice(&entry.header_offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let entry_count = u16::try_from(self.entries.len()).map_err(|_| zip64_required())?;
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        end.extend_from_slice(&[0u8; 4]); // ディスク番号
+        end.extend_from_slice(&entry_count.to_le_bytes());
+        end.extend_from_slice(&entry_count.to_le_bytes());
+        end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+
+        self.write_raw(&directory)?;
+        self.write_raw(&end)?;
+        self.writer.flush()
+    }
+}
+
+// ディスクに書かず、切り出したファイルを名前と中身の組でメモリに集める（テストや組み込み用）
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Sink for MemorySink {
+    fn write_file(&mut self, name: &str, data: &[u8]) -> io::Result<String> {
+        self.files.push((name.to_string(), data.to_vec()));
+        Ok(sha256::sha256_hex(data))
+    }
+
+    fn display_name(&self, name: &str) -> String {
+        format!("memory:{}", name)
+    }
+
+    fn location(&self) -> Option<&Path> {
+        None
+    }
+}
+
+#[cfg(test)]
+pub mod test_readers {
+    // tarアーカイブのメンバー (名前, 中身) を順に取り出す
+    pub fn tar_members(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut members = Vec::new();
+        let mut pos = 0;
+        while pos + 512 <= archive.len() && archive[pos] != 0 {
+            let header = &archive[pos..pos + 512];
+            let name_len = header[..100].iter().position(|&b| b == 0).unwrap_or(100);
+            let name = String::from_utf8(header[..name_len].to_vec()).unwrap();
+            let size_field = std::str::from_utf8(&header[124..135]).unwrap();
+            let size = usize::from_str_radix(size_field, 8).unwrap();
+            let data = archive[pos + 512..pos + 512 + size].to_vec();
+            members.push((name, data));
+            pos += 512 + size.div_ceil(512) * 512;
+        }
+        members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_readers::tar_members;
+    use super::*;
+    use crate::test_util::temp_output_dir;
+
+    // ---------------------------
+    // Tests for DirSink
+    // ---------------------------
+
+    #[test]
+    fn test_should_create_directory_on_first_write_only() {
+        // 1. setup
+        let dir = temp_output_dir("dir_sink_deferred").join("recovered");
+        let mut sink = open_sink(&dir, None, true).unwrap();
+        let created_on_open = dir.exists();
+
+        // 2. execute
+        sink.write_file("fragmented/a.jpg", &[1, 2, 3]).unwrap();
+
+        // 3. verify
+        assert!(!created_on_open);
+        assert_eq!(
+            std::fs::read(dir.join("fragmented/a.jpg")).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_should_fail_without_creating_directory_when_create_dirs_is_disabled() {
+        // 1. setup
+        let dir = temp_output_dir("dir_sink_no_create").join("recovered");
+        let mut sink = open_sink(&dir, None, false).unwrap();
+
+        // 2. execute
+        let result = sink.write_file("a.jpg", &[1, 2, 3]);
+
+        // 3. verify
+        assert!(result.is_err());
+        assert!(!dir.exists());
+    }
+
+    // ---------------------------
+    // Tests for TarSink
+    // ---------------------------
+
+    #[test]
+    fn test_should_store_members_in_order_when_writing_tar() {
+        // 1. setup
+        let dir = temp_output_dir("tar_sink");
+        let path = dir.join("out.tar");
+        let mut sink = TarSink::create(&path).unwrap();
+
+        // 2. execute
+        sink.write_file("a.jpg", &[1, 2, 3]).unwrap();
+        sink.write_file("b.rw2", &[9; 600]).unwrap();
+        sink.finish().unwrap();
+
+        // 3. verify
+        let archive = std::fs::read(&path).unwrap();
+        assert_eq!(archive.len() % 512, 0);
+        let members = tar_members(&archive);
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0], ("a.jpg".to_string(), vec![1, 2, 3]));
+        assert_eq!(members[1], ("b.rw2".to_string(), vec![9; 600]));
+    }
+
+    #[test]
+    fn test_should_write_base256_size_when_tar_member_exceeds_octal_field() {
+        // 1. setup
+        let size = (8 << 30) + 5;
+
+        // 2. execute
+        let small = tar_header("a.jpg", TAR_OCTAL_SIZE_LIMIT - 1).unwrap();
+        let large = tar_header("b.mp4", size).unwrap();
+
+        // 3. verify
+        assert_eq!(&small[124..136], b"77777777777\0");
+        assert_eq!(large[124], 0x80);
+        assert_eq!(&large[125..128], &[0, 0, 0]);
+        assert_eq!(
+            u64::from_be_bytes(large[128..136].try_into().unwrap()),
+            size
+        );
+    }
+
+    // ---------------------------
+    // Tests for ZipSink
+    // ---------------------------
+
+    #[test]
+    fn test_should_write_local_headers_and_central_directory_when_writing_zip() {
+        // 1. setup
+        let dir = temp_output_dir("zip_sink");
+        let path = dir.join("out.zip");
+        let mut sink = ZipSink::create(&path).unwrap();
+
+        // 2. execute
+        sink.write_file("a.jpg", b"hello").unwrap();
+        sink.finish().unwrap();
+
+        // 3. verify
+        let archive = std::fs::read(&path).unwrap();
+        assert_eq!(&archive[..4], &[0x50, 0x4B, 0x03, 0x04]);
+        assert_eq!(&archive[14..18], &crc32::crc32(b"hello").to_le_bytes());
+        assert_eq!(&archive[30..35], b"a.jpg");
+        assert_eq!(&archive[35..40], b"hello");
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&end[..4], &[0x50, 0x4B, 0x05, 0x06]);
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 1);
+        assert_eq!(u32::from_le_bytes([end[16], end[17], end[18], end[19]]), 40);
+    }
+
+    #[test]
+    fn test_should_refuse_entry_and_keep_archive_readable_when_zip_entry_limit_is_reached() {
+        // 1. setup
+        let dir = temp_output_dir("zip_sink_entry_limit");
+        let path = dir.join("out.zip");
+        let mut sink = ZipSink::create(&path).unwrap();
+        for i in 0..ZIP_MAX_ENTRIES {
+            sink.write_file(&format!("{}.jpg", i), &[]).unwrap();
+        }
+
+        // 2. execute
+        let refused = sink.write_file("last.jpg", b"hello");
+        let finished = sink.finish();
+
+        // 3. verify
+        assert_eq!(refused.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(finished.is_ok());
+        let archive = std::fs::read(&path).unwrap();
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&end[..4], &[0x50, 0x4B, 0x05, 0x06]);
+        assert_eq!(
+            u16::from_le_bytes([end[10], end[11]]) as usize,
+            ZIP_MAX_ENTRIES
+        );
+        let directory_offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(
+            &archive[directory_offset..directory_offset + 4],
+            &[0x50, 0x4B, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_should_refuse_entry_when_zip_offset_would_pass_4gib() {
+        // 1. setup
+        let dir = temp_output_dir("zip_sink_offset_limit");
+        let mut sink = ZipSink::create(&dir.join("out.zip")).unwrap();
+        // 4GiB 近くまで書いた後と同じ状態にする
+        sink.written = ZIP_MAX_OFFSET - 100;
+
+        // 2. execute
+        let refused = sink.write_file("a.jpg", &[0; 100]);
+        let accepted = sink.write_file("b.jpg", &[0; 10]);
+
+        // 3. verify
+        assert_eq!(refused.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(accepted.is_ok());
+        assert_eq!(sink.entries.len(), 1);
+        assert_eq!(sink.entries[0].name, "b.jpg");
+        assert!(sink.finish().is_ok());
+    }
+
+    // ---------------------------
+    // Tests for MemorySink
+    // ---------------------------
+
+    #[test]
+    fn test_should_collect_files_in_order_when_writing_to_memory() {
+        // 1. setup
+        let mut sink = MemorySink::default();
+
+        // 2. execute
+        let digest = sink.write_file("a.jpg", b"hello").unwrap();
+        sink.write_duplicate("b.jpg", "a.jpg", b"hello").unwrap();
+        sink.finish().unwrap();
+
+        // 3. verify
+        assert_eq!(digest, sha256::sha256_hex(b"hello"));
+        assert_eq!(
+            sink.files,
+            vec![
+                ("a.jpg".to_string(), b"hello".to_vec()),
+                ("b.jpg".to_string(), b"hello".to_vec()),
+            ]
+        );
+        assert_eq!(sink.location(), None);
+    }
+
+    // ---------------------------
+    // Tests for open_sink
+    // ---------------------------
+
+    #[test]
+    fn test_should_reject_archive_when_extension_is_unknown() {
+        // 1. setup
+        let dir = temp_output_dir("unknown_archive");
+
+        // 2. execute
+        let result = open_sink(&dir, Some(&dir.join("out.rar")), true);
+
+        // 3. verify
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,447 @@
+// パーティションテーブル（MBR / GPT）とファイルシステムの判別
+pub const DEFAULT_SECTOR_SIZE: u64 = 512;
+
+// テーブルの解析に使うデバイス先頭の長さ（GPTのエントリ 128個 × 128バイトを4Kセクタでも含む）
+pub const TABLE_READ_LEN: usize = 64 * 1024;
+
+// ファイルシステムの判別に使う、パーティション先頭の長さ（ext のスーパーブロックを含む）
+pub const BOOT_SECTOR_READ_LEN: usize = 2048;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_LEN: usize = 16;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const GPT_MAX_ENTRIES: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    // 1始まりの番号（MBRはスロット番号、GPTはエントリ番号）
+    pub index: usize,
+    // デバイス先頭からのバイト数
+    pub start: u64,
+    pub len: u64,
+    // MBRはパーティションタイプ（例: 0x0c）、GPTは名前
+    pub label: String,
+}
+
+// ファイルシステムのクラスタ（ext はブロック）の並び。オフセットはファイルシステム先頭から
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterLayout {
+    // 最初のクラスタが始まる位置（FAT はFATとルートディレクトリの後ろ、exFAT はクラスタヒープ）
+    pub heap_offset: u64,
+    pub cluster_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionTable {
+    Mbr(Vec<Partition>),
+    Gpt(Vec<Partition>),
+    // パーティションを切らずに全体をフォーマットしている、またはテーブルが読めない
+    None,
+}
+
+impl PartitionTable {
+    pub fn partitions(&self) -> &[Partition] {
+        match self {
+            PartitionTable::Mbr(partitions) | PartitionTable::Gpt(partitions) => partitions,
+            PartitionTable::None => &[],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartitionTable::Mbr(_) => "MBR",
+            PartitionTable::Gpt(_) => "GPT",
+            PartitionTable::None => "なし",
+        }
+    }
+}
+
+fn u16_le(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_le(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn u64_le(data: &[u8], pos: usize) -> Option<u64> {
+    let bytes = data.get(pos..pos + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+// data はデバイスの先頭（TABLE_READ_LEN 程度）
+pub fn parse(data: &[u8], sector_size: u64) -> PartitionTable {
+    if data.get(510..512) != Some(&MBR_SIGNATURE[..]) {
+        return PartitionTable::None;
+    }
+    // ブートセクタ自体がファイルシステムなら（SDカードのスーパーフロッピー形式）テーブルはない
+    if detect_filesystem(data).is_some() {
+        return PartitionTable::None;
+    }
+
+    let entries: Vec<&[u8]> = (0..4)
+        .map(|i| {
+            let pos = MBR_ENTRIES_OFFSET + i * MBR_ENTRY_LEN;
+            &data[pos..pos + MBR_ENTRY_LEN]
+        })
+        .collect();
+    // ブートフラグは 0x00 か 0x80 のみ
+    if entries.iter().any(|entry| entry[0] & 0x7F != 0) {
+        return PartitionTable::None;
+    }
+
+    if entries
+        .iter()
+        .any(|entry| entry[4] == MBR_TYPE_GPT_PROTECTIVE)
+        && let Some(partitions) = parse_gpt(data, sector_size)
+    {
+        return PartitionTable::Gpt(partitions);
+    }
+
+    let partitions = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry[4] != 0)
+        .filter_map(|(i, entry)| {
+            let first_lba = u32_le(entry, 8)? as u64;
+            let sectors = u32_le(entry, 12)? as u64;
+            Some(Partition {
+                index: i + 1,
+                start: first_lba * sector_size,
+                len: sectors * sector_size,
+                label: format!("0x{:02x}", entry[4]),
+            })
+        })
+        .collect();
+    PartitionTable::Mbr(partitions)
+}
+
+fn parse_gpt(data: &[u8], sector_size: u64) -> Option<Vec<Partition>> {
+    let header_pos = sector_size as usize;
+    let header = data.get(header_pos..header_pos + 92)?;
+    if !header.starts_with(GPT_SIGNATURE) {
+        return None;
+    }
+
+    let entries_lba = u64_le(header, 72)?;
+    let entry_count = (u32_le(header, 80)? as usize).min(GPT_MAX_ENTRIES);
+    let entry_len = u32_le(header, 84)? as usize;
+    if entry_len < 128 {
+        return None;
+    }
+
+    let entries_pos = usize::try_from(entries_lba.checked_mul(sector_size)?).ok()?;
+    let mut partitions = Vec::new();
+    for i in 0..entry_count {
+        let pos = entries_pos + i * entry_len;
+        let Some(entry) = data.get(pos..pos + 128) else {
+            break;
+        };
+        // タイプGUIDがすべてゼロなら未使用
+        if entry[..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first_lba = u64_le(entry, 32)?;
+        let last_lba = u64_le(entry, 40)?;
+        if last_lba < first_lba {
+            continue;
+        }
+
+        let name: Vec<u16> = (0..36)
+            .filter_map(|c| u16_le(entry, 56 + c * 2))
+            .take_while(|&c| c != 0)
+            .collect();
+        partitions.push(Partition {
+            index: i + 1,
+            start: first_lba * sector_size,
+            len: (last_lba - first_lba + 1) * sector_size,
+            label: String::from_utf16_lossy(&name),
+        });
+    }
+    Some(partitions)
+}
+
+// data はパーティション（またはデバイス）の先頭
+pub fn detect_filesystem(data: &[u8]) -> Option<&'static str> {
+    if data.get(3..11) == Some(b"EXFAT   ") {
+        return Some("exFAT");
+    }
+    if data.get(3..11) == Some(b"NTFS    ") {
+        return Some("NTFS");
+    }
+    if data.get(82..90) == Some(b"FAT32   ") {
+        return Some("FAT32");
+    }
+    if data.get(54..62) == Some(b"FAT16   ") {
+        return Some("FAT16");
+    }
+    if data.get(54..62) == Some(b"FAT12   ") {
+        return Some("FAT12");
+    }
+    // ext2/3/4 のスーパーブロックはパーティション先頭から1024バイト目
+    if u16_le(data, 1024 + 56) == Some(0xEF53) {
+        return Some("ext");
+    }
+    None
+}
+
+// data はファイルシステムの先頭（BOOT_SECTOR_READ_LEN 程度）
+pub fn cluster_layout(data: &[u8]) -> Option<ClusterLayout> {
+    match detect_filesystem(data)? {
+        "exFAT" => {
+            let bytes_per_sector = 1u64 << (*data.get(108)?).min(12);
+            let cluster_size = bytes_per_sector << (*data.get(109)?).min(25);
+            let heap_offset = u32_le(data, 88)? as u64 * bytes_per_sector;
+            Some(ClusterLayout {
+                heap_offset,
+                cluster_size,
+            })
+        }
+        "NTFS" => {
+            let bytes_per_sector = bytes_per_sector(data)?;
+            // 0x80 以上は 2 の (256 - 値) 乗クラスタ
+            let sectors_per_cluster = match *data.get(13)? {
+                0 => return None,
+                n @ 1..=0x80 => n as u64,
+                n => 1u64 << (256 - n as u32).min(31),
+            };
+            Some(ClusterLayout {
+                heap_offset: 0,
+                cluster_size: bytes_per_sector * sectors_per_cluster,
+            })
+        }
+        "ext" => {
+            let log_block_size = u32_le(data, 1024 + 24)?.min(6);
+            Some(ClusterLayout {
+                heap_offset: 0,
+                cluster_size: 1024 << log_block_size,
+            })
+        }
+        // FAT12 / FAT16 / FAT32
+        _ => {
+            let bytes_per_sector = bytes_per_sector(data)?;
+            let sectors_per_cluster = *data.get(13)? as u64;
+            if !sectors_per_cluster.is_power_of_two() {
+                return None;
+            }
+            let reserved = u16_le(data, 14)? as u64;
+            let fats = *data.get(16)? as u64;
+            let root_entries = u16_le(data, 17)? as u64;
+            let fat_sectors = match u16_le(data, 22)? {
+                0 => u32_le(data, 36)? as u64,
+                n => n as u64,
+            };
+            let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+            Some(ClusterLayout {
+                heap_offset: (reserved + fats * fat_sectors + root_sectors) * bytes_per_sector,
+                cluster_size: bytes_per_sector * sectors_per_cluster,
+            })
+        }
+    }
+}
+
+fn bytes_per_sector(data: &[u8]) -> Option<u64> {
+    let size = u16_le(data, 11)? as u64;
+    (size.is_power_of_two() && (512..=4096).contains(&size)).then_some(size)
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // (タイプ, 開始LBA, セクタ数) のエントリを持つMBR
+    pub fn mbr(entries: &[(u8, u32, u32)]) -> Vec<u8> {
+        let mut data = vec![0u8; 512];
+        for (i, (kind, first_lba, sectors)) in entries.iter().enumerate() {
+            let pos = 446 + i * 16;
+            data[pos + 4] = *kind;
+            data[pos + 8..pos + 12].copy_from_slice(&first_lba.to_le_bytes());
+            data[pos + 12..pos + 16].copy_from_slice(&sectors.to_le_bytes());
+        }
+        data[510] = 0x55;
+        data[511] = 0xAA;
+        data
+    }
+
+    // 512バイトセクタ、予約 32 セクタ、FAT 2つ × fat_sectors のFAT32ブートセクタ
+    pub fn fat32_boot_sector(sectors_per_cluster: u8, fat_sectors: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 512];
+        data[11..13].copy_from_slice(&512u16.to_le_bytes());
+        data[13] = sectors_per_cluster;
+        data[14..16].copy_from_slice(&32u16.to_le_bytes());
+        data[16] = 2;
+        data[36..40].copy_from_slice(&fat_sectors.to_le_bytes());
+        data[82..90].copy_from_slice(b"FAT32   ");
+        data[510] = 0x55;
+        data[511] = 0xAA;
+        data
+    }
+
+    // 保護MBR + LBA1 のヘッダ + LBA2 からのエントリ（512バイトセクタ）
+    // エントリは (開始LBA, 終了LBA, 名前)
+    pub fn gpt(entries: &[(u64, u64, &str)]) -> Vec<u8> {
+        let mut data = mbr(&[(0xEE, 1, u32::MAX)]);
+        data.resize(512 * 34, 0x00);
+
+        let header = &mut data[512..512 + 92];
+        header[..8].copy_from_slice(b"EFI PART");
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+
+        for (i, (first_lba, last_lba, name)) in entries.iter().enumerate() {
+            let pos = 1024 + i * 128;
+            data[pos..pos + 16].copy_from_slice(&[0xA2; 16]);
+            data[pos + 32..pos + 40].copy_from_slice(&first_lba.to_le_bytes());
+            data[pos + 40..pos + 48].copy_from_slice(&last_lba.to_le_bytes());
+            for (c, unit) in name.encode_utf16().enumerate() {
+                data[pos + 56 + c * 2..pos + 58 + c * 2].copy_from_slice(&unit.to_le_bytes());
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::{fat32_boot_sector, gpt, mbr};
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse
+    // ---------------------------
+
+    #[test]
+    fn test_should_list_partitions_with_byte_offsets_when_mbr_has_entries() {
+        // 1. setup
+        let data = mbr(&[(0x0C, 8192, 1_000_000), (0x83, 1_008_192, 2048)]);
+
+        // 2. execute
+        let result = parse(&data, DEFAULT_SECTOR_SIZE);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            PartitionTable::Mbr(vec![
+                Partition {
+                    index: 1,
+                    start: 8192 * 512,
+                    len: 1_000_000 * 512,
+                    label: "0x0c".to_string(),
+                },
+                Partition {
+                    index: 2,
+                    start: 1_008_192 * 512,
+                    len: 2048 * 512,
+                    label: "0x83".to_string(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_should_list_gpt_partitions_when_mbr_is_protective() {
+        // 1. setup
+        let data = gpt(&[(2048, 4095, "EFI"), (4096, 1_052_671, "SDCARD")]);
+
+        // 2. execute
+        let result = parse(&data, DEFAULT_SECTOR_SIZE);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            PartitionTable::Gpt(vec![
+                Partition {
+                    index: 1,
+                    start: 2048 * 512,
+                    len: 2048 * 512,
+                    label: "EFI".to_string(),
+                },
+                Partition {
+                    index: 2,
+                    start: 4096 * 512,
+                    len: 1_048_576 * 512,
+                    label: "SDCARD".to_string(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_should_return_none_when_boot_sector_is_exfat() {
+        // 1. setup
+        let mut data = mbr(&[]);
+        data[3..11].copy_from_slice(b"EXFAT   ");
+
+        // 2. execute
+        let result = parse(&data, DEFAULT_SECTOR_SIZE);
+
+        // 3. verify
+        assert_eq!(result, PartitionTable::None);
+    }
+
+    // ---------------------------
+    // Tests for cluster_layout
+    // ---------------------------
+
+    #[test]
+    fn test_should_place_heap_after_fats_when_boot_sector_is_fat32() {
+        // 1. setup
+        let data = fat32_boot_sector(8, 100);
+
+        // 2. execute
+        let result = cluster_layout(&data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            Some(ClusterLayout {
+                heap_offset: (32 + 2 * 100) * 512,
+                cluster_size: 4096,
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_read_shifts_when_boot_sector_is_exfat() {
+        // 1. setup
+        let mut data = vec![0u8; 512];
+        data[3..11].copy_from_slice(b"EXFAT   ");
+        data[88..92].copy_from_slice(&2048u32.to_le_bytes());
+        data[108] = 9;
+        data[109] = 6;
+
+        // 2. execute
+        let result = cluster_layout(&data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            Some(ClusterLayout {
+                heap_offset: 2048 * 512,
+                cluster_size: 32 * 1024,
+            })
+        );
+    }
+
+    // ---------------------------
+    // Tests for detect_filesystem
+    // ---------------------------
+
+    #[test]
+    fn test_should_detect_fat32_when_boot_sector_has_fat32_label() {
+        // 1. setup
+        let mut data = vec![0u8; 512];
+        data[82..90].copy_from_slice(b"FAT32   ");
+
+        // 2. execute
+        let result = detect_filesystem(&data);
+
+        // 3. verify
+        assert_eq!(result, Some("FAT32"));
+    }
+}
@@ -0,0 +1,145 @@
+// --preview N: 長い実行の前に、最初に見つかる N 個の候補を表で確かめる（保存はしない）
+use std::io::{self, Write};
+
+use crate::carve;
+use crate::cli::Options;
+use crate::color;
+use crate::run_log::log_eprintln;
+use crate::source::{self, ReadError, Source};
+use crate::{FileType, first_candidate, scan_overlap};
+
+#[derive(Debug, PartialEq)]
+pub struct Candidate {
+    pub file_type: FileType,
+    pub offset: u64,
+    // 終端が見つからなければ None
+    pub size: Option<u64>,
+}
+
+// 先頭から読み進め、スタートシグネチャを limit 個見つけたところで止める
+pub fn first_candidates<R: Source>(
+    reader: &mut R,
+    options: &Options,
+    limit: usize,
+) -> Vec<Candidate> {
+    let mut starts = Vec::new();
+    let overlap = scan_overlap(options);
+    let mut buffer = Vec::new();
+    let mut temp = vec![0u8; options.block_size];
+    let mut read_bytes = 0u64;
+    // 持ち越した部分で同じ候補を2度数えないよう、ここより前は見つけ済み
+    let mut next_offset = reader.start_offset();
+
+    while starts.len() < limit {
+        let position = reader.start_offset() + read_bytes;
+        let n = match source::read_block(reader, &mut temp, position) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                let error = ReadError {
+                    offset: position,
+                    file_index: None,
+                    error: e,
+                };
+                log_eprintln!("{}", color::error(format_args!("{}", error)));
+                break;
+            }
+        };
+        read_bytes += n as u64;
+        buffer.extend_from_slice(&temp[..n]);
+        let buffer_offset = reader.start_offset() + read_bytes - buffer.len() as u64;
+
+        let mut from = next_offset.saturating_sub(buffer_offset) as usize;
+        while starts.len() < limit && from < buffer.len() {
+            let Some((idx, file_type)) =
+                first_candidate(&buffer[from..], buffer_offset + from as u64, options)
+            else {
+                break;
+            };
+            let offset = buffer_offset + (from + idx) as u64;
+            starts.push((file_type, offset));
+            next_offset = offset + 1;
+            from += idx + 1;
+        }
+
+        let keep_from = buffer.len().saturating_sub(overlap);
+        buffer = buffer.split_off(keep_from);
+    }
+
+    starts
+        .into_iter()
+        .map(|(file_type, offset)| Candidate {
+            file_type,
+            offset,
+            size: carve::carve_at(reader, offset, options).map(|file| file.data.len() as u64),
+        })
+        .collect()
+}
+
+pub fn write_table<W: Write>(out: &mut W, candidates: &[Candidate]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>4}  {:<6}  {:>14}  {:>12}",
+        "#", "種類", "オフセット", "サイズ"
+    )?;
+    for (index, candidate) in candidates.iter().enumerate() {
+        let size = candidate
+            .size
+            .map_or_else(|| "不明".to_string(), |size| size.to_string());
+        writeln!(
+            out,
+            "{:>4}  {:<6}  {:>#14x}  {:>12}",
+            index + 1,
+            candidate.file_type.name(),
+            candidate.offset,
+            size
+        )?;
+    }
+    writeln!(out, "候補: {} 個", candidates.len())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+    use std::io::Cursor;
+
+    // ---------------------------
+    // Tests for first_candidates
+    // ---------------------------
+
+    #[test]
+    fn test_should_report_exactly_limit_candidates_when_device_has_more() {
+        // 1. setup
+        let jpeg = minimal_jpeg(&[0x01, 0x02, 0x03]);
+        let mut data = Vec::new();
+        for _ in 0..5 {
+            data.extend_from_slice(&[0x00; 10]);
+            data.extend_from_slice(&jpeg);
+        }
+        let options = Options {
+            block_size: 16,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let candidates = first_candidates(&mut Cursor::new(data), &options, 3);
+
+        // 3. verify
+        let stride = (10 + jpeg.len()) as u64;
+        assert_eq!(
+            candidates,
+            (0..3)
+                .map(|i| Candidate {
+                    file_type: FileType::Jpeg,
+                    offset: 10 + i * stride,
+                    size: Some(jpeg.len() as u64),
+                })
+                .collect::<Vec<_>>()
+        );
+        let mut out = Vec::new();
+        write_table(&mut out, &candidates).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("候補: 3 個\n"));
+    }
+}
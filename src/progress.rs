@@ -0,0 +1,76 @@
+// --progress-file: 画面を見られない実行でも別の端末から cat できるよう、進捗を出力先の progress.json に書く
+// 書きかけを読まれないように、一時ファイルに書いてから置き換える
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::Summary;
+
+pub const PROGRESS_FILE_NAME: &str = "progress.json";
+const TEMP_SUFFIX: &str = ".tmp";
+
+// 範囲の大きさや残り時間が分からなければ null
+pub fn to_json(summary: &Summary, elapsed: Duration, finished: bool) -> String {
+    let total = summary.total_bytes.filter(|&total| total > 0);
+    let eta = if finished {
+        Some(Duration::ZERO)
+    } else {
+        total.and_then(|total| {
+            summary
+                .throughput
+                .eta(total.saturating_sub(summary.scanned_bytes))
+        })
+    };
+    let or_null = |value: Option<u64>| value.map_or("null".to_string(), |value| value.to_string());
+    format!(
+        "{{\"scanned_bytes\": {}, \"total_bytes\": {}, \"files_found\": {}, \"elapsed_seconds\": {}, \"eta_seconds\": {}, \"finished\": {}}}\n",
+        summary.scanned_bytes,
+        or_null(total),
+        summary.entries.len(),
+        elapsed.as_secs(),
+        or_null(eta.map(|eta| eta.as_secs())),
+        finished
+    )
+}
+
+pub fn write(
+    output_dir: &Path,
+    summary: &Summary,
+    elapsed: Duration,
+    finished: bool,
+) -> io::Result<()> {
+    let path = output_dir.join(PROGRESS_FILE_NAME);
+    let temp = output_dir.join(format!("{}{}", PROGRESS_FILE_NAME, TEMP_SUFFIX));
+    fs::create_dir_all(output_dir)?;
+    fs::write(&temp, to_json(summary, elapsed, finished))?;
+    fs::rename(temp, path)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for to_json
+    // ---------------------------
+
+    #[test]
+    fn test_should_write_null_total_and_eta_when_range_size_is_unknown() {
+        // 1. setup
+        let summary = Summary {
+            scanned_bytes: 4096,
+            ..Summary::default()
+        };
+
+        // 2. execute
+        let json = to_json(&summary, Duration::from_secs(12), false);
+
+        // 3. verify
+        assert_eq!(
+            json,
+            "{\"scanned_bytes\": 4096, \"total_bytes\": null, \"files_found\": 0, \"elapsed_seconds\": 12, \"eta_seconds\": null, \"finished\": false}\n"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// Photoshop（PSD / PSB）のヘッダと、その後ろに長さ付きで並ぶセクションから求めるファイルの大きさ
+// 値はすべてビッグエンディアン。カラーモードデータ・画像リソース・レイヤーとマスク情報の後ろに
+// 合成済みの画像データが続き、その大きさは圧縮方式ごとにヘッダの寸法から計算する
+pub const MAGIC: &[u8] = b"8BPS";
+
+// シグネチャ, 版, 予約 6 バイト, チャンネル数, 高さ, 幅, ビット深度, カラーモード
+pub const HEADER_LEN: usize = 26;
+const VERSION_PSD: u16 = 1;
+const VERSION_PSB: u16 = 2;
+const MAX_CHANNELS: u16 = 56;
+const MAX_COLOR_MODE: u16 = 9;
+
+const COMPRESSION_RAW: u16 = 0;
+const COMPRESSION_RLE: u16 = 1;
+const COMPRESSION_ZIP: u16 = 2;
+const COMPRESSION_ZIP_PREDICTION: u16 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum PsdHeader {
+    Valid { size: u64 },
+    // 画像データが ZIP 圧縮で、長さを計算できない
+    Unsized,
+    Incomplete,
+    Invalid,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u64 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as u64
+}
+
+fn u64_at(data: &[u8], pos: usize) -> u64 {
+    u64::from_be_bytes(data[pos..pos + 8].try_into().unwrap())
+}
+
+// pos にある長さ欄（len_bytes バイト）とその中身を読み飛ばした位置
+fn skip_section(data: &[u8], pos: u64, len_bytes: usize) -> Result<u64, PsdHeader> {
+    if (data.len() as u64) < pos.saturating_add(len_bytes as u64) {
+        return Err(PsdHeader::Incomplete);
+    }
+    let field = pos as usize;
+    let len = if len_bytes == 8 {
+        u64_at(data, field)
+    } else {
+        u32_at(data, field)
+    };
+    pos.checked_add(len_bytes as u64)
+        .and_then(|pos| pos.checked_add(len))
+        .ok_or(PsdHeader::Invalid)
+}
+
+// data は "8BPS" から始まっていること
+pub fn parse_header(data: &[u8]) -> PsdHeader {
+    match parse(data) {
+        Ok(header) | Err(header) => header,
+    }
+}
+
+// 途中で結論が出たものは Err で返す（? で抜けるため）
+fn parse(data: &[u8]) -> Result<PsdHeader, PsdHeader> {
+    if data.len() < HEADER_LEN {
+        return Err(PsdHeader::Incomplete);
+    }
+    let version = u16_at(data, 4);
+    let channels = u16_at(data, 12) as u64;
+    let height = u32_at(data, 14);
+    let width = u32_at(data, 18);
+    let depth = u16_at(data, 22) as u64;
+    let color_mode = u16_at(data, 24);
+    let max_dimension = if version == VERSION_PSB {
+        300_000
+    } else {
+        30_000
+    };
+    if !(version == VERSION_PSD || version == VERSION_PSB)
+        || data[6..12] != [0; 6]
+        || !(1..=MAX_CHANNELS as u64).contains(&channels)
+        || !(1..=max_dimension).contains(&height)
+        || !(1..=max_dimension).contains(&width)
+        || ![1, 8, 16, 32].contains(&depth)
+        || color_mode > MAX_COLOR_MODE
+    {
+        return Err(PsdHeader::Invalid);
+    }
+    // PSB はレイヤーとマスク情報の長さと RLE の行ごとのバイト数が倍の幅
+    let wide = if version == VERSION_PSB { 8 } else { 4 };
+
+    let pos = skip_section(data, HEADER_LEN as u64, 4)?;
+    let pos = skip_section(data, pos, 4)?;
+    let pos = skip_section(data, pos, wide)?;
+    if (data.len() as u64) < pos.saturating_add(2) {
+        return Err(PsdHeader::Incomplete);
+    }
+    let image_data = pos + 2;
+    let rows = channels * height;
+    match u16_at(data, pos as usize) {
+        COMPRESSION_RAW => {
+            let row_bytes = (width * depth).div_ceil(8);
+            Ok(PsdHeader::Valid {
+                size: image_data + rows * row_bytes,
+            })
+        }
+        // 先頭に全チャンネルの行ごとの圧縮後のバイト数が並ぶ
+        COMPRESSION_RLE => {
+            let count_len = wide as u64 / 2;
+            let table_end = image_data + rows * count_len;
+            if (data.len() as u64) < table_end {
+                return Err(PsdHeader::Incomplete);
+            }
+            let compressed: u64 = (0..rows)
+                .map(|row| {
+                    let pos = (image_data + row * count_len) as usize;
+                    if count_len == 4 {
+                        u32_at(data, pos)
+                    } else {
+                        u16_at(data, pos) as u64
+                    }
+                })
+                .sum();
+            Ok(PsdHeader::Valid {
+                size: table_end + compressed,
+            })
+        }
+        COMPRESSION_ZIP | COMPRESSION_ZIP_PREDICTION => Ok(PsdHeader::Unsized),
+        _ => Err(PsdHeader::Invalid),
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+
+    // 8ビット RGB、幅 width × 高さ height の PSD。画像リソースに 12 バイトだけ置く
+    // rle なら 1 行を 2 バイト（"リテラル1個 + 値"）に圧縮した形にする
+    pub fn minimal_psd(width: u32, height: u32, rle: bool) -> Vec<u8> {
+        let mut data = b"8BPS".to_vec();
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&3u16.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&8u16.to_be_bytes());
+        data.extend_from_slice(&3u16.to_be_bytes());
+        // カラーモードデータ、画像リソース、レイヤーとマスク情報
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&12u32.to_be_bytes());
+        data.extend_from_slice(b"8BIM\x04\x04\x00\x00\x00\x00\x00\x00");
+        data.extend_from_slice(&0u32.to_be_bytes());
+        let rows = 3 * height as usize;
+        if rle {
+            data.extend_from_slice(&1u16.to_be_bytes());
+            for _ in 0..rows {
+                data.extend_from_slice(&2u16.to_be_bytes());
+            }
+            for _ in 0..rows {
+                data.extend_from_slice(&[0x00, 0x7F]);
+            }
+        } else {
+            data.extend_from_slice(&0u16.to_be_bytes());
+            data.resize(data.len() + rows * width as usize, 0x7F);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_psd;
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse_header
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_sections_plus_pixels_when_image_data_is_raw() {
+        // 1. setup
+        let data = minimal_psd(4, 2, false);
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(result, PsdHeader::Valid { size: 76 });
+        assert_eq!(data.len(), 76);
+    }
+
+    #[test]
+    fn test_should_sum_row_byte_counts_when_image_data_is_rle() {
+        // 1. setup
+        let data = minimal_psd(100, 2, true);
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            PsdHeader::Valid {
+                size: data.len() as u64
+            }
+        );
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_version_or_channels_are_absurd() {
+        // 1. setup
+        let mut bad_version = minimal_psd(4, 2, false);
+        bad_version[4..6].copy_from_slice(&7u16.to_be_bytes());
+        let mut no_channels = minimal_psd(4, 2, false);
+        no_channels[12..14].copy_from_slice(&0u16.to_be_bytes());
+
+        // 2. execute
+        let results = [parse_header(&bad_version), parse_header(&no_channels)];
+
+        // 3. verify
+        assert_eq!(results, [PsdHeader::Invalid, PsdHeader::Invalid]);
+    }
+}
@@ -0,0 +1,103 @@
+// Fujifilm RAW（RAF）のヘッダに書かれた、JPEGプレビューと RAW データなどの位置と長さ
+// 値はすべてビッグエンディアン。ファイルはこれらのうち最も後ろにあるものの終わりまで
+pub const MAGIC: &[u8] = b"FUJIFILM";
+
+// "FUJIFILMCCD-RAW " + 版 + 機種ID + 機種名 + ディレクトリの版などの後ろに並ぶ
+// (JPEG の位置, 長さ), (CFA ヘッダの位置, 長さ), (CFA の位置, 長さ)
+const OFFSET_TABLE: usize = 0x54;
+const OFFSET_PAIRS: usize = 3;
+pub const HEADER_LEN: usize = OFFSET_TABLE + OFFSET_PAIRS * 8;
+
+#[derive(Debug, PartialEq)]
+pub enum RafHeader {
+    // 申告された領域のうち最も後ろの終わり
+    Valid { size: u64 },
+    Incomplete,
+    Invalid,
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u64 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as u64
+}
+
+// data は "FUJIFILM" から始まっていること
+// 位置がヘッダの中を指していたり、長さがすべて 0 だったりすれば偽物とみなす
+pub fn parse_header(data: &[u8]) -> RafHeader {
+    if data.len() < HEADER_LEN {
+        return RafHeader::Incomplete;
+    }
+
+    let mut size = 0;
+    for i in 0..OFFSET_PAIRS {
+        let offset = u32_at(data, OFFSET_TABLE + i * 8);
+        let length = u32_at(data, OFFSET_TABLE + i * 8 + 4);
+        if length == 0 {
+            continue;
+        }
+        if offset < HEADER_LEN as u64 {
+            return RafHeader::Invalid;
+        }
+        size = size.max(offset + length);
+    }
+    if size == 0 {
+        return RafHeader::Invalid;
+    }
+    RafHeader::Valid { size }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::{HEADER_LEN, OFFSET_TABLE};
+
+    // JPEG プレビュー、CFA ヘッダ、CFA を (位置, 長さ) のとおりに置いた RAF
+    pub fn minimal_raf(blocks: [(u32, u32); 3]) -> Vec<u8> {
+        let mut data = b"FUJIFILMCCD-RAW 0201FF129502".to_vec();
+        data.resize(OFFSET_TABLE, 0x00);
+        for (offset, length) in blocks {
+            data.extend_from_slice(&offset.to_be_bytes());
+            data.extend_from_slice(&length.to_be_bytes());
+        }
+        let size = blocks
+            .iter()
+            .map(|&(offset, length)| (offset + length) as usize)
+            .max()
+            .unwrap_or(0);
+        data.resize(size.max(HEADER_LEN), 0x6B);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_raf;
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse_header
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_furthest_block_end_when_header_has_offsets() {
+        // 1. setup
+        let data = minimal_raf([(0x100, 0x80), (0x400, 0x20), (0x200, 0x100)]);
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(result, RafHeader::Valid { size: 0x420 });
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_offset_points_into_header() {
+        // 1. setup
+        let data = minimal_raf([(0x10, 0x80), (0, 0), (0, 0)]);
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(result, RafHeader::Invalid);
+    }
+}
@@ -0,0 +1,77 @@
+// --verify-against-fs: 切り出した結果を FAT32 のディレクトリと突き合わせる
+// 先頭がファイルシステム上のファイルの最初のクラスタと一致すれば、まだ消されていないファイル
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::fat::FsFile;
+use crate::manifest::ManifestEntry;
+
+pub const RECONCILIATION_FILE_NAME: &str = "reconciliation.txt";
+
+#[derive(Debug, PartialEq)]
+pub struct Reconciliation<'a> {
+    // ファイルシステムにも載っているもの（切り出したもの, 載っているもの）
+    pub live: Vec<(&'a ManifestEntry, &'a FsFile)>,
+    // 切り出したがファイルシステムには載っていないもの（削除されたファイル）
+    pub deleted: Vec<&'a ManifestEntry>,
+    // ファイルシステムには載っているが切り出せなかったもの
+    pub missed: Vec<&'a FsFile>,
+}
+
+pub fn reconcile<'a>(entries: &'a [ManifestEntry], files: &'a [FsFile]) -> Reconciliation<'a> {
+    let mut live = Vec::new();
+    let mut deleted = Vec::new();
+    let mut matched = HashSet::new();
+    for entry in entries {
+        match files.iter().position(|file| file.offset == entry.offset) {
+            Some(i) => {
+                matched.insert(i);
+                live.push((entry, &files[i]));
+            }
+            None => deleted.push(entry),
+        }
+    }
+    let missed = files
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !matched.contains(i))
+        .map(|(_, file)| file)
+        .collect();
+    Reconciliation {
+        live,
+        deleted,
+        missed,
+    }
+}
+
+pub fn write_report<W: Write>(out: &mut W, reconciliation: &Reconciliation) -> io::Result<()> {
+    writeln!(
+        out,
+        "# 削除済みから復旧: {} 件",
+        reconciliation.deleted.len()
+    )?;
+    for entry in &reconciliation.deleted {
+        writeln!(
+            out,
+            "{:#x}\t{}\t{}",
+            entry.offset, entry.size, entry.filename
+        )?;
+    }
+    writeln!(
+        out,
+        "# ファイルシステムに残っている: {} 件",
+        reconciliation.live.len()
+    )?;
+    for (entry, file) in &reconciliation.live {
+        writeln!(
+            out,
+            "{:#x}\t{}\t{}\t{}",
+            entry.offset, entry.size, entry.filename, file.path
+        )?;
+    }
+    writeln!(out, "# 取りこぼし: {} 件", reconciliation.missed.len())?;
+    for file in &reconciliation.missed {
+        writeln!(out, "{:#x}\t{}\t{}", file.offset, file.size, file.path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+// 実行ごとのログファイル。標準エラーに出した警告やエラーを、色を外して
+// 保存先の recover_YYYYMMDD_HHMMSS.log にも残す（依存クレートを持たないので、log クレートの代わりに
+// eprintln! と同じ形で使える log_eprintln! を置く）
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 開くまでは標準エラーにだけ出す（開いたものはプロセスの終わりまで使う）
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+// 標準エラーに出し、ログファイルを開いていればそこにも書く
+macro_rules! log_eprintln {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        eprintln!("{}", line);
+        $crate::run_log::write_line(&line);
+    }};
+}
+pub(crate) use log_eprintln;
+
+// 1970-01-01 からの日数を (年, 月, 日) にする
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// タイムゾーンは標準ライブラリだけでは分からないので UTC
+pub fn file_name(now: SystemTime) -> String {
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs()) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let time = secs.rem_euclid(86_400);
+    format!(
+        "recover_{:04}{:02}{:02}_{:02}{:02}{:02}.log",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// 保存先を作ってログファイルを開き、以降の log_eprintln! をそこにも書く
+pub fn open(output_dir: &Path, now: SystemTime) -> io::Result<PathBuf> {
+    fs::create_dir_all(output_dir)?;
+    let path = output_dir.join(file_name(now));
+    let file = File::create(&path)?;
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    Ok(path)
+}
+
+// 端末向けの色のエスケープシーケンス（ESC [ ... m）を取り除く
+fn strip_colors(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+// 書き込みに失敗しても、ログのために復旧は止めない
+pub fn write_line(line: &str) {
+    if let Some(file) = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        let _ = writeln!(file, "{}", strip_colors(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color;
+    use crate::test_util::temp_output_dir;
+    use std::time::Duration;
+
+    // ---------------------------
+    // Tests for file_name
+    // ---------------------------
+
+    #[test]
+    fn test_should_name_log_after_utc_time_when_timestamp_is_given() {
+        // 1. setup
+        let now = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+
+        // 2. execute
+        let name = file_name(now);
+
+        // 3. verify
+        assert_eq!(name, "recover_20240229_123456.log");
+    }
+
+    // ---------------------------
+    // Tests for open
+    // ---------------------------
+
+    #[test]
+    fn test_should_mirror_logged_lines_without_colors_when_log_file_is_open() {
+        // 1. setup
+        let output_dir = temp_output_dir("run_log").join("out");
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // 2. execute
+        let path = open(&output_dir, now).unwrap();
+        log_eprintln!(
+            "警告: オフセット {} の jpeg は終端が見つからないため読み飛ばします",
+            4096
+        );
+        write_line(&format!("\x1b[31m{}\x1b[0m", "読み込みエラー"));
+        log_eprintln!("{}", color::skipped("読み飛ばし"));
+
+        // 3. verify
+        assert_eq!(path, output_dir.join("recover_20231114_221320.log"));
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(
+            text.contains("警告: オフセット 4096 の jpeg は終端が見つからないため読み飛ばします\n")
+        );
+        assert!(text.contains("読み込みエラー\n"));
+        assert!(text.contains("読み飛ばし\n"));
+        assert!(!text.contains('\x1b'));
+    }
+}
@@ -0,0 +1,58 @@
+// --sector-bitmap: デバイスのセクタごとに、復旧したファイルに含まれるかを1ビットで表したビットマップ
+// 立っていないセクタは、どの既知の形式にも当てはまらなかった（または空の）領域
+pub const SECTOR_BITMAP_FILE_NAME: &str = "sectors.bitmap";
+
+// i 番目のセクタ（デバイス先頭から）は i / 8 バイト目の下位から i % 8 ビット目
+// ranges は (オフセット, 長さ)。1バイトでも含むセクタを立てる
+pub fn build(ranges: &[(u64, u64)], device_len: u64, sector_size: u64) -> Vec<u8> {
+    let sectors = device_len.div_ceil(sector_size);
+    let mut bitmap = vec![0u8; sectors.div_ceil(8) as usize];
+    for &(offset, len) in ranges.iter().filter(|(_, len)| *len > 0) {
+        let first = offset / sector_size;
+        let last = ((offset + len - 1) / sector_size).min(sectors.saturating_sub(1));
+        for sector in first..=last {
+            bitmap[(sector / 8) as usize] |= 1 << (sector % 8);
+        }
+    }
+    bitmap
+}
+
+pub fn count_marked(bitmap: &[u8]) -> u64 {
+    bitmap.iter().map(|byte| byte.count_ones() as u64).sum()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for build
+    // ---------------------------
+
+    #[test]
+    fn test_should_mark_every_sector_touched_when_ranges_are_unaligned() {
+        // 1. setup
+        // セクタ1の途中からセクタ3の途中まで、とセクタ9ちょうど
+        let ranges = [(700, 1000), (9 * 512, 512)];
+
+        // 2. execute
+        let bitmap = build(&ranges, 12 * 512, 512);
+
+        // 3. verify
+        assert_eq!(bitmap, vec![0b0000_1110, 0b0000_0010]);
+        assert_eq!(count_marked(&bitmap), 4);
+    }
+
+    #[test]
+    fn test_should_leave_bitmap_empty_when_nothing_was_recovered() {
+        // 1. setup
+        let ranges = [];
+
+        // 2. execute
+        let bitmap = build(&ranges, 4096 + 1, 512);
+
+        // 3. verify
+        assert_eq!(bitmap, vec![0, 0]);
+    }
+}
@@ -0,0 +1,157 @@
+// selftest: 組み込みのフィクスチャを切り出し、ビルドしたバイナリが一通り動くかを確かめる
+// デバイスも保存先もメモリ上に置くので、ディスクには何も書かない
+use std::io::{self, Cursor, Write};
+
+use crate::cli::Options;
+use crate::output::MemorySink;
+use crate::tiff::{TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS};
+use crate::{FileType, recover_to, sha256};
+
+// TIFF の LONG 型
+const TIFF_LONG: u16 = 4;
+
+// ファイルのあいだを埋めるゴミ（どのスタートシグネチャにも当たらない値）
+const FILLER: u8 = 0xEE;
+const FILLER_LEN: usize = 1000;
+
+struct Expected {
+    file_type: FileType,
+    offset: u64,
+    data: Vec<u8>,
+}
+
+// セグメント構造として正しい JPEG（SOI, SOS, スキャンデータ, EOI）
+fn fixture_jpeg() -> Vec<u8> {
+    let mut data = vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x03, 0x01];
+    // スタッフィング（FF 00）を含むスキャンデータ
+    data.extend((0..256u32).map(|i| (i * 31 % 251) as u8));
+    data.extend_from_slice(&[0xFF, 0x00, 0x12, 0x34]);
+    data.extend_from_slice(&[0xFF, 0xD9]);
+    data
+}
+
+// IFD0 のストリップから終端が決まる、リトルエンディアンの RW2
+fn fixture_rw2() -> Vec<u8> {
+    let strip_offset: u32 = 64;
+    let strip_len: u32 = 192;
+    let mut data = vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00];
+    data.extend_from_slice(&2u16.to_le_bytes());
+    for (tag, value) in [
+        (TAG_STRIP_OFFSETS, strip_offset),
+        (TAG_STRIP_BYTE_COUNTS, strip_len),
+    ] {
+        data.extend_from_slice(&tag.to_le_bytes());
+        data.extend_from_slice(&TIFF_LONG.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.resize(strip_offset as usize, 0x00);
+    data.resize((strip_offset + strip_len) as usize, 0x5A);
+    data
+}
+
+// ゴミ、JPEG、ゴミ、RW2、ゴミの順に並べたデバイスと、そこから切り出されるべきファイル
+fn fixture() -> (Vec<u8>, Vec<Expected>) {
+    let mut device = vec![FILLER; FILLER_LEN];
+    let mut expected = Vec::new();
+    for (file_type, data) in [
+        (FileType::Jpeg, fixture_jpeg()),
+        (FileType::Rw2, fixture_rw2()),
+    ] {
+        expected.push(Expected {
+            file_type,
+            offset: device.len() as u64,
+            data: data.clone(),
+        });
+        device.extend_from_slice(&data);
+        device.resize(device.len() + FILLER_LEN, FILLER);
+    }
+    (device, expected)
+}
+
+// 食い違いを1件ずつ返す（空なら PASS）
+fn check() -> Vec<String> {
+    let (device, expected) = fixture();
+    let options = Options {
+        summary_only: true,
+        ..Options::default()
+    };
+    let mut sink = MemorySink::default();
+    let summary = recover_to(&mut Cursor::new(device), &options, &mut sink);
+
+    let mut failures = Vec::new();
+    if summary.entries.len() != expected.len() {
+        failures.push(format!(
+            "{} 個のファイルを期待しましたが {} 個でした",
+            expected.len(),
+            summary.entries.len()
+        ));
+    }
+    for want in &expected {
+        let Some(entry) = summary.entries.iter().find(|e| e.offset == want.offset) else {
+            failures.push(format!(
+                "オフセット {} の {} が見つかりませんでした",
+                want.offset,
+                want.file_type.name()
+            ));
+            continue;
+        };
+        if entry.file_type != want.file_type || entry.size != want.data.len() as u64 {
+            failures.push(format!(
+                "オフセット {} は {} ({} バイト) を期待しましたが {} ({} バイト) でした",
+                want.offset,
+                want.file_type.name(),
+                want.data.len(),
+                entry.file_type.name(),
+                entry.size
+            ));
+        }
+        match sink.files.iter().find(|(name, _)| *name == entry.filename) {
+            Some((_, written)) if *written == want.data => {}
+            Some(_) => failures.push(format!("{} の中身が一致しません", entry.filename)),
+            None => failures.push(format!("{} が保存されていません", entry.filename)),
+        }
+        if entry.sha256 != sha256::sha256_hex(&want.data) {
+            failures.push(format!("{} の SHA-256 が一致しません", entry.filename));
+        }
+    }
+    failures
+}
+
+// PASS / FAIL を out に書き、成功したら true を返す
+pub fn run<W: Write>(out: &mut W) -> io::Result<bool> {
+    let failures = check();
+
+    for failure in &failures {
+        writeln!(out, "FAIL: {}", failure)?;
+    }
+    if failures.is_empty() {
+        writeln!(out, "PASS: 組み込みの JPEG / RW2 をすべて復旧できました")?;
+    }
+    Ok(failures.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for run
+    // ---------------------------
+
+    #[test]
+    fn test_should_pass_when_built_in_fixture_is_carved() {
+        // 1. setup
+        let mut out = Vec::new();
+
+        // 2. execute
+        let passed = run(&mut out).unwrap();
+
+        // 3. verify
+        let report = String::from_utf8(out).unwrap();
+        assert!(passed, "{}", report);
+        assert!(report.starts_with("PASS"));
+    }
+}
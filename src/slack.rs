@@ -0,0 +1,91 @@
+// --include-trailing-slack: 切り出したファイルの終わりから次のクラスタ境界までの領域（スラック）
+// 以前に削除されたファイルの残りが入っていることがあるので、image_000001.slack として別に書き出す
+use std::io;
+
+use crate::partition::{self, ClusterLayout};
+use crate::source::{Source, read_at};
+
+pub const SLACK_EXTENSION: &str = "slack";
+
+// デバイス上の1つのファイルシステム（オフセットはデバイス先頭から）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filesystem {
+    pub start: u64,
+    pub end: u64,
+    pub layout: ClusterLayout,
+}
+
+// スキャンする範囲の先頭がファイルシステムならそれだけ、パーティションテーブルならクラスタの大きさが分かる各パーティション
+pub fn detect<R: Source>(reader: &mut R) -> io::Result<Vec<Filesystem>> {
+    let start = reader.start_offset();
+    let head = read_at(reader, start, partition::TABLE_READ_LEN as u64)?;
+    if let Some(layout) = partition::cluster_layout(&head) {
+        return Ok(vec![Filesystem {
+            start,
+            end: u64::MAX,
+            layout,
+        }]);
+    }
+
+    let mut filesystems = Vec::new();
+    for part in partition::parse(&head, partition::DEFAULT_SECTOR_SIZE).partitions() {
+        let part_start = start + part.start;
+        let boot_sector = read_at(reader, part_start, partition::BOOT_SECTOR_READ_LEN as u64)?;
+        if let Some(layout) = partition::cluster_layout(&boot_sector) {
+            filesystems.push(Filesystem {
+                start: part_start,
+                end: part_start + part.len,
+                layout,
+            });
+        }
+    }
+    Ok(filesystems)
+}
+
+// file_end から、それを含むファイルシステムの次のクラスタ境界までの (オフセット, 長さ)
+// ちょうど境界で終わっているか、クラスタの領域の外なら None
+pub fn region(filesystems: &[Filesystem], file_end: u64) -> Option<(u64, u64)> {
+    let filesystem = filesystems
+        .iter()
+        .find(|fs| (fs.start..fs.end).contains(&file_end))?;
+    let heap_start = filesystem.start + filesystem.layout.heap_offset;
+    let cluster_size = filesystem.layout.cluster_size;
+    if file_end < heap_start || cluster_size == 0 {
+        return None;
+    }
+    match (file_end - heap_start) % cluster_size {
+        0 => None,
+        used => Some((file_end, cluster_size - used)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for region
+    // ---------------------------
+
+    #[test]
+    fn test_should_measure_from_cluster_heap_when_heap_is_not_cluster_aligned() {
+        // 1. setup
+        let filesystems = [Filesystem {
+            start: 1000,
+            end: 1 << 20,
+            layout: ClusterLayout {
+                heap_offset: 512,
+                cluster_size: 4096,
+            },
+        }];
+
+        // 2. execute
+        let inside = region(&filesystems, 1000 + 512 + 4096 + 100);
+        let on_boundary = region(&filesystems, 1000 + 512 + 4096);
+
+        // 3. verify
+        assert_eq!(inside, Some((1000 + 512 + 4096 + 100, 3996)));
+        assert_eq!(on_boundary, None);
+    }
+}
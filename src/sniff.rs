@@ -0,0 +1,262 @@
+// 切り出した中身の先頭バイトからの種類判別（スタートシグネチャとは別の意見として使う）
+use crate::FileType;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sniffed {
+    // FileType::name と同じ名前空間
+    pub name: &'static str,
+    pub extension: &'static str,
+}
+
+struct Magic {
+    offset: usize,
+    bytes: &'static [u8],
+}
+
+const fn magic(offset: usize, bytes: &'static [u8]) -> Magic {
+    Magic { offset, bytes }
+}
+
+// より限定的なものを先に並べる（例: CR2 は TIFF でもある）
+const KINDS: &[(&[Magic], Sniffed)] = &[
+    (
+        &[magic(0, &[0xFF, 0xD8, 0xFF])],
+        Sniffed {
+            name: "jpeg",
+            extension: "jpg",
+        },
+    ),
+    (
+        &[magic(0, &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A])],
+        Sniffed {
+            name: "png",
+            extension: "png",
+        },
+    ),
+    (
+        &[magic(0, b"GIF8")],
+        Sniffed {
+            name: "gif",
+            extension: "gif",
+        },
+    ),
+    (
+        &[magic(0, b"BM")],
+        Sniffed {
+            name: "bmp",
+            extension: "bmp",
+        },
+    ),
+    (
+        &[magic(0, b"%PDF-")],
+        Sniffed {
+            name: "pdf",
+            extension: "pdf",
+        },
+    ),
+    (
+        &[magic(0, &[0x50, 0x4B, 0x03, 0x04])],
+        Sniffed {
+            name: "zip",
+            extension: "zip",
+        },
+    ),
+    (
+        &[magic(0, &[0x49, 0x49, 0x2A, 0x00]), magic(8, b"CR")],
+        Sniffed {
+            name: "cr2",
+            extension: "cr2",
+        },
+    ),
+    (
+        &[magic(0, &[0x49, 0x49, 0x1A, 0x00]), magic(6, b"HEAPCCDR")],
+        Sniffed {
+            name: "crw",
+            extension: "crw",
+        },
+    ),
+    (
+        &[magic(0, &[0x49, 0x49, 0x55, 0x00])],
+        Sniffed {
+            name: "rw2",
+            extension: "rw2",
+        },
+    ),
+    (
+        &[magic(0, b"FOVb")],
+        Sniffed {
+            name: "x3f",
+            extension: "x3f",
+        },
+    ),
+    (
+        &[magic(0, b"<?xpacket ")],
+        Sniffed {
+            name: "xmp",
+            extension: "xmp",
+        },
+    ),
+    (
+        &[magic(0, b"<x:xmpmeta")],
+        Sniffed {
+            name: "xmp",
+            extension: "xmp",
+        },
+    ),
+    (
+        &[magic(128, b"DICM")],
+        Sniffed {
+            name: "dicom",
+            extension: "dcm",
+        },
+    ),
+    (
+        &[magic(0, b"8BPS")],
+        Sniffed {
+            name: "psd",
+            extension: "psd",
+        },
+    ),
+    (
+        &[magic(0, b"FUJIFILM")],
+        Sniffed {
+            name: "raf",
+            extension: "raf",
+        },
+    ),
+    (
+        &[magic(0, b"IIRO")],
+        Sniffed {
+            name: "orf",
+            extension: "orf",
+        },
+    ),
+    (
+        &[magic(0, b"IIRS")],
+        Sniffed {
+            name: "orf",
+            extension: "orf",
+        },
+    ),
+    (
+        &[magic(0, &[0x49, 0x49, 0x2A, 0x00])],
+        Sniffed {
+            name: "tiff",
+            extension: "tif",
+        },
+    ),
+    (
+        &[magic(0, &[0x4D, 0x4D, 0x00, 0x2A])],
+        Sniffed {
+            name: "tiff",
+            extension: "tif",
+        },
+    ),
+    (
+        &[magic(0, b"RIFF"), magic(8, b"WEBP")],
+        Sniffed {
+            name: "webp",
+            extension: "webp",
+        },
+    ),
+    (
+        &[magic(4, b"ftypcrx ")],
+        Sniffed {
+            name: "cr3",
+            extension: "cr3",
+        },
+    ),
+    (
+        &[magic(4, b"ftyp")],
+        Sniffed {
+            name: "mp4",
+            extension: "mp4",
+        },
+    ),
+];
+
+pub fn sniff(data: &[u8]) -> Option<Sniffed> {
+    KINDS
+        .iter()
+        .find(|(magics, _)| {
+            magics
+                .iter()
+                .all(|m| data.get(m.offset..m.offset + m.bytes.len()) == Some(m.bytes))
+        })
+        .map(|(_, sniffed)| *sniffed)
+}
+
+// 判別できて、かつ file_type と食い違う場合だけ判別結果を返す
+// DNG は IFD のタグで判別済みで、先頭バイトだけでは TIFF と区別できないので食い違いとはみなさない
+pub fn mismatch(file_type: FileType, data: &[u8]) -> Option<Sniffed> {
+    sniff(data).filter(|sniffed| {
+        sniffed.name != file_type.name() && !(file_type == FileType::Dng && sniffed.name == "tiff")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+
+    // ---------------------------
+    // Tests for mismatch
+    // ---------------------------
+
+    #[test]
+    fn test_should_report_cr2_when_rw2_candidate_is_canon_raw() {
+        // 1. setup
+        let mut data = vec![0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00];
+        data.extend_from_slice(b"CR\x02\x00");
+        data.resize(64, 0x00);
+
+        // 2. execute
+        let result = mismatch(FileType::Rw2, &data);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            Some(Sniffed {
+                name: "cr2",
+                extension: "cr2",
+            })
+        );
+    }
+
+    #[test]
+    fn test_should_return_none_when_sniff_agrees_with_signature() {
+        // 1. setup
+        let data = minimal_jpeg(&[0x01, 0x02]);
+
+        // 2. execute
+        let result = mismatch(FileType::Jpeg, &data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_should_return_none_when_cr3_candidate_has_crx_brand() {
+        // 1. setup
+        let data = crate::bmff::test_fixtures::minimal_mp4(b"crx ", 8);
+
+        // 2. execute
+        let result = mismatch(FileType::Cr3, &data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_should_return_none_when_content_is_unknown() {
+        // 1. setup
+        let data = [0xFF, 0xD8, 0x00, 0x00, 0x00, 0x00];
+
+        // 2. execute
+        let result = mismatch(FileType::Jpeg, &data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
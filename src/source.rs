@@ -0,0 +1,892 @@
+// スキャン対象の読み込み元（デバイスやイメージファイル）
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::thread;
+use std::time::Duration;
+
+use crate::color;
+use crate::run_log::log_eprintln;
+
+// ドライバによっては終わりでもないのに Ok(0) を返すことがあるので、続けてこの回数までは読み直す
+const ZERO_READ_RETRIES: u32 = 3;
+const ZERO_READ_RETRY_DELAY: Duration = Duration::from_millis(10);
+// 開き直すまでに最初に待つ時間（1 回ごとに倍にする）
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// デバイスそのものがなくなったときの読み取りエラー（Linux と macOS で同じ値）
+const ENXIO: i32 = 6;
+const ENODEV: i32 = 19;
+
+// --fadvise でカーネルに伝える読み方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Advice {
+    // 先頭から順に読むので、先読みを増やしてよい
+    Sequential,
+    // 読み終えたのでページキャッシュから落としてよい
+    DontNeed,
+}
+
+pub trait Source: Read {
+    // offset がスパースファイルの穴の中なら、次にデータが現れる位置を返す
+    fn next_data(&mut self, _offset: u64) -> Option<u64> {
+        None
+    }
+
+    fn seek_to(&mut self, _offset: u64) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    // 最初に読むバイトのデバイス上のオフセット
+    fn start_offset(&self) -> u64 {
+        0
+    }
+
+    // start_offset から読める終わりまでのバイト数（分からなければ None）
+    fn total_len(&mut self) -> Option<u64> {
+        None
+    }
+
+    // offset から len バイト（0 なら終わりまで）の読み方をカーネルに伝える。伝える先がなければ何もしない
+    fn advise(&mut self, _advice: Advice, _offset: u64, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Source for File {
+    fn next_data(&mut self, offset: u64) -> Option<u64> {
+        #[cfg(target_os = "linux")]
+        {
+            sparse::next_data(self, offset)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = offset;
+            None
+        }
+    }
+
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset)).map(|_| ())
+    }
+
+    // ブロックデバイスはメタデータの長さが 0 なので、末尾まで移動して求める
+    fn total_len(&mut self) -> Option<u64> {
+        let position = self.stream_position().ok()?;
+        let len = self.seek(SeekFrom::End(0)).ok()?;
+        self.seek(SeekFrom::Start(position)).ok()?;
+        Some(len)
+    }
+
+    fn advise(&mut self, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            fadvise::advise(self, advice, offset, len)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (advice, offset, len);
+            Ok(())
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Source for Cursor<T> {
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.set_position(offset);
+        Ok(())
+    }
+
+    fn total_len(&mut self) -> Option<u64> {
+        Some(self.get_ref().as_ref().len() as u64)
+    }
+}
+
+// スキャンを終えてから保存したファイルの周りを読み直すためのもの。末尾に近ければ len より短く返す
+pub fn read_at<R: Source>(reader: &mut R, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    reader.seek_to(offset)?;
+    let mut data = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// read と同じだが、一時的な Ok(0) では終わりにしない。position は読む位置のデバイス上のオフセット
+// 長さが分かっていれば終わりに着いたときの Ok(0) はすぐ返し、分からなければ続けて 0 が返ったら終わりとみなす
+pub fn read_block<R: Source>(reader: &mut R, buf: &mut [u8], position: u64) -> io::Result<usize> {
+    let mut retries = 0;
+    loop {
+        let n = reader.read(buf)?;
+        if n > 0 || buf.is_empty() || retries == ZERO_READ_RETRIES {
+            return Ok(n);
+        }
+        if let Some(len) = reader.total_len()
+            && position >= reader.start_offset() + len
+        {
+            return Ok(0);
+        }
+        retries += 1;
+        thread::sleep(ZERO_READ_RETRY_DELAY * retries);
+    }
+}
+
+// 1つのデバイスをパーティションごとに Limited で区切って順に読むため
+impl<R: Source + ?Sized> Source for &mut R {
+    fn next_data(&mut self, offset: u64) -> Option<u64> {
+        (**self).next_data(offset)
+    }
+
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        (**self).seek_to(offset)
+    }
+
+    fn start_offset(&self) -> u64 {
+        (**self).start_offset()
+    }
+
+    fn total_len(&mut self) -> Option<u64> {
+        (**self).total_len()
+    }
+
+    fn advise(&mut self, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+        (**self).advise(advice, offset, len)
+    }
+}
+
+// 読み取りに失敗したデバイス上の位置。カードのどの辺りが傷んでいるかを突き合わせられるようにする
+#[derive(Debug)]
+pub struct ReadError {
+    pub offset: u64,
+    // 切り出している途中のファイルの番号（ファイルの外で失敗したなら None）
+    pub file_index: Option<usize>,
+    pub error: io::Error,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "オフセット {} (0x{:x}) の読み取りエラー",
+            self.offset, self.offset
+        )?;
+        if let Some(index) = self.file_index {
+            write!(f, "（ファイル番号 {} の切り出し中）", index)?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+// デバイスの一部（パーティションなど）だけを読む。オフセットはデバイス先頭からのまま
+pub struct Limited<R> {
+    inner: R,
+    start: u64,
+    end: u64,
+    position: u64,
+}
+
+impl<R: Source> Limited<R> {
+    pub fn new(mut inner: R, start: u64, len: u64) -> io::Result<Self> {
+        inner.seek_to(start)?;
+        Ok(Limited {
+            inner,
+            start,
+            end: start.saturating_add(len),
+            position: start,
+        })
+    }
+}
+
+impl<R: Source> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.end.saturating_sub(self.position);
+        let len = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..len])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Source> Source for Limited<R> {
+    fn next_data(&mut self, offset: u64) -> Option<u64> {
+        self.inner.next_data(offset).map(|next| next.min(self.end))
+    }
+
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        let offset = offset.clamp(self.start, self.end);
+        self.inner.seek_to(offset)?;
+        self.position = offset;
+        Ok(())
+    }
+
+    fn start_offset(&self) -> u64 {
+        self.start
+    }
+
+    fn total_len(&mut self) -> Option<u64> {
+        let inner_len = self.inner.total_len().unwrap_or(self.end);
+        Some(inner_len.min(self.end).saturating_sub(self.start))
+    }
+
+    // 終わりまで（0）は、区切った範囲の終わりまでにする
+    fn advise(&mut self, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+        let end = if len == 0 {
+            self.end
+        } else {
+            offset.saturating_add(len).min(self.end)
+        };
+        if end <= offset {
+            return Ok(());
+        }
+        let len = if end == u64::MAX { 0 } else { end - offset };
+        self.inner.advise(advice, offset, len)
+    }
+}
+
+// --scan-beyond-size: 報告されたサイズで読み終えず、その先も読み取りが実際に失敗するまで読む
+// 報告されたサイズに着いて Ok(0) が返れば、その位置へ移動し直して読んでみる
+// 報告されたサイズより後ろでの読み取りエラーは、本当の終わりに着いたものとみなす
+pub struct BeyondSize<R> {
+    inner: R,
+    // 報告されたサイズの終わりのデバイス上のオフセット（None なら何もしない）
+    declared_end: Option<u64>,
+    position: u64,
+    ended: bool,
+}
+
+impl<R: Source> BeyondSize<R> {
+    pub fn new(mut inner: R, enabled: bool) -> Self {
+        let start = inner.start_offset();
+        let declared_end = enabled.then(|| start + inner.total_len().unwrap_or(0));
+        BeyondSize {
+            inner,
+            declared_end,
+            position: start,
+            ended: false,
+        }
+    }
+
+    fn probe(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.seek_to(self.position)?;
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Source> Read for BeyondSize<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(declared_end) = self.declared_end else {
+            return self.inner.read(buf);
+        };
+        if self.ended {
+            return Ok(0);
+        }
+        let result = match self.inner.read(buf) {
+            Ok(0) if self.position >= declared_end && !buf.is_empty() => self.probe(buf),
+            result => result,
+        };
+        match result {
+            Ok(0) if self.position >= declared_end && !buf.is_empty() => {
+                self.ended = true;
+                Ok(0)
+            }
+            Ok(n) => {
+                self.position += n as u64;
+                Ok(n)
+            }
+            Err(e) if self.position >= declared_end && e.kind() != io::ErrorKind::Interrupted => {
+                println!(
+                    "報告されたサイズ（{} バイト）の {} バイト先で読めなくなったため、ここを終わりとみなします: {}",
+                    declared_end,
+                    self.position - declared_end,
+                    e
+                );
+                self.ended = true;
+                Ok(0)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<R: Source> Source for BeyondSize<R> {
+    fn next_data(&mut self, offset: u64) -> Option<u64> {
+        self.inner.next_data(offset)
+    }
+
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.inner.seek_to(offset)?;
+        self.position = offset;
+        self.ended = false;
+        Ok(())
+    }
+
+    fn start_offset(&self) -> u64 {
+        self.inner.start_offset()
+    }
+
+    // 報告されたサイズは信じないので、読み終えるまで分からない
+    fn total_len(&mut self) -> Option<u64> {
+        match self.declared_end {
+            Some(_) => None,
+            None => self.inner.total_len(),
+        }
+    }
+
+    fn advise(&mut self, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+        self.inner.advise(advice, offset, len)
+    }
+}
+
+// パーティションテーブルを読むため
+impl<R: Source + Seek> Seek for BeyondSize<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.position = position;
+        self.ended = false;
+        Ok(position)
+    }
+}
+
+// 読み取りに失敗したら、抜けた USB リーダーが同じパスで認識し直されるのを待って開き直し、
+// 最後に読めた位置から読み直す。retries 回開き直しても読めなければ、最後のエラーを返す
+pub struct Reconnecting<R, F> {
+    inner: R,
+    reopen: F,
+    retries: u32,
+    delay: Duration,
+    // inner の次に読むデバイス上のオフセット
+    position: u64,
+}
+
+impl<R: Source, F: FnMut() -> io::Result<R>> Reconnecting<R, F> {
+    pub fn new(inner: R, reopen: F, retries: u32, delay: Duration) -> Self {
+        let position = inner.start_offset();
+        Reconnecting {
+            inner,
+            reopen,
+            retries,
+            delay,
+            position,
+        }
+    }
+}
+
+// 抜けたデバイスの読み取りエラーか。ただの EIO は不良セクタのことが多く、開き直しても読めない
+fn is_disconnected(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(ENXIO | ENODEV))
+}
+
+impl<R: Source, F: FnMut() -> io::Result<R>> Read for Reconnecting<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut error = match self.inner.read(buf) {
+            Ok(n) => {
+                self.position += n as u64;
+                return Ok(n);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Err(e),
+            Err(e) => e,
+        };
+        // 不良セクタは待たずに返し、呼び出し側の読み飛ばしに任せる
+        if !is_disconnected(&error) {
+            return Err(error);
+        }
+        for attempt in 0..self.retries {
+            log_eprintln!(
+                "{}",
+                color::skipped(format_args!(
+                    "警告: オフセット {} の読み取りエラーのため、デバイスを開き直します（{}/{} 回目）: {}",
+                    self.position,
+                    attempt + 1,
+                    self.retries,
+                    error
+                ))
+            );
+            thread::sleep(self.delay * 2u32.saturating_pow(attempt));
+            // 開けなければ、まだ認識し直されていないのでもう一度待つ
+            match (self.reopen)() {
+                Ok(inner) => self.inner = inner,
+                Err(e) => {
+                    error = e;
+                    continue;
+                }
+            }
+            match self
+                .inner
+                .seek_to(self.position)
+                .and_then(|()| self.inner.read(buf))
+            {
+                Ok(n) => {
+                    println!(
+                        "デバイスを開き直し、オフセット {} から読み直しました",
+                        self.position
+                    );
+                    self.position += n as u64;
+                    return Ok(n);
+                }
+                Err(e) if is_disconnected(&e) => error = e,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(error)
+    }
+}
+
+impl<R: Source, F: FnMut() -> io::Result<R>> Source for Reconnecting<R, F> {
+    fn next_data(&mut self, offset: u64) -> Option<u64> {
+        self.inner.next_data(offset)
+    }
+
+    fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.inner.seek_to(offset)?;
+        self.position = offset;
+        Ok(())
+    }
+
+    fn start_offset(&self) -> u64 {
+        self.inner.start_offset()
+    }
+
+    fn total_len(&mut self) -> Option<u64> {
+        self.inner.total_len()
+    }
+
+    fn advise(&mut self, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+        self.inner.advise(advice, offset, len)
+    }
+}
+
+// パーティションテーブルを読むため
+impl<R: Source + Seek, F: FnMut() -> io::Result<R>> Seek for Reconnecting<R, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sparse {
+    use std::ffi::c_int;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    const SEEK_DATA: c_int = 3;
+    const ENXIO: i32 = 6;
+
+    unsafe extern "C" {
+        fn lseek(fd: c_int, offset: i64, whence: c_int) -> i64;
+    }
+
+    // 通常ファイル以外（ブロックデバイスなど）や SEEK_DATA 非対応なら None
+    pub fn next_data(file: &File, offset: u64) -> Option<u64> {
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+
+        // SAFETY: fd は file が所有する有効なディスクリプタ
+        let result = unsafe { lseek(file.as_raw_fd(), offset as i64, SEEK_DATA) };
+        if result >= 0 {
+            return Some(result as u64);
+        }
+        // ENXIO は offset 以降がすべて穴（ファイル末尾まで）という意味
+        match io::Error::last_os_error().raw_os_error() {
+            Some(ENXIO) => Some(metadata.len()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod fadvise {
+    use std::ffi::c_int;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    use super::Advice;
+
+    const POSIX_FADV_SEQUENTIAL: c_int = 2;
+    const POSIX_FADV_DONTNEED: c_int = 4;
+
+    unsafe extern "C" {
+        fn posix_fadvise(fd: c_int, offset: i64, len: i64, advice: c_int) -> c_int;
+    }
+
+    // errno ではなく戻り値でエラー番号が返る
+    pub fn advise(file: &File, advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+        let advice = match advice {
+            Advice::Sequential => POSIX_FADV_SEQUENTIAL,
+            Advice::DontNeed => POSIX_FADV_DONTNEED,
+        };
+        // SAFETY: fd は file が所有する有効なディスクリプタ
+        match unsafe { posix_fadvise(file.as_raw_fd(), offset as i64, len as i64, advice) } {
+            0 => Ok(()),
+            errno => Err(io::Error::from_raw_os_error(errno)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::Source;
+    use std::io::{self, Cursor, Read};
+
+    // fail_at バイト読んだところで抜けたように読み取りエラーを返す
+    pub struct DisconnectingReader {
+        pub inner: Cursor<Vec<u8>>,
+        pub fail_at: u64,
+    }
+
+    impl Read for DisconnectingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.inner.position() >= self.fail_at {
+                return Err(io::Error::from_raw_os_error(super::ENODEV));
+            }
+            let len = buf
+                .len()
+                .min((self.fail_at - self.inner.position()) as usize);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl Source for DisconnectingReader {
+        fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+            self.inner.seek_to(offset)
+        }
+    }
+
+    // 実際より小さい declared バイトを長さとして報告するデバイス。報告された終わりでは一度 Ok(0) を返すが、
+    // その先へ移動し直せば readable バイトまで読め、そこから先は読み取りエラーになる
+    pub struct UnderreportingReader {
+        pub inner: Cursor<Vec<u8>>,
+        pub declared: u64,
+        pub readable: u64,
+        pub probed: bool,
+    }
+
+    impl Read for UnderreportingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let position = self.inner.position();
+            let end = if self.probed {
+                self.readable
+            } else {
+                self.declared
+            };
+            if position >= self.readable {
+                return Err(io::Error::other("Input/output error"));
+            }
+            if position >= end {
+                return Ok(0);
+            }
+            let len = buf.len().min((end - position) as usize);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl Source for UnderreportingReader {
+        fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+            self.probed |= offset >= self.declared;
+            self.inner.seek_to(offset)
+        }
+
+        fn total_len(&mut self) -> Option<u64> {
+            Some(self.declared)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::{DisconnectingReader, UnderreportingReader};
+    use super::*;
+
+    // ---------------------------
+    // Tests for ReadError
+    // ---------------------------
+
+    #[test]
+    fn test_should_show_offset_and_file_index_when_read_error_is_formatted() {
+        // 1. setup
+        let error = ReadError {
+            offset: 0x1234_5600,
+            file_index: Some(7),
+            error: io::Error::other("I/O error"),
+        };
+
+        // 2. execute
+        let message = error.to_string();
+
+        // 3. verify
+        assert_eq!(
+            message,
+            "オフセット 305419776 (0x12345600) の読み取りエラー（ファイル番号 7 の切り出し中）: I/O error"
+        );
+    }
+
+    // zeros 回だけ Ok(0) を返してから、本当の中身を読む
+    struct FlakyReader {
+        inner: Cursor<Vec<u8>>,
+        zeros: usize,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.zeros > 0 {
+                self.zeros -= 1;
+                return Ok(0);
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Source for FlakyReader {
+        fn total_len(&mut self) -> Option<u64> {
+            self.inner.total_len()
+        }
+    }
+
+    const EIO: i32 = 5;
+
+    // どこを読んでも errno の読み取りエラーを返す
+    struct FailingReader {
+        errno: i32,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::from_raw_os_error(self.errno))
+        }
+    }
+
+    impl Source for FailingReader {
+        fn seek_to(&mut self, _offset: u64) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // ---------------------------
+    // Tests for BeyondSize
+    // ---------------------------
+
+    #[test]
+    fn test_should_read_until_error_past_declared_size_when_enabled() {
+        // 1. setup
+        let device: Vec<u8> = (0..300u32).map(|i| i as u8).collect();
+        let reader = |enabled| {
+            BeyondSize::new(
+                UnderreportingReader {
+                    inner: Cursor::new(device.clone()),
+                    declared: 100,
+                    readable: 300,
+                    probed: false,
+                },
+                enabled,
+            )
+        };
+
+        // 2. execute
+        let mut beyond = Vec::new();
+        let beyond_result = reader(true).read_to_end(&mut beyond);
+        let mut declared = Vec::new();
+        let declared_result = reader(false).read_to_end(&mut declared);
+
+        // 3. verify
+        assert_eq!(beyond_result.unwrap(), 300);
+        assert_eq!(beyond, device);
+        assert_eq!(declared_result.unwrap(), 100);
+        assert_eq!(reader(true).total_len(), None);
+    }
+
+    // ---------------------------
+    // Tests for Reconnecting
+    // ---------------------------
+
+    #[test]
+    fn test_should_resume_from_last_good_offset_when_device_is_reopened() {
+        // 1. setup
+        let data: Vec<u8> = (0..=255).collect();
+        let device = DisconnectingReader {
+            inner: Cursor::new(data.clone()),
+            fail_at: 100,
+        };
+        // 1 回目は認識し直される前で開けず、2 回目で開ける
+        let mut opened = 0;
+        let reopen = || {
+            opened += 1;
+            if opened == 1 {
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+            Ok(DisconnectingReader {
+                inner: Cursor::new(data.clone()),
+                fail_at: u64::MAX,
+            })
+        };
+        let mut reader = Reconnecting::new(device, reopen, 3, Duration::ZERO);
+
+        // 2. execute
+        let mut read = Vec::new();
+        let result = reader.read_to_end(&mut read);
+
+        // 3. verify
+        assert_eq!(result.unwrap(), data.len());
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_should_return_read_error_when_device_does_not_come_back() {
+        // 1. setup
+        let device = DisconnectingReader {
+            inner: Cursor::new(vec![0xAB; 64]),
+            fail_at: 16,
+        };
+        let reopen = || Err(io::Error::from(io::ErrorKind::NotFound));
+        let mut reader: Reconnecting<DisconnectingReader, _> =
+            Reconnecting::new(device, reopen, 2, Duration::ZERO);
+        let mut buf = [0u8; 64];
+
+        // 2. execute
+        let first = reader.read(&mut buf).unwrap();
+        let second = reader.read(&mut buf);
+
+        // 3. verify
+        assert_eq!(first, 16);
+        assert_eq!(second.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_should_return_read_error_without_reopening_when_sector_is_bad() {
+        // 1. setup
+        let mut opened = 0;
+        let reopen = || {
+            opened += 1;
+            Ok(FailingReader { errno: EIO })
+        };
+        let mut reader = Reconnecting::new(
+            FailingReader { errno: EIO },
+            reopen,
+            5,
+            Duration::from_secs(60),
+        );
+        let mut buf = [0u8; 64];
+        let started = std::time::Instant::now();
+
+        // 2. execute
+        let result = reader.read(&mut buf);
+        let elapsed = started.elapsed();
+
+        // 3. verify
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(EIO));
+        assert_eq!(opened, 0);
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_should_stop_reopening_when_reopened_device_has_bad_sector() {
+        // 1. setup
+        let device = FailingReader { errno: ENODEV };
+        let mut opened = 0;
+        let reopen = || {
+            opened += 1;
+            Ok(FailingReader { errno: EIO })
+        };
+        let mut reader = Reconnecting::new(device, reopen, 5, Duration::ZERO);
+        let mut buf = [0u8; 64];
+
+        // 2. execute
+        let result = reader.read(&mut buf);
+
+        // 3. verify
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(EIO));
+        assert_eq!(opened, 1);
+    }
+
+    // ---------------------------
+    // Tests for advise
+    // ---------------------------
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_should_accept_sequential_and_dontneed_when_file_is_regular() {
+        // 1. setup
+        let path = crate::test_util::temp_output_dir("fadvise").join("image.bin");
+        std::fs::write(&path, vec![0xAB; 8192]).unwrap();
+        let mut file = File::open(&path).unwrap();
+
+        // 2. execute
+        let sequential = file.advise(Advice::Sequential, 0, 0);
+        let dontneed = file.advise(Advice::DontNeed, 0, 4096);
+
+        // 3. verify
+        assert!(sequential.is_ok());
+        assert!(dontneed.is_ok());
+    }
+
+    #[test]
+    fn test_should_clip_advice_to_range_when_reader_is_limited() {
+        // 1. setup
+        struct Recorder(Cursor<Vec<u8>>, Vec<(u64, u64)>);
+        impl Read for Recorder {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+        impl Source for Recorder {
+            fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+                self.0.seek_to(offset)
+            }
+            fn advise(&mut self, _advice: Advice, offset: u64, len: u64) -> io::Result<()> {
+                self.1.push((offset, len));
+                Ok(())
+            }
+        }
+        let mut limited =
+            Limited::new(Recorder(Cursor::new(vec![0; 64]), Vec::new()), 16, 32).unwrap();
+
+        // 2. execute
+        limited.advise(Advice::Sequential, 16, 0).unwrap();
+        limited.advise(Advice::DontNeed, 40, 100).unwrap();
+
+        // 3. verify
+        assert_eq!(limited.inner.1, vec![(16, 32), (40, 8)]);
+    }
+
+    // ---------------------------
+    // Tests for read_block
+    // ---------------------------
+
+    #[test]
+    fn test_should_retry_when_zero_read_is_before_known_end() {
+        // 1. setup
+        let mut reader = FlakyReader {
+            inner: Cursor::new(vec![0xAB; 8]),
+            zeros: 2,
+        };
+        let mut buf = [0u8; 16];
+
+        // 2. execute
+        let first = read_block(&mut reader, &mut buf, 0).unwrap();
+        let second = read_block(&mut reader, &mut buf, 8).unwrap();
+
+        // 3. verify
+        assert_eq!(first, 8);
+        assert_eq!(second, 0);
+    }
+
+    #[test]
+    fn test_should_give_up_when_zero_reads_keep_coming() {
+        // 1. setup
+        let mut reader = FlakyReader {
+            inner: Cursor::new(vec![0xAB; 8]),
+            zeros: ZERO_READ_RETRIES as usize + 1,
+        };
+        let mut buf = [0u8; 16];
+
+        // 2. execute
+        let result = read_block(&mut reader, &mut buf, 0).unwrap();
+
+        // 3. verify
+        assert_eq!(result, 0);
+    }
+}
@@ -0,0 +1,224 @@
+// --serve-status: 監視の仕組みからポーリングできるよう、スキャン中の進捗を HTTP の JSON で返す
+// 依存クレートを持たないので、std の TcpListener を裏のスレッドで回すだけの小さなもの
+// どのパスへの GET にも、progress.json と同じ形の最新の進捗を返す
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// 止める合図を確かめる間隔（accept はノンブロッキングにして待つ）
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// 応答は1件ずつなので、要求を少しずつしか送ってこない接続で止めたままにしない
+// 読み込み1回ごとではなく、要求全体をこの時間までに受け取れなければ切る
+const REQUEST_DEADLINE: Duration = Duration::from_secs(2);
+// GET の要求の行とヘッダーには十分で、これより長いものは受け取らない
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct StatusServer {
+    addr: SocketAddr,
+    status: Arc<Mutex<String>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+// 要求の行とヘッダー（空行まで）を読む
+// 読み残したまま閉じると、応答より先に接続がリセットされることがある
+fn read_request(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let deadline = Instant::now() + REQUEST_DEADLINE;
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "要求のヘッダーが長すぎます",
+            ));
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "要求を時間内に受け取れませんでした",
+            ));
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..n]);
+    }
+    Ok(request)
+}
+
+// 要求を受け取れたら1回だけ応答して閉じる（受け取れなければ応答せずに閉じる）
+fn respond(mut stream: TcpStream, status: &Mutex<String>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let request = read_request(&mut stream)?;
+    let (status_line, body) = if request.starts_with(b"GET ") {
+        (
+            "200 OK",
+            status.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        )
+    } else {
+        ("405 Method Not Allowed", String::new())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_line,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+// addr で待ち受けを始め、update されるまでは initial を返す
+pub fn start(addr: SocketAddr, initial: String) -> io::Result<StatusServer> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let status = Arc::new(Mutex::new(initial));
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let status = Arc::clone(&status);
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    // 応答に失敗しても（相手が先に切ったなど）、次の接続を待つ
+                    Ok((stream, _)) => {
+                        let _ = respond(stream, &status);
+                    }
+                    Err(_) => thread::sleep(POLL_INTERVAL),
+                }
+            }
+        })
+    };
+    Ok(StatusServer {
+        addr,
+        status,
+        stop,
+        handle,
+    })
+}
+
+impl StatusServer {
+    // ポート 0 を指定したときも、実際に待ち受けているアドレス
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn update(&self, json: String) {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner()) = json;
+    }
+
+    // 裏のスレッドが終わるのを待つので、戻ったときにはポートを閉じている
+    pub fn shutdown(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Summary;
+    use crate::progress;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    // ---------------------------
+    // Tests for start
+    // ---------------------------
+
+    #[test]
+    fn test_should_answer_other_clients_when_one_client_sends_headers_slowly() {
+        // 1. setup
+        let server = start("127.0.0.1:0".parse().unwrap(), "{}\n".to_string()).unwrap();
+        let addr = server.addr();
+        let mut slow = TcpStream::connect(addr).unwrap();
+        let trickle = thread::spawn(move || {
+            let _ = slow.write_all(b"GET / HTTP/1.1\r\n");
+            // 読み込みごとの待ち時間より短い間隔で、ヘッダーを1バイトずつ送り続ける
+            for _ in 0..80 {
+                if slow.write_all(b"X").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+        let mut oversized = TcpStream::connect(addr).unwrap();
+        oversized
+            .write_all(
+                &[
+                    b"GET / HTTP/1.1\r\nX-Padding: ".as_slice(),
+                    &[b'a'; 16 * 1024],
+                ]
+                .concat(),
+            )
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let started = Instant::now();
+
+        // 2. execute
+        let response = get(addr, "GET / HTTP/1.1\r\n\r\n");
+        let elapsed = started.elapsed();
+        let mut rejected = String::new();
+        let _ = oversized.read_to_string(&mut rejected);
+        server.shutdown();
+        trickle.join().unwrap();
+
+        // 3. verify
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(elapsed < REQUEST_DEADLINE * 2, "{:?}", elapsed);
+        assert!(rejected.is_empty());
+    }
+
+    #[test]
+    fn test_should_return_latest_progress_json_when_status_is_requested() {
+        // 1. setup
+        let summary = Summary {
+            scanned_bytes: 8192,
+            total_bytes: Some(8192),
+            ..Summary::default()
+        };
+        let json = progress::to_json(&summary, Duration::from_secs(3), true);
+        let server = start(
+            "127.0.0.1:0".parse().unwrap(),
+            progress::to_json(&Summary::default(), Duration::ZERO, false),
+        )
+        .unwrap();
+        let addr = server.addr();
+
+        // 2. execute
+        server.update(json.clone());
+        let response = get(addr, "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let rejected = get(addr, "POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        server.shutdown();
+
+        // 3. verify
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: application/json\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}", json.len())));
+        assert_eq!(
+            body,
+            "{\"scanned_bytes\": 8192, \"total_bytes\": 8192, \"files_found\": 0, \"elapsed_seconds\": 3, \"eta_seconds\": 0, \"finished\": true}\n"
+        );
+        assert!(rejected.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+// --strategy: デバイスを少しずつ読みながらスキャンするか、全体をメモリに読み込んでからスキャンするか
+use std::fs::File;
+use std::io::{self, Read};
+
+use crate::BUFFER_CAP_FACTOR;
+use crate::cli::Options;
+use crate::gzip;
+
+// --max-memory のうち読み込みブロックに充てる割合（1/16）と、それより小さくはしないブロックの大きさ
+const BLOCK_SHARE: u64 = 16;
+const MIN_BLOCK_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanStrategy {
+    // 小さな通常ファイル（イメージファイル）は Load、ブロックデバイスや大きなファイルは Stream
+    Auto,
+    Stream,
+    Load,
+}
+
+impl ScanStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ScanStrategy::Auto),
+            "stream" => Some(ScanStrategy::Stream),
+            "load" => Some(ScanStrategy::Load),
+            _ => None,
+        }
+    }
+}
+
+// 空きメモリの半分までに収まる通常ファイルだけを読み込む（空きメモリが分からなければ読み込まない）
+// --max-memory があればその半分にも収まること。load を指定されても収まらなければ Err
+pub fn choose(
+    choice: ScanStrategy,
+    is_regular_file: bool,
+    len: u64,
+    available_memory: Option<u64>,
+    max_memory: Option<u64>,
+) -> Result<ScanStrategy, String> {
+    let fits = |limit: u64| len <= limit / 2;
+    match choice {
+        ScanStrategy::Auto => match available_memory {
+            Some(available)
+                if is_regular_file && fits(available) && max_memory.is_none_or(fits) =>
+            {
+                Ok(ScanStrategy::Load)
+            }
+            _ => Ok(ScanStrategy::Stream),
+        },
+        ScanStrategy::Load if !max_memory.is_none_or(fits) => Err(format!(
+            "--strategy load では {} バイトを読み込むため --max-memory {} に収まりません（--strategy stream を使ってください）",
+            len,
+            max_memory.unwrap_or(0)
+        )),
+        choice => Ok(choice),
+    }
+}
+
+pub fn resolve(
+    choice: ScanStrategy,
+    file: &File,
+    max_memory: Option<u64>,
+) -> Result<ScanStrategy, String> {
+    let (is_regular_file, len) = match file.metadata() {
+        Ok(metadata) => (metadata.is_file(), metadata.len()),
+        Err(_) => (false, 0),
+    };
+    choose(choice, is_regular_file, len, available_memory(), max_memory)
+}
+
+// 少しずつ読むときに使うメモリは、読み込みブロック2つ（読んだものと持ち越した分）と、
+// 終端を待つ候補を貯めるバッファ（ファイルの上限の BUFFER_CAP_FACTOR 倍まで）、
+// --compress なら gzip にした写し（ファイルの上限の 9/8 ほど）と gzip が一致を探す表。
+// SHA-256 の状態は数百バイトで、書き込みは待ち行列に貯めずにその場で行う。
+// これが limit に収まるように、ブロックと（形式ごとのものも含めた）ファイルの上限を小さくする
+pub fn fit_to_memory(options: &mut Options, limit: u64) -> Result<(), String> {
+    let block_size = (limit / BLOCK_SHARE).min(options.block_size as u64);
+    if block_size < MIN_BLOCK_SIZE {
+        return Err(format!(
+            "--max-memory には {} バイト以上を指定してください: {}",
+            MIN_BLOCK_SIZE * BLOCK_SHARE,
+            limit
+        ));
+    }
+    options.block_size = block_size as usize;
+
+    let file_limit = max_file_limit(limit - 2 * block_size, options.compress);
+    if file_limit == 0 {
+        return Err(format!(
+            "--max-memory {} では --compress の作業領域（{} バイト）が足りません",
+            limit,
+            gzip::WORKING_MEMORY
+        ));
+    }
+    if let Some((file_type, size)) = options
+        .max_file_size_by_type
+        .iter()
+        .find(|(_, size)| *size > file_limit)
+    {
+        return Err(format!(
+            "--max-size {}={} は --max-memory {} に収まりません（{} 以下にしてください）",
+            file_type.name(),
+            size,
+            limit,
+            file_limit
+        ));
+    }
+    options.max_file_size = options.max_file_size.min(file_limit);
+    for &file_type in &options.types {
+        if options.max_file_size_for(file_type) > file_limit {
+            options.max_file_size_by_type.push((file_type, file_limit));
+        }
+    }
+    Ok(())
+}
+
+// 終端を待つバッファと gzip にした写しが、どちらもいちばん大きくなっても available に収まるファイルの上限
+fn max_file_limit(available: u64, compress: bool) -> u64 {
+    if !compress {
+        return available / BUFFER_CAP_FACTOR;
+    }
+    let available = available.saturating_sub(gzip::WORKING_MEMORY);
+    let mut file_limit = available / (8 * BUFFER_CAP_FACTOR + 9) * 8;
+    while file_limit > 0
+        && BUFFER_CAP_FACTOR * file_limit + gzip::max_compressed_len(file_limit) > available
+    {
+        file_limit -= 1;
+    }
+    file_limit
+}
+
+pub fn load(file: &mut File) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// /proc/meminfo の MemAvailable（バイト）
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    kib.checked_mul(1024)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for choose
+    // ---------------------------
+
+    #[test]
+    fn test_should_load_small_image_file_and_stream_large_device_when_auto() {
+        // 1. setup
+        let available = Some(8 << 30);
+
+        // 2. execute
+        let small_file = choose(ScanStrategy::Auto, true, 1 << 20, available, None);
+        let large_device = choose(ScanStrategy::Auto, false, 64 << 30, available, None);
+        let oversized_file = choose(ScanStrategy::Auto, true, 6 << 30, available, None);
+
+        // 3. verify
+        assert_eq!(small_file, Ok(ScanStrategy::Load));
+        assert_eq!(large_device, Ok(ScanStrategy::Stream));
+        assert_eq!(oversized_file, Ok(ScanStrategy::Stream));
+    }
+
+    #[test]
+    fn test_should_keep_explicit_choice_when_strategy_is_not_auto() {
+        // 1. setup
+        let available = None;
+
+        // 2. execute
+        let result = choose(ScanStrategy::Load, false, 64 << 30, available, None);
+
+        // 3. verify
+        assert_eq!(result, Ok(ScanStrategy::Load));
+    }
+
+    #[test]
+    fn test_should_stream_instead_of_loading_when_max_memory_is_tight() {
+        // 1. setup
+        let available = Some(8 << 30);
+        let max_memory = Some(128 << 20);
+
+        // 2. execute
+        let auto = choose(ScanStrategy::Auto, true, 1 << 30, available, max_memory);
+        let load = choose(ScanStrategy::Load, true, 1 << 30, available, max_memory);
+        let small = choose(ScanStrategy::Auto, true, 16 << 20, available, max_memory);
+
+        // 3. verify
+        assert_eq!(auto, Ok(ScanStrategy::Stream));
+        assert!(load.is_err());
+        assert_eq!(small, Ok(ScanStrategy::Load));
+    }
+
+    // ---------------------------
+    // Tests for fit_to_memory
+    // ---------------------------
+
+    #[test]
+    fn test_should_shrink_blocks_and_file_limits_when_max_memory_is_low() {
+        // 1. setup
+        let mut options = Options::default();
+
+        // 2. execute
+        fit_to_memory(&mut options, 128 << 20).unwrap();
+
+        // 3. verify
+        assert_eq!(options.block_size, 512 << 10);
+        let file_limit = ((128 << 20) - 2 * (512 << 10)) / BUFFER_CAP_FACTOR;
+        assert_eq!(options.max_file_size, file_limit);
+        assert!(
+            options
+                .types
+                .iter()
+                .all(|&file_type| options.max_file_size_for(file_type) <= file_limit)
+        );
+    }
+
+    #[test]
+    fn test_should_fit_full_pending_buffer_and_gzip_copy_in_limit_when_max_memory_is_set() {
+        for (limit, compress) in [
+            (64 << 10, false),
+            ((128 << 20) + 12345, false),
+            (1 << 20, true),
+            ((128 << 20) + 12345, true),
+            (4 << 30, true),
+        ] {
+            // 1. setup
+            let mut options = Options {
+                compress,
+                ..Options::default()
+            };
+
+            // 2. execute
+            fit_to_memory(&mut options, limit).unwrap();
+
+            // 3. verify
+            // 終端を待つ候補が上限の BUFFER_CAP_FACTOR 倍に達し、同じ大きさのファイルを gzip にしているときがいちばん多い
+            for &file_type in &options.types {
+                let file_size = options.max_file_size_for(file_type);
+                let pending_buffer = BUFFER_CAP_FACTOR * file_size;
+                let gzip_copy = if compress {
+                    gzip::max_compressed_len(file_size) + gzip::WORKING_MEMORY
+                } else {
+                    0
+                };
+                let blocks = 2 * options.block_size as u64;
+                assert!(
+                    pending_buffer + gzip_copy + blocks <= limit,
+                    "{} {}: {} + {} + {} > {}",
+                    limit,
+                    file_type.name(),
+                    pending_buffer,
+                    gzip_copy,
+                    blocks,
+                    limit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_reject_max_memory_when_compress_tables_do_not_fit() {
+        // 1. setup
+        let mut options = Options {
+            compress: true,
+            ..Options::default()
+        };
+
+        // 2. execute
+        let result = fit_to_memory(&mut options, 256 << 10);
+
+        // 3. verify
+        assert!(result.unwrap_err().contains("--compress"));
+    }
+}
@@ -0,0 +1,18 @@
+// テスト用の一時ディレクトリ。テスト名ごとに分けて並列実行でも衝突しないようにする
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::PathBuf;
+
+pub fn temp_output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sdcard_test_{}_{}", std::process::id(), name));
+    let _ = remove_dir_all(&dir);
+    create_dir_all(&dir).unwrap();
+    dir
+}
+
+// セグメント構造として正しい最小のJPEG（SOI, SOS, スキャンデータ, EOI）
+pub fn minimal_jpeg(scan_data: &[u8]) -> Vec<u8> {
+    let mut data = vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x03, 0x01];
+    data.extend_from_slice(scan_data);
+    data.extend_from_slice(&[0xFF, 0xD9]);
+    data
+}
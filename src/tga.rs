@@ -0,0 +1,167 @@
+// TGA（TARGA 2.0）。ヘッダにマジックナンバーがないので、ファイル末尾のフッターから後ろ向きに先頭を探す
+pub const FOOTER_MAGIC: &[u8] = b"TRUEVISION-XFILE.\0";
+// フッターは拡張領域のオフセット + 開発者領域のオフセット + FOOTER_MAGIC
+pub const FOOTER_MAGIC_OFFSET: usize = 8;
+const FOOTER_LEN: usize = FOOTER_MAGIC_OFFSET + FOOTER_MAGIC.len();
+const HEADER_LEN: usize = 18;
+// 拡張領域の先頭の長さ欄は常にこの値
+const EXTENSION_AREA_LEN: u16 = 495;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TgaStart {
+    // ヘッダから求めた画像データの終わりが、拡張領域（なければフッター）の位置とちょうど一致する
+    Exact(usize),
+    // RLE 圧縮で長さは求められないが、フッターが指す拡張領域がヘッダからの位置に見つかる
+    ExtensionArea(usize),
+    // RLE 圧縮で、ヘッダとして読めることしか確かめられない
+    HeaderOnly(usize),
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+struct Header {
+    // ヘッダ・画像ID・カラーマップの合計
+    data_offset: u64,
+    // 無圧縮ならピクセルデータのバイト数（RLE 圧縮なら None）
+    pixel_bytes: Option<u64>,
+}
+
+// どの欄も取りうる値の組み合わせになっているものだけをヘッダとみなす
+fn parse_header(data: &[u8]) -> Option<Header> {
+    let header = data.get(..HEADER_LEN)?;
+    let (id_len, colormap_type, image_type) = (header[0], header[1], header[2]);
+    let colormap_len = u16_at(header, 5)? as u64;
+    let colormap_entry_bits = header[7];
+    let width = u16_at(header, 12)? as u64;
+    let height = u16_at(header, 14)? as u64;
+    let depth = header[16];
+    let descriptor = header[17];
+
+    let valid_depth = match image_type {
+        // カラーマップ
+        1 | 9 => colormap_type == 1 && depth == 8,
+        // トゥルーカラー
+        2 | 10 => colormap_type == 0 && matches!(depth, 15 | 16 | 24 | 32),
+        // グレースケール
+        3 | 11 => colormap_type == 0 && matches!(depth, 8 | 16),
+        _ => false,
+    };
+    let valid_colormap = if colormap_type == 1 {
+        colormap_len > 0 && matches!(colormap_entry_bits, 15 | 16 | 24 | 32)
+    } else {
+        header[3..8].iter().all(|&b| b == 0)
+    };
+    // 上位2ビット（インターリーブ）は TGA 2.0 では使わない
+    if !valid_depth || !valid_colormap || width == 0 || height == 0 || descriptor & 0xC0 != 0 {
+        return None;
+    }
+
+    let colormap_bytes = colormap_len * (colormap_entry_bits as u64).div_ceil(8);
+    Some(Header {
+        data_offset: HEADER_LEN as u64 + id_len as u64 + colormap_bytes,
+        pixel_bytes: (image_type < 8).then(|| width * height * (depth as u64).div_ceil(8)),
+    })
+}
+
+// data はフッターで終わっていること。フッターに近い側から1バイトずつ戻り、最初に噛み合った先頭を返す
+pub fn find_start(data: &[u8]) -> Option<TgaStart> {
+    let footer_pos = data.len().checked_sub(FOOTER_LEN)?;
+    let footer = &data[footer_pos..];
+    if !footer.ends_with(FOOTER_MAGIC) {
+        return None;
+    }
+    let extension_offset = u32_at(footer, 0)? as u64;
+    let developer_offset = u32_at(footer, 4)? as u64;
+    // 画像データのあとは拡張領域・開発者領域（あれば）・フッターの順
+    let content_len = |start: usize| -> u64 {
+        [extension_offset, developer_offset]
+            .into_iter()
+            .filter(|&offset| offset != 0)
+            .min()
+            .unwrap_or((footer_pos - start) as u64)
+    };
+
+    for start in (0..=footer_pos.checked_sub(HEADER_LEN)?).rev() {
+        let Some(header) = parse_header(&data[start..]) else {
+            continue;
+        };
+        let content_len = content_len(start);
+        if header.data_offset > content_len || start as u64 + content_len > footer_pos as u64 {
+            continue;
+        }
+        let has_extension_area = extension_offset != 0
+            && u16_at(data, start + extension_offset as usize) == Some(EXTENSION_AREA_LEN);
+        if extension_offset != 0 && !has_extension_area {
+            continue;
+        }
+        match header.pixel_bytes {
+            Some(pixel_bytes) if header.data_offset + pixel_bytes == content_len => {
+                return Some(TgaStart::Exact(start));
+            }
+            Some(_) => continue,
+            None if has_extension_area => return Some(TgaStart::ExtensionArea(start)),
+            None => return Some(TgaStart::HeaderOnly(start)),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // 無圧縮のトゥルーカラー（24ビット）で、拡張領域のない TGA 2.0
+    pub fn minimal_tga(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0u8; 18];
+        data[2] = 2;
+        data[12..14].copy_from_slice(&width.to_le_bytes());
+        data[14..16].copy_from_slice(&height.to_le_bytes());
+        data[16] = 24;
+        data.extend((0..width as usize * height as usize * 3).map(|i| (i % 200) as u8 + 1));
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(super::FOOTER_MAGIC);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_tga;
+    use super::*;
+
+    // ---------------------------
+    // Tests for find_start
+    // ---------------------------
+
+    #[test]
+    fn test_should_find_header_behind_footer_when_garbage_precedes_tga() {
+        // 1. setup
+        let tga = minimal_tga(4, 3);
+        let data = [vec![0x00; 100], tga].concat();
+
+        // 2. execute
+        let result = find_start(&data);
+
+        // 3. verify
+        assert_eq!(result, Some(TgaStart::Exact(100)));
+    }
+
+    #[test]
+    fn test_should_return_none_when_pixel_data_length_does_not_match_header() {
+        // 1. setup
+        let mut tga = minimal_tga(4, 3);
+        // 幅を 5 にすると、ヘッダから求めた長さがフッターの位置と合わない
+        tga[12] = 5;
+
+        // 2. execute
+        let result = find_start(&tga);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+}
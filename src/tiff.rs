@@ -0,0 +1,683 @@
+// TIFF（とTIFFベースのRAW）のIFD解析と、そこから求める本来のファイルサイズ
+use std::collections::HashSet;
+
+// --validate-raw で IFD を最後までたどれなかった RAW の保存先
+pub const REJECTED_DIR: &str = "rejected";
+
+// 壊れたヘッダでギガバイト単位のゴミを切り出さないための上限
+pub const MAX_TIFF_FILE_SIZE: usize = 256 * 1024 * 1024;
+
+const MAX_IFD_ENTRIES: usize = 1024;
+const MAX_IFD_COUNT: usize = 64;
+
+pub const TAG_NEW_SUBFILE_TYPE: u16 = 254;
+pub const TAG_MAKE: u16 = 271;
+pub const TAG_STRIP_OFFSETS: u16 = 273;
+pub const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+pub const TAG_PAGE_NUMBER: u16 = 297;
+pub const TAG_TILE_OFFSETS: u16 = 324;
+pub const TAG_TILE_BYTE_COUNTS: u16 = 325;
+pub const TAG_SUB_IFDS: u16 = 330;
+pub const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 513;
+pub const TAG_JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 514;
+pub const TAG_EXIF_IFD: u16 = 34665;
+pub const TAG_GPS_IFD: u16 = 34853;
+// Panasonic RW2 の IFD0 にある、フル解像度のJPEGプレビュー
+pub const TAG_PANASONIC_JPG_FROM_RAW: u16 = 0x002E;
+// DNG の IFD0 にだけある
+pub const TAG_DNG_VERSION: u16 = 0xC612;
+// NRW の IFD0 の Make（"NIKON CORPORATION"）の先頭
+const NIKON_MAKE: &[u8] = b"NIKON";
+// NewSubfileType の「複数ページの 1 ページ」のビット
+const SUBFILE_PAGE: u64 = 0x2;
+
+const JPEG_START: &[u8] = &[0xFF, 0xD8];
+
+// (オフセットのタグ, 長さのタグ) の組
+const DATA_BLOCK_TAGS: [(u16, u16); 3] = [
+    (TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS),
+    (TAG_TILE_OFFSETS, TAG_TILE_BYTE_COUNTS),
+    (
+        TAG_JPEG_INTERCHANGE_FORMAT,
+        TAG_JPEG_INTERCHANGE_FORMAT_LENGTH,
+    ),
+];
+const CHILD_IFD_TAGS: [u16; 3] = [TAG_SUB_IFDS, TAG_EXIF_IFD, TAG_GPS_IFD];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    // 解析に必要な部分がまだバッファにない
+    NeedMoreData,
+    // TIFFとして矛盾している
+    Malformed,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TiffEnd {
+    Found(usize),
+    Incomplete,
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IfdEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    // 値そのもの（4バイト以内の場合）か、値が置かれている位置
+    pub value_offset: u32,
+    // エントリ内の値フィールドの位置
+    pub value_field: usize,
+}
+
+#[derive(Debug)]
+pub struct Ifd {
+    pub entries: Vec<IfdEntry>,
+    pub next: usize,
+    // IFD（エントリ数から次IFDへのポインタまで）の直後の位置
+    pub end: usize,
+}
+
+impl Ifd {
+    pub fn find(&self, tag: u16) -> Option<&IfdEntry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+pub struct TiffReader<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+}
+
+impl<'a> TiffReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ParseError> {
+        let header = data.get(..4).ok_or(ParseError::NeedMoreData)?;
+        let order = match header {
+            [0x49, 0x49, _, _] => ByteOrder::LittleEndian,
+            [0x4D, 0x4D, _, _] => ByteOrder::BigEndian,
+            _ => return Err(ParseError::Malformed),
+        };
+        Ok(TiffReader { data, order })
+    }
+
+    pub fn first_ifd(&self) -> Result<usize, ParseError> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    pub fn u16_at(&self, pos: usize) -> Result<u16, ParseError> {
+        let bytes = self.bytes(pos, 2)?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(match self.order {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        })
+    }
+
+    pub fn u32_at(&self, pos: usize) -> Result<u32, ParseError> {
+        let bytes = self.bytes(pos, 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(match self.order {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        })
+    }
+
+    pub fn bytes(&self, pos: usize, len: usize) -> Result<&'a [u8], ParseError> {
+        let end = pos.checked_add(len).ok_or(ParseError::Malformed)?;
+        if end > MAX_TIFF_FILE_SIZE {
+            return Err(ParseError::Malformed);
+        }
+        self.data.get(pos..end).ok_or(ParseError::NeedMoreData)
+    }
+
+    pub fn read_ifd(&self, offset: usize) -> Result<Ifd, ParseError> {
+        if offset < 8 {
+            return Err(ParseError::Malformed);
+        }
+        let count = self.u16_at(offset)? as usize;
+        if count == 0 || count > MAX_IFD_ENTRIES {
+            return Err(ParseError::Malformed);
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let pos = offset + 2 + i * 12;
+            entries.push(IfdEntry {
+                tag: self.u16_at(pos)?,
+                field_type: self.u16_at(pos + 2)?,
+                count: self.u32_at(pos + 4)?,
+                value_offset: self.u32_at(pos + 8)?,
+                value_field: pos + 8,
+            });
+        }
+
+        let next_pos = offset + 2 + count * 12;
+        let next = self.u32_at(next_pos)? as usize;
+        Ok(Ifd {
+            entries,
+            next,
+            end: next_pos + 4,
+        })
+    }
+
+    // 値の置き場所 (位置, バイト数)。型が不明なら None
+    pub fn value_location(&self, entry: &IfdEntry) -> Option<(usize, usize)> {
+        let size = type_size(entry.field_type)?.checked_mul(entry.count as usize)?;
+        if size <= 4 {
+            Some((entry.value_field, size))
+        } else {
+            Some((entry.value_offset as usize, size))
+        }
+    }
+
+    // SHORT / LONG の配列として値を読む
+    pub fn unsigned_values(&self, entry: &IfdEntry) -> Result<Vec<u64>, ParseError> {
+        let (pos, _) = self.value_location(entry).ok_or(ParseError::Malformed)?;
+        (0..entry.count as usize)
+            .map(|i| match entry.field_type {
+                3 => self.u16_at(pos + i * 2).map(u64::from),
+                4 | 13 => self.u32_at(pos + i * 4).map(u64::from),
+                _ => Err(ParseError::Malformed),
+            })
+            .collect()
+    }
+
+    // IFD0 からチェーンと子IFD（SubIFD / Exif / GPS）をすべてたどる
+    pub fn all_ifds(&self) -> Result<Vec<Ifd>, ParseError> {
+        let mut ifds = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![self.first_ifd()?];
+
+        while let Some(offset) = pending.pop() {
+            if offset == 0 || !visited.insert(offset) {
+                continue;
+            }
+            if visited.len() > MAX_IFD_COUNT {
+                return Err(ParseError::Malformed);
+            }
+
+            let ifd = self.read_ifd(offset)?;
+            pending.push(ifd.next);
+            for entry in ifd
+                .entries
+                .iter()
+                .filter(|e| CHILD_IFD_TAGS.contains(&e.tag))
+            {
+                for child in self.unsigned_values(entry)? {
+                    pending.push(child as usize);
+                }
+            }
+            ifds.push(ifd);
+        }
+
+        Ok(ifds)
+    }
+
+    // IFDとそこから参照されるデータのうち、最も後ろにあるものの終端
+    pub fn extent(&self) -> Result<usize, ParseError> {
+        let mut end = 8;
+
+        for ifd in self.all_ifds()? {
+            end = end.max(ifd.end);
+
+            for entry in &ifd.entries {
+                if let Some((pos, size)) = self.value_location(entry) {
+                    end = end.max(pos.saturating_add(size));
+                }
+            }
+
+            for (offset_tag, length_tag) in DATA_BLOCK_TAGS {
+                let (Some(offsets), Some(lengths)) = (ifd.find(offset_tag), ifd.find(length_tag))
+                else {
+                    continue;
+                };
+                let offsets = self.unsigned_values(offsets)?;
+                let lengths = self.unsigned_values(lengths)?;
+                for (offset, length) in offsets.iter().zip(lengths.iter()) {
+                    end = end.max(offset.saturating_add(*length) as usize);
+                }
+            }
+        }
+
+        if end > MAX_TIFF_FILE_SIZE {
+            return Err(ParseError::Malformed);
+        }
+        Ok(end)
+    }
+
+    // 埋め込まれたJPEGプレビューの (位置, 長さ)。複数あれば最も大きいもの
+    pub fn preview_jpeg(&self) -> Result<Option<(usize, usize)>, ParseError> {
+        let mut candidates = Vec::new();
+        for ifd in self.all_ifds()? {
+            if let Some(entry) = ifd.find(TAG_PANASONIC_JPG_FROM_RAW)
+                && let Some(location) = self.value_location(entry)
+            {
+                candidates.push(location);
+            }
+            if let (Some(offset), Some(length)) = (
+                ifd.find(TAG_JPEG_INTERCHANGE_FORMAT),
+                ifd.find(TAG_JPEG_INTERCHANGE_FORMAT_LENGTH),
+            ) {
+                let offset = self.unsigned_values(offset)?;
+                let length = self.unsigned_values(length)?;
+                if let (Some(&offset), Some(&length)) = (offset.first(), length.first()) {
+                    candidates.push((offset as usize, length as usize));
+                }
+            }
+        }
+
+        Ok(candidates
+            .into_iter()
+            .filter(|&(pos, len)| {
+                len > JPEG_START.len()
+                    && self
+                        .data
+                        .get(pos..pos.saturating_add(len))
+                        .is_some_and(|jpeg| jpeg.starts_with(JPEG_START))
+            })
+            .max_by_key(|&(_, len)| len))
+    }
+}
+
+pub fn type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+// data は TIFF ヘッダから始まっていること
+pub fn find_tiff_end(data: &[u8]) -> TiffEnd {
+    let extent = TiffReader::new(data).and_then(|reader| reader.extent());
+    match extent {
+        Ok(end) if end <= data.len() => TiffEnd::Found(end),
+        Ok(_) | Err(ParseError::NeedMoreData) => TiffEnd::Incomplete,
+        Err(ParseError::Malformed) => TiffEnd::Invalid,
+    }
+}
+
+// 切り出したものが TIFF として最後まで矛盾なく読めるか（find_tiff_end より厳しく調べる）
+// IFD のチェーンと子IFD、タグの値、ストリップ・タイルがすべてファイルの中に収まっていること
+pub fn validate(data: &[u8]) -> Result<(), &'static str> {
+    let reader = TiffReader::new(data).map_err(|_| "TIFF ヘッダを読めません")?;
+    let ifds = reader.all_ifds().map_err(|e| match e {
+        ParseError::NeedMoreData => "IFD がファイルの外を指しています",
+        ParseError::Malformed => "IFD のチェーンが壊れています",
+    })?;
+
+    let mut has_image_data = false;
+    for ifd in &ifds {
+        for entry in &ifd.entries {
+            if let Some((pos, size)) = reader.value_location(entry)
+                && pos.saturating_add(size) > data.len()
+            {
+                return Err("タグの値がファイルの外にあります");
+            }
+        }
+        for (offset_tag, length_tag) in DATA_BLOCK_TAGS {
+            let (offsets, lengths) = match (ifd.find(offset_tag), ifd.find(length_tag)) {
+                (Some(offsets), Some(lengths)) => (offsets, lengths),
+                (None, None) => continue,
+                _ => return Err("ストリップ・タイルの位置と長さがそろっていません"),
+            };
+            let offsets = reader
+                .unsigned_values(offsets)
+                .map_err(|_| "ストリップ・タイルの位置を読めません")?;
+            let lengths = reader
+                .unsigned_values(lengths)
+                .map_err(|_| "ストリップ・タイルの長さを読めません")?;
+            if offsets.len() != lengths.len() {
+                return Err("ストリップ・タイルの位置と長さの数が違います");
+            }
+            for (offset, length) in offsets.iter().zip(&lengths) {
+                if offset.saturating_add(*length) > data.len() as u64 {
+                    return Err("ストリップ・タイルがファイルの外にあります");
+                }
+            }
+            has_image_data |= !offsets.is_empty();
+        }
+    }
+    if !has_image_data {
+        return Err("画像データ（ストリップ・タイル）がありません");
+    }
+    Ok(())
+}
+
+// IFD0 に DNGVersion タグがあるか（TIFFベースのRAWのうち DNG を見分ける）
+pub fn has_dng_version(data: &[u8]) -> Result<bool, ParseError> {
+    let reader = TiffReader::new(data)?;
+    let ifd = reader.read_ifd(reader.first_ifd()?)?;
+    Ok(ifd.find(TAG_DNG_VERSION).is_some())
+}
+
+// IFD0 の Make が "NIKON" で始まるか（TIFFベースのRAWのうち NRW を見分ける）
+pub fn is_nikon(data: &[u8]) -> Result<bool, ParseError> {
+    let reader = TiffReader::new(data)?;
+    let ifd = reader.read_ifd(reader.first_ifd()?)?;
+    let Some((pos, len)) = ifd
+        .find(TAG_MAKE)
+        .and_then(|entry| reader.value_location(entry))
+    else {
+        return Ok(false);
+    };
+    Ok(len >= NIKON_MAKE.len() && reader.bytes(pos, NIKON_MAKE.len())? == NIKON_MAKE)
+}
+
+// IFD0 が複数ページの 1 ページ目か（スキャナやFAXの出力を RAW と見分ける）
+// PageNumber タグがあるか、NewSubfileType にページのビットが立っていること
+pub fn is_multi_page(data: &[u8]) -> Result<bool, ParseError> {
+    let reader = TiffReader::new(data)?;
+    let ifd = reader.read_ifd(reader.first_ifd()?)?;
+    if ifd.find(TAG_PAGE_NUMBER).is_some() {
+        return Ok(true);
+    }
+    let Some(entry) = ifd.find(TAG_NEW_SUBFILE_TYPE) else {
+        return Ok(false);
+    };
+    Ok(reader
+        .unsigned_values(entry)?
+        .first()
+        .is_some_and(|value| value & SUBFILE_PAGE != 0))
+}
+
+// 切り出したRAWに埋め込まれているJPEGプレビュー
+pub fn find_preview_jpeg(data: &[u8]) -> Option<&[u8]> {
+    let reader = TiffReader::new(data).ok()?;
+    let (pos, len) = reader.preview_jpeg().ok()??;
+    data.get(pos..pos + len)
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // リトルエンディアンのTIFFを組み立てる（エントリは (tag, type, count, value)）
+    pub fn little_endian_tiff(entries: &[(u16, u16, u32, u32)], total_len: usize) -> Vec<u8> {
+        let mut data = vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, field_type, count, value) in entries {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&field_type.to_le_bytes());
+            data.extend_from_slice(&count.to_le_bytes());
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.resize(total_len.max(data.len()), 0x5A);
+        data
+    }
+
+    // 2 ページのスキャナ出力。どちらの IFD も 1 本のストリップと PageNumber（ページ, 総数）を持つ
+    // IFD0 (8..50) → ストリップ (50..66) → IFD1 (66..108) → ストリップ (108..128)
+    pub fn two_page_tiff() -> Vec<u8> {
+        let mut data = vec![0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00];
+        for (page, strip, strip_len, next) in [(0u16, 50u32, 16u32, 66u32), (1, 108, 20, 0)] {
+            data.extend_from_slice(&3u16.to_le_bytes());
+            for (tag, field_type, count, value) in [
+                (super::TAG_STRIP_OFFSETS, 4u16, 1u32, strip),
+                (super::TAG_STRIP_BYTE_COUNTS, 4, 1, strip_len),
+                (super::TAG_PAGE_NUMBER, 3, 2, page as u32 | (2 << 16)),
+            ] {
+                data.extend_from_slice(&tag.to_le_bytes());
+                data.extend_from_slice(&field_type.to_le_bytes());
+                data.extend_from_slice(&count.to_le_bytes());
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&next.to_le_bytes());
+            data.resize((strip + strip_len) as usize, 0x5A);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::{little_endian_tiff, two_page_tiff};
+    use super::*;
+    use crate::test_util::minimal_jpeg;
+
+    // ---------------------------
+    // Tests for validate
+    // ---------------------------
+
+    #[test]
+    fn test_should_accept_when_every_ifd_and_strip_is_inside_file() {
+        // 1. setup
+        let data = little_endian_tiff(
+            &[
+                (TAG_STRIP_OFFSETS, 4, 1, 100),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            150,
+        );
+
+        // 2. execute
+        let result = validate(&data);
+
+        // 3. verify
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_should_reject_when_strip_runs_past_truncated_file() {
+        // 1. setup
+        let mut data = little_endian_tiff(
+            &[
+                (TAG_STRIP_OFFSETS, 4, 1, 100),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            150,
+        );
+        data.truncate(120);
+
+        // 2. execute
+        let result = validate(&data);
+
+        // 3. verify
+        assert_eq!(result, Err("ストリップ・タイルがファイルの外にあります"));
+    }
+
+    #[test]
+    fn test_should_reject_when_next_ifd_points_outside_file() {
+        // 1. setup
+        let mut data = little_endian_tiff(
+            &[
+                (TAG_STRIP_OFFSETS, 4, 1, 100),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            150,
+        );
+        // IFD0 の次の IFD へのオフセット（8 + 2 + 12 * 2 の位置）
+        data[34..38].copy_from_slice(&1000u32.to_le_bytes());
+
+        // 2. execute
+        let result = validate(&data);
+
+        // 3. verify
+        assert_eq!(result, Err("IFD がファイルの外を指しています"));
+    }
+
+    // ---------------------------
+    // Tests for find_tiff_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_include_last_page_strip_when_ifd_chain_has_two_pages() {
+        // 1. setup
+        let tiff = two_page_tiff();
+        let data = [tiff.as_slice(), &[0xEE; 64]].concat();
+
+        // 2. execute
+        let result = find_tiff_end(&data);
+
+        // 3. verify
+        assert_eq!(tiff.len(), 128);
+        assert_eq!(result, TiffEnd::Found(128));
+    }
+
+    #[test]
+    fn test_should_return_strip_end_when_ifd_has_strip_offsets_and_counts() {
+        // 1. setup
+        let mut data = little_endian_tiff(
+            &[
+                (TAG_STRIP_OFFSETS, 4, 1, 100),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            150,
+        );
+        data.extend_from_slice(&[0xEE; 64]); // 後ろに続くゴミ
+
+        // 2. execute
+        let result = find_tiff_end(&data);
+
+        // 3. verify
+        assert_eq!(result, TiffEnd::Found(150));
+    }
+
+    #[test]
+    fn test_should_return_incomplete_when_strip_data_is_not_buffered_yet() {
+        // 1. setup
+        let data = little_endian_tiff(
+            &[
+                (TAG_STRIP_OFFSETS, 4, 1, 100),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, 50),
+            ],
+            120,
+        );
+
+        // 2. execute
+        let result = find_tiff_end(&data);
+
+        // 3. verify
+        assert_eq!(result, TiffEnd::Incomplete);
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_declared_size_is_absurd() {
+        // 1. setup
+        let data = little_endian_tiff(
+            &[
+                (TAG_STRIP_OFFSETS, 4, 1, 100),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, 0xF000_0000),
+            ],
+            150,
+        );
+
+        // 2. execute
+        let result = find_tiff_end(&data);
+
+        // 3. verify
+        assert_eq!(result, TiffEnd::Invalid);
+    }
+
+    // ---------------------------
+    // Tests for has_dng_version
+    // ---------------------------
+
+    #[test]
+    fn test_should_detect_dng_when_ifd0_has_dng_version_tag() {
+        // 1. setup
+        let dng = little_endian_tiff(&[(TAG_DNG_VERSION, 1, 4, 0x0000_0401)], 64);
+        let plain = little_endian_tiff(&[(TAG_STRIP_OFFSETS, 4, 1, 40)], 64);
+
+        // 2. execute
+        let dng_result = has_dng_version(&dng);
+        let plain_result = has_dng_version(&plain);
+
+        // 3. verify
+        assert_eq!(dng_result, Ok(true));
+        assert_eq!(plain_result, Ok(false));
+    }
+
+    // ---------------------------
+    // Tests for is_nikon
+    // ---------------------------
+
+    #[test]
+    fn test_should_detect_nikon_when_make_is_stored_after_ifd() {
+        // 1. setup
+        let mut data = little_endian_tiff(&[(TAG_MAKE, 2, 18, 26)], 64);
+        data[26..44].copy_from_slice(b"NIKON CORPORATION\0");
+        let other = little_endian_tiff(&[(TAG_MAKE, 2, 4, u32::from_le_bytes(*b"SON\0"))], 64);
+
+        // 2. execute
+        let results = [is_nikon(&data), is_nikon(&other)];
+
+        // 3. verify
+        assert_eq!(results, [Ok(true), Ok(false)]);
+    }
+
+    // ---------------------------
+    // Tests for is_multi_page
+    // ---------------------------
+
+    #[test]
+    fn test_should_detect_page_when_ifd0_has_page_number_or_page_subfile_bit() {
+        // 1. setup
+        let paged = two_page_tiff();
+        let subfile = little_endian_tiff(&[(TAG_NEW_SUBFILE_TYPE, 4, 1, 0x2)], 64);
+        let reduced = little_endian_tiff(&[(TAG_NEW_SUBFILE_TYPE, 4, 1, 0x1)], 64);
+
+        // 2. execute
+        let results = [
+            is_multi_page(&paged),
+            is_multi_page(&subfile),
+            is_multi_page(&reduced),
+        ];
+
+        // 3. verify
+        assert_eq!(results, [Ok(true), Ok(true), Ok(false)]);
+    }
+
+    // ---------------------------
+    // Tests for find_preview_jpeg
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_preview_when_panasonic_preview_tag_points_at_jpeg() {
+        // 1. setup
+        let preview = minimal_jpeg(&[0x11, 0x22, 0x33]);
+        let mut data = little_endian_tiff(
+            &[(TAG_PANASONIC_JPG_FROM_RAW, 7, preview.len() as u32, 100)],
+            100 + preview.len(),
+        );
+        data[100..].copy_from_slice(&preview);
+
+        // 2. execute
+        let result = find_preview_jpeg(&data);
+
+        // 3. verify
+        assert_eq!(result, Some(&preview[..]));
+    }
+
+    #[test]
+    fn test_should_return_none_when_preview_tag_points_at_non_jpeg() {
+        // 1. setup
+        let data = little_endian_tiff(&[(TAG_PANASONIC_JPG_FROM_RAW, 7, 20, 100)], 120);
+
+        // 2. execute
+        let result = find_preview_jpeg(&data);
+
+        // 3. verify
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_ifd_has_no_entries() {
+        // 1. setup
+        let data = little_endian_tiff(&[], 64);
+
+        // 2. execute
+        let result = find_tiff_end(&data);
+
+        // 3. verify
+        assert_eq!(result, TiffEnd::Invalid);
+    }
+}
@@ -0,0 +1,47 @@
+// --trim-trailing: 次のスタートシグネチャ（またはバッファの終わり）まで切り出したファイルの後ろに付いた、
+// 次のファイルまでの詰め物のゼロを落とす
+// 構造から本来の大きさが分かる形式はもともとそこで切っているので、ここに来るのはそれが分からなかったものだけ
+use crate::FileType;
+
+// data の後ろに続くゼロを除いた長さ（スタートシグネチャより短くはしない）
+pub fn trimmed_len(data: &[u8], file_type: FileType) -> usize {
+    let end = data
+        .iter()
+        .rposition(|&b| b != 0x00)
+        .map_or(0, |pos| pos + 1);
+    end.max(file_type.signature_len().min(data.len()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for trimmed_len
+    // ---------------------------
+
+    #[test]
+    fn test_should_drop_trailing_zeros_but_keep_inner_zeros() {
+        // 1. setup
+        let data = [0x49, 0x49, 0x2A, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00];
+
+        // 2. execute
+        let result = trimmed_len(&data, FileType::Rw2);
+
+        // 3. verify
+        assert_eq!(result, 6);
+    }
+
+    #[test]
+    fn test_should_keep_signature_when_everything_after_it_is_zero() {
+        // 1. setup
+        let data = [0x49, 0x49, 0x2A, 0x00, 0x00, 0x00];
+
+        // 2. execute
+        let result = trimmed_len(&data, FileType::Rw2);
+
+        // 3. verify
+        assert_eq!(result, 4);
+    }
+}
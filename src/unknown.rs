@@ -0,0 +1,78 @@
+// --dump-unknown: どの形式としても切り出さなかった領域を、手で調べるために unknown/ に書き出す
+pub const UNKNOWN_DIR: &str = "unknown";
+
+// carved は (オフセット, 長さ)。start..end のうち carved に含まれない連続した領域のうち、min_len 以上のもの
+pub fn gaps(carved: &[(u64, u64)], start: u64, end: u64, min_len: u64) -> Vec<(u64, u64)> {
+    let mut carved = carved.to_vec();
+    carved.sort_unstable();
+
+    let mut gaps = Vec::new();
+    let mut position = start;
+    for (offset, len) in carved {
+        if offset > position {
+            gaps.push((position, offset.min(end) - position));
+        }
+        position = position.max(offset + len);
+        if position >= end {
+            break;
+        }
+    }
+    if position < end {
+        gaps.push((position, end - position));
+    }
+    gaps.retain(|&(_, len)| len >= min_len.max(1));
+    gaps
+}
+
+// 大きな領域は max_len ごとに分け、それぞれの先頭のオフセットで名前を付ける
+pub fn pieces(gap: (u64, u64), max_len: u64) -> impl Iterator<Item = (u64, u64)> {
+    let (offset, len) = gap;
+    let step = max_len.max(1);
+    (0..len.div_ceil(step)).map(move |i| {
+        let piece_offset = offset + i * step;
+        (piece_offset, step.min(offset + len - piece_offset))
+    })
+}
+
+pub fn file_name(offset: u64) -> String {
+    format!("{}/region_{}.bin", UNKNOWN_DIR, offset)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for gaps
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_regions_between_and_after_carved_files_when_they_are_long_enough() {
+        // 1. setup
+        let carved = [(5000, 1000), (100, 400)];
+
+        // 2. execute
+        let result = gaps(&carved, 0, 10_000, 200);
+
+        // 3. verify
+        // 0..100 は短いので除く
+        assert_eq!(result, vec![(500, 4500), (6000, 4000)]);
+    }
+
+    // ---------------------------
+    // Tests for pieces
+    // ---------------------------
+
+    #[test]
+    fn test_should_split_gap_into_bounded_pieces_when_gap_exceeds_max_len() {
+        // 1. setup
+        let gap = (1000, 2500);
+
+        // 2. execute
+        let result: Vec<_> = pieces(gap, 1000).collect();
+
+        // 3. verify
+        assert_eq!(result, vec![(1000, 1000), (2000, 1000), (3000, 500)]);
+    }
+}
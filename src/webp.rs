@@ -0,0 +1,127 @@
+// WebP（RIFFコンテナ）のヘッダの検証と、そこに書かれたファイルサイズ
+const HEADER_LEN: usize = 12;
+const FORM_TYPE: &[u8] = b"WEBP";
+
+#[derive(Debug, PartialEq)]
+pub enum WebpHeader {
+    // RIFFのサイズ欄はその直後からの長さなので、8を足したものがファイルサイズ
+    Valid { size: u64 },
+    Incomplete,
+    Invalid,
+}
+
+// data は "RIFF" から始まっていること（AVI や WAV などの RIFF は偽物とみなす）
+pub fn parse_header(data: &[u8]) -> WebpHeader {
+    if data.len() < HEADER_LEN {
+        return WebpHeader::Incomplete;
+    }
+    if data.get(8..12) != Some(FORM_TYPE) {
+        return WebpHeader::Invalid;
+    }
+
+    let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    WebpHeader::Valid {
+        size: riff_size as u64 + 8,
+    }
+}
+
+// 最初のチャンク（VP8 / VP8L / VP8X）から (幅, 高さ) を読む
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let chunk = data.get(12..16)?;
+    let payload = data.get(20..)?;
+    let le24 = |pos: usize| -> Option<u32> {
+        let bytes = payload.get(pos..pos + 3)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+    };
+    match chunk {
+        // フレームタグ3バイト + 開始コード 9D 01 2A のあとに 14ビットずつ
+        b"VP8 " if payload.get(3..6)? == [0x9D, 0x01, 0x2A] => {
+            let width = u16::from_le_bytes([*payload.get(6)?, *payload.get(7)?]) & 0x3FFF;
+            let height = u16::from_le_bytes([*payload.get(8)?, *payload.get(9)?]) & 0x3FFF;
+            Some((width as u32, height as u32))
+        }
+        // シグネチャ 0x2F のあとに 幅-1, 高さ-1 を 14ビットずつ
+        b"VP8L" if *payload.first()? == 0x2F => {
+            let bits = u32::from_le_bytes(payload.get(1..5)?.try_into().ok()?);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        // フラグ4バイトのあとにキャンバスの 幅-1, 高さ-1 を 24ビットずつ
+        b"VP8X" => Some((le24(4)? + 1, le24(7)? + 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    // VP8L チャンクを1つだけ持つ WebP を組み立てる
+    pub fn minimal_webp(payload: usize) -> Vec<u8> {
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&((4 + 8 + payload) as u32).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(b"VP8L");
+        data.extend_from_slice(&(payload as u32).to_le_bytes());
+        data.resize(data.len() + payload, 0x2F);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_webp;
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse_header
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_riff_size_plus_header_when_form_type_is_webp() {
+        // 1. setup
+        let webp = minimal_webp(10);
+
+        // 2. execute
+        let result = parse_header(&webp);
+
+        // 3. verify
+        assert_eq!(
+            result,
+            WebpHeader::Valid {
+                size: webp.len() as u64
+            }
+        );
+    }
+
+    #[test]
+    fn test_should_return_invalid_when_riff_is_not_webp() {
+        // 1. setup
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&100u32.to_le_bytes());
+        data.extend_from_slice(b"WAVE");
+
+        // 2. execute
+        let result = parse_header(&data);
+
+        // 3. verify
+        assert_eq!(result, WebpHeader::Invalid);
+    }
+
+    // ---------------------------
+    // Tests for dimensions
+    // ---------------------------
+
+    #[test]
+    fn test_should_read_canvas_size_when_first_chunk_is_vp8x() {
+        // 1. setup
+        let mut data = minimal_webp(10);
+        data[12..16].copy_from_slice(b"VP8X");
+        data[24..27].copy_from_slice(&[0x7F, 0x07, 0x00]); // 1920 - 1
+        data[27..30].copy_from_slice(&[0x37, 0x04, 0x00]); // 1080 - 1
+
+        // 2. execute
+        let result = dimensions(&data);
+
+        // 3. verify
+        assert_eq!(result, Some((1920, 1080)));
+    }
+}
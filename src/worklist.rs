@@ -0,0 +1,56 @@
+// --worklist で渡す、切り出したいオフセットの一覧（1行に1つ、10進数か 0x から始まる16進数）
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cli::parse_offset;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Worklist {
+    pub offsets: Vec<u64>,
+    // 解釈できなかった (行番号, 行の内容)。行番号は1始まり
+    pub invalid: Vec<(usize, String)>,
+}
+
+// 空行と # から始まるコメント行は無視する
+pub fn parse(text: &str) -> Worklist {
+    let mut worklist = Worklist::default();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_offset(line) {
+            Some(offset) => worklist.offsets.push(offset),
+            None => worklist.invalid.push((idx + 1, line.to_string())),
+        }
+    }
+    worklist
+}
+
+pub fn load(path: &Path) -> io::Result<Worklist> {
+    fs::read_to_string(path).map(|text| parse(&text))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // ---------------------------
+    // Tests for parse
+    // ---------------------------
+
+    #[test]
+    fn test_should_keep_valid_offsets_and_report_line_numbers_when_some_lines_are_invalid() {
+        // 1. setup
+        let text = "# forensic report\n4096\n\n0x2000\nnot-an-offset\n  12288  \n";
+
+        // 2. execute
+        let result = parse(text);
+
+        // 3. verify
+        assert_eq!(result.offsets, vec![4096, 0x2000, 12288]);
+        assert_eq!(result.invalid, vec![(5, "not-an-offset".to_string())]);
+    }
+}
@@ -0,0 +1,139 @@
+// Sigma X3F の終端。ヘッダにファイルの長さはないが、ファイルの最後の 4 バイトが
+// ディレクトリ（"SECd" + 版 + エントリ数 + (位置, 長さ, 種類) の並び）の位置を指している
+// 値はすべてリトルエンディアン
+pub const MAGIC: &[u8] = b"FOVb";
+
+// "FOVb", 版, 固有ID 16 バイト, マークビット, 幅, 高さ, 回転
+const HEADER_LEN: usize = 40;
+const DIRECTORY_MAGIC: &[u8] = b"SECd";
+const DIRECTORY_HEADER_LEN: usize = 12;
+const DIRECTORY_ENTRY_LEN: usize = 12;
+// 実際のエントリは PROP / IMAG / IMA2 / CAMF の数個
+const MAX_DIRECTORY_ENTRIES: usize = 256;
+// 版は上位 16 ビットが主版数（2.0 〜 4.x）
+const VERSIONS: std::ops::RangeInclusive<u32> = 2..=4;
+
+#[derive(Debug, PartialEq)]
+pub enum X3fEnd {
+    // ディレクトリの位置を指す末尾の 4 バイトの直後までの長さ
+    Found(usize),
+    Incomplete,
+    Invalid,
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+// pos の "SECd" が本物のディレクトリなら、その後ろの末尾のポインタまでの長さ
+// エントリの指す範囲がディレクトリより前に収まり、末尾のポインタが pos を指していること
+fn directory_end(data: &[u8], pos: usize) -> Option<usize> {
+    let count = u32_at(data, pos + 8)? as usize;
+    if count == 0 || count > MAX_DIRECTORY_ENTRIES {
+        return None;
+    }
+    let entries = pos + DIRECTORY_HEADER_LEN;
+    for i in 0..count {
+        let entry = entries + i * DIRECTORY_ENTRY_LEN;
+        let offset = u32_at(data, entry)? as usize;
+        let len = u32_at(data, entry + 4)? as usize;
+        if offset < HEADER_LEN || offset.checked_add(len)? > pos {
+            return None;
+        }
+    }
+    let pointer = entries + count * DIRECTORY_ENTRY_LEN;
+    (u32_at(data, pointer)? as usize == pos).then_some(pointer + 4)
+}
+
+// data は "FOVb" から始まっていること
+pub fn find_x3f_end(data: &[u8]) -> X3fEnd {
+    let Some(version) = u32_at(data, 4) else {
+        return X3fEnd::Incomplete;
+    };
+    if !VERSIONS.contains(&(version >> 16)) {
+        return X3fEnd::Invalid;
+    }
+    let mut from = HEADER_LEN;
+    while let Some(idx) = data.get(from..).and_then(|rest| {
+        rest.windows(DIRECTORY_MAGIC.len())
+            .position(|w| w == DIRECTORY_MAGIC)
+    }) {
+        let pos = from + idx;
+        if let Some(end) = directory_end(data, pos) {
+            return X3fEnd::Found(end);
+        }
+        from = pos + 1;
+    }
+    X3fEnd::Incomplete
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::HEADER_LEN;
+
+    // 版 2.2 のヘッダの後ろに image_len バイトの IMAG セクションと、それを指すディレクトリを置いた X3F
+    pub fn minimal_x3f(image_len: u32) -> Vec<u8> {
+        let mut data = b"FOVb".to_vec();
+        data.extend_from_slice(&0x0002_0002u32.to_le_bytes());
+        data.resize(HEADER_LEN, 0x00);
+        data.resize(HEADER_LEN + image_len as usize, 0x3C);
+        let directory = data.len() as u32;
+        data.extend_from_slice(b"SECd");
+        data.extend_from_slice(&0x0002_0000u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&(HEADER_LEN as u32).to_le_bytes());
+        data.extend_from_slice(&image_len.to_le_bytes());
+        data.extend_from_slice(b"IMAG");
+        data.extend_from_slice(&directory.to_le_bytes());
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_x3f;
+    use super::*;
+
+    // ---------------------------
+    // Tests for find_x3f_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_end_after_directory_pointer_when_directory_is_consistent() {
+        // 1. setup
+        let x3f = minimal_x3f(64);
+        let data = [x3f.as_slice(), &[0x77; 32]].concat();
+
+        // 2. execute
+        let result = find_x3f_end(&data);
+
+        // 3. verify
+        assert_eq!(result, X3fEnd::Found(x3f.len()));
+    }
+
+    #[test]
+    fn test_should_skip_secd_in_image_data_when_it_is_not_a_directory() {
+        // 1. setup
+        let mut x3f = minimal_x3f(64);
+        x3f[50..54].copy_from_slice(b"SECd");
+
+        // 2. execute
+        let result = find_x3f_end(&x3f);
+
+        // 3. verify
+        assert_eq!(result, X3fEnd::Found(x3f.len()));
+    }
+
+    #[test]
+    fn test_should_wait_for_more_data_when_directory_is_not_yet_read() {
+        // 1. setup
+        let x3f = minimal_x3f(64);
+
+        // 2. execute
+        let result = find_x3f_end(&x3f[..80]);
+
+        // 3. verify
+        assert_eq!(result, X3fEnd::Incomplete);
+    }
+}
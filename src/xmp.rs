@@ -0,0 +1,121 @@
+// XMP サイドカー（.xmp）の終端。中身はテキストの XML で、長さを示す欄はない
+// "<?xpacket begin=...?>" で始まるパケットは "<?xpacket end="w"?>" の後ろまで、
+// パケットで包まれていない "<x:xmpmeta" は "</x:xmpmeta>" の後ろまで
+// スタートシグネチャは同じ長さにそろえる
+pub const PACKET_START: &[u8] = b"<?xpacket ";
+pub const META_START: &[u8] = b"<x:xmpmeta";
+
+// パケットの終わりの処理命令。この後ろに 'w' か 'r' を引用符で囲んだものと "?>" が続く
+pub const PACKET_END: &[u8] = b"<?xpacket end=";
+const META_END: &[u8] = b"</x:xmpmeta>";
+// end="w"?> の、"end=" より後ろの長さ
+const PACKET_END_TAIL_LEN: usize = 5;
+
+#[derive(Debug, PartialEq)]
+pub enum XmpEnd {
+    // 終わりの印の直後までの長さ
+    Found(usize),
+    Incomplete,
+    // 終わりの印より前に NUL がある（テキストではないものに上書きされている）
+    Invalid,
+}
+
+// "<?xpacket end=" の後ろの 'w'?> / "r"?> を確かめる
+fn packet_tail_is_valid(tail: &[u8]) -> bool {
+    matches!(tail, [quote @ (b'"' | b'\''), b'w' | b'r', close, b'?', b'>'] if close == quote)
+}
+
+// data は PACKET_START か META_START から始まっていること
+pub fn find_xmp_end(data: &[u8]) -> XmpEnd {
+    let (marker, tail_len) = if data.starts_with(PACKET_START) {
+        (PACKET_END, PACKET_END_TAIL_LEN)
+    } else {
+        (META_END, 0)
+    };
+    let Some(pos) = data.windows(marker.len()).position(|w| w == marker) else {
+        return if data.contains(&0) {
+            XmpEnd::Invalid
+        } else {
+            XmpEnd::Incomplete
+        };
+    };
+    if data[..pos].contains(&0) {
+        return XmpEnd::Invalid;
+    }
+    let end = pos + marker.len() + tail_len;
+    match data.get(pos + marker.len()..end) {
+        Some(tail) if tail_len == 0 || packet_tail_is_valid(tail) => XmpEnd::Found(end),
+        Some(_) => XmpEnd::Invalid,
+        None => XmpEnd::Incomplete,
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+
+    // Lightroom のサイドカーのように、xmpmeta をパケットで包んだもの
+    pub fn xmp_packet(rating: u8) -> Vec<u8> {
+        format!(
+            "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+             <rdf:Description xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmp:Rating=\"{}\"/>\n \
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            rating
+        )
+        .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::xmp_packet;
+    use super::*;
+
+    // ---------------------------
+    // Tests for find_xmp_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_end_after_packet_end_when_packet_is_complete() {
+        // 1. setup
+        let xmp = xmp_packet(5);
+        let data = [xmp.as_slice(), &[0x00; 32]].concat();
+
+        // 2. execute
+        let result = find_xmp_end(&data);
+
+        // 3. verify
+        assert_eq!(result, XmpEnd::Found(xmp.len()));
+    }
+
+    #[test]
+    fn test_should_end_after_closing_tag_when_xmpmeta_is_not_wrapped() {
+        // 1. setup
+        let xmp = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>".to_vec();
+        let data = [xmp.as_slice(), b"\n\n"].concat();
+
+        // 2. execute
+        let result = find_xmp_end(&data);
+
+        // 3. verify
+        assert_eq!(result, XmpEnd::Found(xmp.len()));
+    }
+
+    #[test]
+    fn test_should_wait_or_reject_when_end_marker_is_missing() {
+        // 1. setup
+        let xmp = xmp_packet(3);
+        let cut = &xmp[..xmp.len() - 4];
+        let overwritten = [&xmp[..40], &[0x00; 8], &xmp[40..]].concat();
+
+        // 2. execute
+        let results = [find_xmp_end(cut), find_xmp_end(&overwritten)];
+
+        // 3. verify
+        assert_eq!(results, [XmpEnd::Incomplete, XmpEnd::Invalid]);
+    }
+}
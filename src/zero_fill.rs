@@ -0,0 +1,27 @@
+// 検出したファイル領域だけを元の位置に書き戻し、それ以外をゼロにした再構成イメージ（診断用）
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const RECONSTRUCTED_FILE_NAME: &str = "reconstructed.img";
+
+pub struct ZeroFillImage {
+    file: File,
+}
+
+impl ZeroFillImage {
+    pub fn create(output_dir: &Path) -> io::Result<Self> {
+        let file = File::create(output_dir.join(RECONSTRUCTED_FILE_NAME))?;
+        Ok(ZeroFillImage { file })
+    }
+
+    pub fn write_region(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)
+    }
+
+    // 書き込まなかった部分はスパース領域としてゼロになる
+    pub fn finish(self, total_len: u64) -> io::Result<()> {
+        self.file.set_len(total_len)
+    }
+}
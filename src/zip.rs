@@ -0,0 +1,313 @@
+// ZIP（と docx / xlsx / odt などZIPベースの文書）の終端検出と中身による拡張子の判別
+use crate::ZIP_START;
+
+const EOCD_SIGNATURE: &[u8] = &[0x50, 0x4B, 0x05, 0x06];
+const EOCD_LEN: usize = 22;
+const CENTRAL_HEADER_SIGNATURE: &[u8] = &[0x50, 0x4B, 0x01, 0x02];
+const CENTRAL_HEADER_LEN: usize = 46;
+
+#[derive(Debug, PartialEq)]
+pub enum ZipEnd {
+    Found(usize),
+    Incomplete,
+    Invalid,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<usize> {
+    let bytes = data.get(pos..pos + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<usize> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+// セントラルディレクトリの位置と大きさが終端レコードの位置と噛み合うものだけを本物とみなす
+// （格納された別のZIPの終端レコードや、データ中の偶然の一致を除くため）
+fn central_directory(data: &[u8], eocd: usize) -> Option<(usize, usize)> {
+    let size = u32_at(data, eocd + 12)?;
+    let offset = u32_at(data, eocd + 16)?;
+    (offset.checked_add(size)? == eocd).then_some((offset, size))
+}
+
+// data は ZIP のローカルファイルヘッダから始まっていること
+// 終端レコードが見つからないまま max_size を超えたら諦める
+pub fn find_zip_end(data: &[u8], max_size: u64) -> ZipEnd {
+    if !data.starts_with(ZIP_START) {
+        return ZipEnd::Invalid;
+    }
+
+    let mut search_from = ZIP_START.len();
+    while let Some(idx) = data
+        .get(search_from..)
+        .unwrap_or_default()
+        .windows(EOCD_SIGNATURE.len())
+        .position(|window| window == EOCD_SIGNATURE)
+    {
+        let eocd = search_from + idx;
+        search_from = eocd + 1;
+
+        let Some(comment_len) = u16_at(data, eocd + 20) else {
+            return ZipEnd::Incomplete;
+        };
+        if central_directory(data, eocd).is_none() {
+            continue;
+        }
+
+        // 終端レコードの後ろにコメントが続く
+        let end = eocd + EOCD_LEN + comment_len;
+        if end > data.len() {
+            return ZipEnd::Incomplete;
+        }
+        return ZipEnd::Found(end);
+    }
+
+    if data.len() as u64 > max_size {
+        ZipEnd::Invalid
+    } else {
+        ZipEnd::Incomplete
+    }
+}
+
+// セントラルディレクトリに載っているファイル名
+fn entry_names(data: &[u8]) -> Vec<&[u8]> {
+    let mut names = Vec::new();
+    let Some(eocd) = data
+        .windows(EOCD_SIGNATURE.len())
+        .rposition(|window| window == EOCD_SIGNATURE)
+    else {
+        return names;
+    };
+    let Some((mut pos, size)) = central_directory(data, eocd) else {
+        return names;
+    };
+
+    let directory_end = pos + size;
+    while pos + CENTRAL_HEADER_LEN <= directory_end
+        && data
+            .get(pos..)
+            .is_some_and(|rest| rest.starts_with(CENTRAL_HEADER_SIGNATURE))
+    {
+        let (Some(name_len), Some(extra_len), Some(comment_len)) = (
+            u16_at(data, pos + 28),
+            u16_at(data, pos + 30),
+            u16_at(data, pos + 32),
+        ) else {
+            break;
+        };
+        let name_start = pos + CENTRAL_HEADER_LEN;
+        let Some(name) = data.get(name_start..name_start + name_len) else {
+            break;
+        };
+        names.push(name);
+        pos = name_start + name_len + extra_len + comment_len;
+    }
+    names
+}
+
+// OpenDocument は先頭のエントリ "mimetype" に無圧縮で種類が書かれている
+fn opendocument_extension(data: &[u8]) -> Option<&'static str> {
+    const MIMETYPE: &[u8] = b"mimetype";
+    let name_len = u16_at(data, 26)?;
+    let extra_len = u16_at(data, 28)?;
+    if data.get(30..30 + name_len)? != MIMETYPE {
+        return None;
+    }
+    let content_len = u32_at(data, 18)?;
+    let content_start = 30 + name_len + extra_len;
+    let content = data.get(content_start..content_start + content_len)?;
+
+    match content {
+        b"application/vnd.oasis.opendocument.text" => Some("odt"),
+        b"application/vnd.oasis.opendocument.spreadsheet" => Some("ods"),
+        b"application/vnd.oasis.opendocument.presentation" => Some("odp"),
+        _ => None,
+    }
+}
+
+// 中身を見て、Office 文書なら対応する拡張子を返す
+pub fn document_extension(data: &[u8]) -> &'static str {
+    if let Some(extension) = opendocument_extension(data) {
+        return extension;
+    }
+
+    let names = entry_names(data);
+    if names.iter().any(|name| *name == b"[Content_Types].xml") {
+        for (prefix, extension) in [
+            (&b"word/"[..], "docx"),
+            (&b"xl/"[..], "xlsx"),
+            (&b"ppt/"[..], "pptx"),
+        ] {
+            if names.iter().any(|name| name.starts_with(prefix)) {
+                return extension;
+            }
+        }
+    }
+    "zip"
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use crate::crc32;
+
+    // 無圧縮のZIPを組み立てる（エントリは (名前, 中身)）
+    pub fn minimal_zip(entries: &[(&str, &[u8])], comment: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+
+        for (name, content) in entries {
+            let offset = data.len() as u32;
+            let crc = crc32::crc32(content);
+            let size = content.len() as u32;
+
+            data.extend_from_slice(&[0x50, 0x4B, 0x03, 0x04, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            data.extend_from_slice(&crc.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(&0u16.to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(content);
+
+            directory.extend_from_slice(&[0x50, 0x4B, 0x01, 0x02, 20, 0, 20, 0, 0, 0, 0, 0]);
+            directory.extend_from_slice(&[0, 0, 0, 0]);
+            directory.extend_from_slice(&crc.to_le_bytes());
+            directory.extend_from_slice(&size.to_le_bytes());
+            directory.extend_from_slice(&size.to_le_bytes());
+            directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = data.len() as u32;
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(&[0x50, 0x4B, 0x05, 0x06, 0, 0, 0, 0]);
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        data.extend_from_slice(&directory_offset.to_le_bytes());
+        data.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        data.extend_from_slice(comment);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_fixtures::minimal_zip;
+    use super::*;
+
+    // ---------------------------
+    // Tests for find_zip_end
+    // ---------------------------
+
+    #[test]
+    fn test_should_include_comment_when_eocd_has_comment_length() {
+        // 1. setup
+        let zip = minimal_zip(&[("a.txt", b"hello"), ("b.txt", b"world")], b"note");
+        let mut data = zip.clone();
+        data.extend_from_slice(&[0xEE; 32]); // 後ろに続くゴミ
+
+        // 2. execute
+        let result = find_zip_end(&data, 1 << 20);
+
+        // 3. verify
+        assert_eq!(result, ZipEnd::Found(zip.len()));
+    }
+
+    #[test]
+    fn test_should_skip_inner_eocd_when_zip_stores_another_zip() {
+        // 1. setup
+        let inner = minimal_zip(&[("inner.txt", b"inner")], b"");
+        let outer = minimal_zip(&[("inner.zip", &inner), ("after.txt", b"after")], b"");
+
+        // 2. execute
+        let result = find_zip_end(&outer, 1 << 20);
+
+        // 3. verify
+        assert_eq!(result, ZipEnd::Found(outer.len()));
+    }
+
+    #[test]
+    fn test_should_return_incomplete_when_comment_is_not_buffered_yet() {
+        // 1. setup
+        let zip = minimal_zip(&[("a.txt", b"hello")], b"a long comment");
+        let data = &zip[..zip.len() - 4];
+
+        // 2. execute
+        let result = find_zip_end(data, 1 << 20);
+
+        // 3. verify
+        assert_eq!(result, ZipEnd::Incomplete);
+    }
+
+    #[test]
+    fn test_should_give_up_when_no_eocd_is_found_within_max_size() {
+        // 1. setup
+        let zip = minimal_zip(&[("a.txt", &[0x11; 4096])], b"");
+        let data = &zip[..4096];
+
+        // 2. execute
+        let within = find_zip_end(data, 4096);
+        let beyond = find_zip_end(data, 4095);
+
+        // 3. verify
+        assert_eq!(within, ZipEnd::Incomplete);
+        assert_eq!(beyond, ZipEnd::Invalid);
+    }
+
+    // ---------------------------
+    // Tests for document_extension
+    // ---------------------------
+
+    #[test]
+    fn test_should_return_docx_when_zip_has_word_parts() {
+        // 1. setup
+        let zip = minimal_zip(
+            &[
+                ("[Content_Types].xml", b"<Types/>"),
+                ("word/document.xml", b"<w:document/>"),
+            ],
+            b"",
+        );
+
+        // 2. execute
+        let result = document_extension(&zip);
+
+        // 3. verify
+        assert_eq!(result, "docx");
+    }
+
+    #[test]
+    fn test_should_return_odt_when_mimetype_is_opendocument_text() {
+        // 1. setup
+        let zip = minimal_zip(
+            &[
+                ("mimetype", b"application/vnd.oasis.opendocument.text"),
+                ("content.xml", b"<office:document-content/>"),
+            ],
+            b"",
+        );
+
+        // 2. execute
+        let result = document_extension(&zip);
+
+        // 3. verify
+        assert_eq!(result, "odt");
+    }
+
+    #[test]
+    fn test_should_return_zip_when_contents_are_not_a_document() {
+        // 1. setup
+        let zip = minimal_zip(&[("photo.txt", b"plain")], b"");
+
+        // 2. execute
+        let result = document_extension(&zip);
+
+        // 3. verify
+        assert_eq!(result, "zip");
+    }
+}